@@ -0,0 +1,57 @@
+#![no_main]
+
+use libfuzzer_sys::arbitrary::{self, Arbitrary};
+use libfuzzer_sys::fuzz_target;
+use rust_chip8::chip8::{Chip8, Quirks, MEMORY_SIZE};
+use rust_chip8::crash;
+
+#[derive(Debug, Arbitrary)]
+struct Frame {
+    // Held for the frame, 0 to F, or none
+    key: Option<u8>,
+    // Timers forced to these before it runs, as a frontend's might drift
+    delay: Option<u8>,
+    sound: Option<u8>,
+    instructions: u8,
+}
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    quirks: [bool; 5],
+    seed: u64,
+    rom: Vec<u8>,
+    frames: Vec<Frame>,
+}
+
+// A ROM played with any quirks, keys and timers, frame by frame as the
+// frontends run it
+fuzz_target!(|input: Input| {
+    let mut cpu = Chip8::new();
+    cpu.seed(input.seed);
+    cpu.load_font();
+    cpu.load_program(&input.rom[..input.rom.len().min(MEMORY_SIZE - 0x200)]);
+    let [vf_reset, shift_vy, increment_i, jump_vx, clip] = input.quirks;
+    cpu.quirks = Quirks {
+        vf_reset,
+        shift_vy,
+        increment_i,
+        jump_vx,
+        clip,
+    };
+    for frame in &input.frames {
+        cpu.input = frame.key.map_or(0, |key| key & 0x0F);
+        if let Some(delay) = frame.delay {
+            cpu.delay_timer = delay;
+        }
+        if let Some(sound) = frame.sound {
+            cpu.sound_timer = sound;
+        }
+        for _ in 0..frame.instructions {
+            if crash::fault(&cpu).is_some() {
+                return;
+            }
+            cpu.tick();
+        }
+        cpu.decrement_timers();
+    }
+});
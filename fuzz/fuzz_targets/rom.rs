@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_chip8::chip8::{Chip8, MEMORY_SIZE};
+use rust_chip8::crash;
+
+// About ten seconds of a game at the default speed
+const INSTRUCTIONS: usize = 6000;
+
+// Any bytes as a ROM, run until the core reports a fault. crash::fault has to
+// catch every instruction that would panic, since the frontends rely on it.
+fuzz_target!(|rom: &[u8]| {
+    let mut cpu = Chip8::new();
+    cpu.seed(0);
+    cpu.load_font();
+    cpu.load_program(&rom[..rom.len().min(MEMORY_SIZE - 0x200)]);
+    for _ in 0..INSTRUCTIONS {
+        if crash::fault(&cpu).is_some() {
+            break;
+        }
+        cpu.tick();
+    }
+});
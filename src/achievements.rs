@@ -0,0 +1,322 @@
+use crate::chip8::Chip8;
+use crate::debugger::parse_addr;
+use log::error;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*    Achievements File
+ * rom.achievements.json next to the ROM, each achievement unlocking on the
+ * first frame all of its conditions hold:
+ *   {"achievements": [
+ *     {"id": "score-100", "title": "Century", "description": "Score 100",
+ *      "when": ["[0x3f0] >= 100"]},
+ *     {"id": "level-5", "title": "Halfway", "when": ["v3 >= 5", "dt == 0"]}
+ *   ]}
+ *
+ * A condition compares two of [addr] (a byte of memory, hex as in the
+ * debugger), v0-vf, i, dt, st or a number (decimal, or hex with 0x) with one
+ * of == != < <= > >=. Unlocks are kept in rom.achieved.json, ids to the unix
+ * time they were earned, so they stay unlocked across runs.
+ */
+pub struct Achievements {
+    entries: Vec<Achievement>,
+    // Where unlocks are saved, none for a list that isn't kept
+    saved: Option<PathBuf>,
+}
+
+pub struct Achievement {
+    pub id: String,
+    pub title: String,
+    pub description: String,
+    pub unlocked: Option<u64>,
+    when: Vec<Condition>,
+}
+
+struct Condition {
+    left: Operand,
+    op: Op,
+    right: Operand,
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Memory(usize),
+    Register(usize),
+    Address,
+    Delay,
+    Sound,
+    Number(u16),
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+// Two characters first, so <= isn't read as <
+const OPS: [(&str, Op); 6] = [
+    ("==", Op::Equal),
+    ("!=", Op::NotEqual),
+    ("<=", Op::LessOrEqual),
+    (">=", Op::GreaterOrEqual),
+    ("<", Op::Less),
+    (">", Op::Greater),
+];
+
+impl Achievements {
+    pub fn new() -> Achievements {
+        Achievements {
+            entries: Vec::new(),
+            saved: None,
+        }
+    }
+
+    // rom.achievements.json next to the ROM, with its unlocks so far
+    pub fn find(rom_path: &Path) -> Option<Achievements> {
+        let path = rom_path.with_extension("achievements.json");
+        if !path.is_file() {
+            return None;
+        }
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                error!("Unable to read {}: {}", path.display(), e);
+                return None;
+            }
+        };
+        let mut achievements = match Achievements::parse(&text) {
+            Ok(achievements) => achievements,
+            Err(e) => {
+                error!("{}: {}", path.display(), e);
+                return None;
+            }
+        };
+        achievements.keep(&rom_path.with_extension("achieved.json"));
+        Some(achievements)
+    }
+
+    pub fn parse(text: &str) -> Result<Achievements, String> {
+        let json: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let list = json
+            .get("achievements")
+            .unwrap_or(&json)
+            .as_array()
+            .ok_or("expected a list of achievements")?;
+        let mut achievements = Achievements::new();
+        for (index, entry) in list.iter().enumerate() {
+            let achievement = parse_achievement(entry)
+                .map_err(|e| format!("achievement {}: {}", index + 1, e))?;
+            if achievements.entries.iter().any(|a| a.id == achievement.id) {
+                return Err(format!("duplicate id {}", achievement.id));
+            }
+            achievements.entries.push(achievement);
+        }
+        Ok(achievements)
+    }
+
+    // Read the unlocks already saved at path, and save new ones there
+    pub fn keep(&mut self, path: &Path) {
+        if let Ok(text) = std::fs::read_to_string(path) {
+            match serde_json::from_str::<Map<String, Value>>(&text) {
+                Ok(unlocked) => {
+                    for achievement in &mut self.entries {
+                        achievement.unlocked =
+                            unlocked.get(&achievement.id).and_then(Value::as_u64);
+                    }
+                }
+                Err(e) => error!("{}: {}", path.display(), e),
+            }
+        }
+        self.saved = Some(path.to_path_buf());
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn unlocked(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|achievement| achievement.unlocked.is_some())
+            .count()
+    }
+
+    // Called once per frame, returns the titles of any that just unlocked
+    pub fn check(&mut self, cpu: &Chip8) -> Vec<String> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let mut titles = Vec::new();
+        for achievement in &mut self.entries {
+            if achievement.unlocked.is_none()
+                && achievement
+                    .when
+                    .iter()
+                    .all(|condition| condition.holds(cpu))
+            {
+                achievement.unlocked = Some(now);
+                titles.push(achievement.title.clone());
+            }
+        }
+        if !titles.is_empty() {
+            if let Err(e) = self.save() {
+                error!("{}", e);
+            }
+        }
+        titles
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = match &self.saved {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let unlocked: Map<String, Value> = self
+            .entries
+            .iter()
+            .filter_map(|achievement| Some((achievement.id.clone(), achievement.unlocked?.into())))
+            .collect();
+        let text = serde_json::to_string_pretty(&unlocked).map_err(|e| e.to_string())?;
+        std::fs::write(path, text + "\n")
+            .map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+    }
+
+    pub fn list(&self) -> String {
+        if self.is_empty() {
+            return String::from("No achievements loaded");
+        }
+        let mut lines = vec![format!("{} of {} unlocked", self.unlocked(), self.len())];
+        for achievement in &self.entries {
+            let mark = match achievement.unlocked {
+                Some(_) => "x",
+                None => " ",
+            };
+            lines.push(format!("[{}] {}", mark, achievement.title));
+            if !achievement.description.is_empty() {
+                lines.push(format!("    {}", achievement.description));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl Default for Achievements {
+    fn default() -> Achievements {
+        Achievements::new()
+    }
+}
+
+fn parse_achievement(entry: &Value) -> Result<Achievement, String> {
+    let text = |key: &str| {
+        entry
+            .get(key)
+            .and_then(Value::as_str)
+            .unwrap_or("")
+            .to_string()
+    };
+    let id = text("id");
+    if id.is_empty() {
+        return Err(String::from("missing id"));
+    }
+    let when = match entry.get("when") {
+        Some(Value::String(condition)) => vec![parse_condition(condition)?],
+        Some(Value::Array(conditions)) => conditions
+            .iter()
+            .map(|condition| match condition.as_str() {
+                Some(condition) => parse_condition(condition),
+                None => Err(String::from("conditions are strings")),
+            })
+            .collect::<Result<Vec<Condition>, String>>()?,
+        _ => return Err(format!("{} has no conditions", id)),
+    };
+    let title = match text("title") {
+        title if title.is_empty() => id.clone(),
+        title => title,
+    };
+    Ok(Achievement {
+        id,
+        title,
+        description: text("description"),
+        unlocked: None,
+        when,
+    })
+}
+
+fn parse_condition(text: &str) -> Result<Condition, String> {
+    let (at, symbol, op) = OPS
+        .iter()
+        .find_map(|(symbol, op)| text.find(symbol).map(|at| (at, *symbol, *op)))
+        .ok_or(format!("no comparison in {}", text))?;
+    let operand =
+        |text: &str| parse_operand(text.trim()).ok_or(format!("invalid operand {}", text.trim()));
+    Ok(Condition {
+        left: operand(&text[..at])?,
+        op,
+        right: operand(&text[at + symbol.len()..])?,
+    })
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    let lower = text.to_lowercase();
+    if let Some(addr) = lower
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+    {
+        return parse_addr(addr.trim()).map(Operand::Memory);
+    }
+    match lower.as_str() {
+        "i" => return Some(Operand::Address),
+        "dt" => return Some(Operand::Delay),
+        "st" => return Some(Operand::Sound),
+        _ => (),
+    }
+    if let Some(register) = lower.strip_prefix('v') {
+        return usize::from_str_radix(register, 16)
+            .ok()
+            .filter(|register| *register < 16)
+            .map(Operand::Register);
+    }
+    match lower.strip_prefix("0x") {
+        Some(digits) => u16::from_str_radix(digits, 16).ok(),
+        None => lower.parse().ok(),
+    }
+    .map(Operand::Number)
+}
+
+impl Condition {
+    fn holds(&self, cpu: &Chip8) -> bool {
+        let (left, right) = (self.left.value(cpu), self.right.value(cpu));
+        match self.op {
+            Op::Equal => left == right,
+            Op::NotEqual => left != right,
+            Op::Less => left < right,
+            Op::LessOrEqual => left <= right,
+            Op::Greater => left > right,
+            Op::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+impl Operand {
+    fn value(self, cpu: &Chip8) -> u16 {
+        match self {
+            Operand::Memory(addr) => cpu.memory.get(addr).copied().unwrap_or(0) as u16,
+            Operand::Register(register) => cpu.registers[register] as u16,
+            Operand::Address => cpu.address_reg,
+            Operand::Delay => cpu.delay_timer as u16,
+            Operand::Sound => cpu.sound_timer as u16,
+            Operand::Number(number) => number,
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use crate::disasm::opcode_class;
+use crate::info::{extension, successors};
+use std::collections::{BTreeMap, BTreeSet};
+
+const START: usize = 0x200;
+// Values of I tracked at an instruction before giving up on it
+const MAX_POINTERS: usize = 32;
+// Instructions in a row before unreached bytes count as code
+const MIN_UNREACHED: usize = 3;
+
+/*    Static Analysis
+ * What each ROM byte is, without running anything:
+ *   Code      an instruction reachable from 0x200, see info::reachable
+ *   Sprite    drawn by a DXYN, from where I can point when it runs
+ *   Unknown   anything else, data that isn't drawn or code nothing reaches
+ *
+ * Where I points is followed through the code: i := sets it, i += keeps the
+ * table it was pointed at, and a call or font lookup loses track. A table
+ * takes in the data after it, a sprite at a time, as far as it goes. Runs of
+ * unknown bytes that decode as at least three instructions and end in a
+ * jump or return are reported as unreachable code.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Code,
+    Sprite,
+    Unknown,
+}
+
+pub struct Analysis {
+    classes: Vec<Class>,
+    instructions: BTreeSet<usize>,
+    // Start and end (exclusive) of each run of code nothing reaches
+    pub unreachable: Vec<(usize, usize)>,
+    // Address and length in bytes of each sprite drawn, a table split into
+    // sprites of the length it's drawn at
+    pub sprites: Vec<(usize, usize)>,
+}
+
+// Where I might point: at a known address, somewhere in a table starting
+// at one, or anywhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Pointer {
+    At(usize),
+    Table(usize),
+    Unknown,
+}
+
+pub fn analyze(rom: &[u8]) -> Analysis {
+    let end = START + rom.len();
+    let opcode_at = |addr: usize| {
+        let offset = addr - START;
+        u16::from_be_bytes([rom[offset], rom[offset + 1]])
+    };
+    let mut states: BTreeMap<usize, BTreeSet<Pointer>> = BTreeMap::new();
+    // Instructions with too many values of I, left at Unknown for good
+    let mut saturated = BTreeSet::new();
+    let mut pending = vec![(START, BTreeSet::from([Pointer::Unknown]))];
+    while let Some((addr, incoming)) = pending.pop() {
+        if addr < START || addr + 1 >= end || saturated.contains(&addr) {
+            continue;
+        }
+        let state = states.entry(addr).or_default();
+        let before = state.len();
+        state.extend(incoming);
+        if state.len() == before && before > 0 {
+            continue;
+        }
+        if state.len() > MAX_POINTERS {
+            *state = BTreeSet::from([Pointer::Unknown]);
+            saturated.insert(addr);
+        }
+        let opcode = opcode_at(addr);
+        let state = state.clone();
+        let outgoing: BTreeSet<Pointer> = match opcode_class(opcode) {
+            "ANNN" => BTreeSet::from([Pointer::At((opcode & 0x0FFF) as usize)]),
+            // Save and load may move I on, depending on the quirk
+            "FX1E" | "FX55" | "FX65" => state
+                .iter()
+                .map(|pointer| match pointer {
+                    Pointer::At(base) | Pointer::Table(base) => Pointer::Table(*base),
+                    Pointer::Unknown => Pointer::Unknown,
+                })
+                .collect(),
+            "FX29" => BTreeSet::from([Pointer::Unknown]),
+            _ => match extension(opcode) {
+                Some((_, "F000")) if addr + 3 < end => {
+                    BTreeSet::from([Pointer::At(opcode_at(addr + 2) as usize)])
+                }
+                Some((_, "F000" | "FX30")) => BTreeSet::from([Pointer::Unknown]),
+                _ => state,
+            },
+        };
+        for next in successors(opcode, addr) {
+            // The routine called may point I anywhere before returning
+            let pointers = match opcode_class(opcode) == "2NNN" && next == addr + 2 {
+                true => BTreeSet::from([Pointer::Unknown]),
+                false => outgoing.clone(),
+            };
+            pending.push((next, pointers));
+        }
+    }
+
+    let mut classes = vec![Class::Unknown; rom.len()];
+    // Where each sprite starts, its length and whether it's the start of a table
+    let mut drawn: BTreeMap<usize, (usize, bool)> = BTreeMap::new();
+    for (addr, pointers) in &states {
+        let opcode = opcode_at(*addr);
+        if opcode_class(opcode) != "DXYN" {
+            continue;
+        }
+        // DXY0 draws a 16x16 sprite on SCHIP
+        let len = match (opcode & 0x000F) as usize {
+            0 => 32,
+            n => n,
+        };
+        for pointer in pointers {
+            if let Pointer::At(base) | Pointer::Table(base) = pointer {
+                for addr in (*base..base + len).filter(|addr| (START..end).contains(addr)) {
+                    classes[addr - START] = Class::Sprite;
+                }
+                let sprite = drawn.entry(*base).or_default();
+                sprite.0 = sprite.0.max(len);
+                sprite.1 |= matches!(pointer, Pointer::Table(_));
+            }
+        }
+    }
+    for addr in states.keys() {
+        classes[addr - START] = Class::Code;
+        classes[addr + 1 - START] = Class::Code;
+    }
+    let mut analysis = Analysis {
+        classes,
+        instructions: states.keys().copied().collect(),
+        unreachable: Vec::new(),
+        sprites: Vec::new(),
+    };
+    analysis.unreachable = analysis.find_unreachable(rom);
+    analysis.split_sprites(&drawn, end);
+    analysis
+}
+
+impl Analysis {
+    pub fn class(&self, addr: usize) -> Class {
+        match addr.checked_sub(START) {
+            Some(offset) => self.classes.get(offset).copied().unwrap_or(Class::Unknown),
+            None => Class::Unknown,
+        }
+    }
+
+    // Whether a reachable instruction starts at addr
+    pub fn is_instruction(&self, addr: usize) -> bool {
+        self.instructions.contains(&addr)
+    }
+
+    pub fn is_unreachable(&self, addr: usize) -> bool {
+        self.unreachable
+            .iter()
+            .any(|(start, end)| (*start..*end).contains(&addr))
+    }
+
+    pub fn count(&self, class: Class) -> usize {
+        self.classes.iter().filter(|byte| **byte == class).count()
+    }
+
+    // One line of totals, then any unreachable code
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "{} code, {} sprite, {} unknown bytes",
+            self.count(Class::Code),
+            self.count(Class::Sprite),
+            self.count(Class::Unknown)
+        )];
+        for (start, end) in &self.unreachable {
+            lines.push(format!(
+                "  {:#05x}-{:#05x} looks like code but nothing reaches it",
+                start,
+                end - 1
+            ));
+        }
+        lines.join("\n")
+    }
+
+    // Tables run on over data, a sprite at a time, up to the next sprite or
+    // anything that isn't data
+    fn split_sprites(&mut self, drawn: &BTreeMap<usize, (usize, bool)>, end: usize) {
+        for (base, (len, table)) in drawn
+            .iter()
+            .filter(|(base, _)| (START..end).contains(*base))
+        {
+            self.sprites.push((*base, *len));
+            if !*table {
+                continue;
+            }
+            let mut addr = base + len;
+            while addr + len <= end
+                && !drawn.contains_key(&addr)
+                && (addr..addr + len)
+                    .all(|addr| self.class(addr) != Class::Code && !self.is_unreachable(addr))
+            {
+                for offset in addr - START..addr + len - START {
+                    self.classes[offset] = Class::Sprite;
+                }
+                self.sprites.push((addr, *len));
+                addr += len;
+            }
+        }
+    }
+
+    // Unknown words that decode as instructions, ending in a jump or return
+    fn find_unreachable(&self, rom: &[u8]) -> Vec<(usize, usize)> {
+        let end = START + rom.len();
+        let unknown = |addr: usize| {
+            self.class(addr) == Class::Unknown && self.class(addr + 1) == Class::Unknown
+        };
+        let mut runs = Vec::new();
+        let mut addr = START;
+        while addr + 1 < end {
+            let mut next = addr;
+            let mut count = 0;
+            while next + 1 < end && unknown(next) {
+                let offset = next - START;
+                let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+                let valid = match opcode_class(opcode) {
+                    "unknown" | "0NNN" => extension(opcode).is_some(),
+                    _ => true,
+                };
+                if !valid {
+                    break;
+                }
+                next += 2;
+                count += 1;
+                if matches!(opcode_class(opcode), "1NNN" | "BNNN" | "00EE") {
+                    if count >= MIN_UNREACHED {
+                        runs.push((addr, next));
+                    }
+                    break;
+                }
+            }
+            addr = match next > addr {
+                true => next,
+                false => addr + 2,
+            };
+        }
+        runs
+    }
+}
+
+// A row of sprite pixels, # for each one set
+pub fn pixels(byte: u8) -> String {
+    (0..8)
+        .map(|bit| match byte << bit & 0x80 {
+            0 => '.',
+            _ => '#',
+        })
+        .collect()
+}
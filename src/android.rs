@@ -0,0 +1,49 @@
+use crate::chip8::Chip8;
+use crate::debugger::Debugger;
+use crate::osd::Osd;
+use crate::window::{self, Instance};
+use log::error;
+use std::ffi::CString;
+use std::io::Read;
+
+// Bundled in the APK from assets/, there's no command line to pass one on
+const ROM_ASSET: &str = "rom.ch8";
+
+// Entry point for cargo-apk, which loads this crate as a native activity
+#[ndk_glue::main(backtrace = "on", logger(level = "info", tag = "chip8"))]
+pub fn main() {
+    let mut rom = Vec::new();
+    let name = CString::new(ROM_ASSET).unwrap();
+    match ndk_glue::native_activity().asset_manager().open(&name) {
+        Some(mut asset) => {
+            if let Err(e) = asset.read_to_end(&mut rom) {
+                error!("Unable to read {}: {}", ROM_ASSET, e);
+            }
+        }
+        None => error!("No {} in the APK assets", ROM_ASSET),
+    }
+    let mut cpu = Chip8::new();
+    cpu.load_font();
+    cpu.load_program(&rom);
+    let instance = Instance {
+        cpu,
+        // No stdin for the debugger console either
+        debugger: Debugger::without_console(),
+        title: String::from(ROM_ASSET),
+        osd: Osd::default(),
+        asleep: None,
+    };
+    if let Err(e) = window::run(
+        vec![instance],
+        1,
+        // The app pauses when it loses its surface instead
+        false,
+        false,
+        false,
+        None,
+        |_, _| false,
+        |_, _| Err(String::from("Only the APK's ROM runs on Android")),
+    ) {
+        error!("{}", e);
+    }
+}
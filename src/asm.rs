@@ -0,0 +1,691 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+
+// Programs are loaded at 0x200 and may fill the rest of the 4K address space
+const START: usize = 0x200;
+const END: usize = 0x1000;
+// Includes and macros inside each other, which also stops one including or
+// expanding itself forever
+const MAX_DEPTH: usize = 16;
+// Operators from the loosest binding to the tightest
+const LEVELS: [&[&str]; 6] = [
+    &["|"],
+    &["^"],
+    &["&"],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+#[derive(Debug)]
+pub struct AsmError {
+    pub line: usize,
+    // The included file the line is in, none for the one assembled
+    pub file: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{} line {}: {}", file, self.line, self.message),
+            None => write!(f, "line {}: {}", self.line, self.message),
+        }
+    }
+}
+
+// Where an item came from, for errors
+#[derive(Debug, Clone)]
+struct Place {
+    line: usize,
+    file: Option<String>,
+}
+
+// A source line broken into its parts, before labels are resolved
+#[derive(Debug)]
+enum Item {
+    Label(String),
+    Const(String, String),
+    Org(String),
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+    Instruction(String, Vec<String>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Operand {
+    V(u16),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    Bcd,
+    Value(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+// Items read so far, with the macros defined along the way
+struct Parser {
+    items: Vec<(Place, Item)>,
+    macros: HashMap<String, Macro>,
+    // Macros being expanded, outermost first
+    expanding: Vec<String>,
+}
+
+/*    Source Format
+ * label:              Labels end with a colon and may share a line
+ * org 0x300           Move the output address
+ * db 0x01, 2, 0b11    Bytes
+ * dw 0x1234, label    Big-endian words
+ * LD V0, 0x05         Cowgod mnemonics, as printed by the disassembler
+ * :const WIDTH 64     Constant, usable anywhere a number is
+ * :macro name a, b    Macro, the lines up to :endm with a and b replaced,
+ * :endm               used as name 1, V2
+ * include "lib.s"     Assemble another file here, relative to this one
+ * ; comment
+ *
+ * Numbers may be decimal, 0x/$ hex or 0b binary. Anywhere a number goes an
+ * expression can too, e.g. LD V0, WIDTH / 2 - 4 or LD I, sprites + 5, with
+ * + - * / % & | ^ << >>, unary - and ~ and brackets, binding as in C.
+ * Constants and org can only use what's defined further up.
+ */
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    assemble_in(source, Path::new("."))
+}
+
+// Includes are looked for next to the file the source came from
+pub fn assemble_file(source: &str, path: &Path) -> Result<Vec<u8>, AsmError> {
+    assemble_in(source, path.parent().unwrap_or(Path::new(".")))
+}
+
+fn assemble_in(source: &str, dir: &Path) -> Result<Vec<u8>, AsmError> {
+    let mut parser = Parser {
+        items: Vec::new(),
+        macros: HashMap::new(),
+        expanding: Vec::new(),
+    };
+    parser.read(source, None, dir, 0)?;
+    let items = parser.items;
+
+    // First pass to find where every label ends up
+    let mut symbols = HashMap::new();
+    let mut addr = START;
+    for (place, item) in &items {
+        match item {
+            Item::Label(name) => {
+                if symbols.insert(name.to_ascii_lowercase(), addr).is_some() {
+                    return Err(place.error(format!("duplicate label {}", name)));
+                }
+            }
+            Item::Const(name, value) => {
+                let value = value_of(value, &symbols, place)?;
+                if symbols.insert(name.to_ascii_lowercase(), value).is_some() {
+                    return Err(place.error(format!("{} is already defined", name)));
+                }
+            }
+            Item::Org(value) => addr = value_of(value, &symbols, place)?,
+            Item::Bytes(values) => addr += values.len(),
+            Item::Words(values) => addr += values.len() * 2,
+            Item::Instruction(_, _) => addr += 2,
+        }
+    }
+
+    let mut image = Vec::new();
+    let mut addr = START;
+    for (place, item) in &items {
+        let bytes = match item {
+            Item::Label(_) | Item::Const(_, _) => continue,
+            Item::Org(value) => {
+                addr = value_of(value, &symbols, place)?;
+                continue;
+            }
+            Item::Bytes(values) => values
+                .iter()
+                .map(|value| match value_of(value, &symbols, place)? {
+                    byte if byte <= 0xFF => Ok(byte as u8),
+                    _ => Err(place.error(format!("{} does not fit in a byte", value))),
+                })
+                .collect::<Result<Vec<u8>, AsmError>>()?,
+            Item::Words(values) => {
+                let mut bytes = Vec::new();
+                for value in values {
+                    match value_of(value, &symbols, place)? {
+                        word if word <= 0xFFFF => {
+                            bytes.extend_from_slice(&(word as u16).to_be_bytes())
+                        }
+                        _ => return Err(place.error(format!("{} does not fit in a word", value))),
+                    }
+                }
+                bytes
+            }
+            Item::Instruction(mnemonic, operands) => {
+                let operand_text = operands.join(", ");
+                let operands = operands
+                    .iter()
+                    .map(|operand| operand_of(operand, &symbols, place))
+                    .collect::<Result<Vec<Operand>, AsmError>>()?;
+                encode(mnemonic, &operands)
+                    .map_err(|message| {
+                        place.error(format!("{} {}: {}", mnemonic, operand_text, message))
+                    })?
+                    .to_be_bytes()
+                    .to_vec()
+            }
+        };
+        if addr < START || addr + bytes.len() > END {
+            return Err(place.error(format!("address {:#05x} is outside the program area", addr)));
+        }
+        let offset = addr - START;
+        if image.len() < offset + bytes.len() {
+            image.resize(offset + bytes.len(), 0);
+        }
+        image[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        addr += bytes.len();
+    }
+    Ok(image)
+}
+
+impl Place {
+    fn error(&self, message: String) -> AsmError {
+        AsmError {
+            line: self.line,
+            file: self.file.clone(),
+            message,
+        }
+    }
+}
+
+impl Parser {
+    // A whole file, collecting macro bodies as they're defined
+    fn read(
+        &mut self,
+        source: &str,
+        file: Option<String>,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        let mut defining: Option<(Place, String, Macro)> = None;
+        for (index, text) in source.lines().enumerate() {
+            let place = Place {
+                line: index + 1,
+                file: file.clone(),
+            };
+            let text = strip_comment(text);
+            let (word, rest) = split_word(text);
+            match (word.to_ascii_lowercase().as_str(), &mut defining) {
+                (":macro", Some(_)) => {
+                    return Err(place.error(String::from("macro inside a macro")))
+                }
+                (":macro", None) => {
+                    let (name, params) = split_word(rest);
+                    let params = operands(params);
+                    if let Some(bad) = std::iter::once(name)
+                        .chain(params.iter().map(String::as_str))
+                        .find(|name| !is_name(name))
+                    {
+                        return Err(place.error(format!("invalid macro name {}", bad)));
+                    }
+                    let body = Macro {
+                        params,
+                        body: Vec::new(),
+                    };
+                    defining = Some((place, name.to_ascii_lowercase(), body));
+                }
+                (":endm", Some(_)) => {
+                    if let Some((_, name, body)) = defining.take() {
+                        self.macros.insert(name, body);
+                    }
+                }
+                (":endm", None) => return Err(place.error(String::from(":endm without :macro"))),
+                (_, Some((_, _, body))) => body.body.push(text.to_string()),
+                (_, None) => self.line(&place, text, dir, depth)?,
+            }
+        }
+        match defining {
+            Some((place, name, _)) => Err(place.error(format!("macro {} has no :endm", name))),
+            None => Ok(()),
+        }
+    }
+
+    fn line(
+        &mut self,
+        place: &Place,
+        text: &str,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        let mut text = text.trim();
+        let (word, rest) = split_word(text);
+        if word.eq_ignore_ascii_case(":const") {
+            let (name, value) = split_word(rest);
+            if !is_name(name) || value.is_empty() {
+                return Err(place.error(String::from(":const takes a name and a value")));
+            }
+            let item = Item::Const(name.to_string(), value.to_string());
+            self.items.push((place.clone(), item));
+            return Ok(());
+        }
+        // Any number of labels may come before the instruction
+        while let Some(colon) = text.find(':') {
+            let name = text[..colon].trim();
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(place.error(format!("invalid label {}", name)));
+            }
+            self.items
+                .push((place.clone(), Item::Label(name.to_string())));
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            return Ok(());
+        }
+        let (mnemonic, rest) = split_word(text);
+        let operands = operands(rest);
+        let item = match mnemonic.to_ascii_lowercase().as_str() {
+            "org" if operands.len() == 1 => Item::Org(operands[0].clone()),
+            "org" => return Err(place.error(String::from("org takes one address"))),
+            "db" => Item::Bytes(operands),
+            "dw" => Item::Words(operands),
+            "include" => return self.include(place, rest, dir, depth),
+            name => match self.macros.get(name) {
+                Some(body) => return self.expand(place, name, body.clone(), &operands, dir, depth),
+                None => Item::Instruction(mnemonic.to_ascii_uppercase(), operands),
+            },
+        };
+        self.items.push((place.clone(), item));
+        Ok(())
+    }
+
+    fn include(
+        &mut self,
+        place: &Place,
+        name: &str,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        if depth >= MAX_DEPTH {
+            return Err(place.error(String::from("includes and macros nest too deeply")));
+        }
+        let path = dir.join(name.trim_matches('"'));
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| place.error(format!("unable to read {}: {}", path.display(), e)))?;
+        let file = Some(path.display().to_string());
+        self.read(&source, file, path.parent().unwrap_or(dir), depth + 1)
+    }
+
+    // Each line of the body with the parameters replaced, errors reported at
+    // the line using it
+    fn expand(
+        &mut self,
+        place: &Place,
+        name: &str,
+        body: Macro,
+        args: &[String],
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        if self.expanding.iter().any(|expanding| expanding == name) {
+            return Err(place.error(format!("{} expands into itself", name)));
+        }
+        if depth >= MAX_DEPTH {
+            return Err(place.error(String::from("includes and macros nest too deeply")));
+        }
+        if args.len() != body.params.len() {
+            return Err(place.error(format!(
+                "{} takes {} arguments, not {}",
+                name,
+                body.params.len(),
+                args.len()
+            )));
+        }
+        self.expanding.push(name.to_string());
+        for text in &body.body {
+            let text = substitute(text, &body.params, args);
+            self.line(place, &text, dir, depth + 1)
+                .map_err(|e| place.error(format!("in {}, {}", name, e.message)))?;
+        }
+        self.expanding.pop();
+        Ok(())
+    }
+}
+
+fn strip_comment(text: &str) -> &str {
+    match text.find(';') {
+        Some(comment) => &text[..comment],
+        None => text,
+    }
+    .trim()
+}
+
+// The first word and the rest of the line, both trimmed
+fn split_word(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    match text.find(char::is_whitespace) {
+        Some(space) => (&text[..space], text[space..].trim()),
+        None => (text, ""),
+    }
+}
+
+fn operands(text: &str) -> Vec<String> {
+    match text.is_empty() {
+        true => Vec::new(),
+        false => text
+            .split(',')
+            .map(|operand| operand.trim().to_string())
+            .collect(),
+    }
+}
+
+// Constants and macro parameters, which mustn't read as numbers
+fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+// Whole words matching a parameter swapped for its argument
+fn substitute(text: &str, params: &[String], args: &[String]) -> String {
+    let mut output = String::new();
+    let mut word = String::new();
+    for c in text.chars().chain(std::iter::once('\n')) {
+        if c.is_alphanumeric() || c == '_' {
+            word.push(c);
+            continue;
+        }
+        match params
+            .iter()
+            .position(|param| param.eq_ignore_ascii_case(&word))
+        {
+            Some(index) => output.push_str(&args[index]),
+            None => output.push_str(&word),
+        }
+        word.clear();
+        output.push(c);
+    }
+    output.pop();
+    output
+}
+
+fn value_of(
+    text: &str,
+    symbols: &HashMap<String, usize>,
+    place: &Place,
+) -> Result<usize, AsmError> {
+    let tokens = tokenize(text).map_err(|e| place.error(e))?;
+    let mut expression = Expression {
+        tokens,
+        pos: 0,
+        symbols,
+    };
+    let value = expression.binary(0).map_err(|e| place.error(e))?;
+    if let Some(token) = expression.tokens.get(expression.pos) {
+        return Err(place.error(format!("unexpected {} in {}", token, text)));
+    }
+    match usize::try_from(value) {
+        Ok(value) => Ok(value),
+        Err(_) => Err(place.error(format!("{} is negative", text))),
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => (),
+            '<' | '>' => match chars.next() {
+                Some(next) if next == c => tokens.push(format!("{}{}", c, c)),
+                _ => return Err(format!("{} should be {}{}", c, c, c)),
+            },
+            '+' | '-' | '*' | '/' | '%' | '&' | '|' | '^' | '~' | '(' | ')' => {
+                tokens.push(c.to_string())
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '$' => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.next_if(|c| c.is_alphanumeric() || *c == '_') {
+                    word.push(next);
+                }
+                tokens.push(word);
+            }
+            _ => return Err(format!("unexpected {} in {}", c, text)),
+        }
+    }
+    Ok(tokens)
+}
+
+// Precedence climbing over LEVELS, in whole numbers
+struct Expression<'a> {
+    tokens: Vec<String>,
+    pos: usize,
+    symbols: &'a HashMap<String, usize>,
+}
+
+impl Expression<'_> {
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn binary(&mut self, level: usize) -> Result<i64, String> {
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self
+            .tokens
+            .get(self.pos)
+            .filter(|token| LEVELS[level].contains(&token.as_str()))
+            .cloned()
+        {
+            self.pos += 1;
+            let right = self.binary(level + 1)?;
+            let shift = u32::try_from(right).ok().filter(|shift| *shift < 63);
+            left = match op.as_str() {
+                "|" => Some(left | right),
+                "^" => Some(left ^ right),
+                "&" => Some(left & right),
+                "<<" => shift.and_then(|shift| left.checked_shl(shift)),
+                ">>" => shift.and_then(|shift| left.checked_shr(shift)),
+                "+" => left.checked_add(right),
+                "-" => left.checked_sub(right),
+                "*" => left.checked_mul(right),
+                "/" => left.checked_div(right),
+                _ => left.checked_rem(right),
+            }
+            .ok_or(format!("{} {} {} is out of range", left, op, right))?;
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        match self.next().as_deref() {
+            Some("-") => Ok(-self.unary()?),
+            Some("~") => Ok(!self.unary()?),
+            Some("(") => {
+                let value = self.binary(0)?;
+                match self.next().as_deref() {
+                    Some(")") => Ok(value),
+                    _ => Err(String::from("( without )")),
+                }
+            }
+            Some(word) => number(word, self.symbols).ok_or(format!("unknown value {}", word)),
+            None => Err(String::from("missing value")),
+        }
+    }
+}
+
+fn number(text: &str, symbols: &HashMap<String, usize>) -> Option<i64> {
+    let lower = text.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix('$')) {
+        usize::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        usize::from_str_radix(binary, 2).ok()
+    } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        symbols.get(&lower).copied()
+    };
+    parsed.and_then(|value| i64::try_from(value).ok())
+}
+
+fn operand_of(
+    text: &str,
+    symbols: &HashMap<String, usize>,
+    place: &Place,
+) -> Result<Operand, AsmError> {
+    let upper = text.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "B" => Operand::Bcd,
+        _ => match upper.strip_prefix('V') {
+            Some(digit) if digit.len() == 1 => match u16::from_str_radix(digit, 16) {
+                Ok(register) => Operand::V(register),
+                Err(_) => Operand::Value(value_of(text, symbols, place)?),
+            },
+            _ => Operand::Value(value_of(text, symbols, place)?),
+        },
+    };
+    Ok(operand)
+}
+
+fn encode(mnemonic: &str, operands: &[Operand]) -> Result<u16, String> {
+    use Operand::*;
+    let addr = |nnn: usize| match nnn <= 0xFFF {
+        true => Ok(nnn as u16),
+        false => Err(format!("address {:#x} is out of range", nnn)),
+    };
+    let byte = |nn: usize| match nn <= 0xFF {
+        true => Ok(nn as u16),
+        false => Err(format!("{:#x} does not fit in a byte", nn)),
+    };
+    let opcode = match (mnemonic, operands) {
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("SYS", [Value(nnn)]) => addr(*nnn)?,
+        ("JP", [Value(nnn)]) => 0x1000 | addr(*nnn)?,
+        ("JP", [V(0), Value(nnn)]) => 0xB000 | addr(*nnn)?,
+        ("CALL", [Value(nnn)]) => 0x2000 | addr(*nnn)?,
+        ("SE", [V(x), Value(nn)]) => 0x3000 | x << 8 | byte(*nn)?,
+        ("SE", [V(x), V(y)]) => 0x5000 | x << 8 | y << 4,
+        ("SNE", [V(x), Value(nn)]) => 0x4000 | x << 8 | byte(*nn)?,
+        ("SNE", [V(x), V(y)]) => 0x9000 | x << 8 | y << 4,
+        ("LD", [V(x), Value(nn)]) => 0x6000 | x << 8 | byte(*nn)?,
+        ("LD", [V(x), V(y)]) => 0x8000 | x << 8 | y << 4,
+        ("LD", [I, Value(nnn)]) => 0xA000 | addr(*nnn)?,
+        ("LD", [V(x), DelayTimer]) => 0xF007 | x << 8,
+        ("LD", [V(x), Key]) => 0xF00A | x << 8,
+        ("LD", [DelayTimer, V(x)]) => 0xF015 | x << 8,
+        ("LD", [SoundTimer, V(x)]) => 0xF018 | x << 8,
+        ("LD", [Font, V(x)]) => 0xF029 | x << 8,
+        ("LD", [Bcd, V(x)]) => 0xF033 | x << 8,
+        ("LD", [IndirectI, V(x)]) => 0xF055 | x << 8,
+        ("LD", [V(x), IndirectI]) => 0xF065 | x << 8,
+        ("ADD", [V(x), Value(nn)]) => 0x7000 | x << 8 | byte(*nn)?,
+        ("ADD", [V(x), V(y)]) => 0x8004 | x << 8 | y << 4,
+        ("ADD", [I, V(x)]) => 0xF01E | x << 8,
+        ("OR", [V(x), V(y)]) => 0x8001 | x << 8 | y << 4,
+        ("AND", [V(x), V(y)]) => 0x8002 | x << 8 | y << 4,
+        ("XOR", [V(x), V(y)]) => 0x8003 | x << 8 | y << 4,
+        ("SUB", [V(x), V(y)]) => 0x8005 | x << 8 | y << 4,
+        ("SHR", [V(x)]) => 0x8006 | x << 8,
+        ("SHR", [V(x), V(y)]) => 0x8006 | x << 8 | y << 4,
+        ("SUBN", [V(x), V(y)]) => 0x8007 | x << 8 | y << 4,
+        ("SHL", [V(x)]) => 0x800E | x << 8,
+        ("SHL", [V(x), V(y)]) => 0x800E | x << 8 | y << 4,
+        ("RND", [V(x), Value(nn)]) => 0xC000 | x << 8 | byte(*nn)?,
+        ("DRW", [V(x), V(y), Value(n)]) if *n <= 0xF => 0xD000 | x << 8 | y << 4 | *n as u16,
+        ("SKP", [V(x)]) => 0xE09E | x << 8,
+        ("SKNP", [V(x)]) => 0xE0A1 | x << 8,
+        ("SEND", [V(x)]) => 0xF070 | x << 8,
+        ("RECV", [V(x)]) => 0xF071 | x << 8,
+        _ => return Err(String::from("unknown instruction or operands")),
+    };
+    Ok(opcode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+
+    fn error(source: &str) -> String {
+        assemble(source).unwrap_err().to_string()
+    }
+
+    // Every instruction the disassembler prints assembles back to itself
+    #[test]
+    fn round_trips_the_disassembler() {
+        for opcode in 0..=0xFFFFu16 {
+            let text = disassemble(opcode);
+            if text.starts_with("DW") {
+                continue;
+            }
+            let bytes = assemble(&text).unwrap_or_else(|e| panic!("{}: {}", text, e));
+            assert_eq!(bytes, opcode.to_be_bytes(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn labels_resolve_forwards_and_backwards() {
+        let source = "start: JP end\nloop: ADD V0, 1\nJP loop\nend: CALL start";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x12, 0x06, 0x70, 0x01, 0x12, 0x02, 0x22, 0x00]
+        );
+    }
+
+    #[test]
+    fn data_org_and_expressions() {
+        let source = ":const WIDTH 64\nLD V0, WIDTH / 2 - 4\nLD I, data + 1\n\
+                      org 0x208\ndata: db 1, $2, 0b11\ndw data";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x60, 0x1C, 0xA2, 0x09, 0, 0, 0, 0, 1, 2, 3, 0x02, 0x08]
+        );
+    }
+
+    #[test]
+    fn macros_expand_their_arguments() {
+        let source = ":macro twice reg, n\nADD reg, n\nADD reg, n\n:endm\ntwice V3, 5";
+        assert_eq!(assemble(source).unwrap(), [0x73, 0x05, 0x73, 0x05]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(
+            error("CLS\nFOO V0"),
+            "line 2: FOO V0: unknown instruction or operands"
+        );
+        assert_eq!(error("a:\na:"), "line 2: duplicate label a");
+        assert_eq!(error("JP nowhere"), "line 1: unknown value nowhere");
+        assert_eq!(
+            error("LD V0, 256"),
+            "line 1: LD V0, 256: 0x100 does not fit in a byte"
+        );
+        assert_eq!(
+            error("JP 0x1000"),
+            "line 1: JP 0x1000: address 0x1000 is out of range"
+        );
+        assert_eq!(
+            error("org 0x100\nCLS"),
+            "line 2: address 0x100 is outside the program area"
+        );
+        assert_eq!(error(":macro m\nCLS"), "line 1: macro m has no :endm");
+        assert_eq!(
+            error(":macro m\nm\n:endm\nm"),
+            "line 4: in m, m expands into itself"
+        );
+    }
+}
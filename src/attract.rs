@@ -0,0 +1,60 @@
+use crate::chip8::Chip8;
+use std::path::Path;
+
+// How long each ROM runs by default
+pub const DEFAULT_SECONDS: u64 = 30;
+
+/*    Attract Mode
+ * Every file in a folder, in name order, for a while each and round again.
+ * Each one starts on a fresh machine, and the time is counted in emulated
+ * frames so a slow machine shows as much of each game as a fast one.
+ * Hidden files are skipped.
+ */
+#[derive(Debug)]
+pub struct Attract {
+    roms: Vec<String>,
+    next: usize,
+    frames: u64,
+}
+
+impl Attract {
+    pub fn open(dir: &Path, seconds: u64) -> Result<Attract, String> {
+        let entries = std::fs::read_dir(dir)
+            .map_err(|e| format!("Unable to read {}: {}", dir.display(), e))?;
+        let mut roms: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_file())
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+            .map(|entry| entry.path().to_string_lossy().into_owned())
+            .collect();
+        if roms.is_empty() {
+            return Err(format!("There are no ROMs in {}", dir.display()));
+        }
+        roms.sort();
+        Ok(Attract {
+            roms,
+            next: 0,
+            frames: seconds * 60,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    // The ROM to load next, wrapping around at the end
+    pub fn next_rom(&mut self) -> &str {
+        let rom = &self.roms[self.next];
+        self.next = (self.next + 1) % self.roms.len();
+        rom
+    }
+
+    // The machine, started fresh for the ROM, has had its time
+    pub fn is_due(&self, cpu: &Chip8) -> bool {
+        cpu.instructions >= self.frames * cpu.speed as u64
+    }
+}
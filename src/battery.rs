@@ -0,0 +1,103 @@
+use crate::chip8::{Chip8, MEMORY_SIZE};
+use crate::debugger::parse_addr;
+use crate::peripheral::Peripheral;
+use log::{error, info};
+use std::io::ErrorKind;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+/*    Battery RAM
+ * A window of memory kept across runs, like the battery-backed RAM in a
+ * cartridge, so a game can keep real save files:
+ *   [game."quest.ch8"]
+ *   save_ram = "0xe00-0xeff"
+ * The window starts as rom.sav next to the ROM left it, or as the ROM did
+ * when there's no file yet, and is written back to rom.sav on exit if it
+ * changed. It's mapped like any device, see peripheral.rs, so games save
+ * with FX33 or FX55 and load with FX65, and a hard reset or a save state
+ * doesn't lose what's saved.
+ */
+pub struct Battery {
+    range: Range<usize>,
+    bytes: Vec<u8>,
+    path: PathBuf,
+    changed: bool,
+}
+
+// An inclusive range of hex addresses, e.g. 0xe00-0xeff
+pub fn parse_range(text: &str) -> Result<Range<usize>, String> {
+    let invalid = || format!("{} is not a range of memory like 0xe00-0xeff", text);
+    let (start, end) = text.split_once('-').ok_or_else(invalid)?;
+    match (parse_addr(start.trim()), parse_addr(end.trim())) {
+        (Some(start), Some(end)) if start <= end && end < MEMORY_SIZE => Ok(start..end + 1),
+        _ => Err(invalid()),
+    }
+}
+
+impl Battery {
+    // Map the window into the machine, with what was saved last time
+    pub fn insert(cpu: &mut Chip8, rom_path: &Path, range: Range<usize>) -> Result<(), String> {
+        let path = rom_path.with_extension("sav");
+        let mut bytes = cpu.memory[range.clone()].to_vec();
+        match std::fs::read(&path) {
+            Ok(saved) => {
+                let len = saved.len().min(bytes.len());
+                bytes[..len].copy_from_slice(&saved[..len]);
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("Unable to read {}: {}", path.display(), e)),
+        }
+        cpu.memory[range.clone()].copy_from_slice(&bytes);
+        cpu.attach(Battery {
+            range,
+            bytes,
+            path,
+            changed: false,
+        });
+        Ok(())
+    }
+
+    fn save(&mut self) {
+        if !self.changed {
+            return;
+        }
+        match std::fs::write(&self.path, &self.bytes) {
+            Ok(()) => info!("Save RAM written to {}", self.path.display()),
+            Err(e) => error!("Unable to write {}: {}", self.path.display(), e),
+        }
+        self.changed = false;
+    }
+}
+
+impl Peripheral for Battery {
+    fn name(&self) -> &str {
+        "battery"
+    }
+
+    fn region(&self) -> Option<Range<usize>> {
+        Some(self.range.clone())
+    }
+
+    fn read(&mut self, addr: usize) -> u8 {
+        self.bytes[addr - self.range.start]
+    }
+
+    fn write(&mut self, addr: usize, value: u8) {
+        let byte = &mut self.bytes[addr - self.range.start];
+        if *byte != value {
+            *byte = value;
+            self.changed = true;
+        }
+    }
+
+    fn flush(&mut self) {
+        self.save();
+    }
+}
+
+// Windows close by dropping their machines, the last of which drops this
+impl Drop for Battery {
+    fn drop(&mut self) {
+        self.save();
+    }
+}
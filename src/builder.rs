@@ -0,0 +1,288 @@
+use std::collections::HashMap;
+
+const START: usize = 0x200;
+
+/*    ROM Builder
+ * Programs put together in code, for tests and fuzzers that need an exact
+ * ROM without a file or the assembler:
+ *   let rom = RomBuilder::new()
+ *       .set(0, 10)
+ *       .label("loop")
+ *       .load_i("ball")
+ *       .draw(0, 1, 4)
+ *       .add(0, 1)
+ *       .jump("loop")
+ *       .label("ball")
+ *       .data(&[0x60, 0xF0, 0xF0, 0x60])
+ *       .build();
+ *
+ * Addresses are a number or the name of a label, which can come later.
+ * Registers are 0 to F. Anything out of range, or a label that's never
+ * given, panics, as a mistake in the test rather than the ROM.
+ */
+#[derive(Debug, Default, Clone)]
+pub struct RomBuilder {
+    bytes: Vec<u8>,
+    labels: HashMap<String, u16>,
+    // Offsets of opcodes waiting for a label's address in their low 12 bits
+    fixups: Vec<(usize, String)>,
+}
+
+// Where a jump, call or i := goes
+#[derive(Debug, Clone)]
+pub enum Target {
+    At(u16),
+    Label(String),
+}
+
+impl From<u16> for Target {
+    fn from(addr: u16) -> Target {
+        Target::At(addr)
+    }
+}
+
+impl From<&str> for Target {
+    fn from(label: &str) -> Target {
+        Target::Label(label.to_string())
+    }
+}
+
+impl RomBuilder {
+    pub fn new() -> RomBuilder {
+        RomBuilder::default()
+    }
+
+    // Where the next opcode or byte goes
+    pub fn here(&self) -> u16 {
+        (START + self.bytes.len()) as u16
+    }
+
+    pub fn label(mut self, name: &str) -> RomBuilder {
+        let addr = self.here();
+        if self.labels.insert(name.to_string(), addr).is_some() {
+            panic!("label {} given twice", name);
+        }
+        self
+    }
+
+    pub fn data(mut self, bytes: &[u8]) -> RomBuilder {
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+
+    // Any opcode, e.g. one the builder has no method for
+    pub fn opcode(self, opcode: u16) -> RomBuilder {
+        self.data(&opcode.to_be_bytes())
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        let mut bytes = self.bytes;
+        for (offset, name) in &self.fixups {
+            let addr = match self.labels.get(name) {
+                Some(addr) => *addr,
+                None => panic!("label {} is never given", name),
+            };
+            bytes[*offset] |= (addr >> 8) as u8;
+            bytes[*offset + 1] = addr as u8;
+        }
+        bytes
+    }
+
+    // 00E0
+    pub fn clear(self) -> RomBuilder {
+        self.opcode(0x00E0)
+    }
+
+    // 00EE
+    pub fn ret(self) -> RomBuilder {
+        self.opcode(0x00EE)
+    }
+
+    // 0NNN
+    pub fn sys(self, target: impl Into<Target>) -> RomBuilder {
+        self.with_target(0x0000, target.into())
+    }
+
+    // 1NNN
+    pub fn jump(self, target: impl Into<Target>) -> RomBuilder {
+        self.with_target(0x1000, target.into())
+    }
+
+    // 2NNN
+    pub fn call(self, target: impl Into<Target>) -> RomBuilder {
+        self.with_target(0x2000, target.into())
+    }
+
+    // 3XNN
+    pub fn skip_eq(self, x: u8, nn: u8) -> RomBuilder {
+        self.opcode(0x3000 | reg(x) << 8 | nn as u16)
+    }
+
+    // 4XNN
+    pub fn skip_ne(self, x: u8, nn: u8) -> RomBuilder {
+        self.opcode(0x4000 | reg(x) << 8 | nn as u16)
+    }
+
+    // 5XY0
+    pub fn skip_eq_reg(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x5000 | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // 6XNN
+    pub fn set(self, x: u8, nn: u8) -> RomBuilder {
+        self.opcode(0x6000 | reg(x) << 8 | nn as u16)
+    }
+
+    // 7XNN
+    pub fn add(self, x: u8, nn: u8) -> RomBuilder {
+        self.opcode(0x7000 | reg(x) << 8 | nn as u16)
+    }
+
+    // 8XY0
+    pub fn set_reg(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x8000 | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // 8XY1
+    pub fn or(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x8001 | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // 8XY2
+    pub fn and(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x8002 | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // 8XY3
+    pub fn xor(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x8003 | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // 8XY4, VF is the carry
+    pub fn add_reg(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x8004 | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // 8XY5, VX -= VY
+    pub fn sub(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x8005 | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // 8XY6
+    pub fn shr(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x8006 | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // 8XY7, VX = VY - VX
+    pub fn subn(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x8007 | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // 8XYE
+    pub fn shl(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x800E | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // 9XY0
+    pub fn skip_ne_reg(self, x: u8, y: u8) -> RomBuilder {
+        self.opcode(0x9000 | reg(x) << 8 | reg(y) << 4)
+    }
+
+    // ANNN
+    pub fn load_i(self, target: impl Into<Target>) -> RomBuilder {
+        self.with_target(0xA000, target.into())
+    }
+
+    // BNNN
+    pub fn jump0(self, target: impl Into<Target>) -> RomBuilder {
+        self.with_target(0xB000, target.into())
+    }
+
+    // CXNN
+    pub fn random(self, x: u8, mask: u8) -> RomBuilder {
+        self.opcode(0xC000 | reg(x) << 8 | mask as u16)
+    }
+
+    // DXYN, the sprite at I drawn at VX, VY
+    pub fn draw(self, x: u8, y: u8, n: u8) -> RomBuilder {
+        assert!(n < 16, "sprites are 0 to 15 rows, not {}", n);
+        self.opcode(0xD000 | reg(x) << 8 | reg(y) << 4 | n as u16)
+    }
+
+    // EX9E
+    pub fn skip_key(self, x: u8) -> RomBuilder {
+        self.opcode(0xE09E | reg(x) << 8)
+    }
+
+    // EXA1
+    pub fn skip_not_key(self, x: u8) -> RomBuilder {
+        self.opcode(0xE0A1 | reg(x) << 8)
+    }
+
+    // FX07
+    pub fn get_delay(self, x: u8) -> RomBuilder {
+        self.opcode(0xF007 | reg(x) << 8)
+    }
+
+    // FX0A
+    pub fn wait_key(self, x: u8) -> RomBuilder {
+        self.opcode(0xF00A | reg(x) << 8)
+    }
+
+    // FX15
+    pub fn set_delay(self, x: u8) -> RomBuilder {
+        self.opcode(0xF015 | reg(x) << 8)
+    }
+
+    // FX18
+    pub fn set_sound(self, x: u8) -> RomBuilder {
+        self.opcode(0xF018 | reg(x) << 8)
+    }
+
+    // FX1E
+    pub fn add_i(self, x: u8) -> RomBuilder {
+        self.opcode(0xF01E | reg(x) << 8)
+    }
+
+    // FX29
+    pub fn font(self, x: u8) -> RomBuilder {
+        self.opcode(0xF029 | reg(x) << 8)
+    }
+
+    // FX33
+    pub fn bcd(self, x: u8) -> RomBuilder {
+        self.opcode(0xF033 | reg(x) << 8)
+    }
+
+    // FX55, V0 to VX
+    pub fn save(self, x: u8) -> RomBuilder {
+        self.opcode(0xF055 | reg(x) << 8)
+    }
+
+    // FX65, V0 to VX
+    pub fn load(self, x: u8) -> RomBuilder {
+        self.opcode(0xF065 | reg(x) << 8)
+    }
+
+    fn with_target(mut self, opcode: u16, target: Target) -> RomBuilder {
+        let addr = match target {
+            Target::At(addr) => {
+                assert!(addr < 0x1000, "address {:#x} is out of range", addr);
+                addr
+            }
+            Target::Label(name) => match self.labels.get(&name) {
+                Some(addr) => *addr,
+                None => {
+                    self.fixups.push((self.bytes.len(), name));
+                    0
+                }
+            },
+        };
+        self.opcode(opcode | addr)
+    }
+}
+
+fn reg(x: u8) -> u16 {
+    assert!(x < 16, "registers are 0 to F, not {:#x}", x);
+    x as u16
+}
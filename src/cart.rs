@@ -0,0 +1,106 @@
+use crate::config::Game;
+use crate::database::parse_color;
+use crate::octo;
+use serde_json::Value;
+
+const MAGIC: &[u8] = b"GIF8";
+
+/*    Octo Cartridges
+ * A GIF whose pixels carry the program alongside the label drawn on it:
+ *   palette index   low 2 bits are payload, the rest draw the label
+ *   payload         2 bits per pixel, high bits first, frame after frame
+ *                   length:4 (big-endian) then that many bytes of JSON
+ *   JSON            {"program": "<Octo source>", "options": {...}}
+ *
+ * Options used here, the rest are for Octo's own display:
+ *   tickrate          instructions per frame
+ *   fillColor         lit pixels
+ *   backgroundColor   unlit pixels
+ *   logicQuirks       8XY1-3 clear VF                   vf-reset
+ *   shiftQuirks       8XY6/8XYE shift VX, not VY        no-shift
+ *   loadStoreQuirks   FX55/FX65 leave I alone           no-memory
+ *   jumpQuirks        BXNN jumps to XNN + VX            jump
+ *   clipQuirks        sprites clip at the edges         clip
+ */
+pub fn is_cart(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+// The assembled program, and the cart's options in the form of a config
+// section
+pub fn load(bytes: &[u8]) -> Result<(Vec<u8>, Game), String> {
+    let payload = payload(bytes)?;
+    let json: Value = serde_json::from_slice(&payload)
+        .map_err(|e| format!("cartridge data isn't JSON: {}", e))?;
+    let source = json["program"].as_str().ok_or("cartridge has no program")?;
+    let rom = octo::assemble(source).map_err(|e| e.to_string())?;
+    Ok((rom, options(&json["options"])))
+}
+
+fn payload(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(bytes).map_err(|e| e.to_string())?;
+    let width = decoder.width() as usize;
+    let mut canvas = vec![0u8; width * decoder.height() as usize];
+    let mut bits = Vec::new();
+    // Frames may only cover part of the image, the payload is read from
+    // the whole of it after each one
+    while let Some(frame) = decoder.read_next_frame().map_err(|e| e.to_string())? {
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        for (i, index) in frame.buffer.iter().enumerate() {
+            let (x, y) = (
+                left + i % frame.width as usize,
+                top + i / frame.width as usize,
+            );
+            if let Some(pixel) = canvas.get_mut(y * width + x).filter(|_| x < width) {
+                *pixel = *index;
+            }
+        }
+        bits.extend(canvas.iter().map(|index| index & 0b11));
+    }
+    let bytes: Vec<u8> = bits
+        .chunks_exact(4)
+        .map(|chunk| chunk.iter().fold(0, |byte, bits| byte << 2 | bits))
+        .collect();
+    let length = match bytes.get(..4) {
+        Some(length) => u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize,
+        None => return Err(String::from("not an Octo cartridge")),
+    };
+    bytes
+        .get(4..4 + length)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| String::from("cartridge data is cut short"))
+}
+
+fn options(options: &Value) -> Game {
+    let flag = |name: &str| options[name].as_bool();
+    let quirks: Vec<String> = [
+        ("vf-reset", flag("logicQuirks")),
+        ("shift", flag("shiftQuirks").map(|on| !on)),
+        ("memory", flag("loadStoreQuirks").map(|on| !on)),
+        ("jump", flag("jumpQuirks")),
+        ("clip", flag("clipQuirks")),
+    ]
+    .into_iter()
+    .filter_map(|(name, on)| match on? {
+        true => Some(name.to_string()),
+        false => Some(format!("no-{}", name)),
+    })
+    .collect();
+    let color = |name: &str| options[name].as_str().and_then(parse_color);
+    Game {
+        speed: options["tickrate"]
+            .as_u64()
+            .filter(|speed| *speed > 0)
+            .map(|speed| speed as usize),
+        quirks: match quirks.is_empty() {
+            true => None,
+            false => Some(quirks.join(",")),
+        },
+        palette: color("fillColor").zip(color("backgroundColor")),
+        keys: Vec::new(),
+        run_ahead: None,
+        save_ram: None,
+    }
+}
@@ -0,0 +1,112 @@
+use crate::chip8::Chip8;
+use log::{error, info};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+pub const TWITCH_SERVER: &str = "irc.chat.twitch.tv:6667";
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(1);
+// Twitch lets anyone read chat under a justinfan name without logging in
+const NICK: &str = "justinfan4712";
+// How long the winning key stays down, about a dozen frames
+const PRESS: Duration = Duration::from_millis(200);
+
+/*    Chat Commands
+ * A message that is only a key 0-F votes for it, in either case:
+ *   5   a   F
+ * Everything else is chatter and ignored.
+ *
+ * When a vote window closes, the key with the most votes is pressed and ties
+ * go to the key voted for first. A zero window presses every key as it comes
+ * in instead, anarchy rather than democracy.
+ */
+pub struct ChatInput {
+    messages: Receiver<String>,
+    window: Duration,
+    window_start: Instant,
+    // Votes this window, in the order each key was first voted for
+    votes: Vec<(u8, usize)>,
+    pressed: Option<(u8, Instant)>,
+}
+
+impl ChatInput {
+    // Join a channel read-only, e.g. TWITCH_SERVER and the streamer's name
+    pub fn connect(server: &str, channel: &str, window: Duration) -> io::Result<ChatInput> {
+        let mut stream = TcpStream::connect(server)?;
+        let channel = channel.trim_start_matches('#').to_lowercase();
+        write!(stream, "NICK {}\r\nJOIN #{}\r\n", NICK, channel)?;
+        info!("Reading chat from #{} on {}", channel, server);
+        let mut writer = stream.try_clone()?;
+        let (sender, messages) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stream).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(e) => {
+                        error!("Chat connection lost: {}", e);
+                        break;
+                    }
+                };
+                // The server drops clients that don't answer
+                if let Some(token) = line.strip_prefix("PING") {
+                    if write!(writer, "PONG{}\r\n", token).is_err() {
+                        break;
+                    }
+                } else if let Some(text) = privmsg(&line) {
+                    if sender.send(text.to_string()).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(ChatInput {
+            messages,
+            window,
+            window_start: Instant::now(),
+            votes: Vec::new(),
+            pressed: None,
+        })
+    }
+
+    // Count new votes and hold the winning key, cpu.input is left alone
+    // while chat isn't pressing anything
+    pub fn poll(&mut self, cpu: &mut Chip8) {
+        for message in self.messages.try_iter() {
+            let key = match message.trim() {
+                digit if digit.len() == 1 => match u8::from_str_radix(digit, 16) {
+                    Ok(key) => key,
+                    Err(_) => continue,
+                },
+                _ => continue,
+            };
+            match self.window.is_zero() {
+                true => self.pressed = Some((key, Instant::now())),
+                false => match self.votes.iter_mut().find(|(voted, _)| *voted == key) {
+                    Some((_, count)) => *count += 1,
+                    None => self.votes.push((key, 1)),
+                },
+            }
+        }
+        if !self.window.is_zero() && self.window_start.elapsed() >= self.window {
+            self.window_start = Instant::now();
+            // max_by_key keeps the last of equals, so search back to front
+            if let Some((key, count)) = self.votes.iter().rev().max_by_key(|(_, count)| *count) {
+                info!("Chat pressed {:X} with {} votes", key, count);
+                self.pressed = Some((*key, Instant::now()));
+            }
+            self.votes.clear();
+        }
+        match self.pressed {
+            Some((key, since)) if since.elapsed() < PRESS => cpu.input = key,
+            _ => self.pressed = None,
+        }
+    }
+}
+
+// Text of ":nick!user@host PRIVMSG #channel :text"
+fn privmsg(line: &str) -> Option<&str> {
+    let (_, target) = line.split_once(" PRIVMSG ")?;
+    let (_, text) = target.split_once(" :")?;
+    Some(text)
+}
@@ -0,0 +1,190 @@
+use crate::chip8::Chip8;
+use crate::debugger::parse_addr;
+use log::error;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Freeze, // Written before every frame
+    Once,   // Written when enabled
+}
+
+pub struct Cheat {
+    pub name: String,
+    pub addr: usize,
+    pub value: u8,
+    pub kind: Kind,
+    pub enabled: bool,
+    applied: bool,
+}
+
+impl Cheat {
+    pub fn new(name: &str, addr: usize, value: u8, kind: Kind) -> Cheat {
+        Cheat {
+            name: name.to_string(),
+            addr,
+            value,
+            kind,
+            enabled: true,
+            applied: false,
+        }
+    }
+}
+
+// Same layout as the cheats file
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            Kind::Freeze => "freeze",
+            Kind::Once => "once",
+        };
+        write!(
+            f,
+            "{} {:#05x} {:02x} {}",
+            kind, self.addr, self.value, self.name
+        )
+    }
+}
+
+/*    Cheats File
+ * # Comment
+ * freeze 0x3f0 05 Infinite lives     Hex address and value, then a name
+ * once 0x3f1 09 Start on level 9
+ * !freeze 0x3f2 ff Disabled until toggled on
+ */
+#[derive(Default)]
+pub struct Cheats {
+    entries: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Cheats, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        Cheats::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // rom.cht next to the ROM
+    pub fn find(rom_path: &Path) -> Option<Cheats> {
+        let path = rom_path.with_extension("cht");
+        if !path.is_file() {
+            return None;
+        }
+        match Cheats::load(&path) {
+            Ok(cheats) => Some(cheats),
+            Err(e) => {
+                error!("{}", e);
+                None
+            }
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Cheats, String> {
+        let mut cheats = Cheats::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (enabled, line) = match line.strip_prefix('!') {
+                Some(rest) => (false, rest),
+                None => (true, line),
+            };
+            match parse_cheat(line) {
+                Ok(mut cheat) => {
+                    cheat.enabled = enabled;
+                    cheats.add(cheat);
+                }
+                Err(e) => return Err(format!("line {}: {}", index + 1, e)),
+            }
+        }
+        Ok(cheats)
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let mut text = String::new();
+        for cheat in &self.entries {
+            if !cheat.enabled {
+                text.push('!');
+            }
+            text.push_str(&format!("{}\n", cheat));
+        }
+        std::fs::write(path, text)
+    }
+
+    pub fn add(&mut self, cheat: Cheat) {
+        self.entries.push(cheat);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Flip a cheat on or off, one-shot cheats fire again when re-enabled
+    pub fn toggle(&mut self, index: usize) -> Option<&Cheat> {
+        let cheat = self.entries.get_mut(index)?;
+        cheat.enabled = !cheat.enabled;
+        cheat.applied = false;
+        Some(cheat)
+    }
+
+    // Called once per frame
+    pub fn apply(&mut self, cpu: &mut Chip8) {
+        for cheat in self.entries.iter_mut().filter(|cheat| cheat.enabled) {
+            if cheat.kind == Kind::Once && cheat.applied {
+                continue;
+            }
+            if let Some(byte) = cpu.memory.get_mut(cheat.addr) {
+                *byte = cheat.value;
+            }
+            cheat.applied = true;
+        }
+    }
+
+    pub fn list(&self) -> String {
+        if self.is_empty() {
+            return String::from("No cheats loaded");
+        }
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(index, cheat)| {
+                let state = match cheat.enabled {
+                    true => "on ",
+                    false => "off",
+                };
+                format!("{:>3} [{}] {}", index, state, cheat)
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+// kind, address, value and an optional name
+pub fn parse_cheat(text: &str) -> Result<Cheat, String> {
+    let mut words = text.split_whitespace();
+    let (kind, addr, value) = match (words.next(), words.next(), words.next()) {
+        (Some(kind), Some(addr), Some(value)) => (kind, addr, value),
+        _ => return Err(String::from("expected freeze|once <addr> <value> [name]")),
+    };
+    let name = words.collect::<Vec<&str>>().join(" ");
+    let kind = match kind {
+        "freeze" => Kind::Freeze,
+        "once" => Kind::Once,
+        _ => return Err(format!("unknown cheat type {}", kind)),
+    };
+    let addr = parse_addr(addr).ok_or(format!("invalid address {}", addr))?;
+    let value = parse_addr(value)
+        .filter(|value| *value <= 0xFF)
+        .ok_or(format!("invalid byte {}", value))?;
+    Ok(Cheat::new(&name, addr, value as u8, kind))
+}
@@ -0,0 +1,969 @@
+use crate::crash;
+use crate::disasm::{class_index, CLASSES};
+use crate::keypad::Keymap;
+use crate::peripheral::Peripherals;
+use log::debug;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::Arc;
+use tracing::{info_span, Span};
+
+pub const MEMORY_SIZE: usize = 0x1000;
+// Memory from 0x200 to the end, the most a ROM can take
+pub const MAX_ROM_SIZE: usize = MEMORY_SIZE - 0x200;
+// Instructions per 60 Hz frame
+pub const DEFAULT_SPEED: usize = 10;
+// Most frames the window may show past the machine
+pub const MAX_RUN_AHEAD: usize = 2;
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+
+#[derive(Debug, Clone)]
+pub enum EmulationStatus {
+    Running,
+    WaitingForKey,
+}
+
+// Behaviours that differ between interpreters, all off for this core's own
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quirks {
+    pub vf_reset: bool,    // 8XY1, 8XY2 and 8XY3 clear VF
+    pub shift_vy: bool,    // 8XY6 and 8XYE shift VY into VX
+    pub increment_i: bool, // FX55 and FX65 leave I after the last register
+    pub jump_vx: bool,     // BNNN jumps to XNN + VX
+    pub clip: bool,        // DXYN clips at the edges instead of wrapping
+}
+
+impl Quirks {
+    pub const NAMES: [&'static str; 5] = ["vf-reset", "shift", "memory", "jump", "clip"];
+
+    // Comma-separated names to turn on, or off with a "no-" prefix, e.g.
+    // "shift,no-clip"
+    pub fn parse(mut self, list: &str) -> Result<Quirks, String> {
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let (name, on) = match name.strip_prefix("no-") {
+                Some(name) => (name, false),
+                None => (name, true),
+            };
+            match name {
+                "vf-reset" => self.vf_reset = on,
+                "shift" => self.shift_vy = on,
+                "memory" => self.increment_i = on,
+                "jump" => self.jump_vx = on,
+                "clip" => self.clip = on,
+                _ => {
+                    return Err(format!(
+                        "Unknown quirk {}, expected one of {}",
+                        name,
+                        Quirks::NAMES.join(", ")
+                    ))
+                }
+            }
+        }
+        Ok(self)
+    }
+}
+
+// The names of the quirks that are on, in the form parse takes
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let on = [
+            self.vf_reset,
+            self.shift_vy,
+            self.increment_i,
+            self.jump_vx,
+            self.clip,
+        ];
+        let names: Vec<&str> = Quirks::NAMES
+            .iter()
+            .zip(on)
+            .filter(|(_, on)| *on)
+            .map(|(name, _)| *name)
+            .collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+// Link cable buffers for FX70 and FX71, filled and emptied by the frontend
+#[derive(Debug, Clone, Default)]
+pub struct Serial {
+    pub incoming: VecDeque<u8>,
+    pub outgoing: VecDeque<u8>,
+}
+
+// An embedder's handler for 0NNN, given the machine with the PC already past
+// the instruction and NNN, e.g. for homebrew syscalls or a VIP routine a ROM
+// needs. See Chip8::on_sys.
+pub type SysCallFn = dyn Fn(&mut Chip8, usize) + Send + Sync;
+
+#[derive(Clone)]
+pub struct SysCall(pub Arc<SysCallFn>);
+
+impl fmt::Debug for SysCall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SysCall")
+    }
+}
+
+// A frontend's callback for the timers, see Chip8::on_sound_start
+pub type Hook = Arc<dyn Fn(&Chip8) + Send + Sync>;
+
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub sound_start: Option<Hook>,
+    pub sound_stop: Option<Hook>,
+    pub timer_zero: Option<Hook>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let set = |hook: &Option<Hook>| hook.is_some();
+        f.debug_struct("Hooks")
+            .field("sound_start", &set(&self.sound_start))
+            .field("sound_stop", &set(&self.sound_stop))
+            .field("timer_zero", &set(&self.timer_zero))
+            .finish()
+    }
+}
+
+fn fire(hook: &Option<Hook>, cpu: &Chip8) {
+    if let Some(hook) = hook {
+        hook(cpu);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Chip8 {
+    pub status: EmulationStatus,
+    pub pc: usize,                          // Program Counter
+    pub sp: usize,                          // Stack Pointer
+    pub memory: [u8; MEMORY_SIZE],          // 4096 Bytes - Standard Chip8
+    pub registers: [u8; 16],                // 0xF is Flag Register
+    pub address_reg: u16,                   // Technically 12-bits
+    pub stack: [usize; 12],                 // 12 levels of nesting
+    pub delay_timer: u8,                    // Ticks down at 60 hz
+    pub sound_timer: u8,                    // Ticks down at 60 hz
+    pub input: u8,                          // Only one button at any time
+    pub video_buffer: [u8; 64 * 32],        // 1 Byte per Pixel
+    pub redraw: bool,                       // Flag for redraw request on video_buffer change
+    pub rom_size: usize,                    // Bytes loaded at 0x200
+    pub program: Vec<u8>,                   // Copy of those bytes for resets
+    pub colors: Option<([u8; 4], [u8; 4])>, // Palette recommended for the ROM
+    pub rng: StdRng,                        // For CXNN, seeded for netplay
+    pub instructions: u64,                  // Executed since power on
+    pub draws: u64,                         // 00E0 and DXYN executed
+    pub class_counts: [u64; CLASSES.len()], // Executed by opcode class, in CLASSES order
+    pub overlay: Vec<u8>,                   // RGBA drawn over the display, empty for none
+    pub serial: Option<Serial>,             // Link cable, None when unplugged
+    pub speed: usize,                       // Instructions per frame for the frontends
+    pub quirks: Quirks,
+    pub keymap: Keymap,                     // Keyboard layout for the frontends
+    pub run_ahead: usize,                   // Frames the window shows past the machine
+    pub sys: Option<SysCall>,               // 0NNN handler, None to pass over them
+    pub peripherals: Peripherals,           // Devices from an embedder, see peripheral.rs
+    pub hooks: Hooks,                       // Timer callbacks for the frontends
+}
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new()
+    }
+}
+impl Chip8 {
+    pub fn new() -> Chip8 {
+        Chip8 {
+            status: EmulationStatus::Running,
+            pc: 0x200,
+            sp: 0,
+            memory: [0; MEMORY_SIZE],
+            registers: [0; 16],
+            address_reg: 0,
+            stack: [0; 12],
+            delay_timer: 0,
+            sound_timer: 0,
+            input: 0,
+            video_buffer: [0; 64 * 32],
+            redraw: false,
+            rom_size: 0,
+            program: Vec::new(),
+            colors: None,
+            rng: StdRng::from_entropy(),
+            instructions: 0,
+            draws: 0,
+            class_counts: [0; CLASSES.len()],
+            overlay: Vec::new(),
+            serial: None,
+            speed: DEFAULT_SPEED,
+            quirks: Quirks::default(),
+            keymap: Keymap::default(),
+            run_ahead: 0,
+            sys: None,
+            peripherals: Peripherals::default(),
+            hooks: Hooks::default(),
+        }
+    }
+    // A machine with the font and rom loaded, ready for step_frame
+    pub fn with_rom(rom: &[u8]) -> Chip8 {
+        let mut cpu = Chip8::new();
+        cpu.load_font();
+        cpu.load_program(rom);
+        cpu
+    }
+    // The way in for embedding the core: one 60 Hz frame of speed
+    // instructions with keys held, bit n for key n, then the timers. The core
+    // reads one key at a time, so the lowest held counts. A frame stops early
+    // at an instruction that can't run, see crash::fault, and the machine
+    // stays on it.
+    pub fn step_frame(&mut self, keys: u16) -> &[u8; DISPLAY_WIDTH * DISPLAY_HEIGHT] {
+        self.input = match keys {
+            0 => 0,
+            keys => keys.trailing_zeros() as u8,
+        };
+        let _frame = self.frame_span().entered();
+        for _ in 0..self.speed {
+            if crash::fault(self).is_some() {
+                break;
+            }
+            self.tick();
+        }
+        self.decrement_timers();
+        &self.video_buffer
+    }
+    // The display's size in pixels, always 64x32 as this core has no hires
+    // mode, for embedders to size their own buffers by
+    pub fn width(&self) -> usize {
+        DISPLAY_WIDTH
+    }
+    pub fn height(&self) -> usize {
+        DISPLAY_HEIGHT
+    }
+    // Same seed and inputs, same random numbers
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+    // Run handler for every 0NNN other than 00E0 and 00EE. It can change
+    // anything, including the PC to go somewhere other than the next
+    // instruction.
+    pub fn on_sys(&mut self, handler: impl Fn(&mut Chip8, usize) + Send + Sync + 'static) {
+        self.sys = Some(SysCall(Arc::new(handler)));
+    }
+    // Called with the machine when the buzzer starts, as FX18 sets the sound
+    // timer from 0
+    pub fn on_sound_start(&mut self, hook: impl Fn(&Chip8) + Send + Sync + 'static) {
+        self.hooks.sound_start = Some(Arc::new(hook));
+    }
+    // When the sound timer gets back to 0, or FX18 sets it there
+    pub fn on_sound_stop(&mut self, hook: impl Fn(&Chip8) + Send + Sync + 'static) {
+        self.hooks.sound_stop = Some(Arc::new(hook));
+    }
+    // When the delay timer counts down to 0
+    pub fn on_timer_zero(&mut self, hook: impl Fn(&Chip8) + Send + Sync + 'static) {
+        self.hooks.timer_zero = Some(Arc::new(hook));
+    }
+    // One 60 Hz step of both timers, for the frontends
+    pub fn decrement_timers(&mut self) {
+        self.decrement_delay();
+        self.decrement_sound();
+    }
+    pub fn decrement_delay(&mut self) {
+        let was = self.delay_timer;
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        if was == 1 {
+            fire(&self.hooks.timer_zero, self);
+        }
+    }
+    pub fn decrement_sound(&mut self) {
+        let was = self.sound_timer;
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.sound_changed(was);
+    }
+    fn sound_changed(&self, was: u8) {
+        match (was, self.sound_timer) {
+            (0, 1..) => fire(&self.hooks.sound_start, self),
+            (1.., 0) => fire(&self.hooks.sound_stop, self),
+            _ => (),
+        }
+    }
+    pub fn tick(&mut self) {
+        match self.peripherals.is_empty() {
+            true => self.exec_opcode(),
+            false => self.exec_with_peripherals(),
+        }
+        self.instructions += 1;
+    }
+
+    // count instructions back to back, without a debugger
+    pub fn run(&mut self, count: usize) {
+        let _batch = self.batch_span(count).entered();
+        for _ in 0..count {
+            self.tick();
+        }
+    }
+
+    // One 60 Hz update on a --timeline, see timeline.rs
+    pub fn frame_span(&self) -> Span {
+        info_span!("frame", instructions = self.instructions)
+    }
+
+    // Instructions run back to back on a --timeline, from the PC and opcode
+    // they start at
+    pub fn batch_span(&self, count: usize) -> Span {
+        let opcode = match self.pc + 1 < self.memory.len() {
+            true => self.opcode_at(self.pc),
+            false => 0,
+        };
+        info_span!(
+            "batch",
+            pc = format_args!("{:03X}", self.pc),
+            opcode = format_args!("{:04X}", opcode),
+            count
+        )
+    }
+
+    // Instructions run since power on or a reset. Each takes one cycle in
+    // this core, so what a routine costs is how far this goes up across it.
+    pub fn cycles(&self) -> u64 {
+        self.instructions
+    }
+
+    // Instructions run of one class, e.g. "DXYN", since the same point
+    pub fn opcode_count(&self, class: &str) -> u64 {
+        CLASSES
+            .iter()
+            .position(|name| *name == class)
+            .map_or(0, |i| self.class_counts[i])
+    }
+
+    // The classes that have run and how often, in CLASSES order
+    pub fn opcode_counts(&self) -> Vec<(&'static str, u64)> {
+        CLASSES
+            .iter()
+            .zip(self.class_counts)
+            .filter(|(_, count)| *count > 0)
+            .map(|(class, count)| (*class, count))
+            .collect()
+    }
+
+    // Big-endian instruction stored at addr
+    pub fn opcode_at(&self, addr: usize) -> u16 {
+        ((self.memory[addr] as u16) << 8) | self.memory[addr + 1] as u16
+    }
+
+    pub(crate) fn exec_opcode(&mut self) {
+        // Break out the opcodes into four nibbles for pattern matching
+        let opcode = self.opcode_at(self.pc);
+        self.class_counts[class_index(opcode)] += 1;
+        let nibbles = (
+            (opcode & 0xF000) >> 12 as u8,
+            (opcode & 0x0F00) >> 8 as u8,
+            (opcode & 0x00F0) >> 4 as u8,
+            (opcode & 0x000F) as u8,
+        );
+        let nnn: usize = (opcode & 0x0FFF).into();
+        let nn: usize = (opcode & 0x00FF).into();
+        let x: usize = nibbles.1.into();
+        let y: usize = nibbles.2.into();
+        let n: usize = nibbles.3.into();
+
+        self.pc = match nibbles {
+            (0x00, 0x00, 0x0E, 0x00) => self.opcode_00e0(),
+            (0x00, 0x00, 0x0E, 0x0E) => self.opcode_00ee(),
+            (0x00, _, _, _) if self.sys.is_some() => self.opcode_0nnn(nnn),
+            (0x01, _, _, _) => self.opcode_1nnn(nnn),
+            (0x02, _, _, _) => self.opcode_2nnn(nnn),
+            (0x03, _, _, _) => self.opcode_3xnn(x, nn),
+            (0x04, _, _, _) => self.opcode_4xnn(x, nn),
+            (0x05, _, _, _) => self.opcode_5xnn(x, y),
+            (0x06, _, _, _) => self.opcode_6xnn(x, nn),
+            (0x07, _, _, _) => self.opcode_7xnn(x, nn),
+            (0x08, _, _, 0x00) => self.opcode_8xy0(x, y),
+            (0x08, _, _, 0x01) => self.opcode_8xy1(x, y),
+            (0x08, _, _, 0x02) => self.opcode_8xy2(x, y),
+            (0x08, _, _, 0x03) => self.opcode_8xy3(x, y),
+            (0x08, _, _, 0x04) => self.opcode_8xy4(x, y),
+            (0x08, _, _, 0x05) => self.opcode_8xy5(x, y),
+            (0x08, _, _, 0x06) => self.opcode_8xy6(x, y),
+            (0x08, _, _, 0x07) => self.opcode_8xy7(x, y),
+            (0x08, _, _, 0x0E) => self.opcode_8xye(x, y),
+            (0x09, _, _, _) => self.opcode_9xy0(x, y),
+            (0x0A, _, _, _) => self.opcode_annn(nnn),
+            (0x0B, _, _, _) => self.opcode_bnnn(x, nnn),
+            (0x0C, _, _, _) => self.opcode_cxnn(x, nn),
+            (0x0D, _, _, _) => self.opcode_dxyn(x, y, n),
+            (0x0E, _, 0x09, 0x0E) => self.opcode_ex9e(x),
+            (0x0E, _, 0x0A, 0x01) => self.opcode_exa1(x),
+            (0x0F, _, 0x00, 0x07) => self.opcode_fx07(x),
+            (0x0F, _, 0x00, 0x0A) => self.opcode_fx0a(x),
+            (0x0F, _, 0x01, 0x05) => self.opcode_fx15(x),
+            (0x0F, _, 0x01, 0x08) => self.opcode_fx18(x),
+            (0x0F, _, 0x01, 0x0E) => self.opcode_fx1e(x),
+            (0x0F, _, 0x02, 0x09) => self.opcode_fx29(x),
+            (0x0F, _, 0x03, 0x03) => self.opcode_fx33(x),
+            (0x0F, _, 0x05, 0x05) => self.opcode_fx55(x),
+            (0x0F, _, 0x06, 0x05) => self.opcode_fx65(x),
+            (0x0F, _, 0x07, 0x00) => self.opcode_fx70(x),
+            (0x0F, _, 0x07, 0x01) => self.opcode_fx71(x),
+            _ => self.pc, // Do Nothing
+        }
+    }
+    // Clear Screen
+    fn opcode_00e0(&mut self) -> usize {
+        debug!("00E0, Clear Screen");
+        self.video_buffer = [0; 32 * 64];
+        self.redraw = true;
+        self.draws += 1;
+        self.pc + 2
+    }
+    // Return
+    fn opcode_00ee(&mut self) -> usize {
+        debug!("00EE, Return");
+        let pc = self.stack[self.sp as usize] as u16;
+        self.sp -= 1;
+        pc.into()
+    }
+    // Machine code routine at nnn, run by the embedder's handler
+    fn opcode_0nnn(&mut self, nnn: usize) -> usize {
+        debug!("0NNN, Sys {:#04x}", nnn);
+        self.pc += 2;
+        if let Some(SysCall(handler)) = self.sys.clone() {
+            handler(self, nnn);
+        }
+        self.pc
+    }
+    // Jump to nnn
+    fn opcode_1nnn(&mut self, nnn: usize) -> usize {
+        debug!("1NNN, Jmp to {:#04x}", nnn);
+        nnn.into()
+    }
+    // Call sub-routine at nnn
+    fn opcode_2nnn(&mut self, nnn: usize) -> usize {
+        log::debug!("2NNN, Call {:#04x}", nnn);
+        self.sp += 1;
+        self.stack[self.sp] = self.pc + 2;
+        nnn.into()
+    }
+    // If (Vx == NN)
+    fn opcode_3xnn(&mut self, x: usize, nn: usize) -> usize {
+        log::debug!("3xNN, Vx == NN");
+        match self.registers[x] == nn as u8 {
+            true => self.pc + 4,
+            false => self.pc + 2,
+        }
+    }
+    // If (Vx != NN)
+    fn opcode_4xnn(&mut self, x: usize, nn: usize) -> usize {
+        log::debug!("4xNN, Vx != NN");
+        match self.registers[x] != nn as u8 {
+            true => self.pc + 4,
+            false => self.pc + 2,
+        }
+    }
+    // If (Vx == Vy)
+    fn opcode_5xnn(&mut self, x: usize, y: usize) -> usize {
+        log::debug!("5xNN, Vy == Vx");
+        match self.registers[x] == self.registers[y] {
+            true => self.pc + 4,
+            false => self.pc + 2,
+        }
+    }
+    // Set Vx to NN
+    fn opcode_6xnn(&mut self, x: usize, nn: usize) -> usize {
+        self.registers[x] = nn as u8;
+        self.pc + 2
+    }
+    // Add Vx to NN
+    fn opcode_7xnn(&mut self, x: usize, nn: usize) -> usize {
+        let (sum, _overflow) = self.registers[x].overflowing_add(nn as u8);
+        self.registers[x] = sum;
+        self.pc + 2
+    }
+
+    // Vx = Vy
+    fn opcode_8xy0(&mut self, x: usize, y: usize) -> usize {
+        self.registers[x] = self.registers[y];
+        self.pc + 2
+    }
+    // Vx | Vy
+    fn opcode_8xy1(&mut self, x: usize, y: usize) -> usize {
+        self.registers[x] |= self.registers[y];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+        self.pc + 2
+    }
+    // Vx & Vy
+    fn opcode_8xy2(&mut self, x: usize, y: usize) -> usize {
+        self.registers[x] &= self.registers[y];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+        self.pc + 2
+    }
+    // Vx ^ Vy
+    fn opcode_8xy3(&mut self, x: usize, y: usize) -> usize {
+        self.registers[x] ^= self.registers[y];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
+        self.pc + 2
+    }
+    // Vx += Vy with Carry, the flag written last so it wins over VF as Vx
+    fn opcode_8xy4(&mut self, x: usize, y: usize) -> usize {
+        let (sum, overflow) = self.registers[x].overflowing_add(self.registers[y]);
+        self.registers[x] = sum;
+        self.registers[0xF] = overflow as u8;
+        self.pc + 2
+    }
+    // Vx -= Vy with Borrow Flag
+    fn opcode_8xy5(&mut self, x: usize, y: usize) -> usize {
+        let (difference, overflow) = self.registers[x].overflowing_sub(self.registers[y]);
+        self.registers[x] = difference;
+        self.registers[0xF] = !overflow as u8;
+        self.pc + 2
+    }
+    // Vx >>= 1, save LSB in Flag
+    fn opcode_8xy6(&mut self, x: usize, y: usize) -> usize {
+        if self.quirks.shift_vy {
+            self.registers[x] = self.registers[y];
+        }
+        let bit = self.registers[x] & 0x01;
+        self.registers[x] >>= 1;
+        self.registers[0xF] = bit;
+        self.pc + 2
+    }
+    // Vx = Vy - Vx with Borrow Flag
+    fn opcode_8xy7(&mut self, x: usize, y: usize) -> usize {
+        let (difference, overflow) = self.registers[y].overflowing_sub(self.registers[x]);
+        self.registers[x] = difference;
+        self.registers[0xF] = !overflow as u8;
+        self.pc + 2
+    }
+
+    // Vx <<= 1, save MSB in Flag
+    fn opcode_8xye(&mut self, x: usize, y: usize) -> usize {
+        if self.quirks.shift_vy {
+            self.registers[x] = self.registers[y];
+        }
+        let bit = self.registers[x] >> 7;
+        self.registers[x] <<= 1;
+        self.registers[0xF] = bit;
+        self.pc + 2
+    }
+    // If (Vx != Vy)
+    fn opcode_9xy0(&mut self, x: usize, y: usize) -> usize {
+        match self.registers[x] != self.registers[y] {
+            true => self.pc + 4,
+            false => self.pc + 2,
+        }
+    }
+    // I = nnn
+    fn opcode_annn(&mut self, nnn: usize) -> usize {
+        self.address_reg = nnn as u16;
+        self.pc + 2
+    }
+    // PC = V0 + nnn, or VX + nnn with the jump quirk
+    fn opcode_bnnn(&mut self, x: usize, nnn: usize) -> usize {
+        match self.quirks.jump_vx {
+            true => self.registers[x] as usize + nnn,
+            false => self.registers[0] as usize + nnn,
+        }
+    }
+    // Vx = rand & nn
+    fn opcode_cxnn(&mut self, x: usize, nn: usize) -> usize {
+        let num: u8 = self.rng.gen();
+        self.registers[x] = (nn & num as usize) as u8;
+        self.pc + 2
+    }
+    // Draw(Vx, Vy, N), N = height
+    fn opcode_dxyn(&mut self, x: usize, y: usize, n: usize) -> usize {
+        self.registers[0xF] = 0; // Reset collision detection
+        for row in 0..n {
+            let x_coord: usize = self.registers[x] as usize;
+            let y_coord: usize = self.registers[y] as usize;
+            let video_addr: usize = (y_coord + row) * 64 + (x_coord);
+
+            for bit in 0..8 {
+                let color = self.memory[self.address_reg as usize + row] >> (7 - bit) & 0x1;
+                // Clipping starts the sprite on screen and drops what runs off it
+                let pixel = match self.quirks.clip {
+                    true => {
+                        let (px, py) = (x_coord % 64 + bit, y_coord % 32 + row);
+                        if px >= 64 || py >= 32 {
+                            continue;
+                        }
+                        py * 64 + px
+                    }
+                    false => (video_addr + bit) % 2048,
+                };
+                // Any pixel collision anywhere may flip this to true
+                self.registers[0xF] |= self.video_buffer[pixel] & color;
+                self.video_buffer[pixel] ^= color;
+            }
+        }
+        self.redraw = true;
+        self.draws += 1;
+        self.pc + 2
+    }
+    // If key == Vx
+    fn opcode_ex9e(&mut self, x: usize) -> usize {
+        match self.registers[x] == self.input {
+            true => self.pc + 4,
+            false => self.pc + 2,
+        }
+    }
+    // If key != Vx
+    fn opcode_exa1(&mut self, x: usize) -> usize {
+        match self.registers[x] != self.input {
+            true => self.pc + 4,
+            false => self.pc + 2,
+        }
+    }
+    // Vx = get_delay()
+    fn opcode_fx07(&mut self, x: usize) -> usize {
+        self.registers[x] = self.delay_timer;
+        self.pc + 2
+    }
+    // Vx = get_key(), staying on the instruction until a key is held. With
+    // one button at a time, input 0 is nothing held, so key 0 can't end it.
+    fn opcode_fx0a(&mut self, x: usize) -> usize {
+        match self.input {
+            0 => {
+                self.status = EmulationStatus::WaitingForKey;
+                self.pc
+            }
+            key => {
+                self.registers[x] = key;
+                self.status = EmulationStatus::Running;
+                self.pc + 2
+            }
+        }
+    }
+    // Set Delay to Vx
+    fn opcode_fx15(&mut self, x: usize) -> usize {
+        self.delay_timer = self.registers[x];
+        self.pc + 2
+    }
+    // Set Sound to Vx
+    fn opcode_fx18(&mut self, x: usize) -> usize {
+        let was = self.sound_timer;
+        self.sound_timer = self.registers[x];
+        self.sound_changed(was);
+        self.pc + 2
+    }
+    // Add Vx to I
+    fn opcode_fx1e(&mut self, x: usize) -> usize {
+        self.address_reg = self.address_reg.wrapping_add(self.registers[x] as u16);
+        self.pc + 2
+    }
+    // Set I to Sprite Address Location (Font), five bytes a digit from 0
+    fn opcode_fx29(&mut self, x: usize) -> usize {
+        self.address_reg = (self.registers[x] & 0xF) as u16 * 5;
+        self.pc + 2
+    }
+    // Store BCD of Vx into I (hundreds), I+1 (tens), I+2 (ones)
+    fn opcode_fx33(&mut self, x: usize) -> usize {
+        self.memory[self.address_reg as usize] = self.registers[x] / 100;
+        self.memory[self.address_reg as usize + 1] = (self.registers[x] % 100) / 10;
+        self.memory[self.address_reg as usize + 2] = self.registers[x] % 10;
+        self.pc + 2
+    }
+    // Dump registers from V0 to Vx into Memory, starting at I
+    fn opcode_fx55(&mut self, x: usize) -> usize {
+        for i in 0x0..x + 1 {
+            self.memory[self.address_reg as usize + i] = self.registers[i];
+        }
+        if self.quirks.increment_i {
+            self.address_reg = self.address_reg.wrapping_add(x as u16 + 1);
+        }
+        self.pc + 2
+    }
+    // Load registers from I
+    fn opcode_fx65(&mut self, x: usize) -> usize {
+        for i in 0x0..x + 1 {
+            self.registers[i] = self.memory[self.address_reg as usize + i];
+        }
+        if self.quirks.increment_i {
+            self.address_reg = self.address_reg.wrapping_add(x as u16 + 1);
+        }
+        self.pc + 2
+    }
+    // Send Vx down the link cable, extension opcode
+    fn opcode_fx70(&mut self, x: usize) -> usize {
+        if let Some(serial) = &mut self.serial {
+            serial.outgoing.push_back(self.registers[x]);
+        }
+        self.pc + 2
+    }
+    // Vx = byte from the link cable, VF = 1 if one had arrived, extension opcode
+    fn opcode_fx71(&mut self, x: usize) -> usize {
+        if let Some(serial) = &mut self.serial {
+            match serial.incoming.pop_front() {
+                Some(byte) => {
+                    self.registers[x] = byte;
+                    self.registers[0xF] = 1;
+                }
+                None => self.registers[0xF] = 0,
+            }
+        }
+        self.pc + 2
+    }
+
+    // Foreground and background RGBA colors
+    pub fn palette(&self) -> ([u8; 4], [u8; 4]) {
+        if let Some(colors) = self.colors {
+            return colors;
+        }
+        // Green for normal, amber on beeps
+        let color = match self.sound_timer {
+            0 => [0xFA, 0xFA, 0x10, 0xFF],
+            _ => [0x10, 0xFA, 0x10, 0xFF]
+        };
+        (color, [0x10, 0x10, 0x10, 0xFF])
+    }
+    pub fn draw(&mut self, frame: &mut [u8]) {
+        if self.redraw {
+            let (color, background) = self.palette();
+            // Flip the buffer into the RGBA space
+            for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+                let mut rgba = [0; 4];
+                match self.video_buffer[i] {
+                    1 => rgba = color,
+                    _ => rgba = background,
+                }
+                // Overlay pixels with any alpha cover the display
+                if let Some(over) = self.overlay.get(i * 4..i * 4 + 4) {
+                    if over[3] != 0 {
+                        rgba = [over[0], over[1], over[2], 0xFF];
+                    }
+                }
+                pixel.copy_from_slice(&rgba);
+            }
+        }
+        self.redraw = false;
+    }
+    pub fn load_font(&mut self) {
+        let font = [
+            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+            0x20, 0x60, 0x20, 0x20, 0x70, // 1
+            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+        ];
+        for i in 0..80 {
+            self.memory[i] = font[i];
+        }
+    }
+    pub fn load_program(&mut self, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            self.memory[0x200 + i] = *byte;
+        }
+        self.rom_size = bytes.len();
+        self.program = bytes.to_vec();
+    }
+    // Start the program again as if just loaded: registers, stack, timers and
+    // display cleared, and its bytes put back over any it changed. The rest
+    // of memory is left alone.
+    pub fn soft_reset(&mut self) {
+        self.status = EmulationStatus::Running;
+        self.pc = 0x200;
+        self.sp = 0;
+        self.registers = [0; 16];
+        self.address_reg = 0;
+        self.stack = [0; 12];
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.input = 0;
+        self.video_buffer = [0; 64 * 32];
+        self.redraw = true;
+        self.instructions = 0;
+        self.draws = 0;
+        self.class_counts = [0; CLASSES.len()];
+        let program = std::mem::take(&mut self.program);
+        self.load_program(&program);
+    }
+    // A soft reset with every byte of memory cleared first, so nothing a
+    // program left behind survives it
+    pub fn hard_reset(&mut self) {
+        self.memory = [0; MEMORY_SIZE];
+        self.load_font();
+        self.soft_reset();
+    }
+    // Program bytes as loaded, before any self-modification
+    pub fn rom(&self) -> &[u8] {
+        &self.program
+    }
+    /*    State Hash
+     * crc32 of, in this order:
+     *   pc:2 i:2 sp:1        big-endian like the instructions
+     *   v0-vF:16
+     *   stack:12x2
+     *   delay:1 sound:1 waiting for key:1
+     *   memory:4096
+     *   display:2048         one byte per pixel
+     *
+     * Two machines with the same hash will run the same from here given the
+     * same keys and random numbers, so replays, netplay and tests can compare
+     * them without the whole state. The order won't change without a note in
+     * the README. The random number generator, counters, held key, speed,
+     * quirks and palette aren't included.
+     */
+    pub fn state_hash(&self) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&(self.pc as u16).to_be_bytes());
+        hasher.update(&self.address_reg.to_be_bytes());
+        hasher.update(&[self.sp as u8]);
+        hasher.update(&self.registers);
+        for entry in self.stack {
+            hasher.update(&(entry as u16).to_be_bytes());
+        }
+        hasher.update(&[
+            self.delay_timer,
+            self.sound_timer,
+            matches!(self.status, EmulationStatus::WaitingForKey) as u8,
+        ]);
+        hasher.update(&self.memory);
+        hasher.update(&self.video_buffer);
+        hasher.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // V3 holds 0x2A, so an instruction reading the index instead shows up
+    fn machine(opcode: u16) -> Chip8 {
+        let mut cpu = Chip8::with_rom(&opcode.to_be_bytes());
+        cpu.registers[3] = 0x2A;
+        cpu.address_reg = 0x300;
+        cpu
+    }
+
+    // VX op= VY from x and y, giving VX and VF
+    fn alu(opcode: u16, x: u8, y: u8) -> (u8, u8) {
+        let mut cpu = Chip8::with_rom(&opcode.to_be_bytes());
+        cpu.registers[1] = x;
+        cpu.registers[2] = y;
+        cpu.tick();
+        (cpu.registers[1], cpu.registers[0xF])
+    }
+
+    #[test]
+    fn alu_8xy4_sets_vf_to_the_carry() {
+        assert_eq!(alu(0x8124, 0x10, 0x20), (0x30, 0));
+        assert_eq!(alu(0x8124, 0xF0, 0x20), (0x10, 1));
+    }
+
+    #[test]
+    fn alu_8xy5_sets_vf_when_there_is_no_borrow() {
+        assert_eq!(alu(0x8125, 0x30, 0x10), (0x20, 1));
+        assert_eq!(alu(0x8125, 0x10, 0x30), (0xE0, 0));
+        assert_eq!(alu(0x8125, 0x10, 0x10), (0x00, 1));
+    }
+
+    #[test]
+    fn alu_8xy7_subtracts_vx_from_vy() {
+        assert_eq!(alu(0x8127, 0x10, 0x30), (0x20, 1));
+        assert_eq!(alu(0x8127, 0x30, 0x10), (0xE0, 0));
+    }
+
+    #[test]
+    fn alu_8xy6_and_8xye_set_vf_to_the_bit_shifted_out() {
+        assert_eq!(alu(0x8126, 0x81, 0x81), (0x40, 1));
+        assert_eq!(alu(0x8126, 0x42, 0x42), (0x21, 0));
+        assert_eq!(alu(0x812E, 0x81, 0x81), (0x02, 1));
+        assert_eq!(alu(0x812E, 0x42, 0x42), (0x84, 0));
+    }
+
+    // With VF as VX the flag is what's left, not the result
+    #[test]
+    fn the_flag_wins_over_vf_as_vx() {
+        let mut cpu = Chip8::with_rom(&[0x8F, 0x14, 0x8F, 0x15, 0x8F, 0xF6]);
+        cpu.registers[0xF] = 0xFF;
+        cpu.registers[1] = 0x01;
+        cpu.tick();
+        assert_eq!(cpu.registers[0xF], 1);
+        cpu.registers[1] = 0x20;
+        cpu.tick();
+        assert_eq!(cpu.registers[0xF], 0);
+        cpu.registers[0xF] = 0x03;
+        cpu.tick();
+        assert_eq!(cpu.registers[0xF], 1);
+    }
+
+    #[test]
+    fn fx0a_waits_for_a_key() {
+        let mut cpu = machine(0xF30A);
+        cpu.tick();
+        assert_eq!(cpu.pc, 0x200);
+        assert!(matches!(cpu.status, EmulationStatus::WaitingForKey));
+        assert_eq!(cpu.registers[3], 0x2A);
+        cpu.input = 0xB;
+        cpu.tick();
+        assert_eq!(cpu.pc, 0x202);
+        assert!(matches!(cpu.status, EmulationStatus::Running));
+        assert_eq!(cpu.registers[3], 0xB);
+    }
+
+    #[test]
+    fn fx15_loads_the_delay_timer_from_vx() {
+        let mut cpu = machine(0xF315);
+        cpu.tick();
+        assert_eq!(cpu.delay_timer, 0x2A);
+    }
+
+    #[test]
+    fn fx18_loads_the_sound_timer_from_vx() {
+        let mut cpu = machine(0xF318);
+        cpu.tick();
+        assert_eq!(cpu.sound_timer, 0x2A);
+    }
+
+    // F018 with V0 set starts the buzzer and F318 with V3 at 0 stops it,
+    // the other way round if they went by X
+    #[test]
+    fn fx18_hooks_follow_vx() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut cpu = Chip8::with_rom(&[0xF0, 0x18, 0xF3, 0x18]);
+        cpu.registers[0] = 0x2A;
+        let start = events.clone();
+        cpu.on_sound_start(move |cpu| start.lock().unwrap().push(("start", cpu.sound_timer)));
+        let stop = events.clone();
+        cpu.on_sound_stop(move |cpu| stop.lock().unwrap().push(("stop", cpu.sound_timer)));
+        cpu.tick();
+        cpu.tick();
+        assert_eq!(*events.lock().unwrap(), [("start", 0x2A), ("stop", 0)]);
+    }
+
+    #[test]
+    fn fx1e_adds_vx_to_i() {
+        let mut cpu = machine(0xF31E);
+        cpu.tick();
+        assert_eq!(cpu.address_reg, 0x32A);
+    }
+
+    #[test]
+    fn fx29_points_i_at_the_digit_in_vx() {
+        let mut cpu = machine(0xF329);
+        cpu.registers[3] = 0x7;
+        cpu.tick();
+        assert_eq!(cpu.address_reg, 7 * 5);
+        assert_eq!(
+            cpu.memory[cpu.address_reg as usize..][..5],
+            [0xF0, 0x10, 0x20, 0x40, 0x40]
+        );
+    }
+}
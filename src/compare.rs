@@ -0,0 +1,139 @@
+use crate::chip8::{Chip8, Quirks};
+use crate::crash;
+use crate::disasm::{disassemble, opcode_class};
+use crate::trace::TraceEntry;
+use crate::variant::Variant;
+
+/*    Quirk Comparison
+ * chip8 compare <rom> <a> <b> runs a ROM on two machines in lockstep, from
+ * seed 0 with no keys held, and stops at the first instruction after which
+ * they differ. A machine is a comma-separated list of quirks, optionally
+ * starting with a variant whose quirks it begins from:
+ *   chip8 compare game.ch8 chip8 schip
+ *   chip8 compare game.ch8 vf-reset no-vf-reset
+ *   chip8 compare game.ch8 schip schip,no-jump
+ * A list without a variant begins from the machine's usual quirks. When the
+ * instruction they split on is one a quirk changes, and the two disagree on
+ * it, that's the quirk the ROM depends on.
+ */
+pub fn config(base: Quirks, spec: &str) -> Result<Quirks, String> {
+    let (first, rest) = spec.split_once(',').unwrap_or((spec, ""));
+    match Variant::parse(first.trim()) {
+        Some(variant) => variant.quirks().parse(rest),
+        None => base.parse(spec),
+    }
+}
+
+// The quirk that changes what an instruction does, and whether it's on
+fn quirk(class: &str, quirks: &Quirks) -> Option<(&'static str, bool)> {
+    match class {
+        "8XY1" | "8XY2" | "8XY3" => Some(("vf-reset", quirks.vf_reset)),
+        "8XY6" | "8XYE" => Some(("shift", quirks.shift_vy)),
+        "FX55" | "FX65" => Some(("memory", quirks.increment_i)),
+        "BNNN" => Some(("jump", quirks.jump_vx)),
+        "DXYN" => Some(("clip", quirks.clip)),
+        _ => None,
+    }
+}
+
+// How long the two stayed the same, or the report of where they split. Both
+// are left as they were at the end, for a side by side screenshot.
+pub fn compare(a: &mut Chip8, b: &mut Chip8, frames: u64) -> Result<String, String> {
+    a.seed(0);
+    b.seed(0);
+    let start = a.instructions;
+    for frame in 0..frames {
+        for _ in 0..a.speed {
+            match (crash::fault(a), crash::fault(b)) {
+                (Some(fault), Some(_)) => {
+                    return Ok(format!(
+                        "Same for {} frames, until both stopped at {}",
+                        frame, fault
+                    ))
+                }
+                (Some(fault), None) => return Err(format!("Only A stopped, at {}", fault)),
+                (None, Some(fault)) => return Err(format!("Only B stopped, at {}", fault)),
+                (None, None) => (),
+            }
+            let (pc, opcode) = (a.pc, a.opcode_at(a.pc));
+            a.tick();
+            b.tick();
+            if !same(a, b) {
+                let instruction = a.instructions - start;
+                return Err(divergence(a, b, frame + 1, instruction, pc, opcode));
+            }
+        }
+        for cpu in [&mut *a, &mut *b] {
+            cpu.decrement_timers();
+        }
+    }
+    Ok(format!("Same over {} frames", frames))
+}
+
+fn same(a: &Chip8, b: &Chip8) -> bool {
+    a.pc == b.pc
+        && a.registers == b.registers
+        && a.address_reg == b.address_reg
+        && a.sp == b.sp
+        && a.stack == b.stack
+        && a.delay_timer == b.delay_timer
+        && a.sound_timer == b.sound_timer
+        && a.video_buffer == b.video_buffer
+        && a.memory == b.memory
+}
+
+fn divergence(
+    a: &Chip8,
+    b: &Chip8,
+    frame: u64,
+    instruction: u64,
+    pc: usize,
+    opcode: u16,
+) -> String {
+    let class = opcode_class(opcode);
+    let mut lines = vec![format!(
+        "Diverged in frame {} at instruction {}, {:04X} at {:#05x} ({})",
+        frame,
+        instruction,
+        opcode,
+        pc,
+        disassemble(opcode)
+    )];
+    if let (Some((name, on_a)), Some((_, on_b))) =
+        (quirk(class, &a.quirks), quirk(class, &b.quirks))
+    {
+        if on_a != on_b {
+            let on = |on| match on {
+                true => "on",
+                false => "off",
+            };
+            lines.push(format!(
+                "  {} depends on the {} quirk, {} in A and {} in B",
+                class,
+                name,
+                on(on_a),
+                on(on_b)
+            ));
+        }
+    }
+    if a.pc != b.pc {
+        lines.push(format!("  PC {:#05x} vs {:#05x}", a.pc, b.pc));
+    }
+    let entry = TraceEntry::new(a, b);
+    for (name, old, new) in &entry.registers {
+        lines.push(format!("  {} {:#04x} vs {:#04x}", name, old, new));
+    }
+    for (addr, old, new) in &entry.memory {
+        lines.push(format!("  [{:#05x}] {:#04x} vs {:#04x}", addr, old, new));
+    }
+    let pixels = a
+        .video_buffer
+        .iter()
+        .zip(b.video_buffer.iter())
+        .filter(|(a, b)| a != b)
+        .count();
+    if pixels > 0 {
+        lines.push(format!("  {} pixels differ", pixels));
+    }
+    lines.join("\n")
+}
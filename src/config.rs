@@ -0,0 +1,231 @@
+use crate::battery;
+use crate::chip8::{Chip8, Quirks, MAX_RUN_AHEAD};
+use crate::database::parse_color;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::ops::Range;
+use std::path::Path;
+use toml::{Table, Value};
+
+// One for each of Ctrl+1 to Ctrl+9
+pub const MAX_RECENT: usize = 9;
+
+/*    Config File
+ * [game."brix.ch8"]                  By file name, or by the ROM's SHA-1
+ * speed = 15                         Instructions per frame
+ * quirks = "shift,no-clip"           On top of the variant's
+ * palette = ["#ffb000", "#202020"]   Foreground, background
+ * keys = { 4 = "j", 6 = "l" }        CHIP-8 key to keyboard key
+ * run_ahead = 1                      Frames the window shows ahead, 0 to 2
+ * save_ram = "0xe00-0xeff"           Memory kept in rom.sav, see battery.rs
+ *
+ * recent = ["/roms/brix.ch8", ...]   Kept by the emulator, newest first
+ * pause_on_focus_loss = false        Keep running in the background
+ * pause_when_hidden = true           Stop while minimized, even so
+ *
+ * A game's settings apply whenever that ROM loads, and options given on the
+ * command line still win over them. A SHA-1 section wins over a file name
+ * one, so a hack can have different settings from the game it's based on.
+ * A missing config file is not an error.
+ */
+#[derive(Debug, Clone)]
+pub struct Config {
+    games: HashMap<String, Game>,
+    pub recent: Vec<String>,
+    // Stop the machine and its sound while no window has focus
+    pub pause_on_focus_loss: bool,
+    // Stop it while every window is minimized, whatever the focus
+    pub pause_when_hidden: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            games: HashMap::new(),
+            recent: Vec::new(),
+            pause_on_focus_loss: true,
+            pause_when_hidden: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Game {
+    pub speed: Option<usize>,
+    pub quirks: Option<String>,
+    pub palette: Option<([u8; 4], [u8; 4])>,
+    pub keys: Vec<(u8, char)>,
+    pub run_ahead: Option<usize>,
+    pub save_ram: Option<Range<usize>>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, String> {
+        if !path.is_file() {
+            return Ok(Config::default());
+        }
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let table: Table = text
+            .parse()
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut games = HashMap::new();
+        if let Some(sections) = table.get("game") {
+            let sections = sections
+                .as_table()
+                .ok_or_else(|| format!("{}: game must be a table", path.display()))?;
+            for (name, section) in sections {
+                let game = Game::parse(section)
+                    .map_err(|e| format!("{}: game.\"{}\": {}", path.display(), name, e))?;
+                games.insert(name.clone(), game);
+            }
+        }
+        let recent = match table.get("recent") {
+            Some(recent) => recent
+                .as_array()
+                .and_then(|recent| recent.iter().map(Value::as_str).collect::<Option<Vec<_>>>())
+                .ok_or_else(|| format!("{}: recent must be a list of ROMs", path.display()))?
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(Config {
+            games,
+            recent,
+            pause_on_focus_loss: flag(&table, "pause_on_focus_loss", true, path)?,
+            pause_when_hidden: flag(&table, "pause_when_hidden", false, path)?,
+        })
+    }
+
+    // Settings for a ROM, by hash first and then file name
+    pub fn game(&self, rom_path: &Path, rom: &[u8]) -> Option<&Game> {
+        let hash = sha1_smol::Sha1::from(rom).digest().to_string();
+        self.games.get(&hash).or_else(|| {
+            let name = rom_path.file_name()?.to_str()?;
+            self.games.get(name)
+        })
+    }
+}
+
+fn flag(table: &Table, name: &str, default: bool, path: &Path) -> Result<bool, String> {
+    match table.get(name) {
+        Some(value) => value
+            .as_bool()
+            .ok_or_else(|| format!("{}: {} must be true or false", path.display(), name)),
+        None => Ok(default),
+    }
+}
+
+// Move a ROM to the top of the recent list in the config file, creating it
+// if need be. The rest of the file is left as it was, comments and all.
+pub fn remember(path: &Path, rom_path: &str) -> Result<Vec<String>, String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(format!("Unable to read {}: {}", path.display(), e)),
+    };
+    let mut document: toml_edit::DocumentMut = text
+        .parse()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut recent: Vec<String> = document
+        .get("recent")
+        .and_then(|recent| recent.as_array())
+        .map(|recent| {
+            recent
+                .iter()
+                .filter_map(|rom| rom.as_str())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    recent.retain(|rom| rom != rom_path);
+    recent.insert(0, rom_path.to_string());
+    recent.truncate(MAX_RECENT);
+    let list: toml_edit::Array = recent.iter().map(String::as_str).collect();
+    document["recent"] = toml_edit::value(list);
+    std::fs::write(path, document.to_string())
+        .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+    Ok(recent)
+}
+
+impl Game {
+    fn parse(section: &Value) -> Result<Game, String> {
+        let section = section.as_table().ok_or("must be a table")?;
+        let mut game = Game::default();
+        for (setting, value) in section {
+            match setting.as_str() {
+                "speed" => match value.as_integer() {
+                    Some(speed) if speed > 0 => game.speed = Some(speed as usize),
+                    _ => return Err(String::from("speed must be a positive number")),
+                },
+                "quirks" => {
+                    let list = value.as_str().ok_or("quirks must be a string")?;
+                    Quirks::default().parse(list)?;
+                    game.quirks = Some(list.to_string());
+                }
+                "palette" => {
+                    let colors = value
+                        .as_array()
+                        .map(|colors| colors.iter().map(Value::as_str).collect::<Vec<_>>());
+                    game.palette = match colors.as_deref() {
+                        Some([Some(foreground), Some(background)]) => {
+                            parse_color(foreground).zip(parse_color(background))
+                        }
+                        _ => None,
+                    };
+                    if game.palette.is_none() {
+                        return Err(String::from(
+                            "palette must be two colors, e.g. [\"#ffb000\", \"#202020\"]",
+                        ));
+                    }
+                }
+                "keys" => {
+                    let keys = value.as_table().ok_or("keys must be a table")?;
+                    for (value, key) in keys {
+                        let value = u8::from_str_radix(value, 16)
+                            .ok()
+                            .filter(|value| *value < 16)
+                            .ok_or_else(|| format!("{} is not a CHIP-8 key, 0 to F", value))?;
+                        let key = match key.as_str().map(|key| key.chars().collect::<Vec<_>>()) {
+                            Some(key) if key.len() == 1 && key[0].is_ascii_alphanumeric() => key[0],
+                            _ => return Err(String::from("keys map to a letter or digit")),
+                        };
+                        game.keys.push((value, key));
+                    }
+                }
+                "run_ahead" => match value.as_integer() {
+                    Some(frames) if (0..=MAX_RUN_AHEAD as i64).contains(&frames) => {
+                        game.run_ahead = Some(frames as usize)
+                    }
+                    _ => return Err(format!("run_ahead must be 0 to {}", MAX_RUN_AHEAD)),
+                },
+                "save_ram" => {
+                    let range = value.as_str().ok_or("save_ram must be a string")?;
+                    game.save_ram = Some(battery::parse_range(range)?);
+                }
+                setting => return Err(format!("unknown setting {}", setting)),
+            }
+        }
+        Ok(game)
+    }
+
+    pub fn apply(&self, cpu: &mut Chip8) {
+        if let Some(speed) = self.speed {
+            cpu.speed = speed;
+        }
+        // Checked when the config was loaded
+        if let Some(Ok(quirks)) = self.quirks.as_ref().map(|list| cpu.quirks.parse(list)) {
+            cpu.quirks = quirks;
+        }
+        if let Some(palette) = self.palette {
+            cpu.colors = Some(palette);
+        }
+        for (value, key) in &self.keys {
+            cpu.keymap.bind(*value, *key);
+        }
+        if let Some(frames) = self.run_ahead {
+            cpu.run_ahead = frames;
+        }
+    }
+}
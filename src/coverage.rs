@@ -0,0 +1,225 @@
+use crate::analysis::{self, Analysis, Class};
+use crate::chip8::Chip8;
+use crate::disasm::disassemble;
+use std::collections::BTreeMap;
+
+// ROM bytes per row of the coverage map
+const MAP_WIDTH: usize = 64;
+
+// Tracks which bytes ran as code, which were read as data and which way
+// each conditional skip went
+pub struct Coverage {
+    executed: Vec<bool>,
+    data: Vec<bool>,
+    branches: BTreeMap<usize, (bool, bool)>, // Skipped, fell through
+}
+
+impl Coverage {
+    pub fn new(memory_size: usize) -> Coverage {
+        Coverage {
+            executed: vec![false; memory_size],
+            data: vec![false; memory_size],
+            branches: BTreeMap::new(),
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.executed.iter_mut().for_each(|byte| *byte = false);
+        self.data.iter_mut().for_each(|byte| *byte = false);
+        self.branches.clear();
+    }
+
+    // Called with the state before and after an instruction ran
+    pub fn record(&mut self, before: &Chip8, after: &Chip8) {
+        let pc = before.pc;
+        self.mark(pc, 2, true);
+        let opcode = before.opcode_at(pc);
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let i = before.address_reg as usize;
+        match opcode & 0xF000 {
+            0xD000 => self.mark(i, (opcode & 0x000F) as usize, false),
+            0xF000 if opcode & 0x00FF == 0x65 => self.mark(i, x + 1, false),
+            _ => {}
+        }
+        if is_skip(opcode) {
+            let branch = self.branches.entry(pc).or_insert((false, false));
+            match after.pc == pc + 4 {
+                true => branch.0 = true,
+                false => branch.1 = true,
+            }
+        }
+    }
+
+    fn mark(&mut self, addr: usize, len: usize, executed: bool) {
+        let map = match executed {
+            true => &mut self.executed,
+            false => &mut self.data,
+        };
+        for byte in map.iter_mut().skip(addr).take(len) {
+            *byte = true;
+        }
+    }
+
+    fn rom_range(cpu: &Chip8) -> std::ops::Range<usize> {
+        0x200..(0x200 + cpu.rom_size).min(cpu.memory.len())
+    }
+
+    // What the untouched bytes look like, from the ROM as it is in memory
+    fn analyze(cpu: &Chip8) -> Analysis {
+        analysis::analyze(&cpu.memory[Coverage::rom_range(cpu)])
+    }
+
+    // What the analysis makes of an untouched range
+    fn describe(analysis: &Analysis, first: usize, last: usize) -> String {
+        let mut kinds = Vec::new();
+        for addr in first..=last {
+            let kind = match (analysis.is_unreachable(addr), analysis.class(addr)) {
+                (true, _) => "unreachable code",
+                (false, Class::Code) => "code",
+                (false, Class::Sprite) => "sprite",
+                (false, Class::Unknown) => "unknown",
+            };
+            if !kinds.contains(&kind) {
+                kinds.push(kind);
+            }
+        }
+        kinds.join(", ")
+    }
+
+    // Totals for the ROM, untouched ranges and one-sided branches
+    pub fn report(&self, cpu: &Chip8) -> String {
+        let rom = Coverage::rom_range(cpu);
+        if rom.is_empty() {
+            return String::from("No ROM loaded");
+        }
+        let code = rom.clone().filter(|addr| self.executed[*addr]).count();
+        let data = rom
+            .clone()
+            .filter(|addr| self.data[*addr] && !self.executed[*addr])
+            .count();
+        let mut lines = vec![format!(
+            "ROM {} bytes: {} code ({:.1}%), {} data ({:.1}%), {} untouched",
+            rom.len(),
+            code,
+            code as f64 * 100.0 / rom.len() as f64,
+            data,
+            data as f64 * 100.0 / rom.len() as f64,
+            rom.len() - code - data
+        )];
+
+        let mut untouched = Vec::new();
+        let mut start = None;
+        for addr in rom.clone() {
+            let touched = self.executed[addr] || self.data[addr];
+            match (touched, start) {
+                (false, None) => start = Some(addr),
+                (true, Some(first)) => {
+                    untouched.push((first, addr - 1));
+                    start = None;
+                }
+                _ => {}
+            }
+        }
+        if let Some(first) = start {
+            untouched.push((first, rom.end - 1));
+        }
+        let analysis = Coverage::analyze(cpu);
+        lines.push(format!("Static:  {}", analysis.summary()));
+        if !untouched.is_empty() {
+            lines.push(String::from("Untouched:"));
+            for (first, last) in untouched {
+                lines.push(format!(
+                    "  {:#05x}-{:#05x}  {}",
+                    first,
+                    last,
+                    Coverage::describe(&analysis, first, last)
+                ));
+            }
+        }
+
+        let one_sided: Vec<String> = self
+            .branches
+            .iter()
+            .filter(|(_, (skipped, fell_through))| !(*skipped && *fell_through))
+            .map(|(addr, (skipped, _))| {
+                format!(
+                    "  {:#05x}: {:<18} only {}",
+                    addr,
+                    disassemble(cpu.opcode_at(*addr)),
+                    match skipped {
+                        true => "skipped",
+                        false => "fell through",
+                    }
+                )
+            })
+            .collect();
+        if !one_sided.is_empty() {
+            lines.push(String::from("Branches taken one way:"));
+            lines.extend(one_sided);
+        }
+        lines.join("\n")
+    }
+
+    // One character per ROM byte: X code, d data, and for untouched bytes
+    // what the analysis makes of them, c code, s sprite, u unreachable code
+    // and . unknown
+    pub fn map(&self, cpu: &Chip8) -> String {
+        let rom = Coverage::rom_range(cpu);
+        let analysis = Coverage::analyze(cpu);
+        let mut lines = Vec::new();
+        for row in rom.clone().step_by(MAP_WIDTH) {
+            let cells: String = (row..(row + MAP_WIDTH).min(rom.end))
+                .map(|addr| match (self.executed[addr], self.data[addr]) {
+                    (true, _) => 'X',
+                    (false, true) => 'd',
+                    _ if analysis.is_unreachable(addr) => 'u',
+                    _ => match analysis.class(addr) {
+                        Class::Code => 'c',
+                        Class::Sprite => 's',
+                        Class::Unknown => '.',
+                    },
+                })
+                .collect();
+            lines.push(format!("{:#05x}: {}", row, cells));
+        }
+        lines.join("\n")
+    }
+
+    // Disassembly of the ROM, green for code, cyan for data, red if unused
+    // and the analysis says it's code, grey for other unused bytes
+    pub fn disassembly(&self, cpu: &Chip8) -> String {
+        let rom = Coverage::rom_range(cpu);
+        let analysis = Coverage::analyze(cpu);
+        let mut lines = Vec::new();
+        for addr in rom.step_by(2) {
+            if addr + 1 >= cpu.memory.len() {
+                break;
+            }
+            let code = analysis.class(addr) == Class::Code || analysis.is_unreachable(addr);
+            let color = match (self.executed[addr], self.data[addr] || self.data[addr + 1]) {
+                (true, _) => "\x1b[32m",
+                (false, true) => "\x1b[36m",
+                (false, false) if code => "\x1b[31m",
+                (false, false) => "\x1b[90m",
+            };
+            let opcode = cpu.opcode_at(addr);
+            lines.push(format!(
+                "{}{:#05x}: {:04x}  {}\x1b[0m",
+                color,
+                addr,
+                opcode,
+                disassemble(opcode)
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+// Conditional instructions that skip the next instruction
+fn is_skip(opcode: u16) -> bool {
+    match opcode & 0xF000 {
+        0x3000 | 0x4000 | 0x5000 | 0x9000 => true,
+        0xE000 => matches!(opcode & 0x00FF, 0x9E | 0xA1),
+        _ => false,
+    }
+}
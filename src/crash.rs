@@ -0,0 +1,116 @@
+use crate::chip8::{Chip8, MEMORY_SIZE};
+use crate::debugger::{call_stack, format_registers};
+use crate::disasm::{disassemble, opcode_class};
+use crate::info::extension;
+use crate::symbols::Symbols;
+use crate::trace::TraceEntry;
+use crate::{screenshot, state};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Instructions leading up to a fault kept in the report
+pub const TRACE_DEPTH: usize = 32;
+
+/*    Crash Dumps
+ * crash-<unix time>/
+ *   report.txt     The fault, registers, call stack and the instructions
+ *                  leading up to it
+ *   memory.bin     All 4096 bytes of memory
+ *   screen.png     The display
+ *   machine.state  A save state, for the HTTP API or state tools to load
+ *
+ * Written when a running program reaches an instruction the core can't carry
+ * out, with the machine as it was just before. Instructions from later
+ * variants aren't faults, the core passes over them as it always has and
+ * lint points them out.
+ */
+
+// Why the instruction at the PC can't run, if it can't
+pub fn fault(cpu: &Chip8) -> Option<String> {
+    if cpu.pc + 1 >= MEMORY_SIZE {
+        return Some(format!("PC {:#05x} is outside memory", cpu.pc));
+    }
+    let opcode = cpu.opcode_at(cpu.pc);
+    let i = cpu.address_reg as usize;
+    let x = (opcode as usize & 0x0F00) >> 8;
+    // Bytes from I the instruction reads or writes
+    let length = match opcode_class(opcode) {
+        _ if extension(opcode).is_some() || cpu.peripherals.handles(opcode) => 0,
+        "0NNN" if cpu.sys.is_some() => 0,
+        "0NNN" if opcode != 0 => {
+            return Some(format!(
+                "SYS {:#05x} calls machine code, which only a COSMAC VIP runs",
+                opcode & 0x0FFF
+            ))
+        }
+        "unknown" | "0NNN" => return Some(format!("Unknown opcode {:04X}", opcode)),
+        "2NNN" if cpu.sp + 1 >= cpu.stack.len() => {
+            return Some(format!("Stack overflow, {} calls deep", cpu.sp))
+        }
+        "00EE" if cpu.sp == 0 => return Some(String::from("Return with an empty stack")),
+        "DXYN" => opcode as usize & 0x000F,
+        "FX33" => 3,
+        "FX55" | "FX65" => x + 1,
+        _ => 0,
+    };
+    match i + length > MEMORY_SIZE {
+        true => Some(format!(
+            "{} reaches past the end of memory from I={:#05x}",
+            disassemble(opcode),
+            i
+        )),
+        false => None,
+    }
+}
+
+// Write a dump into a new folder under dir, returning the folder
+pub fn write(
+    dir: &Path,
+    cpu: &Chip8,
+    fault: &str,
+    trace: &[TraceEntry],
+    symbols: &Symbols,
+) -> Result<PathBuf, String> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let folder = dir.join(format!("crash-{}", time));
+    let error = |e: std::io::Error| format!("Unable to write {}: {}", folder.display(), e);
+    std::fs::create_dir_all(&folder).map_err(error)?;
+    std::fs::write(
+        folder.join("report.txt"),
+        report(cpu, fault, trace, symbols),
+    )
+    .map_err(error)?;
+    std::fs::write(folder.join("memory.bin"), cpu.memory).map_err(error)?;
+    screenshot::save(cpu, &folder.join("screen.png")).map_err(error)?;
+    std::fs::write(folder.join("machine.state"), state::save(cpu)).map_err(error)?;
+    Ok(folder)
+}
+
+fn report(cpu: &Chip8, fault: &str, trace: &[TraceEntry], symbols: &Symbols) -> String {
+    let program = sha1_smol::Sha1::from(&cpu.program).digest().to_string();
+    let stack: Vec<String> = cpu.stack[1..=cpu.sp.min(cpu.stack.len() - 1)]
+        .iter()
+        .map(|entry| format!("{:#05x}", entry))
+        .collect();
+    let quirks = match cpu.quirks.to_string() {
+        quirks if quirks.is_empty() => String::from("none"),
+        quirks => quirks,
+    };
+    let mut lines = vec![
+        format!("Fault: {}", fault),
+        format!("ROM: {} bytes, SHA-1 {}", cpu.rom_size, program),
+        format!("Instructions executed: {}", cpu.instructions),
+        format!("Quirks: {}", quirks),
+        String::new(),
+        format_registers(cpu),
+        format!("Stack: [{}]", stack.join(", ")),
+        call_stack(cpu, symbols),
+        String::new(),
+        format!("Last {} instructions, oldest first:", trace.len()),
+    ];
+    lines.extend(trace.iter().map(TraceEntry::to_string));
+    lines.push(String::new());
+    lines.join("\n")
+}
@@ -0,0 +1,453 @@
+use crate::chip8::Chip8;
+use crate::debugger::Debugger;
+use crate::disasm::disassemble;
+use crate::symbols::Symbols;
+use log::{error, info};
+use serde_json::{json, Value};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+
+const NO_LINE_INFO: &str = "No line information for this ROM";
+
+// Debug Adapter Protocol server for editors, polled from the event loop
+// the same way as the gdb stub. Only one client is served at a time.
+pub struct DapServer {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: Vec<u8>,
+    seq: u64,
+    // Editor is waiting for a stopped event
+    running: bool,
+    stop_on_entry: bool,
+    instruction_breakpoints: Vec<usize>,
+    function_breakpoints: Vec<usize>,
+}
+
+impl DapServer {
+    pub fn bind(address: &str) -> std::io::Result<DapServer> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        info!("Waiting for a DAP client on {}", address);
+        Ok(DapServer {
+            listener,
+            client: None,
+            buffer: Vec::new(),
+            seq: 1,
+            running: false,
+            stop_on_entry: false,
+            instruction_breakpoints: Vec::new(),
+            function_breakpoints: Vec::new(),
+        })
+    }
+
+    pub fn poll(&mut self, debugger: &mut Debugger, cpu: &mut Chip8) {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        error!("DAP connection failed: {}", e);
+                        return;
+                    }
+                    info!("DAP client connected from {}", peer);
+                    self.client = Some(stream);
+                    self.buffer.clear();
+                    self.running = !debugger.is_paused();
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    error!("DAP accept failed: {}", e);
+                    return;
+                }
+            }
+        }
+        if !self.receive() {
+            info!("DAP client disconnected");
+            self.client = None;
+            return;
+        }
+        while let Some(message) = self.next_message() {
+            self.handle(&message, debugger, cpu);
+        }
+        if self.running && debugger.is_paused() {
+            self.running = false;
+            self.stopped("breakpoint");
+        }
+    }
+
+    fn receive(&mut self) -> bool {
+        let mut chunk = [0; 4096];
+        let client = match &mut self.client {
+            Some(client) => client,
+            None => return false,
+        };
+        loop {
+            match client.read(&mut chunk) {
+                Ok(0) => return false,
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return true,
+                Err(e) => {
+                    error!("DAP read failed: {}", e);
+                    return false;
+                }
+            }
+        }
+    }
+
+    // Messages are JSON bodies behind a Content-Length header
+    fn next_message(&mut self) -> Option<Value> {
+        let header_end = self
+            .buffer
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")?;
+        let header = String::from_utf8_lossy(&self.buffer[..header_end]).to_string();
+        let length = header
+            .lines()
+            .filter_map(|line| line.strip_prefix("Content-Length:"))
+            .find_map(|length| length.trim().parse::<usize>().ok());
+        let length = match length {
+            Some(length) => length,
+            None => {
+                error!("DAP header without Content-Length: {}", header);
+                self.buffer.drain(..header_end + 4);
+                return None;
+            }
+        };
+        let body_start = header_end + 4;
+        if self.buffer.len() < body_start + length {
+            return None;
+        }
+        let body: Vec<u8> = self.buffer.drain(..body_start + length).collect();
+        match serde_json::from_slice(&body[body_start..]) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                error!("Invalid DAP message: {}", e);
+                None
+            }
+        }
+    }
+
+    fn handle(&mut self, request: &Value, debugger: &mut Debugger, cpu: &mut Chip8) {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        let body = match command {
+            "initialize" => {
+                self.respond(
+                    request,
+                    Ok(json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsInstructionBreakpoints": true,
+                        "supportsFunctionBreakpoints": true,
+                        "supportsStepBack": true,
+                        "supportsReadMemoryRequest": true,
+                        "supportsDisassembleRequest": true,
+                    })),
+                );
+                self.event("initialized", json!({}));
+                return;
+            }
+            "launch" => {
+                self.stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+                match arguments["program"].as_str() {
+                    Some(path) => match std::fs::read(path) {
+                        Ok(bytes) => {
+                            *cpu = Chip8::new();
+                            cpu.load_font();
+                            cpu.load_program(&bytes);
+                            debugger
+                                .load_symbols(Symbols::find(Path::new(path)).unwrap_or_default());
+                            debugger.pause();
+                            Ok(json!({}))
+                        }
+                        Err(e) => Err(format!("Unable to read {}: {}", path, e)),
+                    },
+                    None => Err(String::from("launch needs a program path")),
+                }
+            }
+            "attach" => {
+                self.stop_on_entry = true;
+                debugger.pause();
+                Ok(json!({}))
+            }
+            "configurationDone" => {
+                self.respond(request, Ok(json!({})));
+                match self.stop_on_entry {
+                    true => self.stopped("entry"),
+                    false => {
+                        debugger.resume();
+                        self.running = true;
+                    }
+                }
+                return;
+            }
+            "setBreakpoints" => {
+                // Source lines need a symbol file to map onto addresses
+                let breakpoints: Vec<Value> = arguments["breakpoints"]
+                    .as_array()
+                    .map(|breakpoints| {
+                        breakpoints
+                            .iter()
+                            .map(|_| json!({"verified": false, "message": NO_LINE_INFO}))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                Ok(json!({ "breakpoints": breakpoints }))
+            }
+            "setInstructionBreakpoints" => {
+                for addr in self.instruction_breakpoints.drain(..) {
+                    debugger.remove_breakpoint(addr);
+                }
+                let mut breakpoints = Vec::new();
+                for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+                    let addr = breakpoint["instructionReference"]
+                        .as_str()
+                        .and_then(parse_reference)
+                        .map(|addr| addr as i64 + breakpoint["offset"].as_i64().unwrap_or(0));
+                    match addr {
+                        Some(addr) if addr >= 0 && (addr as usize) < cpu.memory.len() => {
+                            debugger.add_breakpoint(addr as usize);
+                            self.instruction_breakpoints.push(addr as usize);
+                            breakpoints.push(json!({
+                                "verified": true,
+                                "instructionReference": format!("{:#05x}", addr),
+                            }));
+                        }
+                        _ => breakpoints.push(json!({"verified": false})),
+                    }
+                }
+                Ok(json!({ "breakpoints": breakpoints }))
+            }
+            "setFunctionBreakpoints" => {
+                for addr in self.function_breakpoints.drain(..) {
+                    debugger.remove_breakpoint(addr);
+                }
+                let mut breakpoints = Vec::new();
+                for breakpoint in arguments["breakpoints"].as_array().into_iter().flatten() {
+                    let name = breakpoint["name"].as_str().unwrap_or_default();
+                    match debugger.symbols().addr_of(name) {
+                        Some(addr) => {
+                            debugger.add_breakpoint(addr);
+                            self.function_breakpoints.push(addr);
+                            breakpoints.push(json!({
+                                "verified": true,
+                                "instructionReference": format!("{:#05x}", addr),
+                            }));
+                        }
+                        None => breakpoints.push(json!({
+                            "verified": false,
+                            "message": format!("No symbol named {}", name),
+                        })),
+                    }
+                }
+                Ok(json!({ "breakpoints": breakpoints }))
+            }
+            "threads" => Ok(json!({"threads": [{"id": 1, "name": "Chip8"}]})),
+            "stackTrace" => {
+                let symbols = debugger.symbols();
+                let mut frames = vec![frame(cpu, symbols, 0, cpu.pc)];
+                for depth in (1..=cpu.sp.min(cpu.stack.len() - 1)).rev() {
+                    let call_site = cpu.stack[depth].wrapping_sub(2);
+                    if call_site < cpu.memory.len() - 1 {
+                        frames.push(frame(cpu, symbols, frames.len(), call_site));
+                    }
+                }
+                Ok(json!({"stackFrames": frames, "totalFrames": frames.len()}))
+            }
+            "scopes" => Ok(json!({"scopes": [
+                {"name": "Registers", "variablesReference": 1, "expensive": false}
+            ]})),
+            "variables" => {
+                let mut variables: Vec<Value> = cpu
+                    .registers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| variable(&format!("V{:X}", i), format!("{:#04x}", value)))
+                    .collect();
+                variables.push(variable("I", format!("{:#05x}", cpu.address_reg)));
+                variables.push(variable("PC", format!("{:#05x}", cpu.pc)));
+                variables.push(variable("SP", cpu.sp.to_string()));
+                variables.push(variable("DT", cpu.delay_timer.to_string()));
+                variables.push(variable("ST", cpu.sound_timer.to_string()));
+                Ok(json!({ "variables": variables }))
+            }
+            "continue" => {
+                debugger.resume();
+                self.running = true;
+                Ok(json!({"allThreadsContinued": true}))
+            }
+            "next" | "stepIn" | "stepOut" => {
+                debugger.pause();
+                debugger.step(cpu);
+                self.respond(request, Ok(json!({})));
+                self.stopped("step");
+                return;
+            }
+            "stepBack" => {
+                debugger.pause();
+                debugger.step_back(cpu);
+                self.respond(request, Ok(json!({})));
+                self.stopped("step");
+                return;
+            }
+            "pause" => {
+                debugger.pause();
+                self.running = false;
+                self.respond(request, Ok(json!({})));
+                self.stopped("pause");
+                return;
+            }
+            "readMemory" => {
+                let start = arguments["memoryReference"]
+                    .as_str()
+                    .and_then(parse_reference)
+                    .map(|addr| addr as i64 + arguments["offset"].as_i64().unwrap_or(0));
+                let count = arguments["count"].as_u64().unwrap_or(0) as usize;
+                match start {
+                    Some(start) if start >= 0 && (start as usize) < cpu.memory.len() => {
+                        let start = start as usize;
+                        let end = (start + count).min(cpu.memory.len());
+                        Ok(json!({
+                            "address": format!("{:#05x}", start),
+                            "data": encode_base64(&cpu.memory[start..end]),
+                            "unreadableBytes": count - (end - start),
+                        }))
+                    }
+                    _ => Err(String::from("Invalid memory reference")),
+                }
+            }
+            "disassemble" => {
+                let start = arguments["memoryReference"]
+                    .as_str()
+                    .and_then(parse_reference)
+                    .map(|addr| {
+                        addr as i64
+                            + arguments["offset"].as_i64().unwrap_or(0)
+                            + arguments["instructionOffset"].as_i64().unwrap_or(0) * 2
+                    });
+                let count = arguments["instructionCount"].as_u64().unwrap_or(0) as i64;
+                match start {
+                    Some(start) => {
+                        let instructions: Vec<Value> = (0..count)
+                            .map(|i| start + i * 2)
+                            .map(
+                                |addr| match addr >= 0 && (addr as usize) < cpu.memory.len() - 1 {
+                                    true => {
+                                        let opcode = cpu.opcode_at(addr as usize);
+                                        json!({
+                                            "address": format!("{:#05x}", addr),
+                                            "instructionBytes": format!("{:04x}", opcode),
+                                            "instruction": disassemble(opcode),
+                                        })
+                                    }
+                                    false => json!({
+                                        "address": format!("{:#x}", addr),
+                                        "instruction": "??",
+                                        "presentationHint": "invalid",
+                                    }),
+                                },
+                            )
+                            .collect();
+                        Ok(json!({ "instructions": instructions }))
+                    }
+                    None => Err(String::from("Invalid memory reference")),
+                }
+            }
+            "disconnect" => {
+                debugger.resume();
+                self.respond(request, Ok(json!({})));
+                self.client = None;
+                info!("DAP client disconnected");
+                return;
+            }
+            _ => Err(format!("Unsupported request: {}", command)),
+        };
+        self.respond(request, body);
+    }
+
+    fn stopped(&mut self, reason: &str) {
+        self.event(
+            "stopped",
+            json!({"reason": reason, "threadId": 1, "allThreadsStopped": true}),
+        );
+    }
+
+    fn respond(&mut self, request: &Value, body: Result<Value, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+        });
+        match body {
+            Ok(body) => {
+                response["success"] = json!(true);
+                response["body"] = body;
+            }
+            Err(message) => {
+                response["success"] = json!(false);
+                response["message"] = json!(message);
+            }
+        }
+        self.send(response);
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.send(json!({"type": "event", "event": event, "body": body}));
+    }
+
+    fn send(&mut self, mut message: Value) {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        let body = message.to_string();
+        let packet = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        if let Some(client) = &mut self.client {
+            if let Err(e) = client.write_all(packet.as_bytes()) {
+                error!("DAP write failed: {}", e);
+                self.client = None;
+            }
+        }
+    }
+}
+
+// Frames are named after the enclosing label when symbols are loaded
+fn frame(cpu: &Chip8, symbols: &Symbols, id: usize, addr: usize) -> Value {
+    let instruction = disassemble(cpu.opcode_at(addr));
+    let name = match symbols.describe(addr) {
+        Some(label) => format!("{}: {}", label, instruction),
+        None => instruction,
+    };
+    json!({
+        "id": id,
+        "name": name,
+        "line": 0,
+        "column": 0,
+        "instructionPointerReference": format!("{:#05x}", addr),
+    })
+}
+
+fn variable(name: &str, value: String) -> Value {
+    json!({"name": name, "value": value, "variablesReference": 0})
+}
+
+// Memory and instruction references are hex addresses
+fn parse_reference(reference: &str) -> Option<usize> {
+    usize::from_str_radix(reference.trim_start_matches("0x"), 16).ok()
+}
+
+fn encode_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let group = match chunk.len() {
+            1 => (chunk[0] as u32) << 16,
+            2 => (chunk[0] as u32) << 16 | (chunk[1] as u32) << 8,
+            _ => (chunk[0] as u32) << 16 | (chunk[1] as u32) << 8 | chunk[2] as u32,
+        };
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(group >> (18 - i * 6) & 0x3F) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
@@ -0,0 +1,128 @@
+use serde_json::Value;
+use std::path::Path;
+
+// QWERTY key for each Chip8 key, following the mapping in main
+const KEY_LABELS: [&str; 16] = [
+    "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+];
+
+// Metadata for a ROM from the community CHIP-8 database
+pub struct ProgramInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    pub release: Option<String>,
+    pub description: Option<String>,
+    pub colors: Option<([u8; 4], [u8; 4])>, // Foreground, background
+    pub keys: Vec<(String, u8)>,            // Action, Chip8 key
+}
+
+impl ProgramInfo {
+    // Title with its authors and year
+    pub fn full_title(&self) -> String {
+        let mut title = self.title.clone();
+        let credits: Vec<String> = [
+            Some(self.authors.join(", ")).filter(|authors| !authors.is_empty()),
+            self.release.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !credits.is_empty() {
+            title.push_str(&format!(" ({})", credits.join(", ")));
+        }
+        title
+    }
+
+    pub fn summary(&self) -> String {
+        let mut lines = vec![self.full_title()];
+        if let Some(description) = &self.description {
+            lines.push(description.clone());
+        }
+        if !self.keys.is_empty() {
+            let keys: Vec<String> = self
+                .keys
+                .iter()
+                .map(|(action, key)| format!("{} {}", action, KEY_LABELS[*key as usize]))
+                .collect();
+            lines.push(format!("Keys: {}", keys.join(", ")));
+        }
+        lines.join("\n")
+    }
+}
+
+/*    Database Layout
+ * sha1-hashes.json   {"<sha1 of rom>": <index into programs.json>, ...}
+ * programs.json      [{"title", "authors", "release", "description",
+ *                      "roms": {"<sha1>": {"colors": {"pixels": [..]},
+ *                                          "keys": {"up": 5, ..}}}}, ..]
+ *
+ * Missing databases and unknown ROMs are not errors.
+ */
+pub fn lookup(dir: &Path, rom: &[u8]) -> Result<Option<ProgramInfo>, String> {
+    let hashes_path = dir.join("sha1-hashes.json");
+    if !hashes_path.is_file() {
+        return Ok(None);
+    }
+    let hash = sha1_smol::Sha1::from(rom).digest().to_string();
+    let index = match read_json(&hashes_path)?[&hash].as_u64() {
+        Some(index) => index as usize,
+        None => return Ok(None),
+    };
+    let programs = read_json(&dir.join("programs.json"))?;
+    let program = match programs.get(index) {
+        Some(program) => program,
+        None => return Err(format!("programs.json has no entry {}", index)),
+    };
+    let rom_entry = &program["roms"][&hash];
+
+    let text = |value: &Value| value.as_str().map(String::from);
+    let colors =
+        rom_entry["colors"]["pixels"]
+            .as_array()
+            .and_then(|pixels| match pixels.as_slice() {
+                [background, foreground, ..] => Some((
+                    parse_color(foreground.as_str()?)?,
+                    parse_color(background.as_str()?)?,
+                )),
+                _ => None,
+            });
+    let keys = rom_entry["keys"]
+        .as_object()
+        .map(|keys| {
+            keys.iter()
+                .filter_map(|(action, key)| match key.as_u64() {
+                    Some(key) if key < 16 => Some((action.clone(), key as u8)),
+                    _ => None,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(Some(ProgramInfo {
+        title: text(&program["title"]).unwrap_or_else(|| String::from("Unknown")),
+        authors: program["authors"]
+            .as_array()
+            .map(|authors| authors.iter().filter_map(text).collect())
+            .unwrap_or_default(),
+        release: text(&program["release"]),
+        description: text(&program["description"]),
+        colors,
+        keys,
+    }))
+}
+
+fn read_json(path: &Path) -> Result<Value, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// #rrggbb to RGBA
+pub fn parse_color(text: &str) -> Option<[u8; 4]> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Some([r, g, b, 0xFF])
+}
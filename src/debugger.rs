@@ -0,0 +1,1113 @@
+use crate::achievements::Achievements;
+use crate::cheats::{self, Cheats};
+use crate::chip8::{Chip8, MEMORY_SIZE};
+use crate::coverage::Coverage;
+use crate::crash;
+use crate::diff;
+use crate::disasm::{disassemble, OpcodePattern};
+use crate::draws::DrawLog;
+use crate::explain;
+use crate::graph::{Graphs, Source};
+use crate::mix::Mix;
+use crate::octo;
+use crate::profiler::Profiler;
+use crate::reference;
+use crate::rewind::Rewind;
+use crate::search::{Filter, RamSearch};
+use crate::symbols::Symbols;
+use crate::trace::{TraceEntry, Tracer};
+use std::collections::{BTreeSet, VecDeque};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// Number of executed instructions that can be stepped back through
+const REWIND_DEPTH: usize = 1000;
+// Number of executed addresses highlighted in the disassembly
+const RECENT_DEPTH: usize = 16;
+// Instructions shown either side of the centre of the disassembly
+const DISASM_CONTEXT: usize = 8;
+// Bytes shown by the memory view when no length is given
+const MEMORY_VIEW_LEN: usize = 64;
+// Trace entries kept in memory
+const TRACE_DEPTH: usize = 4096;
+// Trace entries shown when no count is given
+const TRACE_VIEW_LEN: usize = 20;
+// Frames of draws kept, a second's worth
+const DRAW_DEPTH: usize = 60;
+// Frames of samples kept for each graph, five minutes' worth
+const GRAPH_DEPTH: usize = 18000;
+
+// Memory view colours for the font, the loaded ROM and everything else
+const FONT_COLOR: &str = "\x1b[36m";
+const ROM_COLOR: &str = "\x1b[32m";
+const SCRATCH_COLOR: &str = "\x1b[90m";
+const RESET_COLOR: &str = "\x1b[0m";
+
+const HELP: &str = "\
+Commands:
+  p, pause            Pause execution
+  c, continue         Resume execution
+  s, step [n]         Execute n instructions (default 1)
+  teach on|off        Explain each step in plain English
+  sb, back [n]        Step backwards n instructions (default 1)
+  f, frame [n]        Run n frames of instructions and timer ticks (default 1)
+  bp, break <addr>    Set a breakpoint (addresses may also be symbol names)
+  delete <addr>       Remove a breakpoint
+  bp op [pattern]     Break before opcodes like DXYN, 8X_6 or SHR, or list them
+  delete op <pattern> Stop breaking on an opcode pattern
+  r, reg, regs        Show registers
+  d, disasm [addr]    Disassemble around addr (default PC)
+  syntax octo|cowgod  Choose the disassembly syntax
+  ref [text]          Look up opcodes by pattern, name or what they do
+  m, mem [addr|i] [n] Hex dump n bytes from addr or I
+  follow              Toggle showing memory at I when paused
+  poke <addr> <b>..   Write bytes to memory (while paused)
+  set <reg> <value>   Set V0-VF, I, PC, SP, DT or ST (while paused)
+  sprite [addr|i] [n] Draw n bytes (1-f) from addr or I as 8xN and 16x16 sprites
+  bt [watch]          Show the call stack, or toggle printing it on every call
+  trace [n]           Show the last n traced instructions
+  trace on|off        Start or stop tracing into memory
+  trace file <path>   Also write traced instructions to a file
+  trace range <a> <b> Only trace instructions between a and b (or 'off')
+  profile [heat]      Show hotspots, or the heat coloured disassembly
+  profile on|off      Start or stop counting executed instructions
+  profile reset       Clear the profile counters
+  draws [n]           Show the sprites drawn in the last n frames (default 1)
+  draws clear         Forget the draws recorded so far
+  mix [reset]         Show or clear what the instructions run so far were
+  mix save <path>     Write the instruction mix to a file
+  cov [map|disasm]    Show the coverage summary, byte map or coloured disassembly
+  cov on|off|reset    Start, stop or clear coverage tracking
+  cov save <path>     Write the coverage summary and map to a file
+  sym                 List the loaded symbols
+  sym load <path>     Load an Octo-style .sym or JSON symbol file
+  cheat               List cheats with their numbers
+  cheat <n>           Toggle a cheat on or off
+  cheat freeze|once <addr> <value> [name]
+                      Add a cheat, frozen every frame or written once
+  cheat load|save <path>
+                      Read or write a cheats file
+  search start        Snapshot memory to search for a value such as lives
+  search eq <value>   Keep addresses now holding value
+  search changed|unchanged|inc|dec
+                      Keep addresses that changed that way since the last search
+  search              Show the remaining addresses
+  diff mark           Remember the machine as it is now to diff against
+  diff [path]         Show what changed since the mark, or since a save state
+  graph add <addr|reg> [name]
+                      Sample memory or V0-VF, I, DT or ST every frame
+  graph remove <name> Stop graphing a value
+  graph [clear]       Plot the values over time, or forget them all
+  graph save <path>   Write the samples to a CSV file
+  ach                 List achievements and which are unlocked";
+
+pub struct Debugger {
+    paused: bool,
+    breakpoints: BTreeSet<usize>,
+    opcode_breaks: Vec<OpcodePattern>,
+    rewind: Rewind,
+    recent: VecDeque<usize>,
+    follow_i: bool,
+    octo_syntax: bool,
+    watch_stack: bool,
+    // Steps explain what they ran, see explain.rs
+    teaching: bool,
+    tracing: bool,
+    tracer: Tracer,
+    profiling: bool,
+    profiler: Profiler,
+    covering: bool,
+    coverage: Coverage,
+    draws: DrawLog,
+    // Kept across ROMs
+    mix: Mix,
+    symbols: Symbols,
+    cheats: Cheats,
+    achievements: Achievements,
+    // Titles unlocked since the window last asked
+    unlocked: Vec<String>,
+    search: RamSearch,
+    diff_mark: Option<Box<Chip8>>,
+    graphs: Graphs,
+    console: Option<Receiver<String>>,
+    // Instruction count of the last fault dumped, so resuming into the same
+    // one doesn't write another
+    crashed_at: Option<u64>,
+    crash: Option<PathBuf>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        // Commands are typed into the terminal, the window only shows the display
+        let (sender, console) = mpsc::channel();
+        thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+        Debugger {
+            console: Some(console),
+            ..Debugger::without_console()
+        }
+    }
+
+    // For frontends that own the terminal, commands then only come through
+    // execute
+    pub fn without_console() -> Debugger {
+        Debugger {
+            paused: false,
+            breakpoints: BTreeSet::new(),
+            opcode_breaks: Vec::new(),
+            rewind: Rewind::new(REWIND_DEPTH),
+            recent: VecDeque::with_capacity(RECENT_DEPTH),
+            follow_i: false,
+            octo_syntax: false,
+            watch_stack: false,
+            teaching: false,
+            tracing: false,
+            tracer: Tracer::new(TRACE_DEPTH),
+            profiling: false,
+            profiler: Profiler::new(MEMORY_SIZE),
+            covering: false,
+            coverage: Coverage::new(MEMORY_SIZE),
+            draws: DrawLog::new(DRAW_DEPTH),
+            mix: Mix::default(),
+            symbols: Symbols::new(),
+            cheats: Cheats::new(),
+            achievements: Achievements::new(),
+            unlocked: Vec::new(),
+            search: RamSearch::new(),
+            diff_mark: None,
+            graphs: Graphs::new(GRAPH_DEPTH),
+            console: None,
+            crashed_at: None,
+            crash: None,
+        }
+    }
+
+    // Forget everything about the last ROM, keeping the console and the
+    // instruction mix
+    pub fn reset(&mut self) {
+        let console = self.console.take();
+        let mix = std::mem::take(&mut self.mix);
+        *self = Debugger {
+            console,
+            mix,
+            ..Debugger::without_console()
+        };
+    }
+
+    // Write the instruction mix to path when the emulator exits
+    pub fn save_mix_on_exit(&mut self, path: &Path) {
+        self.mix.save_on_exit(path);
+    }
+
+    // For exits that skip dropping the debugger
+    pub fn save_mix(&self) {
+        self.mix.save();
+    }
+
+    // Run any commands typed since the last poll
+    pub fn poll(&mut self, cpu: &mut Chip8) {
+        let lines: Vec<String> = match &self.console {
+            Some(console) => console.try_iter().collect(),
+            None => Vec::new(),
+        };
+        for line in lines {
+            let output = self.execute(cpu, &line);
+            if !output.is_empty() {
+                println!("{}", output);
+            }
+        }
+    }
+
+    // Execute one instruction unless paused, stopping on breakpoints
+    pub fn tick(&mut self, cpu: &mut Chip8) {
+        if self.is_paused() {
+            return;
+        }
+        if let Some(fault) = crash::fault(cpu) {
+            self.paused = true;
+            if self.crashed_at != Some(cpu.instructions) {
+                self.crashed_at = Some(cpu.instructions);
+                self.dump(cpu, &fault);
+            }
+            return;
+        }
+        let depth = cpu.sp;
+        self.step(cpu);
+        if self.watch_stack && cpu.sp != depth {
+            println!("{}", call_stack(cpu, &self.symbols));
+        }
+        if cpu.instructions.is_multiple_of(cpu.speed as u64) {
+            self.check_achievements(cpu);
+        }
+        if self.breakpoints.contains(&cpu.pc) {
+            self.paused = true;
+            println!("Breakpoint at {}", self.label(cpu.pc));
+            println!("{}", self.status(cpu));
+        } else if let Some(pattern) = self.opcode_break(cpu) {
+            self.paused = true;
+            println!("Break on {} at {}", pattern, self.label(cpu.pc));
+            println!("{}", self.status(cpu));
+        }
+    }
+
+    // The end of a 60 Hz frame, with the timers: the cheats written for the
+    // next one
+    pub fn end_frame(&mut self, cpu: &mut Chip8) {
+        self.cheats.apply(cpu);
+    }
+
+    // Write a crash dump to the current folder for a bug report
+    fn dump(&mut self, cpu: &Chip8, fault: &str) {
+        let snapshots: Vec<&Chip8> = self
+            .rewind
+            .last(crash::TRACE_DEPTH)
+            .chain(std::iter::once(cpu))
+            .collect();
+        let trace: Vec<TraceEntry> = snapshots
+            .windows(2)
+            .map(|pair| TraceEntry::new(pair[0], pair[1]))
+            .collect();
+        println!("{} at {:#05x}", fault, cpu.pc);
+        match crash::write(Path::new("."), cpu, fault, &trace, &self.symbols) {
+            Ok(folder) => {
+                println!("Crash dump written to {}", folder.display());
+                self.crash = Some(folder);
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+
+    // The folder of a crash dump written since the last call
+    pub fn take_crash(&mut self) -> Option<PathBuf> {
+        self.crash.take()
+    }
+
+    fn check_achievements(&mut self, cpu: &Chip8) {
+        for title in self.achievements.check(cpu) {
+            println!("Achievement unlocked: {}", title);
+            self.unlocked.push(title);
+        }
+    }
+
+    // Titles of the achievements unlocked since the last call
+    pub fn take_unlocked(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.unlocked)
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn is_tracing(&self) -> bool {
+        self.tracing
+    }
+
+    pub fn add_breakpoint(&mut self, addr: usize) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: usize) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    // The first opcode pattern the next instruction matches
+    fn opcode_break(&self, cpu: &Chip8) -> Option<String> {
+        if self.opcode_breaks.is_empty() || cpu.pc + 1 >= cpu.memory.len() {
+            return None;
+        }
+        let opcode = cpu.opcode_at(cpu.pc);
+        self.opcode_breaks
+            .iter()
+            .find(|pattern| pattern.matches(opcode))
+            .map(|pattern| pattern.text.clone())
+    }
+
+    pub fn load_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn load_cheats(&mut self, cheats: Cheats) {
+        self.cheats = cheats;
+    }
+
+    pub fn load_achievements(&mut self, achievements: Achievements) {
+        self.achievements = achievements;
+    }
+
+    pub fn step(&mut self, cpu: &mut Chip8) {
+        // The core would panic or spin, see status for why
+        if crash::fault(cpu).is_some() {
+            self.paused = true;
+            return;
+        }
+        self.rewind.record(cpu);
+        if self.recent.len() == RECENT_DEPTH {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(cpu.pc);
+        if self.profiling {
+            self.profiler.record(cpu.pc, cpu.opcode_at(cpu.pc));
+        }
+        self.mix.record(cpu.opcode_at(cpu.pc));
+        cpu.tick();
+        if cpu.instructions.is_multiple_of(cpu.speed as u64) && !self.graphs.is_empty() {
+            self.graphs.record(cpu);
+        }
+        if let Some(before) = self.rewind.latest() {
+            if self.tracing {
+                self.tracer.record(before, cpu);
+            }
+            if self.covering {
+                self.coverage.record(before, cpu);
+            }
+            if before.opcode_at(before.pc) & 0xF000 == 0xD000 {
+                self.draws.record(before, cpu);
+            }
+        }
+    }
+
+    // count instructions back to back, as one batch on a --timeline
+    pub fn run(&mut self, cpu: &mut Chip8, count: usize) {
+        let _batch = cpu.batch_span(count).entered();
+        for _ in 0..count {
+            self.tick(cpu);
+        }
+    }
+
+    // One 60 Hz frame while paused: speed instructions, then a tick of each
+    // timer
+    pub fn step_frame(&mut self, cpu: &mut Chip8) {
+        self.paused = true;
+        for _ in 0..cpu.speed {
+            self.step(cpu);
+        }
+        cpu.decrement_timers();
+        self.end_frame(cpu);
+        self.check_achievements(cpu);
+    }
+
+    // Restore the state from before the last executed instruction
+    pub fn step_back(&mut self, cpu: &mut Chip8) -> bool {
+        match self.rewind.step_back() {
+            Some(snapshot) => {
+                *cpu = snapshot;
+                cpu.redraw = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn execute(&mut self, cpu: &mut Chip8, line: &str) -> String {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        match args.as_slice() {
+            [] => String::new(),
+            ["h"] | ["help"] => HELP.to_string(),
+            ["p"] | ["pause"] => {
+                self.pause();
+                self.status(cpu)
+            }
+            ["c"] | ["continue"] => {
+                self.resume();
+                String::from("Running")
+            }
+            ["s"] | ["step"] => self.execute(cpu, "step 1"),
+            ["s", count] | ["step", count] => match count.parse::<usize>() {
+                Ok(count) => {
+                    self.paused = true;
+                    let mut lessons = Vec::new();
+                    for _ in 0..count {
+                        let instructions = cpu.instructions;
+                        self.step(cpu);
+                        if let (true, Some(before)) = (
+                            self.teaching && cpu.instructions != instructions,
+                            self.rewind.latest(),
+                        ) {
+                            lessons.push(explain::explain(before, cpu));
+                        }
+                    }
+                    lessons.push(self.status(cpu));
+                    lessons.join("\n\n")
+                }
+                Err(_) => format!("Invalid count: {}", count),
+            },
+            ["f"] | ["frame"] => self.execute(cpu, "frame 1"),
+            ["f", count] | ["frame", count] => match count.parse::<usize>() {
+                Ok(count) => {
+                    for _ in 0..count {
+                        self.step_frame(cpu);
+                    }
+                    self.status(cpu)
+                }
+                Err(_) => format!("Invalid count: {}", count),
+            },
+            ["teach", "on"] => {
+                self.teaching = true;
+                String::from("Teaching on, each step explains what it ran")
+            }
+            ["teach", "off"] => {
+                self.teaching = false;
+                String::from("Teaching off")
+            }
+            ["sb"] | ["back"] => self.execute(cpu, "back 1"),
+            ["sb", count] | ["back", count] => match count.parse::<usize>() {
+                Ok(count) => {
+                    self.paused = true;
+                    for _ in 0..count {
+                        if !self.step_back(cpu) {
+                            return format!(
+                                "No earlier instructions recorded\n{}",
+                                self.status(cpu)
+                            );
+                        }
+                    }
+                    format!(
+                        "{}\n({} instructions left to step back)",
+                        self.status(cpu),
+                        self.rewind.len()
+                    )
+                }
+                Err(_) => format!("Invalid count: {}", count),
+            },
+            ["bp", "op"] | ["break", "op"] => match self.opcode_breaks.is_empty() {
+                true => String::from("No opcode breakpoints"),
+                false => {
+                    let patterns: Vec<&str> = self
+                        .opcode_breaks
+                        .iter()
+                        .map(|pattern| pattern.text.as_str())
+                        .collect();
+                    format!("Breaking on {}", patterns.join(", "))
+                }
+            },
+            ["bp", "op", text] | ["break", "op", text] => match OpcodePattern::parse(text) {
+                Some(pattern) if self.opcode_breaks.contains(&pattern) => {
+                    format!("Already breaking on {}", pattern.text)
+                }
+                Some(pattern) => {
+                    let reply = format!("Breaking before any {}", pattern.text);
+                    self.opcode_breaks.push(pattern);
+                    reply
+                }
+                None => format!("Invalid opcode pattern: {} (try DXYN or 8X_6)", text),
+            },
+            ["delete", "op", text] => {
+                let text = text.to_ascii_uppercase();
+                let len = self.opcode_breaks.len();
+                self.opcode_breaks.retain(|pattern| pattern.text != text);
+                match self.opcode_breaks.len() == len {
+                    true => format!("Not breaking on {}", text),
+                    false => format!("Stopped breaking on {}", text),
+                }
+            }
+            ["bp", addr] | ["break", addr] => match self.address(addr) {
+                Some(addr) => {
+                    self.add_breakpoint(addr);
+                    format!("Breakpoint set at {}", self.label(addr))
+                }
+                None => format!("Invalid address: {}", addr),
+            },
+            ["delete", addr] => match self.address(addr) {
+                Some(addr) if self.remove_breakpoint(addr) => {
+                    format!("Breakpoint removed at {}", self.label(addr))
+                }
+                Some(addr) => format!("No breakpoint at {:#05x}", addr),
+                None => format!("Invalid address: {}", addr),
+            },
+            ["r"] | ["reg"] | ["regs"] => format_registers(cpu),
+            ["d"] | ["disasm"] => self.disassembly(cpu, cpu.pc),
+            ["d", addr] | ["disasm", addr] => match self.address(addr) {
+                Some(addr) if addr < cpu.memory.len() => self.disassembly(cpu, addr),
+                _ => format!("Invalid address: {}", addr),
+            },
+            ["m"] | ["mem"] => self.execute(cpu, "mem i"),
+            ["m", addr] | ["mem", addr] => {
+                self.execute(cpu, &format!("mem {} {:x}", addr, MEMORY_VIEW_LEN))
+            }
+            ["m", addr, len] | ["mem", addr, len] => {
+                let start = match *addr {
+                    "i" | "I" => Some(cpu.address_reg as usize),
+                    _ => self.address(addr),
+                };
+                match (start, parse_addr(len)) {
+                    (Some(start), Some(len)) if start < cpu.memory.len() => {
+                        hexdump(cpu, start, len)
+                    }
+                    (Some(_), Some(_)) | (None, _) => format!("Invalid address: {}", addr),
+                    (_, None) => format!("Invalid length: {}", len),
+                }
+            }
+            ["syntax", "octo"] => {
+                self.octo_syntax = true;
+                String::from("Disassembling as Octo")
+            }
+            ["syntax", "cowgod"] => {
+                self.octo_syntax = false;
+                String::from("Disassembling with Cowgod mnemonics")
+            }
+            ["ref", query @ ..] => {
+                let lines: Vec<String> = reference::search(&query.join(" "))
+                    .iter()
+                    .map(|entry| entry.describe(&cpu.quirks))
+                    .collect();
+                match lines.is_empty() {
+                    true => String::from("No opcodes match"),
+                    false => lines.join("\n"),
+                }
+            }
+            ["follow"] => {
+                self.follow_i = !self.follow_i;
+                match self.follow_i {
+                    true => String::from("Memory view follows I"),
+                    false => String::from("Memory view hidden"),
+                }
+            }
+            ["poke", addr, values @ ..] if !values.is_empty() => {
+                if !self.paused {
+                    return String::from("Pause before editing memory");
+                }
+                let addr = match self.address(addr) {
+                    Some(addr) if addr.saturating_add(values.len()) <= cpu.memory.len() => addr,
+                    _ => return format!("Invalid address: {}", addr),
+                };
+                let mut bytes = Vec::new();
+                for value in values {
+                    match u8::from_str_radix(value.trim_start_matches("0x"), 16) {
+                        Ok(byte) => bytes.push(byte),
+                        Err(_) => return format!("Invalid byte: {}", value),
+                    }
+                }
+                cpu.memory[addr..addr + bytes.len()].copy_from_slice(&bytes);
+                hexdump(cpu, addr, bytes.len())
+            }
+            ["set", register, value] => {
+                if !self.paused {
+                    return String::from("Pause before editing registers");
+                }
+                match set_register(cpu, register, value) {
+                    Ok(()) => format_registers(cpu),
+                    Err(e) => e,
+                }
+            }
+            ["sprite"] => self.execute(cpu, "sprite i"),
+            ["sprite", addr] => self.execute(cpu, &format!("sprite {} f", addr)),
+            ["sprite", addr, rows] => {
+                let start = match *addr {
+                    "i" | "I" => Some(cpu.address_reg as usize),
+                    _ => self.address(addr),
+                };
+                // As many rows as DXYN draws
+                let rows = parse_addr(rows)
+                    .filter(|rows| (1..=15).contains(rows))
+                    .ok_or(rows);
+                match (start, rows) {
+                    (Some(start), Ok(rows)) if start < cpu.memory.len() => format!(
+                        "8x{} at {:#05x}\n{}\n16x16 at {:#05x}\n{}",
+                        rows,
+                        start,
+                        sprite(cpu, start, rows, 1),
+                        start,
+                        sprite(cpu, start, 16, 2)
+                    ),
+                    (Some(_), Ok(_)) | (None, _) => format!("Invalid address: {}", addr),
+                    (_, Err(rows)) => format!("Invalid row count: {}, 1 to f", rows),
+                }
+            }
+            ["bt"] => call_stack(cpu, &self.symbols),
+            ["bt", "watch"] => {
+                self.watch_stack = !self.watch_stack;
+                match self.watch_stack {
+                    true => String::from("Printing the call stack on every call and return"),
+                    false => String::from("Stopped printing the call stack"),
+                }
+            }
+            ["trace"] => self.execute(cpu, &format!("trace {}", TRACE_VIEW_LEN)),
+            ["trace", "on"] => {
+                self.tracing = true;
+                String::from("Tracing")
+            }
+            ["trace", "off"] => {
+                self.tracing = false;
+                self.tracer.close_file();
+                String::from("Tracing stopped")
+            }
+            ["trace", "file", path] => match self.tracer.open_file(path) {
+                Ok(()) => {
+                    self.tracing = true;
+                    format!("Tracing to {}", path)
+                }
+                Err(e) => format!("Unable to open {}: {}", path, e),
+            },
+            ["trace", "range", "off"] => {
+                self.tracer.set_range(None);
+                String::from("Tracing all addresses")
+            }
+            ["trace", "range", start, end] => match (self.address(start), self.address(end)) {
+                (Some(start), Some(end)) if start <= end => {
+                    self.tracer.set_range(Some((start, end)));
+                    format!("Tracing {:#05x} to {:#05x}", start, end)
+                }
+                _ => format!("Invalid range: {} {}", start, end),
+            },
+            ["trace", count] => match count.parse::<usize>() {
+                Ok(count) => {
+                    let lines: Vec<String> = self
+                        .tracer
+                        .last(count)
+                        .map(|entry| entry.to_string())
+                        .collect();
+                    match lines.is_empty() {
+                        true => String::from("Trace is empty (trace on to start)"),
+                        false => lines.join("\n"),
+                    }
+                }
+                Err(_) => format!("Invalid count: {}", count),
+            },
+            ["profile"] => self.profiler.report(cpu),
+            ["profile", "heat"] => self.profiler.heatmap(cpu),
+            ["profile", "on"] => {
+                self.profiling = true;
+                String::from("Profiling")
+            }
+            ["profile", "off"] => {
+                self.profiling = false;
+                String::from("Profiling stopped")
+            }
+            ["profile", "reset"] => {
+                self.profiler.reset();
+                String::from("Profile cleared")
+            }
+            ["draws"] => self.draws.report(1),
+            ["draws", "clear"] => {
+                self.draws.clear();
+                String::from("Draws cleared")
+            }
+            ["draws", count] => match count.parse::<usize>() {
+                Ok(count) => self.draws.report(count),
+                Err(_) => format!("Invalid count: {}", count),
+            },
+            ["mix"] => self.mix.report(),
+            ["mix", "reset"] => {
+                self.mix.reset();
+                String::from("Instruction mix cleared")
+            }
+            ["mix", "save", path] => match std::fs::write(path, self.mix.report() + "\n") {
+                Ok(()) => format!("Instruction mix written to {}", path),
+                Err(e) => format!("Unable to write {}: {}", path, e),
+            },
+            ["cov"] => self.coverage.report(cpu),
+            ["cov", "map"] => self.coverage.map(cpu),
+            ["cov", "disasm"] => self.coverage.disassembly(cpu),
+            ["cov", "on"] => {
+                self.covering = true;
+                String::from("Tracking coverage")
+            }
+            ["cov", "off"] => {
+                self.covering = false;
+                String::from("Coverage tracking stopped")
+            }
+            ["cov", "reset"] => {
+                self.coverage.reset();
+                String::from("Coverage cleared")
+            }
+            ["cov", "save", path] => {
+                let contents = format!(
+                    "{}\n\n{}\n",
+                    self.coverage.report(cpu),
+                    self.coverage.map(cpu)
+                );
+                match std::fs::write(path, contents) {
+                    Ok(()) => format!("Coverage written to {}", path),
+                    Err(e) => format!("Unable to write {}: {}", path, e),
+                }
+            }
+            ["sym"] => match self.symbols.is_empty() {
+                true => String::from("No symbols loaded"),
+                false => self
+                    .symbols
+                    .iter()
+                    .map(|(addr, name)| format!("{:#05x}  {}", addr, name))
+                    .collect::<Vec<String>>()
+                    .join("\n"),
+            },
+            ["sym", "load", path] => match Symbols::load(Path::new(path)) {
+                Ok(symbols) => {
+                    let count = symbols.len();
+                    self.symbols = symbols;
+                    format!("Loaded {} symbols from {}", count, path)
+                }
+                Err(e) => e,
+            },
+            ["ach"] | ["achievements"] => self.achievements.list(),
+            ["cheat"] => self.cheats.list(),
+            ["cheat", "load", path] => match Cheats::load(Path::new(path)) {
+                Ok(cheats) => {
+                    self.cheats = cheats;
+                    format!("Loaded {} cheats from {}", self.cheats.len(), path)
+                }
+                Err(e) => e,
+            },
+            ["cheat", "save", path] => match self.cheats.save(Path::new(path)) {
+                Ok(()) => format!("Cheats written to {}", path),
+                Err(e) => format!("Unable to write {}: {}", path, e),
+            },
+            ["cheat", "freeze" | "once", ..] => {
+                match cheats::parse_cheat(line.trim().trim_start_matches("cheat")) {
+                    Ok(cheat) => {
+                        self.cheats.add(cheat);
+                        self.cheats.list()
+                    }
+                    Err(e) => e,
+                }
+            }
+            ["cheat", index] => match index.parse::<usize>() {
+                Ok(index) => match self.cheats.toggle(index) {
+                    Some(cheat) => match cheat.enabled {
+                        true => format!("Enabled {}", cheat),
+                        false => format!("Disabled {}", cheat),
+                    },
+                    None => format!("No cheat {}", index),
+                },
+                Err(_) => format!("Invalid cheat number: {}", index),
+            },
+            ["search", "start"] => {
+                self.search.start(cpu);
+                self.search.results(cpu)
+            }
+            ["search", ..] if !self.search.is_started() => {
+                String::from("Start a search first (search start)")
+            }
+            ["search"] => self.search.results(cpu),
+            ["search", filter @ ..] => {
+                let filter = match filter {
+                    ["eq", value] => match parse_addr(value).filter(|value| *value <= 0xFF) {
+                        Some(value) => Filter::Equal(value as u8),
+                        None => return format!("Invalid byte: {}", value),
+                    },
+                    ["changed"] => Filter::Changed,
+                    ["unchanged"] => Filter::Unchanged,
+                    ["inc"] => Filter::Increased,
+                    ["dec"] => Filter::Decreased,
+                    _ => return format!("Unknown search: {} (try help)", filter.join(" ")),
+                };
+                self.search.filter(cpu, filter);
+                self.search.results(cpu)
+            }
+            ["diff", "mark"] => {
+                self.diff_mark = Some(Box::new(cpu.clone()));
+                String::from("Marked, diff shows what changes from here")
+            }
+            ["diff"] => match &self.diff_mark {
+                Some(mark) => diff::diff(mark, cpu).report(),
+                None => String::from("Mark the machine first (diff mark)"),
+            },
+            ["graph"] => self.graphs.plot(),
+            ["graph", "clear"] => {
+                self.graphs.clear();
+                String::from("Graphs cleared")
+            }
+            ["graph", "save", path] => match std::fs::write(path, self.graphs.csv()) {
+                Ok(()) => format!("Samples written to {}", path),
+                Err(e) => format!("Unable to write {}: {}", path, e),
+            },
+            ["graph", "add", target, name @ ..] => {
+                let source = match Source::register(target) {
+                    Some(source) => source,
+                    None => match self.address(target) {
+                        Some(addr) if addr < cpu.memory.len() => Source::Memory(addr),
+                        _ => return format!("Invalid address: {}", target),
+                    },
+                };
+                let name = match name {
+                    [] => target.to_string(),
+                    name => name.join(" "),
+                };
+                match self.graphs.add(&name, source) {
+                    true => format!("Graphing {} every frame", name),
+                    false => format!("Already graphing {}", name),
+                }
+            }
+            ["graph", "remove", name @ ..] if !name.is_empty() => {
+                let name = name.join(" ");
+                match self.graphs.remove(&name) {
+                    true => format!("Stopped graphing {}", name),
+                    false => format!("Not graphing {}", name),
+                }
+            }
+            ["diff", path] => match diff::load(Path::new(path)) {
+                Ok(state) => diff::diff(&state, cpu).report(),
+                Err(e) => e,
+            },
+            _ => format!("Unknown command: {} (try help)", line.trim()),
+        }
+    }
+
+    // Disassembly following the PC along with the registers
+    fn status(&self, cpu: &Chip8) -> String {
+        let mut status = format!(
+            "{}\n{}",
+            self.disassembly(cpu, cpu.pc),
+            format_registers(cpu)
+        );
+        if cpu.sp > 0 {
+            status.push('\n');
+            status.push_str(&call_stack(cpu, &self.symbols));
+        }
+        if let Some(fault) = crash::fault(cpu) {
+            status.push_str(&format!("\nCan't run: {}", fault));
+            return status;
+        }
+        // Preview what a pending draw is about to put on screen
+        let opcode = cpu.opcode_at(cpu.pc);
+        if opcode & 0xF000 == 0xD000 {
+            let addr = cpu.address_reg as usize;
+            status.push('\n');
+            status.push_str(&match opcode & 0x000F {
+                0 => sprite(cpu, addr, 16, 2),
+                rows => sprite(cpu, addr, rows as usize, 1),
+            });
+        }
+        if self.follow_i {
+            status.push('\n');
+            status.push_str(&hexdump(cpu, cpu.address_reg as usize, MEMORY_VIEW_LEN));
+        }
+        status
+    }
+
+    // Window of instructions centred on addr. Breakpoints are marked with *,
+    // the PC with > and recently executed instructions are highlighted.
+    fn disassembly(&self, cpu: &Chip8, addr: usize) -> String {
+        // Keep the window aligned with addr even near the start of memory
+        let start = addr - (addr.min(DISASM_CONTEXT * 2) & !1);
+        let end = (addr + DISASM_CONTEXT * 2).min(cpu.memory.len() - 2);
+        let mut lines = Vec::new();
+        for line_addr in (start..=end).step_by(2) {
+            let opcode = cpu.opcode_at(line_addr);
+            let breakpoint = match self.breakpoints.contains(&line_addr) {
+                true => '*',
+                false => ' ',
+            };
+            let current = match line_addr == cpu.pc {
+                true => '>',
+                false => ' ',
+            };
+            if let Some(name) = self.symbols.name_at(line_addr) {
+                lines.push(format!("{}:", name));
+            }
+            let mut line = format!(
+                "{}{} {:#05x}: {:04x}  {}",
+                breakpoint,
+                current,
+                line_addr,
+                opcode,
+                match self.octo_syntax {
+                    true => octo::disassemble(opcode),
+                    false => disassemble(opcode),
+                }
+            );
+            // Name the target of jumps, calls and I loads
+            if matches!(opcode & 0xF000, 0x1000 | 0x2000 | 0xA000 | 0xB000) {
+                if let Some(name) = self.symbols.name_at((opcode & 0x0FFF) as usize) {
+                    line.push_str(&format!("  ; {}", name));
+                }
+            }
+            match self.recent.contains(&line_addr) {
+                true => lines.push(format!("\x1b[33m{}\x1b[0m", line)),
+                false => lines.push(line),
+            }
+        }
+        lines.join("\n")
+    }
+
+    // Symbol names take priority over hex so labels like "add" still work
+    fn address(&self, text: &str) -> Option<usize> {
+        self.symbols.addr_of(text).or_else(|| parse_addr(text))
+    }
+
+    // Address followed by the nearest symbol, if any
+    fn label(&self, addr: usize) -> String {
+        match self.symbols.describe(addr) {
+            Some(name) => format!("{:#05x} <{}>", addr, name),
+            None => format!("{:#05x}", addr),
+        }
+    }
+}
+
+impl Default for Debugger {
+    fn default() -> Debugger {
+        Debugger::new()
+    }
+}
+
+// Addresses are always hex, with or without the 0x prefix
+pub fn parse_addr(text: &str) -> Option<usize> {
+    let digits = text.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(digits, 16).ok()
+}
+
+// 2NNN pushes the return address and 00EE pops it, so each stack entry
+// points just past the call that created the frame
+pub fn call_stack(cpu: &Chip8, symbols: &Symbols) -> String {
+    if cpu.sp == 0 {
+        return String::from("Call stack is empty");
+    }
+    let mut lines = vec![String::from("Call stack (innermost first):")];
+    for depth in (1..=cpu.sp.min(cpu.stack.len() - 1)).rev() {
+        let return_addr = cpu.stack[depth];
+        let call_site = return_addr.wrapping_sub(2);
+        let routine = match call_site < cpu.memory.len() - 1 {
+            true => match cpu.opcode_at(call_site) {
+                opcode if opcode & 0xF000 == 0x2000 => {
+                    let addr = (opcode & 0x0FFF) as usize;
+                    match symbols.name_at(addr) {
+                        Some(name) => format!("{} ({:#05x})", name, addr),
+                        None => format!("{:#05x}", addr),
+                    }
+                }
+                _ => String::from("?"),
+            },
+            false => String::from("?"),
+        };
+        lines.push(format!(
+            "  #{} {} called from {:#05x}, returns to {:#05x}",
+            cpu.sp - depth,
+            routine,
+            call_site,
+            return_addr
+        ));
+    }
+    lines.join("\n")
+}
+
+// Sprite rows of width_bytes * 8 pixels drawn with the active palette
+pub fn sprite(cpu: &Chip8, addr: usize, rows: usize, width_bytes: usize) -> String {
+    let (color, background) = cpu.palette();
+    let mut lines = Vec::new();
+    for row in 0..rows {
+        let mut line = String::new();
+        for byte in 0..width_bytes {
+            let value = match addr
+                .checked_add(row * width_bytes + byte)
+                .and_then(|addr| cpu.memory.get(addr))
+            {
+                Some(value) => *value,
+                None => 0,
+            };
+            for bit in 0..8 {
+                let rgba = match value >> (7 - bit) & 0x1 {
+                    1 => color,
+                    _ => background,
+                };
+                line.push_str(&format!(
+                    "\x1b[38;2;{};{};{}m\u{2588}\u{2588}",
+                    rgba[0], rgba[1], rgba[2]
+                ));
+            }
+        }
+        line.push_str(RESET_COLOR);
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+// Register values are hex like addresses
+fn set_register(cpu: &mut Chip8, register: &str, value: &str) -> Result<(), String> {
+    let value = parse_addr(value).ok_or(format!("Invalid value: {}", value))?;
+    let register = register.to_ascii_uppercase();
+    let too_large = || format!("Value too large for {}: {:#x}", register, value);
+    match register.as_str() {
+        "I" if value <= 0xFFF => cpu.address_reg = value as u16,
+        "PC" if value < cpu.memory.len() - 1 => cpu.pc = value,
+        "SP" if value < cpu.stack.len() => cpu.sp = value,
+        "DT" if value <= 0xFF => cpu.delay_timer = value as u8,
+        "ST" if value <= 0xFF => cpu.sound_timer = value as u8,
+        "I" | "PC" | "SP" | "DT" | "ST" => return Err(too_large()),
+        _ => {
+            let index = register
+                .strip_prefix('V')
+                .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+                .filter(|index| *index < cpu.registers.len())
+                .ok_or(format!("Unknown register: {}", register))?;
+            if value > 0xFF {
+                return Err(too_large());
+            }
+            cpu.registers[index] = value as u8;
+        }
+    }
+    Ok(())
+}
+
+// 16 bytes per row with an ASCII column, coloured by memory region
+pub fn hexdump(cpu: &Chip8, addr: usize, len: usize) -> String {
+    let end = addr.saturating_add(len).min(cpu.memory.len());
+    let mut lines = vec![format!(
+        "{}font {}rom {}scratch{}",
+        FONT_COLOR, ROM_COLOR, SCRATCH_COLOR, RESET_COLOR
+    )];
+    for row in (addr & !0xF..end).step_by(16) {
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        for byte_addr in row..row + 16 {
+            if byte_addr < addr || byte_addr >= end {
+                hex.push_str("   ");
+                ascii.push(' ');
+                continue;
+            }
+            let byte = cpu.memory[byte_addr];
+            let color = if byte_addr < 0x50 {
+                FONT_COLOR
+            } else if byte_addr >= 0x200 && byte_addr < 0x200 + cpu.rom_size {
+                ROM_COLOR
+            } else {
+                SCRATCH_COLOR
+            };
+            hex.push_str(&format!("{}{:02x}{} ", color, byte, RESET_COLOR));
+            ascii.push(match byte {
+                0x20..=0x7E => byte as char,
+                _ => '.',
+            });
+        }
+        lines.push(format!("{:#05x}: {} |{}|", row, hex, ascii));
+    }
+    lines.join("\n")
+}
+
+pub fn format_registers(cpu: &Chip8) -> String {
+    let mut registers = String::new();
+    for (i, value) in cpu.registers.iter().enumerate() {
+        registers.push_str(&format!("V{:X}={:02x} ", i, value));
+    }
+    format!(
+        "PC={:#05x} I={:#05x} SP={} DT={} ST={}\n{}",
+        cpu.pc,
+        cpu.address_reg,
+        cpu.sp,
+        cpu.delay_timer,
+        cpu.sound_timer,
+        registers.trim_end()
+    )
+}
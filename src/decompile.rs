@@ -0,0 +1,471 @@
+use crate::analysis::{self, Analysis, Class};
+use crate::disasm::opcode_class;
+use crate::info::reachable;
+use crate::octo;
+use std::collections::{BTreeMap, BTreeSet};
+
+const START: usize = 0x200;
+
+/*    Decompiler
+ * chip8 decompile <rom> lifts a ROM into Octo source that assembles back to
+ * the same bytes:
+ *   : main  : routine-2a4      The entry point and call targets
+ *   : label-2b0                Other jump targets
+ *   : sprite-3c0               Data I points at
+ *   0b11110000  # ####....     Sprite data, see analysis.rs
+ *   loop ... again             A jump back over the code in between
+ *   while v0 != 5              A skip and a jump to just past a loop's again
+ *   if v0 == 5 begin ... end   A skip and a jump forwards, with an else when
+ *                              the code inside ends by jumping over more
+ *
+ * Code is whatever is reachable from 0x200 (see info::reachable), the rest is
+ * data, apart from code nothing reaches which is written out but not
+ * labelled. Jumps that don't fit one of the shapes above, or would overlap
+ * one that does, stay as jumps to labels.
+ */
+pub fn decompile(rom: &[u8]) -> String {
+    let mut decompiler = Decompiler::new(rom);
+    decompiler.find_blocks();
+    decompiler.find_whiles();
+    decompiler.name_labels();
+    decompiler.write()
+}
+
+// A loop or if, from its first instruction to just past its last
+struct Block {
+    start: usize,
+    end: usize,
+    kind: Kind,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Loop,
+    // Where the else part starts, just past the jump over it
+    If { otherwise: Option<usize> },
+}
+
+impl Block {
+    // Where blocks inside this one can be, clear of its own jumps
+    fn inside(&self) -> Vec<(usize, usize)> {
+        match self.kind {
+            Kind::Loop => vec![(self.start, self.end - 2)],
+            Kind::If { otherwise: None } => vec![(self.start + 4, self.end)],
+            Kind::If {
+                otherwise: Some(at),
+            } => vec![(self.start + 4, at - 2), (at, self.end)],
+        }
+    }
+
+    fn holds(&self, other: &Block) -> bool {
+        self.inside()
+            .iter()
+            .any(|(start, end)| *start <= other.start && other.end <= *end)
+    }
+
+    fn fits_with(&self, other: &Block) -> bool {
+        self.end <= other.start || other.end <= self.start || self.holds(other) || other.holds(self)
+    }
+}
+
+struct Decompiler<'a> {
+    rom: &'a [u8],
+    end: usize,
+    // Where each instruction is printed, going through the ROM in order
+    starts: BTreeSet<usize>,
+    // Addresses any instruction jumps to, calls or points I at
+    targets: BTreeSet<usize>,
+    blocks: Vec<Block>,
+    // Skips that start a while
+    whiles: BTreeSet<usize>,
+    labels: BTreeMap<usize, String>,
+    analysis: Analysis,
+}
+
+impl<'a> Decompiler<'a> {
+    fn new(rom: &'a [u8]) -> Decompiler<'a> {
+        let end = START + rom.len();
+        let code = match rom.len() < 2 {
+            true => BTreeSet::new(),
+            false => reachable(rom),
+        };
+        let mut starts = BTreeSet::new();
+        let mut addr = START;
+        while addr < end {
+            match code.contains(&addr) {
+                true => {
+                    starts.insert(addr);
+                    addr += 2;
+                }
+                false => addr += 1,
+            }
+        }
+        let mut decompiler = Decompiler {
+            rom,
+            end,
+            starts,
+            targets: BTreeSet::new(),
+            blocks: Vec::new(),
+            whiles: BTreeSet::new(),
+            labels: BTreeMap::new(),
+            analysis: analysis::analyze(rom),
+        };
+        decompiler.targets = decompiler
+            .starts
+            .iter()
+            .filter_map(|addr| decompiler.target(*addr))
+            .collect();
+        decompiler
+    }
+
+    fn opcode(&self, addr: usize) -> u16 {
+        let offset = addr - START;
+        u16::from_be_bytes([self.rom[offset], self.rom[offset + 1]])
+    }
+
+    fn is_skip(&self, addr: usize) -> bool {
+        self.starts.contains(&addr)
+            && matches!(
+                opcode_class(self.opcode(addr)),
+                "3XNN" | "4XNN" | "5XY0" | "9XY0" | "EX9E" | "EXA1"
+            )
+    }
+
+    // Where the jump at addr goes
+    fn jump(&self, addr: usize) -> Option<usize> {
+        match self.starts.contains(&addr) && opcode_class(self.opcode(addr)) == "1NNN" {
+            true => Some((self.opcode(addr) & 0x0FFF) as usize),
+            false => None,
+        }
+    }
+
+    fn target(&self, addr: usize) -> Option<usize> {
+        match opcode_class(self.opcode(addr)) {
+            "1NNN" | "2NNN" | "ANNN" | "BNNN" => Some((self.opcode(addr) & 0x0FFF) as usize),
+            _ => None,
+        }
+    }
+
+    // An instruction a block can start at, without a skip before it
+    // guarding only its first instruction
+    fn can_start(&self, addr: usize) -> bool {
+        let before = self.starts.range(..addr).next_back();
+        self.starts.contains(&addr) && !before.is_some_and(|before| self.is_skip(*before))
+    }
+
+    fn find_blocks(&mut self) {
+        let mut found = Vec::new();
+        for addr in &self.starts {
+            let addr = *addr;
+            if let Some(start) = self.jump(addr).filter(|start| *start <= addr) {
+                if self.can_start(start) {
+                    found.push(Block {
+                        start,
+                        end: addr + 2,
+                        kind: Kind::Loop,
+                    });
+                }
+            }
+            let end = match self.jump(addr + 2) {
+                Some(end) if self.is_skip(addr) && end >= addr + 4 && self.can_label(end) => end,
+                _ => continue,
+            };
+            if !self.can_start(addr) || self.targets.contains(&(addr + 2)) {
+                continue;
+            }
+            // A jump forwards just before the end skips over an else
+            let otherwise = self
+                .jump(end - 2)
+                .filter(|after| end - 2 > addr + 2 && *after >= end && self.can_label(*after))
+                .filter(|_| self.can_start(end - 2) && !self.targets.contains(&(end - 2)));
+            found.push(match otherwise {
+                Some(after) => Block {
+                    start: addr,
+                    end: after,
+                    kind: Kind::If {
+                        otherwise: Some(end),
+                    },
+                },
+                None => Block {
+                    start: addr,
+                    end,
+                    kind: Kind::If { otherwise: None },
+                },
+            });
+        }
+        // Outer blocks first, and a loop before an if at its first instruction
+        found.sort_by_key(|block| {
+            (
+                block.start,
+                usize::MAX - block.end,
+                block.kind != Kind::Loop,
+            )
+        });
+        for block in found {
+            if self.blocks.iter().all(|other| other.fits_with(&block)) {
+                self.blocks.push(block);
+            }
+        }
+    }
+
+    fn find_whiles(&mut self) {
+        // Nothing can open, close or label between the skip and the jump
+        let mut edges = self.targets.clone();
+        for block in &self.blocks {
+            edges.extend([block.start, block.end]);
+            if let Kind::If {
+                otherwise: Some(at),
+            } = block.kind
+            {
+                edges.extend([at - 2, at]);
+            }
+        }
+        for addr in &self.starts {
+            let addr = *addr;
+            let exit = match self.jump(addr + 2) {
+                Some(exit) if self.is_skip(addr) && self.can_start(addr) => exit,
+                _ => continue,
+            };
+            let taken = self
+                .blocks
+                .iter()
+                .any(|block| block.start == addr && block.kind != Kind::Loop);
+            if taken || edges.contains(&(addr + 2)) {
+                continue;
+            }
+            // The innermost loop around the skip and jump
+            let innermost = self
+                .blocks
+                .iter()
+                .filter(|block| block.kind == Kind::Loop)
+                .filter(|block| block.start <= addr && addr + 4 <= block.end - 2)
+                .min_by_key(|block| block.end - block.start);
+            if innermost.is_some_and(|block| block.end == exit) {
+                self.whiles.insert(addr);
+            }
+        }
+    }
+
+    // Jumps taken over by blocks and whiles don't need labels
+    fn name_labels(&mut self) {
+        let mut taken = self
+            .whiles
+            .iter()
+            .map(|addr| addr + 2)
+            .collect::<BTreeSet<_>>();
+        for block in &self.blocks {
+            match block.kind {
+                Kind::Loop => {
+                    taken.insert(block.end - 2);
+                }
+                Kind::If { otherwise } => {
+                    taken.insert(block.start + 2);
+                    if let Some(at) = otherwise {
+                        taken.insert(at - 2);
+                    }
+                }
+            }
+        }
+        self.labels.insert(START, String::from("main"));
+        for addr in &self.starts {
+            if taken.contains(addr) {
+                continue;
+            }
+            let target = match self.target(*addr) {
+                Some(target) if self.can_label(target) => target,
+                _ => continue,
+            };
+            let kind = match opcode_class(self.opcode(*addr)) {
+                "2NNN" => "routine",
+                "ANNN" if !self.starts.contains(&target) => "sprite",
+                _ => "label",
+            };
+            let name = format!("{}-{:03x}", kind, target);
+            match self.labels.get(&target) {
+                // Calls name a place over anything else
+                Some(old) if old == "main" || old.starts_with("routine") => (),
+                Some(_) if kind == "label" => (),
+                _ => {
+                    self.labels.insert(target, name);
+                }
+            }
+        }
+    }
+
+    // Not partway into an instruction, so a label can go there
+    fn can_label(&self, addr: usize) -> bool {
+        (START..=self.end).contains(&addr) && !self.starts.contains(&(addr - 1))
+    }
+
+    fn write(&self) -> String {
+        let mut lines = Vec::new();
+        let mut depth = 0;
+        // The line of an if ... then, for the instruction it guards to join
+        let mut guard = None;
+        let mut addr = START;
+        while addr <= self.end {
+            // Inner blocks close first
+            let mut ending: Vec<&Block> = self
+                .blocks
+                .iter()
+                .filter(|block| block.end == addr && block.kind != Kind::Loop)
+                .collect();
+            ending.sort_by_key(|block| usize::MAX - block.start);
+            for _ in ending {
+                depth -= 1;
+                lines.push(format!("{}end", indent(depth)));
+            }
+            if let Some(name) = self.labels.get(&addr) {
+                lines.push(format!(": {}", name));
+            }
+            if addr == self.end {
+                break;
+            }
+            let mut opening: Vec<&Block> = self
+                .blocks
+                .iter()
+                .filter(|block| block.start == addr)
+                .collect();
+            opening.sort_by_key(|block| (block.kind != Kind::Loop, usize::MAX - block.end));
+            for block in opening {
+                match block.kind {
+                    Kind::Loop => lines.push(format!("{}loop", indent(depth))),
+                    Kind::If { .. } => lines.push(format!(
+                        "{}if {} begin",
+                        indent(depth),
+                        condition(octo::inverse(self.opcode(addr)))
+                    )),
+                }
+                depth += 1;
+            }
+            if self
+                .blocks
+                .iter()
+                .any(|block| block.kind == Kind::Loop && block.end == addr + 2)
+            {
+                depth -= 1;
+                match guard == Some(lines.len()) {
+                    true => lines.last_mut().unwrap().push_str(" again"),
+                    false => lines.push(format!("{}again", indent(depth))),
+                }
+                addr += 2;
+            } else if self.blocks.iter().any(|block| {
+                block.kind
+                    == Kind::If {
+                        otherwise: Some(addr + 2),
+                    }
+            }) {
+                lines.push(format!("{}else", indent(depth - 1)));
+                addr += 2;
+            } else if self
+                .blocks
+                .iter()
+                .any(|block| block.start == addr && block.kind != Kind::Loop)
+            {
+                addr += 4;
+            } else if self.whiles.contains(&addr) {
+                lines.push(format!(
+                    "{}while {}",
+                    indent(depth),
+                    condition(octo::inverse(self.opcode(addr)))
+                ));
+                addr += 4;
+            } else if self.starts.contains(&addr) {
+                let instruction = self.instruction(addr);
+                let joined = guard == Some(lines.len());
+                match joined {
+                    true => {
+                        let line = lines.last_mut().unwrap();
+                        line.push(' ');
+                        line.push_str(&instruction);
+                    }
+                    false => lines.push(format!("{}{}", indent(depth), instruction)),
+                }
+                // A skip guarding a skip stays on its own line, as the
+                // second only guards the instruction after it
+                if !joined && self.is_skip(addr) {
+                    guard = Some(lines.len());
+                }
+                addr += 2;
+            } else if self.analysis.is_unreachable(addr)
+                && self.analysis.is_unreachable(addr + 1)
+                && !self.labels.contains_key(&(addr + 1))
+            {
+                if !self.analysis.is_unreachable(addr - 2) {
+                    lines.push(format!("{}# Nothing reaches this", indent(depth)));
+                }
+                lines.push(format!(
+                    "{}{}",
+                    indent(depth),
+                    octo::disassemble(self.opcode(addr))
+                ));
+                addr += 2;
+            } else {
+                addr = self.data(addr, depth, &mut lines);
+            }
+        }
+        lines.join("\n") + "\n"
+    }
+
+    // Octo for the instruction at addr, with labels for addresses
+    fn instruction(&self, addr: usize) -> String {
+        let opcode = self.opcode(addr);
+        let name = match self
+            .target(addr)
+            .and_then(|target| self.labels.get(&target))
+        {
+            Some(name) => name,
+            None => return octo::disassemble(opcode),
+        };
+        match opcode & 0xF000 {
+            0x1000 => format!("jump {}", name),
+            0x2000 => format!(":call {}", name),
+            0xA000 => format!("i := {}", name),
+            _ => format!("jump0 {}", name),
+        }
+    }
+
+    // Bytes up to the next instruction, label or change of class, one row
+    // per line for sprites, returning where they stop
+    fn data(&self, start: usize, depth: usize, lines: &mut Vec<String>) -> usize {
+        let class = self.analysis.class(start);
+        let mut end = start + 1;
+        while end < self.end
+            && !self.starts.contains(&end)
+            && !self.labels.contains_key(&end)
+            && !self.analysis.is_unreachable(end)
+            && self.analysis.class(end) == class
+        {
+            end += 1;
+        }
+        let bytes = &self.rom[start - START..end - START];
+        match class {
+            Class::Sprite => {
+                for byte in bytes {
+                    let row = analysis::pixels(*byte);
+                    lines.push(format!("{}{:#010b}  # {}", indent(depth), byte, row));
+                }
+            }
+            _ => {
+                for row in bytes.chunks(8) {
+                    let row: Vec<String> =
+                        row.iter().map(|byte| format!("{:#04x}", byte)).collect();
+                    lines.push(format!("{}{}", indent(depth), row.join(" ")));
+                }
+            }
+        }
+        end
+    }
+}
+
+fn indent(depth: usize) -> String {
+    "\t".repeat(depth + 1)
+}
+
+// "v0 == 0x05" from the skip an if ... then would be
+fn condition(skip: u16) -> String {
+    octo::disassemble(skip)
+        .trim_start_matches("if ")
+        .trim_end_matches(" then")
+        .to_string()
+}
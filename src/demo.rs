@@ -0,0 +1,11 @@
+use crate::octo;
+
+// Stands in for a ROM path, there's no file behind it
+pub const NAME: &str = "demo";
+const SOURCE: &str = include_str!("demo.8o");
+
+// The demo assembled, so the emulator has something to run with no files at
+// all. The source is in demo.8o.
+pub fn rom() -> Vec<u8> {
+    octo::assemble(SOURCE).expect("the built-in demo assembles")
+}
@@ -0,0 +1,169 @@
+use crate::chip8::{Chip8, DISPLAY_WIDTH};
+use crate::state;
+use crate::trace::TraceEntry;
+use std::path::Path;
+
+// Bytes of a changed range shown before the rest are left out
+const MAX_SHOWN: usize = 8;
+
+/*    State Diff
+ * What differs between two machines, e.g. a save state from before a routine
+ * and one from after it:
+ *   PC     0x204 -> 0x21a
+ *   V0     0x00 -> 0x05
+ *   Memory 2 ranges, 4 bytes
+ *     0x3f0-0x3f2  00 00 00 -> 01 02 05
+ *     0xe9f        00 -> ff
+ *   Display 12 pixels on, 3 off
+ * followed by the two displays XORed, + for pixels that came on, - for ones
+ * that went off and # for ones lit in both.
+ */
+pub struct StateDiff {
+    registers: Vec<(String, usize, usize)>,
+    // Start of each run of changed bytes, with its old and new bytes
+    ranges: Vec<(usize, Vec<u8>, Vec<u8>)>,
+    // 0 unlit in both, 1 lit in both, 2 came on, 3 went off
+    pixels: Vec<u8>,
+}
+
+pub fn diff(before: &Chip8, after: &Chip8) -> StateDiff {
+    let entry = TraceEntry::new(before, after);
+    let mut registers = Vec::new();
+    if before.pc != after.pc {
+        registers.push((String::from("PC"), before.pc, after.pc));
+    }
+    registers.extend(entry.registers);
+    for (level, (old, new)) in before.stack.iter().zip(after.stack).enumerate() {
+        if *old != new {
+            registers.push((format!("Stack {}", level), *old, new));
+        }
+    }
+    let mut ranges: Vec<(usize, Vec<u8>, Vec<u8>)> = Vec::new();
+    for (addr, old, new) in entry.memory {
+        match ranges.last_mut() {
+            Some((start, olds, news)) if *start + olds.len() == addr => {
+                olds.push(old);
+                news.push(new);
+            }
+            _ => ranges.push((addr, vec![old], vec![new])),
+        }
+    }
+    let pixels = before
+        .video_buffer
+        .iter()
+        .zip(after.video_buffer.iter())
+        .map(|(old, new)| match (*old, *new) {
+            (0, 0) => 0,
+            (0, _) => 2,
+            (_, 0) => 3,
+            _ => 1,
+        })
+        .collect();
+    StateDiff {
+        registers,
+        ranges,
+        pixels,
+    }
+}
+
+// A save state from a file, as a machine to diff
+pub fn load(path: &Path) -> Result<Chip8, String> {
+    let bytes =
+        std::fs::read(path).map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    let mut cpu = Chip8::new();
+    state::load(&mut cpu, &bytes).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok(cpu)
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty() && self.ranges.is_empty() && self.changed_pixels() == (0, 0)
+    }
+
+    // Pixels that came on and went off
+    pub fn changed_pixels(&self) -> (usize, usize) {
+        let count = |kind| self.pixels.iter().filter(|pixel| **pixel == kind).count();
+        (count(2), count(3))
+    }
+
+    pub fn report(&self) -> String {
+        if self.is_empty() {
+            return String::from("No differences");
+        }
+        let mut lines = Vec::new();
+        for (name, old, new) in &self.registers {
+            let width = match name.as_str() {
+                "PC" | "I" => 5,
+                name if name.starts_with("Stack") => 5,
+                _ => 4,
+            };
+            lines.push(format!(
+                "{:<6} {:#0w$x} -> {:#0w$x}",
+                name,
+                old,
+                new,
+                w = width
+            ));
+        }
+        if !self.ranges.is_empty() {
+            let bytes: usize = self.ranges.iter().map(|(_, olds, _)| olds.len()).sum();
+            lines.push(format!(
+                "Memory {} range{}, {} byte{}",
+                self.ranges.len(),
+                plural(self.ranges.len()),
+                bytes,
+                plural(bytes)
+            ));
+            for (start, olds, news) in &self.ranges {
+                let addrs = match olds.len() {
+                    1 => format!("{:#05x}", start),
+                    len => format!("{:#05x}-{:#05x}", start, start + len - 1),
+                };
+                lines.push(format!("  {:<12} {} -> {}", addrs, hex(olds), hex(news)));
+            }
+        }
+        let (on, off) = self.changed_pixels();
+        if on + off > 0 {
+            lines.push(format!("Display {} pixels on, {} off", on, off));
+            lines.push(self.map());
+        }
+        lines.join("\n")
+    }
+
+    // The two displays XORed, a character a pixel
+    pub fn map(&self) -> String {
+        self.pixels
+            .chunks(DISPLAY_WIDTH)
+            .map(|row| {
+                row.iter()
+                    .map(|pixel| match pixel {
+                        1 => '#',
+                        2 => '+',
+                        3 => '-',
+                        _ => '.',
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut shown: Vec<String> = bytes
+        .iter()
+        .take(MAX_SHOWN)
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if bytes.len() > MAX_SHOWN {
+        shown.push(String::from(".."));
+    }
+    shown.join(" ")
+}
+
+fn plural(count: usize) -> &'static str {
+    match count {
+        1 => "",
+        _ => "s",
+    }
+}
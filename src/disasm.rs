@@ -0,0 +1,201 @@
+use crate::reference::ENTRIES;
+use crate::variant::Variant;
+use std::sync::OnceLock;
+
+// Mnemonics follow Cowgod's Chip-8 technical reference
+pub fn disassemble(opcode: u16) -> String {
+    let nibbles = (
+        (opcode & 0xF000) >> 12,
+        (opcode & 0x0F00) >> 8,
+        (opcode & 0x00F0) >> 4,
+        opcode & 0x000F,
+    );
+    let nnn = opcode & 0x0FFF;
+    let nn = opcode & 0x00FF;
+    let x = nibbles.1;
+    let y = nibbles.2;
+    let n = nibbles.3;
+
+    match nibbles {
+        (0x00, 0x00, 0x0E, 0x00) => String::from("CLS"),
+        (0x00, 0x00, 0x0E, 0x0E) => String::from("RET"),
+        (0x00, _, _, _) => format!("SYS {:#05x}", nnn),
+        (0x01, _, _, _) => format!("JP {:#05x}", nnn),
+        (0x02, _, _, _) => format!("CALL {:#05x}", nnn),
+        (0x03, _, _, _) => format!("SE V{:X}, {:#04x}", x, nn),
+        (0x04, _, _, _) => format!("SNE V{:X}, {:#04x}", x, nn),
+        (0x05, _, _, 0x00) => format!("SE V{:X}, V{:X}", x, y),
+        (0x06, _, _, _) => format!("LD V{:X}, {:#04x}", x, nn),
+        (0x07, _, _, _) => format!("ADD V{:X}, {:#04x}", x, nn),
+        (0x08, _, _, 0x00) => format!("LD V{:X}, V{:X}", x, y),
+        (0x08, _, _, 0x01) => format!("OR V{:X}, V{:X}", x, y),
+        (0x08, _, _, 0x02) => format!("AND V{:X}, V{:X}", x, y),
+        (0x08, _, _, 0x03) => format!("XOR V{:X}, V{:X}", x, y),
+        (0x08, _, _, 0x04) => format!("ADD V{:X}, V{:X}", x, y),
+        (0x08, _, _, 0x05) => format!("SUB V{:X}, V{:X}", x, y),
+        (0x08, _, _, 0x06) => format!("SHR V{:X}, V{:X}", x, y),
+        (0x08, _, _, 0x07) => format!("SUBN V{:X}, V{:X}", x, y),
+        (0x08, _, _, 0x0E) => format!("SHL V{:X}, V{:X}", x, y),
+        (0x09, _, _, 0x00) => format!("SNE V{:X}, V{:X}", x, y),
+        (0x0A, _, _, _) => format!("LD I, {:#05x}", nnn),
+        (0x0B, _, _, _) => format!("JP V0, {:#05x}", nnn),
+        (0x0C, _, _, _) => format!("RND V{:X}, {:#04x}", x, nn),
+        (0x0D, _, _, _) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        (0x0E, _, 0x09, 0x0E) => format!("SKP V{:X}", x),
+        (0x0E, _, 0x0A, 0x01) => format!("SKNP V{:X}", x),
+        (0x0F, _, 0x00, 0x07) => format!("LD V{:X}, DT", x),
+        (0x0F, _, 0x00, 0x0A) => format!("LD V{:X}, K", x),
+        (0x0F, _, 0x01, 0x05) => format!("LD DT, V{:X}", x),
+        (0x0F, _, 0x01, 0x08) => format!("LD ST, V{:X}", x),
+        (0x0F, _, 0x01, 0x0E) => format!("ADD I, V{:X}", x),
+        (0x0F, _, 0x02, 0x09) => format!("LD F, V{:X}", x),
+        (0x0F, _, 0x03, 0x03) => format!("LD B, V{:X}", x),
+        (0x0F, _, 0x05, 0x05) => format!("LD [I], V{:X}", x),
+        (0x0F, _, 0x06, 0x05) => format!("LD V{:X}, [I]", x),
+        (0x0F, _, 0x07, 0x00) => format!("SEND V{:X}", x),
+        (0x0F, _, 0x07, 0x01) => format!("RECV V{:X}", x),
+        _ => format!("DW {:#06x}", opcode), // Not an instruction, likely data
+    }
+}
+
+// Opcode pattern such as "8XY4", used to group instructions by type
+pub fn opcode_class(opcode: u16) -> &'static str {
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => "00E0",
+            0x00EE => "00EE",
+            _ => "0NNN",
+        },
+        0x1000 => "1NNN",
+        0x2000 => "2NNN",
+        0x3000 => "3XNN",
+        0x4000 => "4XNN",
+        0x5000 if opcode & 0x000F == 0 => "5XY0",
+        0x6000 => "6XNN",
+        0x7000 => "7XNN",
+        0x8000 => match opcode & 0x000F {
+            0x0 => "8XY0",
+            0x1 => "8XY1",
+            0x2 => "8XY2",
+            0x3 => "8XY3",
+            0x4 => "8XY4",
+            0x5 => "8XY5",
+            0x6 => "8XY6",
+            0x7 => "8XY7",
+            0xE => "8XYE",
+            _ => "unknown",
+        },
+        0x9000 if opcode & 0x000F == 0 => "9XY0",
+        0xA000 => "ANNN",
+        0xB000 => "BNNN",
+        0xC000 => "CXNN",
+        0xD000 => "DXYN",
+        0xE000 => match opcode & 0x00FF {
+            0x9E => "EX9E",
+            0xA1 => "EXA1",
+            _ => "unknown",
+        },
+        0xF000 => match opcode & 0x00FF {
+            0x07 => "FX07",
+            0x0A => "FX0A",
+            0x15 => "FX15",
+            0x18 => "FX18",
+            0x1E => "FX1E",
+            0x29 => "FX29",
+            0x33 => "FX33",
+            0x55 => "FX55",
+            0x65 => "FX65",
+            0x70 => "FX70",
+            0x71 => "FX71",
+            _ => "unknown",
+        },
+        _ => "unknown",
+    }
+}
+
+/*    Opcode Patterns
+ * Opcodes picked out by their nibbles, for breaking on a kind of instruction
+ * without knowing where it is:
+ *   DXYN  any draw
+ *   FX0A  any wait for a key
+ *   8X_6  any shift right
+ * Hex digits must match and X, Y, N, K, _ or ? match anything. A CHIP-8
+ * mnemonic such as SHR or DRW stands for every opcode with that name.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpcodePattern {
+    pub text: String,
+    // Mask of the nibbles that must match, and what they must be
+    masks: Vec<(u16, u16)>,
+}
+
+impl OpcodePattern {
+    pub fn parse(text: &str) -> Option<OpcodePattern> {
+        let text = text.to_ascii_uppercase();
+        let masks = match nibble_mask(&text) {
+            Some(mask) => vec![mask],
+            None => ENTRIES
+                .iter()
+                .filter(|entry| entry.variant == Variant::Chip8)
+                .filter(|entry| entry.mnemonic.split_whitespace().next() == Some(&text))
+                .filter_map(|entry| nibble_mask(entry.pattern))
+                .collect(),
+        };
+        match masks.is_empty() {
+            true => None,
+            false => Some(OpcodePattern { text, masks }),
+        }
+    }
+
+    pub fn matches(&self, opcode: u16) -> bool {
+        self.masks
+            .iter()
+            .any(|(mask, value)| opcode & mask == *value)
+    }
+}
+
+fn nibble_mask(pattern: &str) -> Option<(u16, u16)> {
+    if pattern.chars().count() != 4 {
+        return None;
+    }
+    let mut mask = 0;
+    let mut value = 0;
+    for c in pattern.chars() {
+        mask <<= 4;
+        value <<= 4;
+        match c {
+            'X' | 'Y' | 'N' | 'K' | '_' | '?' => {}
+            _ => {
+                value |= c.to_digit(16)? as u16;
+                mask |= 0xF;
+            }
+        }
+    }
+    Some((mask, value))
+}
+
+// Every class opcode_class gives, for counting by class_index
+pub const CLASSES: [&str; 38] = [
+    "00E0", "00EE", "0NNN", "1NNN", "2NNN", "3XNN", "4XNN", "5XY0", "6XNN", "7XNN", "8XY0", "8XY1",
+    "8XY2", "8XY3", "8XY4", "8XY5", "8XY6", "8XY7", "8XYE", "9XY0", "ANNN", "BNNN", "CXNN", "DXYN",
+    "EX9E", "EXA1", "FX07", "FX0A", "FX15", "FX18", "FX1E", "FX29", "FX33", "FX55", "FX65", "FX70",
+    "FX71", "unknown",
+];
+
+// Where an opcode's class is in CLASSES, from a table of all 65536 worked
+// out the first time, so the core can count every instruction cheaply
+pub fn class_index(opcode: u16) -> usize {
+    static INDICES: OnceLock<Vec<u8>> = OnceLock::new();
+    let indices = INDICES.get_or_init(|| {
+        (0..=u16::MAX)
+            .map(|opcode| {
+                let class = opcode_class(opcode);
+                CLASSES
+                    .iter()
+                    .position(|name| *name == class)
+                    .unwrap_or(CLASSES.len() - 1) as u8
+            })
+            .collect()
+    });
+    indices[opcode as usize] as usize
+}
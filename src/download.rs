@@ -0,0 +1,40 @@
+// Bigger than any CHIP-8 program, small enough that a wrong link can't fill
+// memory
+pub const MAX_DOWNLOAD: u64 = 64 * 1024;
+
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("http://") || path.starts_with("https://")
+}
+
+/*    ROM URLs
+ * https://example.com/game.ch8               Downloaded into memory
+ * https://example.com/game.ch8#sha1=<hash>   Only if the bytes match
+ *
+ * The fragment never reaches the server, so a list of links can carry the
+ * hashes of the ROMs it was made with.
+ */
+pub fn fetch(url: &str) -> Result<Vec<u8>, String> {
+    let (url, expected) = match url.split_once("#sha1=") {
+        Some((url, hash)) => (url, Some(hash)),
+        None => (url, None),
+    };
+    let mut response = ureq::get(url).call().map_err(|e| e.to_string())?;
+    let rom = response
+        .body_mut()
+        .with_config()
+        .limit(MAX_DOWNLOAD)
+        .read_to_vec()
+        .map_err(|e| match e {
+            ureq::Error::BodyExceedsLimit(_) => {
+                format!("larger than {} bytes, not a ROM", MAX_DOWNLOAD)
+            }
+            e => e.to_string(),
+        })?;
+    if let Some(expected) = expected {
+        let hash = sha1_smol::Sha1::from(&rom).digest().to_string();
+        if !hash.eq_ignore_ascii_case(expected) {
+            return Err(format!("SHA-1 is {}, expected {}", hash, expected));
+        }
+    }
+    Ok(rom)
+}
@@ -0,0 +1,100 @@
+use crate::chip8::Chip8;
+use std::collections::VecDeque;
+
+// One DXYN as it ran
+#[derive(Debug, Clone)]
+pub struct Draw {
+    pub addr: usize,
+    pub x: u8,
+    pub y: u8,
+    pub rows: usize,
+    pub sprite: usize,
+    pub collision: bool,
+}
+
+/*    Draw Timeline
+ * The sprites drawn in each of the last frames, in the order they ran:
+ *   Frame 42, 3 draws
+ *     1  0x2a4  (12, 8)   8x5  from 0x3c0
+ *     2  0x2b0  (20, 8)   8x5  from 0x3c5  collision
+ *
+ * Frames are counted in instructions, speed to a frame, as in --frames.
+ * Frames without draws are left out.
+ */
+pub struct DrawLog {
+    frames: VecDeque<(u64, Vec<Draw>)>,
+    capacity: usize,
+}
+
+impl DrawLog {
+    pub fn new(capacity: usize) -> DrawLog {
+        DrawLog {
+            frames: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    // before is the machine about to run a DXYN, after the one that ran it
+    pub fn record(&mut self, before: &Chip8, after: &Chip8) {
+        let opcode = before.opcode_at(before.pc);
+        let x = before.registers[((opcode & 0x0F00) >> 8) as usize];
+        let y = before.registers[((opcode & 0x00F0) >> 4) as usize];
+        let draw = Draw {
+            addr: before.pc,
+            x,
+            y,
+            rows: (opcode & 0x000F) as usize,
+            sprite: before.address_reg as usize,
+            collision: after.registers[0xF] == 1,
+        };
+        let frame = before.instructions / before.speed.max(1) as u64;
+        match self.frames.back_mut() {
+            Some((last, draws)) if *last == frame => draws.push(draw),
+            _ => {
+                if self.frames.len() == self.capacity {
+                    self.frames.pop_front();
+                }
+                self.frames.push_back((frame, vec![draw]));
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    // The newest count frames that drew anything, oldest first
+    pub fn report(&self, count: usize) -> String {
+        if self.frames.is_empty() {
+            return String::from("Nothing drawn yet");
+        }
+        let mut lines = Vec::new();
+        let skip = self.frames.len().saturating_sub(count);
+        for (frame, draws) in self.frames.iter().skip(skip) {
+            lines.push(format!(
+                "Frame {}, {} draw{}",
+                frame,
+                draws.len(),
+                match draws.len() {
+                    1 => "",
+                    _ => "s",
+                }
+            ));
+            for (i, draw) in draws.iter().enumerate() {
+                lines.push(format!(
+                    "  {:>2}  {:#05x}  {:<9} 8x{:<2} from {:#05x}{}",
+                    i + 1,
+                    draw.addr,
+                    format!("({}, {})", draw.x, draw.y),
+                    draw.rows,
+                    draw.sprite,
+                    match draw.collision {
+                        true => "  collision",
+                        false => "",
+                    }
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,72 @@
+use crate::chip8::Chip8;
+use embedded_graphics_core::draw_target::DrawTarget;
+use embedded_graphics_core::geometry::{Dimensions, Point, Size};
+use embedded_graphics_core::pixelcolor::PixelColor;
+use embedded_graphics_core::primitives::Rectangle;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 32;
+
+// Blits the video buffer to an embedded-graphics display, e.g. an SSD1306
+// at scale 2 or an ST7789 at scale 3. Nothing here allocates.
+pub struct Screen<C: PixelColor> {
+    pub on: C,
+    pub off: C,
+    pub scale: u32,
+    pub top_left: Point,
+}
+
+impl<C: PixelColor> Screen<C> {
+    pub fn new(on: C, off: C) -> Screen<C> {
+        Screen {
+            on,
+            off,
+            scale: 1,
+            top_left: Point::zero(),
+        }
+    }
+
+    // Largest whole scale that fits the target, centred on it
+    pub fn fit<D: Dimensions>(on: C, off: C, target: &D) -> Screen<C> {
+        let area = target.bounding_box();
+        let scale = (area.size.width / WIDTH)
+            .min(area.size.height / HEIGHT)
+            .max(1);
+        let margin = Size::new(
+            area.size.width.saturating_sub(WIDTH * scale) / 2,
+            area.size.height.saturating_sub(HEIGHT * scale) / 2,
+        );
+        Screen {
+            on,
+            off,
+            scale,
+            top_left: area.top_left + margin,
+        }
+    }
+
+    // Pixels the display covers on the target
+    pub fn area(&self) -> Rectangle {
+        Rectangle::new(
+            self.top_left,
+            Size::new(WIDTH * self.scale, HEIGHT * self.scale),
+        )
+    }
+
+    // One contiguous fill, so drivers can stream it as a single window write
+    pub fn draw<D: DrawTarget<Color = C>>(
+        &self,
+        cpu: &Chip8,
+        target: &mut D,
+    ) -> Result<(), D::Error> {
+        let scale = self.scale as usize;
+        let colors = (0..HEIGHT as usize * scale).flat_map(move |y| {
+            (0..WIDTH as usize * scale).map(move |x| {
+                match cpu.video_buffer[(y / scale) * WIDTH as usize + x / scale] {
+                    1 => self.on,
+                    _ => self.off,
+                }
+            })
+        });
+        target.fill_contiguous(&self.area(), colors)
+    }
+}
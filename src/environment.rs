@@ -0,0 +1,150 @@
+use crate::chip8::{Chip8, MAX_ROM_SIZE};
+use crate::crash;
+
+pub const SCREEN_SIZE: usize = 64 * 32;
+
+// Where a game keeps its score, read big-endian
+#[derive(Debug, Clone, Copy)]
+pub struct Score {
+    pub addr: usize,
+    pub length: usize,
+}
+
+pub struct Step {
+    // 64x32, one byte per pixel, 1 for lit
+    pub screen: [u8; SCREEN_SIZE],
+    // Change in score since the last step, 0 without a Score
+    pub reward: i64,
+    pub done: bool,
+}
+
+// Gym-style wrapper for training agents. Every reset starts the ROM from
+// scratch with the same seed, so the same actions always give the same run.
+pub struct Environment {
+    rom: Vec<u8>,
+    seed: u64,
+    cpu: Chip8,
+    frame: u64,
+    score: Option<Score>,
+    last_score: i64,
+    // Why the episode stopped early, see crash::fault
+    fault: Option<String>,
+    // Frames emulated per step, holding the same key
+    pub frame_skip: u64,
+    pub max_frames: Option<u64>,
+    // Episode ends once this byte holds this value, e.g. lives reaching 0
+    pub done_when: Option<(usize, u8)>,
+}
+
+impl Environment {
+    pub fn new(rom: &[u8], seed: u64) -> Result<Environment, String> {
+        if rom.len() > MAX_ROM_SIZE {
+            return Err(format!("A {} byte ROM doesn't fit in memory", rom.len()));
+        }
+        let mut environment = Environment {
+            rom: rom.to_vec(),
+            seed,
+            cpu: Chip8::new(),
+            frame: 0,
+            score: None,
+            last_score: 0,
+            fault: None,
+            frame_skip: 1,
+            max_frames: None,
+            done_when: None,
+        };
+        environment.reset();
+        Ok(environment)
+    }
+
+    // Seed for the next reset
+    pub fn seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    pub fn set_score(&mut self, score: Option<Score>) {
+        self.score = score;
+        self.last_score = self.read_score();
+    }
+
+    pub fn reset(&mut self) -> [u8; SCREEN_SIZE] {
+        self.cpu = Chip8::new();
+        self.cpu.seed(self.seed);
+        self.cpu.load_font();
+        self.cpu.load_program(&self.rom);
+        self.frame = 0;
+        self.fault = None;
+        self.last_score = self.read_score();
+        self.cpu.video_buffer
+    }
+
+    // Hold key (or nothing) for frame_skip frames of cpu.speed instructions.
+    // An instruction that can't run ends the episode instead of panicking.
+    pub fn step(&mut self, key: Option<u8>) -> Step {
+        self.cpu.input = key.unwrap_or(0);
+        for _ in 0..self.frame_skip.max(1) {
+            if self.fault.is_some() {
+                break;
+            }
+            for _ in 0..self.cpu.speed {
+                self.fault = crash::fault(&self.cpu);
+                if self.fault.is_some() {
+                    break;
+                }
+                self.cpu.tick();
+            }
+            self.cpu.decrement_timers();
+            self.frame += 1;
+        }
+        let score = self.read_score();
+        let reward = score - self.last_score;
+        self.last_score = score;
+        Step {
+            screen: self.cpu.video_buffer,
+            reward,
+            done: self.done(),
+        }
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    // The instruction that ended the episode, if one did
+    pub fn fault(&self) -> Option<&str> {
+        self.fault.as_deref()
+    }
+
+    // For reading game state beyond the score
+    pub fn cpu(&self) -> &Chip8 {
+        &self.cpu
+    }
+
+    fn read_score(&self) -> i64 {
+        match self.score {
+            Some(Score { addr, length }) => self
+                .cpu
+                .memory
+                .get(addr..addr.saturating_add(length))
+                .unwrap_or_default()
+                .iter()
+                .fold(0, |score, byte| (score << 8) | *byte as i64),
+            None => 0,
+        }
+    }
+
+    // Out of frames, at the done_when value, or halted on a jump to itself
+    // the way most games end. A fault ends it too.
+    fn done(&self) -> bool {
+        if self.fault.is_some() {
+            return true;
+        }
+        let out_of_frames = self.max_frames.is_some_and(|max| self.frame >= max);
+        let finished = self
+            .done_when
+            .is_some_and(|(addr, value)| self.cpu.memory.get(addr) == Some(&value));
+        let opcode = self.cpu.opcode_at(self.cpu.pc);
+        let halted = opcode & 0xF000 == 0x1000 && (opcode & 0x0FFF) as usize == self.cpu.pc;
+        out_of_frames || finished || halted
+    }
+}
@@ -0,0 +1,218 @@
+use crate::chip8::{Chip8, Quirks};
+use crate::disasm::{disassemble, opcode_class};
+use crate::info;
+use crate::trace::TraceEntry;
+
+/*    Teaching Mode
+ * With teach on, each step in the console explains the instruction it ran:
+ *   0x204: 7a01  ADD VA, 0x01
+ *   Fields: x = A, nn = 0x01 (1)
+ *   Add 0x01 (1) to VA, wrapping round past 0xff. VF is left alone, so
+ *   there's no carry.
+ *   Changed: VA 0x05 -> 0x06, PC 0x204 -> 0x206
+ *
+ * The fields are the ones named in the opcode's pattern, e.g. X and NN in
+ * 7XNN. Sentences follow the machine's quirks, so a shift says which
+ * register it reads.
+ */
+pub fn explain(before: &Chip8, after: &Chip8) -> String {
+    let entry = TraceEntry::new(before, after);
+    let mut changes: Vec<String> = entry
+        .registers
+        .iter()
+        .map(|(name, old, new)| format!("{} {:#04x} -> {:#04x}", name, old, new))
+        .collect();
+    changes.push(format!("PC {:#05x} -> {:#05x}", before.pc, after.pc));
+    changes.extend(
+        entry
+            .memory
+            .iter()
+            .map(|(addr, old, new)| format!("[{:#05x}] {:#04x} -> {:#04x}", addr, old, new)),
+    );
+    if before.video_buffer != after.video_buffer {
+        changes.push(String::from("the display"));
+    }
+    format!(
+        "{:#05x}: {:04x}  {}\nFields: {}\n{}\nChanged: {}",
+        entry.addr,
+        entry.opcode,
+        disassemble(entry.opcode),
+        fields(entry.opcode),
+        describe(entry.opcode, &before.quirks),
+        changes.join(", ")
+    )
+}
+
+// The parts of an opcode its pattern names, e.g. "x = 1, y = 2" for 8124
+pub fn fields(opcode: u16) -> String {
+    let class = opcode_class(opcode);
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let mut fields = Vec::new();
+    if class.contains('X') {
+        fields.push(format!("x = {:X}", x));
+    }
+    if class.contains('Y') {
+        fields.push(format!("y = {:X}", y));
+    }
+    if class.contains("NNN") {
+        fields.push(format!("nnn = {:#05x}", opcode & 0x0FFF));
+    } else if class.contains("NN") {
+        fields.push(format!("nn = {}", byte(opcode & 0x00FF)));
+    } else if class.contains('N') {
+        fields.push(format!("n = {}", opcode & 0x000F));
+    }
+    match fields.is_empty() {
+        true => String::from("none"),
+        false => fields.join(", "),
+    }
+}
+
+// What an instruction does, in a sentence or two
+pub fn describe(opcode: u16, quirks: &Quirks) -> String {
+    let x = format!("V{:X}", (opcode & 0x0F00) >> 8);
+    let y = format!("V{:X}", (opcode & 0x00F0) >> 4);
+    let n = opcode & 0x000F;
+    let nn = byte(opcode & 0x00FF);
+    let nnn = format!("{:#05x}", opcode & 0x0FFF);
+    let logic = |op: &str| {
+        let vf = match quirks.vf_reset {
+            true => " VF is then cleared, the vf-reset quirk.",
+            false => "",
+        };
+        format!("Set {} to {} {} {}, bit by bit.{}", x, x, op, y, vf)
+    };
+    let shifted = match quirks.shift_vy {
+        true => format!("{} into {}", y, x),
+        false => x.clone(),
+    };
+    match opcode_class(opcode) {
+        "00E0" => String::from("Clear the display, turning every pixel off."),
+        "00EE" => String::from(
+            "Return from a subroutine: take the address on top of the stack and carry on from \
+             there.",
+        ),
+        "0NNN" => format!(
+            "Run the machine code routine at {} on the original COSMAC VIP. Later interpreters \
+             can't, and the debugger stops here.",
+            nnn
+        ),
+        "1NNN" => format!("Jump to {}, which becomes the next instruction.", nnn),
+        "2NNN" => format!(
+            "Call the subroutine at {}: push the address of the next instruction onto the stack \
+             so 00EE can return to it, then jump.",
+            nnn
+        ),
+        "3XNN" => format!("Skip the next instruction if {} equals {}.", x, nn),
+        "4XNN" => format!("Skip the next instruction if {} doesn't equal {}.", x, nn),
+        "5XY0" => format!("Skip the next instruction if {} equals {}.", x, y),
+        "6XNN" => format!("Set {} to {}.", x, nn),
+        "7XNN" => format!(
+            "Add {} to {}, wrapping round past 0xff. VF is left alone, so there's no carry.",
+            nn, x
+        ),
+        "8XY0" => format!("Copy {} into {}.", y, x),
+        "8XY1" => logic("OR"),
+        "8XY2" => logic("AND"),
+        "8XY3" => logic("XOR"),
+        "8XY4" => format!(
+            "Add {} to {}. VF becomes 1 if the sum went past 0xff (a carry), otherwise 0.",
+            y, x
+        ),
+        "8XY5" => format!(
+            "Subtract {} from {}. VF becomes 1 if {} was at least {} (no borrow), otherwise 0.",
+            y, x, x, y
+        ),
+        "8XY6" => format!(
+            "Shift {} right one bit, halving it. VF gets the bit that fell off the end.",
+            shifted
+        ),
+        "8XY7" => format!(
+            "Set {} to {} minus {}. VF becomes 1 if {} was at least {} (no borrow), otherwise 0.",
+            x, y, x, y, x
+        ),
+        "8XYE" => format!(
+            "Shift {} left one bit, doubling it. VF gets the bit that fell off the top.",
+            shifted
+        ),
+        "9XY0" => format!("Skip the next instruction if {} doesn't equal {}.", x, y),
+        "ANNN" => format!("Point I at address {}.", nnn),
+        "BNNN" => match quirks.jump_vx {
+            true => format!("Jump to {} plus {}, the jump quirk.", nnn, x),
+            false => format!("Jump to {} plus V0.", nnn),
+        },
+        "CXNN" => format!("Set {} to a random byte ANDed with {}.", x, nn),
+        "DXYN" => format!(
+            "Draw the {} byte sprite at I with its top left corner at ({}, {}). Each set bit \
+             flips a pixel, and VF becomes 1 if that turned any lit pixel off (a collision). \
+             Pixels past the edge {}.",
+            n,
+            x,
+            y,
+            match quirks.clip {
+                true => "are clipped",
+                false => "wrap round to the other side",
+            }
+        ),
+        "EX9E" => format!(
+            "Skip the next instruction if the key in {} is held down.",
+            x
+        ),
+        "EXA1" => format!(
+            "Skip the next instruction if the key in {} isn't held down.",
+            x
+        ),
+        "FX07" => format!("Copy the delay timer into {}.", x),
+        "FX0A" => format!(
+            "Wait for a key press and put the key in {}. The PC stays here until one comes.",
+            x
+        ),
+        "FX15" => format!(
+            "Set the delay timer from {}. It counts down 60 times a second.",
+            x
+        ),
+        "FX18" => format!(
+            "Set the sound timer from {}. The buzzer sounds until it counts down to 0.",
+            x
+        ),
+        "FX1E" => format!("Add {} to I.", x),
+        "FX29" => format!("Point I at the font's sprite for the hex digit in {}.", x),
+        "FX33" => format!(
+            "Write {} as three decimal digits to I, I+1 and I+2: hundreds, tens and ones.",
+            x
+        ),
+        "FX55" => format!(
+            "Store V0 to {} in memory starting at I. {}",
+            x,
+            match quirks.increment_i {
+                true => "I ends up just past the last one, the memory quirk.",
+                false => "I is left alone.",
+            }
+        ),
+        "FX65" => format!(
+            "Load V0 to {} from memory starting at I. {}",
+            x,
+            match quirks.increment_i {
+                true => "I ends up just past the last one, the memory quirk.",
+                false => "I is left alone.",
+            }
+        ),
+        "FX70" => format!("Send {} down the link cable.", x),
+        "FX71" => format!(
+            "Take a byte from the link cable into {}. VF becomes 1 if one had arrived.",
+            x
+        ),
+        _ => match info::extension(opcode) {
+            Some((variant, class)) => format!(
+                "A {} instruction ({}), which this machine doesn't run.",
+                variant, class
+            ),
+            None => String::from("Not an instruction. The program has likely run into data."),
+        },
+    }
+}
+
+// Hex with the decimal value alongside, e.g. "0x2a (42)"
+fn byte(value: u16) -> String {
+    format!("{:#04x} ({})", value, value)
+}
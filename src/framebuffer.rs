@@ -0,0 +1,213 @@
+use crate::chip8::Chip8;
+use crate::debugger::Debugger;
+use crate::keypad::Keymap;
+use crate::watchdog::Watchdog;
+use evdev::{InputEventKind, Key};
+use log::{error, info, warn};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver};
+use std::time::{Duration, Instant};
+use tracing::info_span;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+// 60 Hz, matching the timers
+const FRAME: Duration = Duration::from_micros(16_667);
+// evdev key event values
+const RELEASED: i32 = 0;
+const PRESSED: i32 = 1;
+
+// A Linux framebuffer device such as /dev/fb0, sized from sysfs. KMS
+// drivers, including the Pi's, provide one through fbdev emulation.
+pub struct Framebuffer {
+    file: File,
+    width: usize,
+    height: usize,
+    stride: usize,
+    bytes_per_pixel: usize,
+    frame: Vec<u8>,
+}
+
+impl Framebuffer {
+    pub fn open(path: &Path) -> io::Result<Framebuffer> {
+        let name = path.file_name().unwrap_or_default();
+        let sysfs = Path::new("/sys/class/graphics").join(name);
+        let read = |attribute: &str| -> io::Result<Vec<usize>> {
+            let text = fs::read_to_string(sysfs.join(attribute))?;
+            text.trim()
+                .split(',')
+                .map(|n| n.parse::<usize>())
+                .collect::<Result<Vec<usize>, _>>()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+        };
+        let (width, height) = match read("virtual_size")?.as_slice() {
+            [width, height] => (*width, *height),
+            _ => return Err(invalid("virtual_size isn't width,height")),
+        };
+        let bytes_per_pixel = match read("bits_per_pixel")?.as_slice() {
+            [16] => 2,
+            [32] => 4,
+            _ => return Err(invalid("only 16 and 32 bits per pixel are supported")),
+        };
+        let stride = match read("stride")?.as_slice() {
+            [stride] => *stride,
+            _ => return Err(invalid("stride isn't a number")),
+        };
+        let file = OpenOptions::new().write(true).open(path)?;
+        Ok(Framebuffer {
+            file,
+            width,
+            height,
+            stride,
+            bytes_per_pixel,
+            frame: vec![0; stride * height],
+        })
+    }
+
+    // Scale the display up as far as whole pixels go, centred on the screen
+    pub fn draw(&mut self, cpu: &Chip8) -> io::Result<()> {
+        let scale = (self.width / WIDTH).min(self.height / HEIGHT).max(1);
+        let left = self.width.saturating_sub(WIDTH * scale) / 2;
+        let top = self.height.saturating_sub(HEIGHT * scale) / 2;
+        let (color, background) = cpu.palette();
+        let (on, off) = (self.pixel(color), self.pixel(background));
+        let bpp = self.bytes_per_pixel;
+        for y in 0..self.height {
+            let row = &mut self.frame[y * self.stride..];
+            for x in 0..self.width {
+                let lit = match (x.checked_sub(left), y.checked_sub(top)) {
+                    (Some(dx), Some(dy)) if dx < WIDTH * scale && dy < HEIGHT * scale => {
+                        cpu.video_buffer[(dy / scale) * WIDTH + dx / scale] == 1
+                    }
+                    _ => false,
+                };
+                let bytes = match lit {
+                    true => &on,
+                    false => &off,
+                };
+                row[x * bpp..(x + 1) * bpp].copy_from_slice(&bytes[..bpp]);
+            }
+        }
+        self.file.write_all_at(&self.frame, 0)
+    }
+
+    // RGBA to the device's XRGB8888 or RGB565, both little-endian
+    fn pixel(&self, rgba: [u8; 4]) -> [u8; 4] {
+        let [r, g, b, _] = rgba;
+        match self.bytes_per_pixel {
+            2 => {
+                let rgb565 = ((r as u16 >> 3) << 11) | ((g as u16 >> 2) << 5) | (b as u16 >> 3);
+                let [low, high] = rgb565.to_le_bytes();
+                [low, high, 0, 0]
+            }
+            _ => [b, g, r, 0xFF],
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Reads every keyboard on its own thread, grabbed so keys don't also reach
+// the console underneath
+fn keyboards() -> Receiver<(Key, i32)> {
+    let (sender, receiver) = channel();
+    for (path, mut device) in evdev::enumerate() {
+        let is_keyboard = device
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(Key::KEY_ESC));
+        if !is_keyboard {
+            continue;
+        }
+        if let Err(e) = device.grab() {
+            error!("Unable to grab {}: {}", path.display(), e);
+        }
+        info!("Reading keys from {}", path.display());
+        let sender = sender.clone();
+        std::thread::spawn(move || {
+            while let Ok(events) = device.fetch_events() {
+                for event in events {
+                    if let InputEventKind::Key(key) = event.kind() {
+                        if sender.send((key, event.value())).is_err() {
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+    }
+    receiver
+}
+
+// Runs straight on the framebuffer with no window system, until Escape,
+// restarting the ROM whenever the watchdog finds it stuck
+pub fn run(
+    cpu: &mut Chip8,
+    debugger: &mut Debugger,
+    path: &Path,
+    mut watchdog: Option<Watchdog>,
+) -> io::Result<()> {
+    let mut framebuffer = Framebuffer::open(path)?;
+    let keys = keyboards();
+    let mut held: Option<u8> = None;
+    cpu.redraw = true;
+    loop {
+        let frame_start = Instant::now();
+        let _frame = cpu.frame_span().entered();
+        for (key, value) in keys.try_iter() {
+            match (key, value) {
+                (Key::KEY_ESC, PRESSED) => return Ok(()),
+                (key, PRESSED) => {
+                    if let Some(value) = keypad(key, &cpu.keymap) {
+                        held = Some(value);
+                    }
+                }
+                (key, RELEASED) if held.is_some() && keypad(key, &cpu.keymap) == held => {
+                    held = None
+                }
+                _ => {}
+            }
+        }
+
+        cpu.input = held.unwrap_or(0);
+        debugger.poll(cpu);
+        debugger.run(cpu, cpu.speed);
+        cpu.decrement_timers();
+        debugger.end_frame(cpu);
+        if let Some(watchdog) = &mut watchdog {
+            if watchdog.check(cpu) {
+                warn!(
+                    "Watchdog: the same display with no keys for {} seconds, restarting",
+                    watchdog.timeout().as_secs()
+                );
+                cpu.hard_reset();
+                debugger.resume();
+            }
+        }
+        if cpu.redraw {
+            let _render = info_span!("render").entered();
+            framebuffer.draw(cpu)?;
+            cpu.redraw = false;
+        }
+        std::thread::sleep(FRAME.saturating_sub(frame_start.elapsed()));
+    }
+}
+
+// evdev codes are key positions, so this follows the usual layout. Letters
+// and digits are named KEY_A or KEY_1.
+fn keypad(key: Key, keymap: &Keymap) -> Option<u8> {
+    let name = format!("{:?}", key);
+    match name
+        .strip_prefix("KEY_")?
+        .chars()
+        .collect::<Vec<char>>()
+        .as_slice()
+    {
+        [key] => keymap.key(*key),
+        _ => None,
+    }
+}
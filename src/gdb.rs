@@ -0,0 +1,370 @@
+use crate::chip8::Chip8;
+use crate::debugger::Debugger;
+use log::{error, info};
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/*    GDB Register Layout
+ * 0-15  V0-VF  8 bits
+ * 16    I      16 bits
+ * 17    SP     8 bits
+ * 18    PC     16 bits
+ * 19    DT     8 bits
+ * 20    ST     8 bits
+ *
+ * Multi-byte registers are sent little endian as described in target.xml
+ */
+const REGISTER_COUNT: usize = 21;
+const REGISTER_BYTES: usize = 23;
+
+// Remote serial protocol stub, polled from the event loop so the
+// window keeps running while gdb is attached
+pub struct GdbServer {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: Vec<u8>,
+    // gdb is blocked on a continue and expects a stop reply
+    running: bool,
+}
+
+impl GdbServer {
+    pub fn bind(address: &str) -> std::io::Result<GdbServer> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        info!("Waiting for gdb on {}", address);
+        Ok(GdbServer {
+            listener,
+            client: None,
+            buffer: Vec::new(),
+            running: false,
+        })
+    }
+
+    pub fn poll(&mut self, debugger: &mut Debugger, cpu: &mut Chip8) {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        error!("gdb connection failed: {}", e);
+                        return;
+                    }
+                    info!("gdb attached from {}", peer);
+                    // gdb expects the target to be stopped once attached
+                    debugger.pause();
+                    self.client = Some(stream);
+                    self.buffer.clear();
+                    self.running = false;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    error!("gdb accept failed: {}", e);
+                    return;
+                }
+            }
+        }
+        if !self.receive() {
+            info!("gdb detached");
+            self.client = None;
+            debugger.resume();
+            return;
+        }
+        self.handle_packets(debugger, cpu);
+        // Report breakpoints hit while running
+        if self.running && debugger.is_paused() {
+            self.running = false;
+            self.send("S05");
+        }
+    }
+
+    // Read everything available, false once the connection is gone
+    fn receive(&mut self) -> bool {
+        let mut chunk = [0; 1024];
+        let client = match &mut self.client {
+            Some(client) => client,
+            None => return false,
+        };
+        loop {
+            match client.read(&mut chunk) {
+                Ok(0) => return false,
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return true,
+                Err(e) => {
+                    error!("gdb read failed: {}", e);
+                    return false;
+                }
+            }
+        }
+    }
+
+    fn handle_packets(&mut self, debugger: &mut Debugger, cpu: &mut Chip8) {
+        loop {
+            match self.buffer.first() {
+                None => return,
+                // Ctrl-C from gdb
+                Some(0x03) => {
+                    self.buffer.remove(0);
+                    debugger.pause();
+                    self.running = false;
+                    self.send("S05");
+                    continue;
+                }
+                Some(b'$') => {}
+                // Acks and line noise
+                Some(_) => {
+                    self.buffer.remove(0);
+                    continue;
+                }
+            }
+            let end = match self.buffer.iter().position(|byte| *byte == b'#') {
+                Some(end) if end + 2 < self.buffer.len() => end,
+                _ => return, // Wait for the rest of the packet
+            };
+            let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+            let data = &packet[1..end];
+            let checksum = std::str::from_utf8(&packet[end + 1..])
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+            if checksum != Some(checksum_of(data)) {
+                self.write(b"-");
+                continue;
+            }
+            self.write(b"+");
+            let data = String::from_utf8_lossy(data).to_string();
+            if let Some(reply) = self.handle(&data, debugger, cpu) {
+                self.send(&reply);
+            }
+        }
+    }
+
+    // Reply to one packet, None when the reply is sent later (continue)
+    fn handle(&mut self, packet: &str, debugger: &mut Debugger, cpu: &mut Chip8) -> Option<String> {
+        let command = match packet.get(..1) {
+            Some(command) => (command, &packet[1..]),
+            None => return Some(String::new()),
+        };
+        let reply = match command {
+            ("?", _) => String::from("S05"),
+            ("g", _) => encode_hex(&read_registers(cpu)),
+            ("G", data) => match decode_hex(data) {
+                Some(bytes) if bytes.len() == REGISTER_BYTES => {
+                    for register in 0..REGISTER_COUNT {
+                        let (offset, size) = register_span(register);
+                        write_register(cpu, register, &bytes[offset..offset + size]);
+                    }
+                    String::from("OK")
+                }
+                _ => String::from("E01"),
+            },
+            ("p", number) => match usize::from_str_radix(number, 16) {
+                Ok(register) if register < REGISTER_COUNT => {
+                    let (offset, size) = register_span(register);
+                    encode_hex(&read_registers(cpu)[offset..offset + size])
+                }
+                _ => String::from("E01"),
+            },
+            ("P", assignment) => {
+                let parsed = assignment.split_once('=').and_then(|(number, value)| {
+                    Some((usize::from_str_radix(number, 16).ok()?, decode_hex(value)?))
+                });
+                match parsed {
+                    Some((register, bytes))
+                        if register < REGISTER_COUNT
+                            && bytes.len() == register_span(register).1 =>
+                    {
+                        write_register(cpu, register, &bytes);
+                        String::from("OK")
+                    }
+                    _ => String::from("E01"),
+                }
+            }
+            ("m", range) => match parse_range(range, cpu) {
+                Some((addr, len)) => encode_hex(&cpu.memory[addr..addr + len]),
+                None => String::from("E01"),
+            },
+            ("M", write) => {
+                let parsed = write
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_range(range, cpu)?, decode_hex(data)?)));
+                match parsed {
+                    Some(((addr, len), bytes)) if bytes.len() == len => {
+                        cpu.memory[addr..addr + len].copy_from_slice(&bytes);
+                        cpu.redraw = true;
+                        String::from("OK")
+                    }
+                    _ => String::from("E01"),
+                }
+            }
+            ("c", _) => {
+                debugger.resume();
+                self.running = true;
+                return None;
+            }
+            ("s", _) => {
+                debugger.pause();
+                debugger.step(cpu);
+                String::from("S05")
+            }
+            ("Z" | "z", breakpoint) => {
+                // Software and hardware breakpoints behave the same here
+                let fields: Vec<&str> = breakpoint.split(',').collect();
+                match fields.as_slice() {
+                    ["0" | "1", addr, _kind] => match usize::from_str_radix(addr, 16) {
+                        Ok(addr) if packet.starts_with('Z') => {
+                            debugger.add_breakpoint(addr);
+                            String::from("OK")
+                        }
+                        Ok(addr) => {
+                            debugger.remove_breakpoint(addr);
+                            String::from("OK")
+                        }
+                        Err(_) => String::from("E01"),
+                    },
+                    _ => String::new(),
+                }
+            }
+            ("H", _) => String::from("OK"),
+            ("D", _) => {
+                debugger.resume();
+                self.send("OK");
+                self.client = None;
+                info!("gdb detached");
+                return None;
+            }
+            ("k", _) => {
+                self.client = None;
+                return None;
+            }
+            ("q", query) => match query {
+                _ if query.starts_with("Supported") => {
+                    String::from("PacketSize=1000;qXfer:features:read+")
+                }
+                _ if query.starts_with("Xfer:features:read:target.xml:") => {
+                    let xml = target_xml();
+                    let range = query.trim_start_matches("Xfer:features:read:target.xml:");
+                    let parsed = range.split_once(',').and_then(|(offset, len)| {
+                        Some((
+                            usize::from_str_radix(offset, 16).ok()?,
+                            usize::from_str_radix(len, 16).ok()?,
+                        ))
+                    });
+                    match parsed {
+                        // Whatever reaches the end, or overflows past it, is the last part
+                        Some((offset, len)) => {
+                            match offset.checked_add(len).filter(|end| *end < xml.len()) {
+                                Some(end) => format!("m{}", &xml[offset..end]),
+                                None => format!("l{}", &xml[offset.min(xml.len())..]),
+                            }
+                        }
+                        None => String::from("E01"),
+                    }
+                }
+                "Attached" => String::from("1"),
+                "C" => String::from("QC1"),
+                "fThreadInfo" => String::from("m1"),
+                "sThreadInfo" => String::from("l"),
+                _ => String::new(),
+            },
+            _ => String::new(), // Unsupported
+        };
+        Some(reply)
+    }
+
+    fn send(&mut self, data: &str) {
+        let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+        self.write(packet.as_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        if let Some(client) = &mut self.client {
+            if let Err(e) = client.write_all(bytes) {
+                error!("gdb write failed: {}", e);
+                self.client = None;
+            }
+        }
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    text.as_bytes()
+        .chunks(2)
+        .map(|pair| match pair.len() {
+            2 => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+// "addr,len" in hex, checked against the size of memory
+fn parse_range(range: &str, cpu: &Chip8) -> Option<(usize, usize)> {
+    let (addr, len) = range.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    let len = usize::from_str_radix(len, 16).ok()?;
+    match addr.checked_add(len) {
+        Some(end) if end <= cpu.memory.len() => Some((addr, len)),
+        _ => None,
+    }
+}
+
+// Byte offset and size of a register within the g packet
+fn register_span(register: usize) -> (usize, usize) {
+    match register {
+        0..=15 => (register, 1),
+        16 => (16, 2),
+        17 => (18, 1),
+        18 => (19, 2),
+        19 => (21, 1),
+        _ => (22, 1),
+    }
+}
+
+fn read_registers(cpu: &Chip8) -> Vec<u8> {
+    let mut bytes = cpu.registers.to_vec();
+    bytes.extend_from_slice(&cpu.address_reg.to_le_bytes());
+    bytes.push(cpu.sp as u8);
+    bytes.extend_from_slice(&(cpu.pc as u16).to_le_bytes());
+    bytes.push(cpu.delay_timer);
+    bytes.push(cpu.sound_timer);
+    bytes
+}
+
+fn write_register(cpu: &mut Chip8, register: usize, bytes: &[u8]) {
+    match register {
+        0..=15 => cpu.registers[register] = bytes[0],
+        16 => cpu.address_reg = u16::from_le_bytes([bytes[0], bytes[1]]) & 0x0FFF,
+        17 => cpu.sp = (bytes[0] as usize).min(cpu.stack.len() - 1),
+        18 => {
+            cpu.pc = (u16::from_le_bytes([bytes[0], bytes[1]]) as usize).min(cpu.memory.len() - 2)
+        }
+        19 => cpu.delay_timer = bytes[0],
+        _ => cpu.sound_timer = bytes[0],
+    }
+}
+
+fn target_xml() -> String {
+    let mut registers = String::new();
+    for i in 0..16 {
+        registers.push_str(&format!(
+            "<reg name=\"v{:x}\" bitsize=\"8\" type=\"uint8\"/>",
+            i
+        ));
+    }
+    format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chip8.core\">{}\
+         <reg name=\"i\" bitsize=\"16\" type=\"data_ptr\"/>\
+         <reg name=\"sp\" bitsize=\"8\" type=\"uint8\"/>\
+         <reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+         <reg name=\"dt\" bitsize=\"8\" type=\"uint8\"/>\
+         <reg name=\"st\" bitsize=\"8\" type=\"uint8\"/>\
+         </feature></target>",
+        registers
+    )
+}
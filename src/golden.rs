@@ -0,0 +1,154 @@
+use crate::chip8::Chip8;
+use crate::crash;
+use std::io::{self, Write};
+
+/*    Canonical Trace
+ * One line per instruction, with the machine as it was before running it:
+ *   pc:0200 op:00e0 i:0000 sp:0 dt:00 st:00 v:000102030405060708090a0b0c0d0e0f
+ * v is V0 to VF, two digits each. Lines starting with # are comments, e.g. the
+ * fault a trace stopped at.
+ *
+ * Fields are name:hex in any order and either case. compare-trace checks the
+ * fields both traces have, so another emulator's trace only needs the ones it
+ * can print, and its own extra ones are ignored.
+ */
+pub fn line(cpu: &Chip8) -> String {
+    let v: String = cpu.registers.iter().map(|v| format!("{:02x}", v)).collect();
+    format!(
+        "pc:{:04x} op:{:04x} i:{:04x} sp:{:x} dt:{:02x} st:{:02x} v:{}",
+        cpu.pc,
+        cpu.opcode_at(cpu.pc),
+        cpu.address_reg,
+        cpu.sp,
+        cpu.delay_timer,
+        cpu.sound_timer,
+        v
+    )
+}
+
+// Run frames from seed 0 with no keys held, a line for each instruction,
+// stopping early at a fault. Returns how many instructions ran.
+pub fn record(cpu: &mut Chip8, frames: u64, output: &mut impl Write) -> io::Result<u64> {
+    cpu.seed(0);
+    let start = cpu.instructions;
+    for _ in 0..frames {
+        for _ in 0..cpu.speed {
+            if let Some(fault) = crash::fault(cpu) {
+                writeln!(output, "# {}", fault)?;
+                return Ok(cpu.instructions - start);
+            }
+            writeln!(output, "{}", line(cpu))?;
+            cpu.tick();
+        }
+        cpu.decrement_timers();
+    }
+    Ok(cpu.instructions - start)
+}
+
+// A trace and its name for the report
+pub struct Trace<'a> {
+    pub name: &'a str,
+    pub text: &'a str,
+}
+
+impl<'a> Trace<'a> {
+    // Instructions with their line numbers, skipping comments and blank lines
+    fn instructions(&self) -> impl Iterator<Item = (usize, &'a str)> {
+        self.text
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+    }
+}
+
+fn fields(line: &str) -> Vec<(String, String)> {
+    line.split_whitespace()
+        .filter_map(|field| field.split_once(':'))
+        .map(|(name, value)| (name.to_ascii_lowercase(), normalize(value)))
+        .collect()
+}
+
+// Hex without a prefix or leading zeros, so 0x0A, 0a and A all match. v keeps
+// its digits, two to a register.
+fn normalize(value: &str) -> String {
+    let value = value.trim_start_matches("0x").to_ascii_lowercase();
+    match value.len() {
+        32 => value,
+        _ => match value.trim_start_matches('0') {
+            "" => String::from("0"),
+            digits => digits.to_string(),
+        },
+    }
+}
+
+// How many instructions matched, or a report of the first that didn't with
+// the one before it
+pub fn compare(expected: &Trace, actual: &Trace) -> Result<String, String> {
+    let mut count = 0;
+    let mut previous = None;
+    let mut expected_lines = expected.instructions();
+    let mut actual_lines = actual.instructions();
+    loop {
+        let (a, b) = match (expected_lines.next(), actual_lines.next()) {
+            (Some(a), Some(b)) => (a, b),
+            (None, None) => return Ok(format!("Traces match over {} instructions", count)),
+            (Some(_), None) => return Ok(unequal(count, expected, actual)),
+            (None, Some(_)) => return Ok(unequal(count, actual, expected)),
+        };
+        let differences = differences(a.1, b.1);
+        if !differences.is_empty() {
+            let width = expected.name.len().max(actual.name.len());
+            let mut lines = vec![
+                format!(
+                    "First difference at instruction {}, line {} of {} and line {} of {}",
+                    count + 1,
+                    a.0,
+                    expected.name,
+                    b.0,
+                    actual.name
+                ),
+                format!("  {:<w$}  {}", expected.name, a.1, w = width),
+                format!("  {:<w$}  {}", actual.name, b.1, w = width),
+            ];
+            lines.extend(differences.iter().map(|line| format!("  {}", line)));
+            if let Some(previous) = previous {
+                lines.push(format!("After {}", previous));
+            }
+            return Err(lines.join("\n"));
+        }
+        previous = Some(b.1);
+        count += 1;
+    }
+}
+
+fn unequal(count: usize, longer: &Trace, shorter: &Trace) -> String {
+    format!(
+        "Traces match over the {} instructions both have, {} goes on after {} ends",
+        count, longer.name, shorter.name
+    )
+}
+
+// The fields both lines have that disagree, with registers named one by one
+fn differences(expected: &str, actual: &str) -> Vec<String> {
+    let actual = fields(actual);
+    let mut lines = Vec::new();
+    for (name, a) in &fields(expected) {
+        let b = match actual.iter().find(|(other, _)| other == name) {
+            Some((_, b)) if a != b => b,
+            _ => continue,
+        };
+        match name.as_str() {
+            "v" if a.len() == 32 && b.len() == 32 => {
+                for register in 0..16 {
+                    let (old, new) = (&a[register * 2..][..2], &b[register * 2..][..2]);
+                    if old != new {
+                        lines.push(format!("V{:X} {} vs {}", register, old, new));
+                    }
+                }
+            }
+            name => lines.push(format!("{} {} vs {}", name, a, b)),
+        }
+    }
+    lines
+}
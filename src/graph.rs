@@ -0,0 +1,202 @@
+use crate::chip8::Chip8;
+use std::collections::{BTreeMap, VecDeque};
+
+// Rows and columns of a plot
+const HEIGHT: usize = 8;
+const WIDTH: usize = 64;
+
+// What a graph samples
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Register(usize),
+    I,
+    Delay,
+    Sound,
+    Memory(usize),
+}
+
+impl Source {
+    // V0-VF, I, DT or ST, otherwise None for the caller to try as an address
+    pub fn register(text: &str) -> Option<Source> {
+        match text.to_ascii_uppercase().as_str() {
+            "I" => Some(Source::I),
+            "DT" => Some(Source::Delay),
+            "ST" => Some(Source::Sound),
+            register => register
+                .strip_prefix('V')
+                .filter(|digit| digit.len() == 1)
+                .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+                .map(Source::Register),
+        }
+    }
+
+    pub fn sample(&self, cpu: &Chip8) -> usize {
+        match *self {
+            Source::Register(index) => cpu.registers[index] as usize,
+            Source::I => cpu.address_reg as usize,
+            Source::Delay => cpu.delay_timer as usize,
+            Source::Sound => cpu.sound_timer as usize,
+            Source::Memory(addr) => cpu.memory[addr] as usize,
+        }
+    }
+}
+
+struct Series {
+    name: String,
+    source: Source,
+    samples: VecDeque<(u64, usize)>,
+}
+
+/*    Graphs
+ * Values sampled at the end of every frame and plotted over time, e.g. the
+ * score or the player's Y position:
+ *   score, frames 60 to 900, now 12
+ *      12 |                                          ***********
+ *         |                            **************
+ *         ...
+ *       0 |*****************************
+ * With more frames than columns each column covers several, drawn from the
+ * lowest value in them to the highest. Frames are counted in instructions,
+ * speed to a frame, and stepping back over some drops what was sampled there.
+ */
+pub struct Graphs {
+    series: Vec<Series>,
+    // Frames kept for each series
+    capacity: usize,
+}
+
+impl Graphs {
+    pub fn new(capacity: usize) -> Graphs {
+        Graphs {
+            series: Vec::new(),
+            capacity,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+
+    // Returns false if the name is already graphed
+    pub fn add(&mut self, name: &str, source: Source) -> bool {
+        if self.series.iter().any(|series| series.name == name) {
+            return false;
+        }
+        self.series.push(Series {
+            name: name.to_string(),
+            source,
+            samples: VecDeque::new(),
+        });
+        true
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.series.len();
+        self.series.retain(|series| series.name != name);
+        self.series.len() != len
+    }
+
+    pub fn clear(&mut self) {
+        self.series.clear();
+    }
+
+    // Call at the end of each frame
+    pub fn record(&mut self, cpu: &Chip8) {
+        let frame = cpu.instructions / cpu.speed.max(1) as u64;
+        for series in &mut self.series {
+            let samples = &mut series.samples;
+            while samples.back().is_some_and(|(last, _)| *last >= frame) {
+                samples.pop_back();
+            }
+            if samples.len() == self.capacity {
+                samples.pop_front();
+            }
+            samples.push_back((frame, series.source.sample(cpu)));
+        }
+    }
+
+    pub fn plot(&self) -> String {
+        if self.series.is_empty() {
+            return String::from("Nothing graphed yet (graph add <addr|reg> [name])");
+        }
+        self.series
+            .iter()
+            .map(plot)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    // A row per frame and a column per series, empty where a series has no
+    // sample
+    pub fn csv(&self) -> String {
+        let mut rows: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+        for (column, series) in self.series.iter().enumerate() {
+            for (frame, value) in &series.samples {
+                let row = rows
+                    .entry(*frame)
+                    .or_insert_with(|| vec![String::new(); self.series.len()]);
+                row[column] = value.to_string();
+            }
+        }
+        let mut lines = vec![std::iter::once("frame")
+            .chain(self.series.iter().map(|series| series.name.as_str()))
+            .map(csv_field)
+            .collect::<Vec<_>>()
+            .join(",")];
+        for (frame, values) in rows {
+            lines.push(format!("{},{}", frame, values.join(",")));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+fn plot(series: &Series) -> String {
+    let (first, last) = match (series.samples.front(), series.samples.back()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return format!("{}, nothing sampled yet", series.name),
+    };
+    let values: Vec<usize> = series.samples.iter().map(|(_, value)| *value).collect();
+    let low = values.iter().min().copied().unwrap_or(0);
+    let high = values.iter().max().copied().unwrap_or(0);
+    let row_of = |value: usize| match high - low {
+        0 => 0,
+        range => (value - low) * (HEIGHT - 1) / range,
+    };
+    // Lowest and highest row each column reaches
+    let columns: Vec<(usize, usize)> = values
+        .chunks(values.len().div_ceil(WIDTH))
+        .map(|bucket| {
+            let min = bucket.iter().min().copied().unwrap_or(low);
+            let max = bucket.iter().max().copied().unwrap_or(low);
+            (row_of(min), row_of(max))
+        })
+        .collect();
+    let mut lines = vec![format!(
+        "{}, frames {} to {}, now {}",
+        series.name, first.0, last.0, last.1
+    )];
+    for row in (0..HEIGHT).rev() {
+        let axis = match row {
+            _ if row == HEIGHT - 1 => format!("{:>6}", high),
+            0 => format!("{:>6}", low),
+            _ => String::from("      "),
+        };
+        let line: String = columns
+            .iter()
+            .map(|(bottom, top)| match (*bottom..=*top).contains(&row) {
+                true => '*',
+                false => ' ',
+            })
+            .collect();
+        lines.push(format!("{} |{}", axis, line.trim_end()));
+    }
+    lines.join("\n")
+}
+
+// Names with commas or quotes quoted, as spreadsheets expect
+fn csv_field(text: &str) -> String {
+    match text.contains([',', '"']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
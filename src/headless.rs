@@ -0,0 +1,176 @@
+use crate::chip8::{Chip8, EmulationStatus};
+use crate::debugger::Debugger;
+use log::{error, info};
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+/*    Control Protocol
+ * One JSON object per line each way, e.g.
+ * > {"command": "key", "key": 5}
+ * > {"command": "step", "frames": 60}
+ * < {"frame": 60, "paused": false}
+ *
+ * step    [frames]        Run frames (default 1), timers tick once per frame
+ * state                   Registers, timers, held key, frame count and
+ *                         state hash, see Chip8::state_hash
+ * screen                  32 rows of 64 "0"/"1", top to bottom
+ * key     key             Hold a key 0-F, null to release
+ * read    addr [length]   Memory bytes
+ * debug   line            Any debugger console command
+ * quit                    Stop the server
+ *
+ * Failures reply {"error": "..."}.
+ */
+// Nothing runs between commands, so a client sees the same result on every run
+pub struct Headless {
+    frame: u64,
+    key: Option<u8>,
+}
+
+impl Headless {
+    pub fn new() -> Headless {
+        Headless {
+            frame: 0,
+            key: None,
+        }
+    }
+
+    // Serve one client at a time until one of them sends quit
+    pub fn serve(
+        &mut self,
+        cpu: &mut Chip8,
+        debugger: &mut Debugger,
+        address: &str,
+    ) -> io::Result<()> {
+        let listener = TcpListener::bind(address)?;
+        info!("Control socket listening on {}", address);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            info!("Control client connected from {}", stream.peer_addr()?);
+            match self.session(cpu, debugger, stream) {
+                Ok(true) => return Ok(()),
+                Ok(false) => info!("Control client disconnected"),
+                Err(e) => error!("Control connection failed: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    // True once the client asked to quit
+    fn session(
+        &mut self,
+        cpu: &mut Chip8,
+        debugger: &mut Debugger,
+        stream: TcpStream,
+    ) -> io::Result<bool> {
+        let mut writer = stream.try_clone()?;
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let reply = match serde_json::from_str::<Value>(&line) {
+                Ok(request) if request["command"] == "quit" => {
+                    writeln!(writer, "{}", json!({}))?;
+                    return Ok(true);
+                }
+                Ok(request) => self.handle(&request, cpu, debugger),
+                Err(e) => Err(format!("Invalid JSON: {}", e)),
+            };
+            let reply = reply.unwrap_or_else(|e| json!({ "error": e }));
+            writeln!(writer, "{}", reply)?;
+        }
+        Ok(false)
+    }
+
+    pub fn handle(
+        &mut self,
+        request: &Value,
+        cpu: &mut Chip8,
+        debugger: &mut Debugger,
+    ) -> Result<Value, String> {
+        match request["command"].as_str().unwrap_or_default() {
+            "step" => {
+                let frames = match &request["frames"] {
+                    Value::Null => 1,
+                    frames => frames.as_u64().ok_or("frames must be a number")?,
+                };
+                for _ in 0..frames {
+                    self.step(cpu, debugger);
+                }
+                Ok(json!({ "frame": self.frame, "paused": debugger.is_paused() }))
+            }
+            "state" => Ok(json!({
+                "frame": self.frame,
+                "pc": cpu.pc,
+                "i": cpu.address_reg,
+                "sp": cpu.sp,
+                "v": cpu.registers.to_vec(),
+                "delay": cpu.delay_timer,
+                "sound": cpu.sound_timer,
+                "key": self.key,
+                "paused": debugger.is_paused(),
+                "waiting_for_key": matches!(cpu.status, EmulationStatus::WaitingForKey),
+                "hash": cpu.state_hash(),
+            })),
+            "screen" => {
+                let rows: Vec<String> = cpu
+                    .video_buffer
+                    .chunks(WIDTH)
+                    .map(|row| {
+                        row.iter()
+                            .map(|pixel| match pixel {
+                                1 => '1',
+                                _ => '0',
+                            })
+                            .collect()
+                    })
+                    .collect();
+                Ok(json!({ "width": WIDTH, "height": HEIGHT, "rows": rows }))
+            }
+            "key" => {
+                self.key = match &request["key"] {
+                    Value::Null => None,
+                    key => match key.as_u64() {
+                        Some(key) if key <= 0xF => Some(key as u8),
+                        _ => return Err(String::from("key must be 0-15 or null")),
+                    },
+                };
+                Ok(json!({ "key": self.key }))
+            }
+            "read" => {
+                let addr = request["addr"].as_u64().ok_or("addr must be a number")? as usize;
+                let length = request["length"].as_u64().unwrap_or(1) as usize;
+                match cpu.memory.get(addr..addr.saturating_add(length)) {
+                    Some(bytes) => Ok(json!({ "addr": addr, "bytes": bytes })),
+                    None => Err(format!("{:#x}+{} is outside memory", addr, length)),
+                }
+            }
+            "debug" => {
+                let line = request["line"].as_str().ok_or("line must be a string")?;
+                Ok(json!({ "output": debugger.execute(cpu, line) }))
+            }
+            command => Err(format!("Unknown command {:?}", command)),
+        }
+    }
+
+    // One 60 Hz frame's worth of instructions
+    fn step(&mut self, cpu: &mut Chip8, debugger: &mut Debugger) {
+        let _frame = cpu.frame_span().entered();
+        cpu.input = self.key.unwrap_or(0);
+        debugger.run(cpu, cpu.speed);
+        cpu.decrement_timers();
+        debugger.end_frame(cpu);
+        self.frame += 1;
+    }
+}
+
+impl Default for Headless {
+    fn default() -> Headless {
+        Headless::new()
+    }
+}
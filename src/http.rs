@@ -0,0 +1,288 @@
+use crate::chip8::{Chip8, MAX_ROM_SIZE};
+use crate::debugger::{parse_addr, Debugger};
+use crate::{screenshot, state};
+use log::{error, info};
+use serde_json::{json, Value};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// The most taken in a request's headers and its body, a state being the
+// biggest body there is
+const MAX_HEAD: usize = 8 * 1024;
+const MAX_BODY: usize = 64 * 1024;
+
+/*    Endpoints
+ * POST /rom                  Body is a ROM to run instead, set up as if loaded
+ *                            from a file
+ * POST /pause
+ * POST /resume
+ * GET  /state                Save state, laid out as in src/state.rs
+ * PUT  /state                Load the save state in the body
+ * GET  /screenshot           The display as a PNG
+ * GET  /memory/<addr>        Peek, ?length=16 for more than one byte
+ * PUT  /memory/<addr>        Poke the JSON array of bytes in the body
+ *
+ * Addresses are hex like in the debugger. Other replies are JSON, failures
+ * {"error": "..."} with a 4xx status, 413 for a request bigger than
+ * MAX_HEAD and MAX_BODY. One request per connection, the reply written as
+ * the client takes it so a slow one doesn't hold up the emulator.
+ */
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+struct Client {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    // What's left to write once the request has been handled
+    reply: Option<Vec<u8>>,
+}
+
+// Polled from the event loop like the debugger servers
+pub struct HttpServer {
+    listener: TcpListener,
+    clients: Vec<Client>,
+}
+
+impl HttpServer {
+    pub fn bind(address: &str) -> io::Result<HttpServer> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        info!("HTTP API on http://{}", address);
+        Ok(HttpServer {
+            listener,
+            clients: Vec::new(),
+        })
+    }
+
+    // load gives the machine for a ROM posted to /rom, as any other is loaded
+    pub fn poll(
+        &mut self,
+        debugger: &mut Debugger,
+        cpu: &mut Chip8,
+        load: impl Fn(&[u8]) -> Result<Chip8, String>,
+    ) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match stream.set_nonblocking(true) {
+                    Ok(()) => self.clients.push(Client {
+                        stream,
+                        buffer: Vec::new(),
+                        reply: None,
+                    }),
+                    Err(e) => error!("HTTP connection failed: {}", e),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    error!("HTTP accept failed: {}", e);
+                    break;
+                }
+            }
+        }
+        self.clients.retain_mut(|client| {
+            if client.reply.is_none() {
+                match client.request() {
+                    Ok(Some(request)) => {
+                        client.reply = Some(handle(&request, debugger, cpu, &load))
+                    }
+                    Ok(None) => {}
+                    Err(e) => {
+                        error!("HTTP request failed: {}", e);
+                        return false;
+                    }
+                }
+            }
+            // Kept until the whole reply has gone
+            match client.send() {
+                Ok(sent) => !sent,
+                Err(e) => {
+                    error!("HTTP reply failed: {}", e);
+                    false
+                }
+            }
+        });
+    }
+}
+
+impl Client {
+    // The request once it has all arrived. One that's too big gets its
+    // reply straight away instead.
+    fn request(&mut self) -> io::Result<Option<Request>> {
+        let mut chunk = [0; 4096];
+        while self.buffer.len() <= MAX_HEAD + 4 + MAX_BODY {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::UnexpectedEof)),
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let end = match find(&self.buffer, b"\r\n\r\n") {
+            Some(end) if end <= MAX_HEAD => end,
+            None if self.buffer.len() <= MAX_HEAD => return Ok(None),
+            _ => return Ok(self.refuse(error_response(413, "Headers too large"))),
+        };
+        let head = String::from_utf8_lossy(&self.buffer[..end]).to_string();
+        let mut lines = head.lines();
+        let mut request_line = lines.next().unwrap_or_default().split(' ');
+        let (method, path) = match (request_line.next(), request_line.next()) {
+            (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+            _ => return Err(io::Error::new(ErrorKind::InvalidData, "bad request line")),
+        };
+        let length = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("Content-Length"))
+            .map_or(Some(0), |(_, value)| value.trim().parse::<usize>().ok());
+        let length = match length {
+            Some(length) if length <= MAX_BODY => length,
+            Some(_) => return Ok(self.refuse(error_response(413, "Body too large"))),
+            None => return Ok(self.refuse(error_response(400, "Invalid Content-Length"))),
+        };
+        match self.buffer.get(end + 4..end + 4 + length) {
+            Some(body) => Ok(Some(Request {
+                method,
+                path,
+                body: body.to_vec(),
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn refuse(&mut self, response: Vec<u8>) -> Option<Request> {
+        self.reply = Some(response);
+        None
+    }
+
+    // Write what the socket takes now, true once the reply has all gone
+    fn send(&mut self) -> io::Result<bool> {
+        let reply = match &mut self.reply {
+            Some(reply) => reply,
+            None => return Ok(false),
+        };
+        while !reply.is_empty() {
+            match self.stream.write(reply) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::WriteZero)),
+                Ok(len) => {
+                    reply.drain(..len);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+}
+
+fn handle(
+    request: &Request,
+    debugger: &mut Debugger,
+    cpu: &mut Chip8,
+    load: impl Fn(&[u8]) -> Result<Chip8, String>,
+) -> Vec<u8> {
+    let (path, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["rom"]) => match request.body.len() {
+            0 => error_response(400, "Empty ROM"),
+            size if size > MAX_ROM_SIZE => {
+                error_response(400, &format!("A {} byte ROM doesn't fit in memory", size))
+            }
+            size => match load(&request.body) {
+                Ok(loaded) => {
+                    *cpu = loaded;
+                    cpu.redraw = true;
+                    info!("Loaded a {} byte ROM over HTTP", size);
+                    json_response(200, json!({ "size": size }))
+                }
+                Err(e) => error_response(400, &e),
+            },
+        },
+        ("POST", ["pause"]) => {
+            debugger.pause();
+            json_response(200, json!({ "paused": true }))
+        }
+        ("POST", ["resume"]) => {
+            debugger.resume();
+            json_response(200, json!({ "paused": false }))
+        }
+        ("GET", ["state"]) => response(200, "application/octet-stream", &state::save(cpu)),
+        ("PUT", ["state"]) => match state::load(cpu, &request.body) {
+            Ok(()) => json_response(200, json!({ "pc": cpu.pc })),
+            Err(e) => error_response(400, &e),
+        },
+        ("GET", ["screenshot"]) => response(200, "image/png", &screenshot::png(cpu)),
+        ("GET", ["memory", addr]) => {
+            let length = query
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("length="))
+                .map_or(Some(1), |length| length.parse::<usize>().ok());
+            match (parse_addr(addr), length) {
+                (Some(addr), Some(length)) => match addr
+                    .checked_add(length)
+                    .and_then(|end| cpu.memory.get(addr..end))
+                {
+                    Some(bytes) => json_response(200, json!({ "addr": addr, "bytes": bytes })),
+                    None => error_response(400, "Outside memory"),
+                },
+                _ => error_response(400, "Invalid address or length"),
+            }
+        }
+        ("PUT", ["memory", addr]) => {
+            let bytes = serde_json::from_slice::<Vec<u8>>(&request.body);
+            match (parse_addr(addr), bytes) {
+                (Some(addr), Ok(bytes)) => match addr
+                    .checked_add(bytes.len())
+                    .and_then(|end| cpu.memory.get_mut(addr..end))
+                {
+                    Some(memory) => {
+                        memory.copy_from_slice(&bytes);
+                        json_response(200, json!({ "addr": addr, "length": bytes.len() }))
+                    }
+                    None => error_response(400, "Outside memory"),
+                },
+                (None, _) => error_response(400, "Invalid address"),
+                (_, Err(e)) => error_response(400, &format!("Body must be a byte array: {}", e)),
+            }
+        }
+        (_, ["rom" | "pause" | "resume" | "state" | "screenshot"] | ["memory", _]) => {
+            error_response(405, "Method not allowed")
+        }
+        _ => error_response(404, "Not found"),
+    }
+}
+
+fn response(status: u16, content_type: &str, body: &[u8]) -> Vec<u8> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Payload Too Large",
+    };
+    let mut response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        content_type,
+        body.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(body);
+    response
+}
+
+fn json_response(status: u16, body: Value) -> Vec<u8> {
+    response(status, "application/json", body.to_string().as_bytes())
+}
+
+fn error_response(status: u16, message: &str) -> Vec<u8> {
+    json_response(status, json!({ "error": message }))
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,168 @@
+use crate::analysis;
+use crate::chip8::MAX_ROM_SIZE;
+use crate::disasm::opcode_class;
+use crate::variant::Variant;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Programs are loaded here and execution starts at the first byte
+const START: usize = 0x200;
+
+// Instructions added by later variants, by the variant that introduced them
+pub fn extension(opcode: u16) -> Option<(Variant, &'static str)> {
+    let schip = |class| Some((Variant::SChip, class));
+    let xo_chip = |class| Some((Variant::XoChip, class));
+    match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00C0..=0x00CF => schip("00CN"),
+            0x00D0..=0x00DF => xo_chip("00DN"),
+            0x00FB => schip("00FB"),
+            0x00FC => schip("00FC"),
+            0x00FD => schip("00FD"),
+            0x00FE => schip("00FE"),
+            0x00FF => schip("00FF"),
+            _ => None,
+        },
+        0x5000 => match opcode & 0x000F {
+            0x2 => xo_chip("5XY2"),
+            0x3 => xo_chip("5XY3"),
+            _ => None,
+        },
+        0xD000 if opcode & 0x000F == 0 => schip("DXY0"),
+        0xF000 => match opcode & 0x00FF {
+            0x00 if opcode == 0xF000 => xo_chip("F000"),
+            0x01 => xo_chip("FN01"),
+            0x02 if opcode == 0xF002 => xo_chip("F002"),
+            0x30 => schip("FX30"),
+            0x3A => xo_chip("FX3A"),
+            0x75 => schip("FX75"),
+            0x85 => schip("FX85"),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// Instructions whose behaviour differs between interpreters
+fn quirk(opcode: u16) -> Option<&'static str> {
+    match opcode_class(opcode) {
+        "8XY1" | "8XY2" | "8XY3" => Some("VF reset after logic ops"),
+        "8XY6" | "8XYE" => Some("shifts use VY or VX"),
+        "FX55" | "FX65" => Some("load/store increments I"),
+        "BNNN" => Some("jump uses V0 or VX"),
+        "DXYN" => Some("sprites wrap or clip at the edges"),
+        _ => None,
+    }
+}
+
+// Follow jumps, calls and skips from the entry point so data mixed in with
+// the code isn't counted as instructions. Computed jumps (BNNN) end a path.
+pub fn reachable(rom: &[u8]) -> BTreeSet<usize> {
+    let mut code = BTreeSet::new();
+    let mut pending = vec![START];
+    while let Some(addr) = pending.pop() {
+        if addr < START || addr + 1 >= START + rom.len() || !code.insert(addr) {
+            continue;
+        }
+        let offset = addr - START;
+        let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+        pending.extend(successors(opcode, addr));
+    }
+    code
+}
+
+// Where execution can go after the instruction at addr
+pub fn successors(opcode: u16, addr: usize) -> Vec<usize> {
+    let nnn = (opcode & 0x0FFF) as usize;
+    match opcode_class(opcode) {
+        "00EE" | "BNNN" => vec![],
+        "1NNN" => vec![nnn],
+        "2NNN" => vec![nnn, addr + 2],
+        "3XNN" | "4XNN" | "5XY0" | "9XY0" | "EX9E" | "EXA1" => vec![addr + 2, addr + 4],
+        _ => match extension(opcode) {
+            Some((_, "00FD")) => vec![],
+            Some((_, "5XY2" | "5XY3")) => vec![addr + 2],
+            // Long I load takes the following word as its address
+            Some((_, "F000")) => vec![addr + 4],
+            _ => vec![addr + 2],
+        },
+    }
+}
+
+// Summary of a ROM to help pick the variant and quirks it needs
+pub fn report(rom: &[u8]) -> String {
+    let mut lines = vec![
+        format!("Size:    {} bytes ({:#05x})", rom.len(), rom.len()),
+        format!("SHA-1:   {}", sha1_smol::Sha1::from(rom).digest()),
+        format!("CRC-32:  {:08x}", crc32fast::hash(rom)),
+        format!(
+            "Entry:   loaded at {:#05x}, execution starts at {:#05x}",
+            START, START
+        ),
+    ];
+    if rom.len() > MAX_ROM_SIZE {
+        lines.push(format!(
+            "         larger than the {} bytes available on a 4K machine, needs XO-CHIP memory",
+            MAX_ROM_SIZE
+        ));
+    }
+    if rom.len() < 2 {
+        return lines.join("\n");
+    }
+
+    let code = reachable(rom);
+    let mut histogram: BTreeMap<&'static str, usize> = BTreeMap::new();
+    let mut extensions: BTreeMap<Variant, BTreeSet<&'static str>> = BTreeMap::new();
+    let mut quirks: BTreeSet<&'static str> = BTreeSet::new();
+    for addr in &code {
+        let offset = addr - START;
+        let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+        let class = match extension(opcode) {
+            Some((variant, class)) => {
+                extensions.entry(variant).or_default().insert(class);
+                class
+            }
+            None => opcode_class(opcode),
+        };
+        *histogram.entry(class).or_insert(0) += 1;
+        if let Some(quirk) = quirk(opcode) {
+            quirks.insert(quirk);
+        }
+    }
+    lines.push(format!(
+        "Code:    {} reachable instructions ({} of {} bytes)",
+        code.len(),
+        code.len() * 2,
+        rom.len()
+    ));
+    lines.push(format!("Bytes:   {}", analysis::analyze(rom).summary()));
+
+    lines.push(String::from("Opcodes:"));
+    let mut counts: Vec<(&&str, &usize)> = histogram.iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    for (class, count) in counts {
+        lines.push(format!("  {:<8} {:>5}", class, count));
+    }
+
+    lines.push(match extensions.is_empty() {
+        true => String::from("Variant: CHIP-8"),
+        false => format!(
+            "Variant: needs {}",
+            extensions
+                .iter()
+                .map(|(variant, classes)| format!(
+                    "{} ({})",
+                    variant,
+                    classes.iter().copied().collect::<Vec<&str>>().join(", ")
+                ))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+    });
+    if !quirks.is_empty() {
+        lines.push(String::from("Quirk sensitive:"));
+        for quirk in quirks {
+            lines.push(format!("  {}", quirk));
+        }
+    }
+    lines.join("\n")
+}
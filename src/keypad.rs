@@ -0,0 +1,62 @@
+/*    Key Mappings
+ * Chip8       QWERTY
+ * 1 2 3 C     1 2 3 4
+ * 4 5 6 D >>> Q W E R
+ * 7 8 9 E >>> A S D F
+ * A 0 B F     Z X C V
+ *
+ * Frontends without winit key codes share this table.
+ */
+pub fn from_qwerty(key: char) -> Option<u8> {
+    Keymap::default().key(key)
+}
+
+// Keyboard key for each CHIP-8 key, 0 to F, lowercase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap(pub [char; 16]);
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap([
+            'x', '1', '2', '3', 'q', 'w', 'e', 'a', 's', 'd', 'z', 'c', '4', 'r', 'f', 'v',
+        ])
+    }
+}
+
+impl Keymap {
+    /*    Second Player
+     * Chip8       QWERTY
+     * 1 2 3 C     6 7 8 9
+     * 4 5 6 D >>> Y U I O
+     * 7 8 9 E >>> H J K L
+     * A 0 B F     B N M 0
+     *
+     * The right of the keyboard, for the right half of --versus. F is on 0 as
+     * the row runs out, and P is left for pausing.
+     */
+    pub fn player_two() -> Keymap {
+        Keymap([
+            'n', '6', '7', '8', 'y', 'u', 'i', 'h', 'j', 'k', 'b', 'm', '9', 'o', 'l', '0',
+        ])
+    }
+
+    // CHIP-8 key for a keyboard key
+    pub fn key(&self, key: char) -> Option<u8> {
+        let key = key.to_ascii_lowercase();
+        self.0
+            .iter()
+            .position(|mapped| *mapped == key)
+            .map(|value| value as u8)
+    }
+
+    // Move a CHIP-8 key to another keyboard key. Whatever had that keyboard
+    // key takes the old one, so every CHIP-8 key stays reachable.
+    pub fn bind(&mut self, value: u8, key: char) {
+        let key = key.to_ascii_lowercase();
+        let old = self.0[value as usize & 0xF];
+        if let Some(other) = self.0.iter_mut().find(|mapped| **mapped == key) {
+            *other = old;
+        }
+        self.0[value as usize & 0xF] = key;
+    }
+}
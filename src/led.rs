@@ -0,0 +1,57 @@
+use crate::chip8::Chip8;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+
+/*    Adalight frame
+ * 'A' 'd' 'a'       magic
+ * hi lo             LED count - 1, big-endian
+ * hi ^ lo ^ 0x55    checksum
+ * r g b ..          one triple per LED, row by row from the top left
+ *
+ * Most serial LED firmware understands this, so a microcontroller driving a
+ * 64x32 HUB75 panel only needs to copy the triples out in order.
+ */
+pub struct LedMatrix {
+    device: File,
+    // 0-255, panels are very bright at full power
+    pub brightness: u8,
+    frame: Vec<u8>,
+}
+
+impl LedMatrix {
+    // A serial port such as /dev/ttyACM0, with its baud rate already set, or a
+    // pipe to another program
+    pub fn open(path: &Path) -> io::Result<LedMatrix> {
+        let device = OpenOptions::new().write(true).open(path)?;
+        let count = (WIDTH * HEIGHT - 1) as u16;
+        let [hi, lo] = count.to_be_bytes();
+        let mut frame = vec![b'A', b'd', b'a', hi, lo, hi ^ lo ^ 0x55];
+        frame.resize(frame.len() + WIDTH * HEIGHT * 3, 0);
+        Ok(LedMatrix {
+            device,
+            brightness: 0xFF,
+            frame,
+        })
+    }
+
+    // Send the whole display, colored with the current palette
+    pub fn send(&mut self, cpu: &Chip8) -> io::Result<()> {
+        let (color, background) = cpu.palette();
+        let dim = |rgba: [u8; 4]| rgba.map(|c| (c as u16 * self.brightness as u16 / 0xFF) as u8);
+        let (on, off) = (dim(color), dim(background));
+        let leds = &mut self.frame[6..];
+        for (i, pixel) in cpu.video_buffer.iter().enumerate() {
+            let rgba = match pixel {
+                1 => on,
+                _ => off,
+            };
+            leds[i * 3..i * 3 + 3].copy_from_slice(&rgba[..3]);
+        }
+        self.device.write_all(&self.frame)?;
+        self.device.flush()
+    }
+}
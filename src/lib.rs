@@ -0,0 +1,98 @@
+// Emulator core and tools, shared by the desktop binary and the browser build
+pub mod achievements;
+pub mod analysis;
+#[cfg(target_os = "android")]
+pub mod android;
+pub mod asm;
+pub mod attract;
+pub mod battery;
+pub mod builder;
+pub mod cart;
+pub mod chat;
+pub mod cheats;
+pub mod chip8;
+pub mod compare;
+pub mod config;
+pub mod coverage;
+pub mod crash;
+#[cfg(feature = "dap")]
+pub mod dap;
+pub mod database;
+pub mod debugger;
+pub mod decompile;
+pub mod demo;
+pub mod diff;
+pub mod disasm;
+pub mod draws;
+#[cfg(feature = "download")]
+pub mod download;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+pub mod environment;
+pub mod explain;
+#[cfg(feature = "framebuffer")]
+pub mod framebuffer;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+pub mod golden;
+pub mod graph;
+pub mod headless;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod info;
+pub mod keypad;
+pub mod led;
+pub mod link;
+pub mod lint;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod logview;
+pub mod metrics;
+pub mod mix;
+pub mod monitor;
+pub mod netplay;
+pub mod octo;
+pub mod osd;
+pub mod patch;
+pub mod peripheral;
+pub mod playlist;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod profiler;
+#[cfg(feature = "python")]
+mod python;
+pub mod reference;
+pub mod rewind;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod screensaver;
+pub mod screenshot;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+pub mod search;
+#[cfg(feature = "sdl2")]
+pub mod sdl;
+pub mod sheet;
+pub mod snapshot;
+pub mod soak;
+pub mod state;
+pub mod stats;
+pub mod suite;
+pub mod symbols;
+pub mod telemetry;
+#[cfg(feature = "terminal")]
+pub mod terminal;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "timeline")]
+pub mod timeline;
+pub mod trace;
+pub mod variant;
+pub mod verify;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watch;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod watchdog;
+#[cfg(target_arch = "wasm32")]
+pub mod web;
+pub mod websocket;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod window;
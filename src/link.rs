@@ -0,0 +1,105 @@
+use crate::chip8::{Chip8, Serial};
+use log::{error, info};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+/*    Link Cable
+ * FX70  SEND Vx   Queue Vx for the other machine
+ * FX71  RECV Vx   Vx = the next byte received, VF = 1 if there was one,
+ *                 VF = 0 and Vx untouched if not
+ *
+ * Bytes arrive in order but whenever the other side sends them, so programs
+ * poll RECV, e.g. until a handshake byte turns up. Nothing is buffered while
+ * the cable is unplugged: sends are dropped like on real hardware. Both
+ * opcodes do nothing at all without --link-host or --link-join.
+ */
+// Polled from the event loop. The host waits for a peer and takes a new one
+// if the first leaves, the other side connects once at startup.
+pub struct LinkCable {
+    listener: Option<TcpListener>,
+    stream: Option<TcpStream>,
+}
+
+impl LinkCable {
+    pub fn host(address: &str) -> io::Result<LinkCable> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        info!("Link cable waiting on {}", address);
+        Ok(LinkCable {
+            listener: Some(listener),
+            stream: None,
+        })
+    }
+
+    pub fn join(address: &str) -> io::Result<LinkCable> {
+        let stream = TcpStream::connect(address)?;
+        connected(&stream)?;
+        info!("Link cable connected to {}", address);
+        Ok(LinkCable {
+            listener: None,
+            stream: Some(stream),
+        })
+    }
+
+    pub fn poll(&mut self, cpu: &mut Chip8) {
+        if let (None, Some(listener)) = (&self.stream, &self.listener) {
+            match listener.accept() {
+                Ok((stream, peer)) => match connected(&stream) {
+                    Ok(()) => {
+                        info!("Link cable connected to {}", peer);
+                        self.stream = Some(stream);
+                    }
+                    Err(e) => error!("Link cable connection failed: {}", e),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(e) => error!("Link cable accept failed: {}", e),
+            }
+        }
+        // Plugging the machine in is what makes FX70 and FX71 do something
+        let serial = cpu.serial.get_or_insert_with(Serial::default);
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => {
+                serial.outgoing.clear();
+                return;
+            }
+        };
+        if let Err(e) = transfer(stream, serial) {
+            match e.kind() {
+                ErrorKind::UnexpectedEof => info!("Link cable unplugged"),
+                _ => error!("Link cable failed: {}", e),
+            }
+            serial.outgoing.clear();
+            self.stream = None;
+        }
+    }
+}
+
+fn connected(stream: &TcpStream) -> io::Result<()> {
+    // Bytes are sent one or two at a time, and games wait on them
+    stream.set_nodelay(true)?;
+    stream.set_nonblocking(true)
+}
+
+// Send what's queued and take what's arrived, without waiting for either
+fn transfer(stream: &mut TcpStream, serial: &mut Serial) -> io::Result<()> {
+    while !serial.outgoing.is_empty() {
+        match stream.write(serial.outgoing.make_contiguous()) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(len) => {
+                serial.outgoing.drain(..len);
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => return Err(e),
+        }
+    }
+    let mut chunk = [0; 256];
+    loop {
+        match stream.read(&mut chunk) {
+            Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+            Ok(len) => serial.incoming.extend(&chunk[..len]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use crate::disasm::disassemble;
+use crate::info::{extension, reachable};
+use crate::variant::Variant;
+use std::fmt;
+
+// Programs are loaded at 0x200, below that is the interpreter and font
+const START: usize = 0x200;
+
+pub struct Warning {
+    pub addr: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.addr {
+            Some(addr) => write!(f, "{:#05x}: {}", addr, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+// Instructions and addresses the variant can't handle, found by following
+// the code reachable from 0x200
+pub fn lint(rom: &[u8], variant: Variant) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let available = variant.memory_end() - START;
+    if rom.len() > available {
+        warnings.push(Warning {
+            addr: None,
+            message: format!(
+                "ROM is {} bytes but {} only has room for {}",
+                rom.len(),
+                variant,
+                available
+            ),
+        });
+    }
+    if rom.len() < 2 {
+        return warnings;
+    }
+
+    let rom_end = START + rom.len();
+    for addr in reachable(rom) {
+        let offset = addr - START;
+        let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+        let nnn = (opcode & 0x0FFF) as usize;
+        let mut warn = |message: String| {
+            warnings.push(Warning {
+                addr: Some(addr),
+                message: format!("{:<16} {}", disassemble(opcode), message),
+            })
+        };
+        if let Some((required, class)) = extension(opcode) {
+            if !variant.supports(required) {
+                warn(format!("{} is {} only", class, required));
+            }
+            continue;
+        }
+        match opcode & 0xF000 {
+            0x0000 if opcode != 0x00E0 && opcode != 0x00EE => match variant {
+                Variant::Chip8 => warn(String::from("calls a machine code routine")),
+                _ => warn(format!("machine code routines don't run on {}", variant)),
+            },
+            0x1000 | 0x2000 | 0xB000 if nnn < START => {
+                warn(String::from("jumps into the interpreter area"))
+            }
+            0x1000 | 0x2000 if nnn >= rom_end => {
+                warn(String::from("jumps past the end of the ROM"))
+            }
+            0xA000 if nnn >= variant.memory_end() => warn(format!(
+                "I points at memory reserved by the {} interpreter",
+                variant
+            )),
+            _ => {}
+        }
+    }
+    warnings
+}
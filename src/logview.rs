@@ -0,0 +1,176 @@
+use crate::osd;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+// Most lines kept for the console
+const CAPACITY: usize = 1000;
+// The top row shows the filters
+const LOG_ROWS: usize = osd::ROWS - 1;
+
+// Shared by every window
+static LINES: Mutex<VecDeque<Line>> = Mutex::new(VecDeque::new());
+
+struct Line {
+    level: Level,
+    target: String,
+    message: String,
+}
+
+struct Logger {
+    inner: env_logger::Logger,
+}
+
+// env_logger as before, configured by RUST_LOG, and everything from info up
+// also kept for the on-screen console, so there's something to read without
+// a terminal
+pub fn init() {
+    let inner = env_logger::Builder::from_default_env().build();
+    log::set_max_level(inner.filter().max(LevelFilter::Info));
+    log::set_boxed_logger(Box::new(Logger { inner })).expect("the logger is only set once");
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Info || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if self.inner.matches(record) {
+            self.inner.log(record);
+        }
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut lines = LINES.lock().unwrap_or_else(|e| e.into_inner());
+        if lines.len() == CAPACITY {
+            lines.pop_front();
+        }
+        lines.push_back(Line {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        });
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/*    Log Console
+ * INFO+ ALL          Lowest level shown, and the module or ALL
+ * I WINDOW: LOADED   Level initial, module, message, wrapped at 16
+ * ...
+ *
+ * Drawn over the dimmed display, newest at the bottom. Each window keeps
+ * its own filters and scroll position.
+ */
+#[derive(Debug)]
+pub struct LogView {
+    pub open: bool,
+    level: LevelFilter,
+    module: Option<String>,
+    // Rows up from the newest
+    scroll: usize,
+}
+
+impl Default for LogView {
+    fn default() -> LogView {
+        LogView {
+            open: false,
+            level: LevelFilter::Trace,
+            module: None,
+            scroll: 0,
+        }
+    }
+}
+
+impl LogView {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.scroll = 0;
+    }
+
+    // Positive rows go back in time
+    pub fn scroll(&mut self, rows: isize) {
+        let most = self.rows().len().saturating_sub(LOG_ROWS);
+        self.scroll = self.scroll.saturating_add_signed(rows).min(most);
+    }
+
+    // Everything, then error only, then down a level at a time
+    pub fn next_level(&mut self) {
+        self.level = match self.level {
+            LevelFilter::Trace => LevelFilter::Error,
+            LevelFilter::Error => LevelFilter::Warn,
+            LevelFilter::Warn => LevelFilter::Info,
+            LevelFilter::Info => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        };
+        self.scroll = 0;
+    }
+
+    // Every module, then each one that has logged in turn
+    pub fn next_module(&mut self) {
+        let mut modules: Vec<String> = LINES
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|line| line.target.clone())
+            .collect();
+        modules.sort();
+        modules.dedup();
+        let next = match &self.module {
+            Some(module) => modules
+                .iter()
+                .position(|name| name == module)
+                .map(|i| i + 1),
+            None => Some(0),
+        };
+        self.module = next.and_then(|i| modules.get(i)).cloned();
+        self.scroll = 0;
+    }
+
+    // Paint over a 64 pixel wide RGBA frame
+    pub fn draw(&self, frame: &mut [u8], colors: ([u8; 4], [u8; 4])) {
+        osd::dim(frame);
+        let level = match self.level {
+            LevelFilter::Trace => String::from("ALL"),
+            level => format!("{}+", level),
+        };
+        let module = self.module.as_deref().map_or("ALL", short_name);
+        let rows = self.rows();
+        let end = rows.len() - self.scroll.min(rows.len());
+        let mut lines = vec![format!("{} {}", level, module)];
+        lines.extend_from_slice(&rows[end.saturating_sub(LOG_ROWS)..end]);
+        osd::draw_lines(frame, &lines, colors);
+    }
+
+    // Lines passing the filters, wrapped to the display
+    fn rows(&self) -> Vec<String> {
+        let lines = LINES.lock().unwrap_or_else(|e| e.into_inner());
+        lines
+            .iter()
+            .filter(|line| line.level <= self.level)
+            .filter(|line| {
+                self.module
+                    .as_ref()
+                    .is_none_or(|module| *module == line.target)
+            })
+            .flat_map(|line| {
+                let initial = line.level.as_str().chars().next().unwrap_or(' ');
+                osd::wrap(&format!(
+                    "{} {}: {}",
+                    initial,
+                    short_name(&line.target),
+                    line.message
+                ))
+            })
+            .collect()
+    }
+}
+
+// rust_chip8::window to window
+fn short_name(target: &str) -> &str {
+    target.rsplit("::").next().unwrap_or(target)
+}
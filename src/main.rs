@@ -1,518 +1,1884 @@
-use log::{debug, error};
-use pixels::{Error, Pixels, SurfaceTexture};
-use rand::Rng;
-use std::thread::current;
-use winit::dpi::LogicalSize;
-use winit::event::{Event, VirtualKeyCode};
-use winit::event_loop::{ControlFlow, EventLoop};
-use winit::window::WindowBuilder;
-use winit_input_helper::WinitInputHelper;
-
-const WIDTH: u32 = 64;
-const HEIGHT: u32 = 32;
-
-#[derive(Debug)]
-enum EmulationStatus {
-    Running,
-    WaitingForKey,
-}
-
-#[derive(Debug)]
-struct Chip8 {
-    status: EmulationStatus,
-    pc: usize,                   // Program Counter
-    sp: usize,                   // Stack Pointer
-    memory: [u8; 4000],          // 4000 Bytes - Standard Chip8
-    registers: [u8; 16],         // 0xF is Flag Register
-    address_reg: u16,            // Technically 12-bits
-    stack: [usize; 12],          // 12 levels of nesting
-    delay_timer: u8,             // Ticks down at 60 hz
-    sound_timer: u8,             // Ticks down at 60 hz
-    input: u8,                   // Only one button at any time
-    video_buffer: [u8; 64 * 32], // 1 Byte per Pixel
-    redraw: bool,                // Flag for redraw request on video_buffer change
-}
-impl Chip8 {
-    fn tick(&mut self) {
-        self.exec_opcode();
-    }
-
-    fn exec_opcode(&mut self) {
-        // Break out the opcodes into four nibbles for pattern matching
-        let high_byte: u8 = self.memory[self.pc];
-        let low_byte: u8 = self.memory[self.pc + 1];
-
-        let opcode = ((high_byte as u16) << 8) | low_byte as u16;
-        let nibbles = (
-            (opcode & 0xF000) >> 12 as u8,
-            (opcode & 0x0F00) >> 8 as u8,
-            (opcode & 0x00F0) >> 4 as u8,
-            (opcode & 0x000F) as u8,
+use clap::builder::RangedU64ValueParser;
+use clap::{Args, Parser, Subcommand};
+use log::{error, info, warn};
+use pixels::Error;
+use rust_chip8::achievements::Achievements;
+use rust_chip8::analysis::{self, Class};
+use rust_chip8::attract::{self, Attract};
+use rust_chip8::battery::Battery;
+use rust_chip8::chat::{self, ChatInput};
+use rust_chip8::cheats::Cheats;
+use rust_chip8::chip8::{Chip8, Quirks, MAX_ROM_SIZE, MAX_RUN_AHEAD};
+use rust_chip8::config::{self, Config, Game};
+use rust_chip8::debugger::Debugger;
+use rust_chip8::headless::Headless;
+use rust_chip8::keypad::Keymap;
+use rust_chip8::led::LedMatrix;
+use rust_chip8::link::LinkCable;
+use rust_chip8::metrics::MetricsServer;
+use rust_chip8::monitor::MonitorServer;
+use rust_chip8::netplay::{self, Netplay};
+use rust_chip8::symbols::Symbols;
+use rust_chip8::telemetry::Telemetry;
+use rust_chip8::variant::Variant;
+use rust_chip8::watch::RomWatcher;
+use rust_chip8::watchdog::Watchdog;
+use rust_chip8::websocket::{self, WebSocketServer};
+#[cfg(feature = "dap")]
+use rust_chip8::dap;
+#[cfg(feature = "download")]
+use rust_chip8::download;
+#[cfg(feature = "framebuffer")]
+use rust_chip8::framebuffer;
+#[cfg(feature = "gdb")]
+use rust_chip8::gdb;
+#[cfg(feature = "http")]
+use rust_chip8::http;
+#[cfg(feature = "plugins")]
+use rust_chip8::plugin::Plugins;
+#[cfg(feature = "scripting")]
+use rust_chip8::scripting::Script;
+#[cfg(feature = "sdl2")]
+use rust_chip8::sdl;
+#[cfg(feature = "terminal")]
+use rust_chip8::terminal;
+#[cfg(feature = "timeline")]
+use rust_chip8::timeline::Timeline;
+use rust_chip8::osd::{self, Osd};
+use rust_chip8::playlist::Playlist;
+use rust_chip8::screensaver::{Asleep, Screensaver};
+use rust_chip8::window::{self, Instance, Load};
+use rust_chip8::{
+    asm, cart, compare, database, decompile, demo, diff, disasm, golden, info, lint, logview, octo,
+    patch, screenshot, sheet, snapshot, soak, state, stats, suite, verify,
+};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+const DATABASE_PATH: &str = "./database";
+const SUITE_PATH: &str = "./test-roms";
+const CONFIG_PATH: &str = "./chip8.toml";
+const CONTROL_ADDRESS: &str = "127.0.0.1:4712";
+// Window size in display pixels
+const DEFAULT_SCALE: u32 = 10;
+#[cfg(feature = "dap")]
+const DAP_ADDRESS: &str = "127.0.0.1:4711";
+#[cfg(feature = "framebuffer")]
+const FRAMEBUFFER_PATH: &str = "/dev/fb0";
+#[cfg(feature = "gdb")]
+const GDB_ADDRESS: &str = "127.0.0.1:9000";
+#[cfg(feature = "http")]
+const HTTP_ADDRESS: &str = "127.0.0.1:4714";
+// Stands in for the path of a ROM posted to the HTTP API
+#[cfg(feature = "http")]
+const HTTP_ROM: &str = "ROM from HTTP";
+
+// chip8 [run] [rom].. [options], or one of the tools, with the machine
+// options before or after the command
+#[derive(Parser)]
+#[command(name = "chip8", version, about = "CHIP-8 emulator and tools")]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    run: Box<RunArgs>,
+    #[command(flatten)]
+    machine: MachineArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Run ROMs, each in its own window (the default)")]
+    Run(Box<RunArgs>),
+    #[command(about = "List a ROM's instructions")]
+    Disasm { rom: String },
+    #[command(about = "Lift a ROM into Octo source with labels, loops and sprites")]
+    Decompile { rom: String },
+    #[command(about = "Save the sprites a ROM draws as a PNG sheet labelled with their addresses")]
+    Sprites {
+        rom: String,
+        #[arg(short, long, help = "Defaults to <rom>.sprites.png")]
+        output: Option<PathBuf>,
+        #[arg(long, help = "Add data nothing is seen drawing, marked with a ?")]
+        guess: bool,
+    },
+    #[command(about = "Assemble mnemonics, or Octo for .8o files, into a ROM")]
+    Asm {
+        source: String,
+        #[arg(short, long, help = "Defaults to the source with a .ch8 extension")]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Hashes, opcode usage and the variant a ROM needs")]
+    Info { rom: String },
+    #[command(about = "Check a ROM against --variant, exiting with 1 on any warning")]
+    Lint { rom: String },
+    #[command(about = "Apply an IPS or BPS patch to a ROM")]
+    Patch {
+        rom: String,
+        patch: String,
+        #[arg(short, long, help = "Defaults to <rom>.patched.ch8")]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Run a ROM as fast as possible and report the speed")]
+    Bench {
+        rom: String,
+        #[arg(long, default_value_t = 3600)]
+        frames: u64,
+    },
+    #[command(about = "Print a ROM's screen and hash after running it, or run the test suite")]
+    Test {
+        #[arg(help = "Runs every test in --suite without one")]
+        rom: Option<String>,
+        #[arg(long, default_value_t = 600)]
+        frames: u64,
+        #[arg(long, requires = "rom",
+            help = "crc32 the screen must have, exiting with 1 if it doesn't")]
+        expect: Option<String>,
+        #[arg(long, value_name = "DIR", default_value = SUITE_PATH,
+            help = "Test ROMs and the suite.toml listing them")]
+        suite: PathBuf,
+        #[arg(long, conflicts_with = "rom",
+            help = "Save each test's screen hash as the known-good one")]
+        record: bool,
+    },
+    #[command(about = "Run a ROM without a window and report what it did")]
+    Stats {
+        rom: String,
+        #[arg(long, default_value_t = 600)]
+        frames: u64,
+        #[arg(long, help = "Print the statistics as JSON, for scripts")]
+        json: bool,
+    },
+    #[command(about = "Run every ROM in a folder and report faults, crashes and blank screens")]
+    Soak {
+        dir: PathBuf,
+        #[arg(long, default_value_t = 3600, help = "Frames to run each ROM for")]
+        frames: u64,
+    },
+    #[command(about = "Print a line for each instruction a ROM runs, for compare-trace")]
+    Trace {
+        rom: String,
+        #[arg(long, default_value_t = 600)]
+        frames: u64,
+        #[arg(short, long, help = "Defaults to the terminal")]
+        output: Option<PathBuf>,
+    },
+    #[command(about = "Find the first instruction two traces disagree on, exiting with 1 if any")]
+    CompareTrace {
+        #[arg(help = "Golden trace, or one from another emulator")]
+        expected: PathBuf,
+        actual: PathBuf,
+    },
+    #[command(about = "Run a ROM twice with the same seed and keys, exiting with 1 if they differ")]
+    Verify {
+        rom: String,
+        #[arg(long, default_value_t = 3600)]
+        frames: u64,
+        #[arg(long, default_value_t = 0, help = "Seeds the random numbers and the keys pressed")]
+        seed: u64,
+    },
+    #[command(about = "Run a ROM on two machines in lockstep and report where they first differ")]
+    Compare {
+        rom: String,
+        #[arg(help = "Variant and or quirks, e.g. chip8, schip,no-jump or no-shift")]
+        a: String,
+        b: String,
+        #[arg(long, default_value_t = 3600)]
+        frames: u64,
+        #[arg(long, help = "Save the two displays side by side, differences in red")]
+        png: Option<PathBuf>,
+    },
+    #[command(about = "Show what changed between two save states, exiting with 1 if any")]
+    StateDiff {
+        before: PathBuf,
+        after: PathBuf,
+        #[arg(long, help = "Save the two displays side by side, differences in red")]
+        png: Option<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+struct MachineArgs {
+    #[arg(long, global = true, value_parser = RangedU64ValueParser::<usize>::new().range(1..),
+        help = "Instructions per 60 Hz frame [default: 10]")]
+    speed: Option<usize>,
+    #[arg(long, global = true, default_value_t = DEFAULT_SCALE,
+        value_parser = clap::value_parser!(u32).range(1..), help = "Window size in display pixels")]
+    scale: u32,
+    #[arg(long, global = true, value_parser = parse_variant,
+        help = "chip8, schip or xo-chip, for linting and its usual quirks")]
+    variant: Option<Variant>,
+    #[arg(long, global = true,
+        help = "Quirks to turn on, or off with no-: vf-reset, shift, memory, jump, clip")]
+    quirks: Option<String>,
+    #[arg(long, global = true, value_name = "FILE", default_value = CONFIG_PATH,
+        help = "Per-game settings, applied when that ROM loads")]
+    config: PathBuf,
+    #[arg(long, global = true, value_name = "FRAMES",
+        value_parser = RangedU64ValueParser::<usize>::new().range(0..=MAX_RUN_AHEAD as u64),
+        help = "Show the game this many frames ahead, for quicker controls [default: 0]")]
+    run_ahead: Option<usize>,
+}
+
+#[derive(Args)]
+struct RunArgs {
+    #[arg(help = "ROMs or Octo sources, each in its own window [default: demo, built in]")]
+    roms: Vec<String>,
+    #[arg(long, help = "IPS or BPS patch to apply, repeatable")]
+    patch: Vec<String>,
+    #[arg(long, conflicts_with_all = ["sdl", "fb", "headless"], help = "Run in the terminal")]
+    tui: bool,
+    #[arg(long, conflicts_with_all = ["fb", "headless"], help = "Run in an SDL2 window")]
+    sdl: bool,
+    #[arg(long, conflicts_with = "headless", help = "Run on the Linux framebuffer")]
+    fb: bool,
+    #[arg(long, help = "Run without a display, driven over the control socket")]
+    headless: bool,
+    #[arg(long, conflicts_with = "tui",
+        help = "Start paused at 0x200, to set breakpoints before anything runs")]
+    start_paused: bool,
+    #[arg(long, group = "limit", help = "Exit after this many frames")]
+    frames: Option<u64>,
+    #[arg(long, group = "limit", help = "Exit after this many seconds of emulated time")]
+    seconds: Option<f64>,
+    #[arg(long, value_name = "FILE",
+        help = "Write a save state on exiting after --frames, --seconds or --screenshot-at")]
+    dump_state: Option<PathBuf>,
+    #[arg(long, value_name = "FRAME",
+        help = "Save a PNG of the display at this frame, then exit after the last one")]
+    screenshot_at: Vec<u64>,
+    #[arg(long, value_name = "FILE", help = "Where to save each --screenshot-at, in order")]
+    out: Vec<PathBuf>,
+    #[arg(long, value_name = "DEVICE", help = "Mirror the display to an LED matrix")]
+    led: Option<String>,
+    #[arg(long, value_name = "ADDRESS", help = "Stream to browsers that can play")]
+    websocket: Option<String>,
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "websocket",
+        help = "Stream to browsers that only watch")]
+    broadcast: Option<String>,
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "join", help = "Host a netplay game")]
+    host: Option<String>,
+    #[arg(long, value_name = "ADDRESS", help = "Join a netplay game")]
+    join: Option<String>,
+    #[arg(long, value_name = "FRAMES", default_value_t = netplay::DEFAULT_DELAY,
+        help = "Netplay input delay")]
+    delay: usize,
+    #[arg(long, value_name = "CHANNEL", help = "Take votes for keys from a Twitch chat")]
+    twitch: Option<String>,
+    #[arg(long, value_name = "MS", help = "Twitch vote window, 0 for anarchy [default: 1000]")]
+    vote: Option<u64>,
+    #[arg(long, value_name = "ADDRESS", help = "Serve the debugger console over TCP")]
+    monitor: Option<String>,
+    #[arg(long, value_name = "ADDRESS", help = "Export Prometheus metrics")]
+    metrics: Option<String>,
+    #[arg(long, value_name = "FILE", help = "Run a Rhai script alongside the game")]
+    script: Option<String>,
+    #[arg(long, value_name = "LIBRARY", help = "Load a native plugin, repeatable")]
+    plugin: Vec<String>,
+    #[arg(long, value_name = "FILE",
+        help = "Write how many of each kind of instruction ran on exiting")]
+    mix: Option<PathBuf>,
+    #[arg(long, value_name = "FILE",
+        help = "Write a timeline of frames, instructions and drawing as Chrome trace JSON")]
+    timeline: Option<PathBuf>,
+    #[arg(long, value_name = "FILE",
+        help = "Write a CSV row of counters and the state hash for every frame")]
+    telemetry: Option<PathBuf>,
+    #[arg(long, value_name = "ADDRESS", conflicts_with = "link_join",
+        help = "Wait for another instance on the link cable")]
+    link_host: Option<String>,
+    #[arg(long, value_name = "ADDRESS", help = "Connect the link cable to another instance")]
+    link_join: Option<String>,
+    #[arg(long, conflicts_with_all = ["tui", "sdl", "fb", "headless", "host", "join"],
+        help = "Reload each ROM when its file is written, e.g. by an assembler")]
+    watch: bool,
+    #[arg(long, value_name = "DIR", group = "rotation",
+        conflicts_with_all = ["roms", "tui", "sdl", "fb", "headless", "host", "join", "limit",
+            "screenshot_at", "watch"],
+        help = "Run each ROM in a folder in turn, for kiosks")]
+    attract: Option<PathBuf>,
+    #[arg(long, value_name = "DIR", group = "rotation",
+        conflicts_with_all = ["tui", "sdl", "fb", "headless", "host", "join", "limit",
+            "screenshot_at", "watch", "split", "versus"],
+        help = "Run each ROM in a folder in turn full screen, until a key or the mouse moves")]
+    screensaver: Option<PathBuf>,
+    #[arg(long, value_name = "SECONDS", requires = "screensaver",
+        help = "Run the ROMs given, and the screensaver after this long without a key")]
+    idle: Option<u64>,
+    #[arg(long, value_name = "SECONDS", default_value_t = attract::DEFAULT_SECONDS,
+        requires = "rotation", help = "How long attract mode or the screensaver runs each ROM")]
+    attract_seconds: u64,
+    #[arg(long, value_name = "QUIRKS",
+        conflicts_with_all = ["tui", "sdl", "fb", "headless", "host", "join", "watch", "attract"],
+        help = "Run a copy beside the ROM with other quirks, e.g. schip or no-shift")]
+    split: Option<String>,
+    #[arg(long, conflicts_with_all = ["tui", "sdl", "fb", "headless", "host", "join", "watch",
+            "attract", "split"],
+        help = "Two players race the ROM side by side, the second on the right of the keyboard")]
+    versus: bool,
+    #[arg(long, conflicts_with_all = ["tui", "sdl", "fb", "headless", "host", "join", "watch",
+            "rotation", "split", "versus"],
+        help = "Play the ROMs and .m3u lists of them in one window, Ctrl+PageDown for the next")]
+    playlist: bool,
+    #[arg(long, value_name = "SECONDS", conflicts_with_all = ["tui", "sdl", "headless", "host",
+            "join"],
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Restart or skip a ROM once the display and keys are still this long")]
+    watchdog: Option<u64>,
+}
+
+// Settings from the global options, for every machine started
+#[derive(Clone)]
+struct Machine {
+    // Only when given, so a game's config can set it otherwise
+    speed: Option<usize>,
+    run_ahead: Option<usize>,
+    scale: u32,
+    variant: Variant,
+    // The variant's, before the game's config and --quirks
+    quirks: Quirks,
+    quirk_list: Option<String>,
+    config: Config,
+    config_path: PathBuf,
+}
+
+fn main() -> Result<(), Error> {
+    logview::init();
+    let cli = Cli::parse();
+    // Quirks start from the variant's when one is given, otherwise the core's own
+    let quirks = cli.machine.variant.map(Variant::quirks).unwrap_or_default();
+    // --quirks is applied per ROM, after the game's config, but checked now
+    if let Some(Err(e)) = cli.machine.quirks.as_ref().map(|list| quirks.parse(list)) {
+        eprintln!("{}", e);
+        std::process::exit(2);
+    }
+    let config = Config::load(&cli.machine.config).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let machine = Machine {
+        speed: cli.machine.speed,
+        run_ahead: cli.machine.run_ahead,
+        scale: cli.machine.scale,
+        variant: cli.machine.variant.unwrap_or_default(),
+        quirks,
+        quirk_list: cli.machine.quirks,
+        config,
+        config_path: cli.machine.config,
+    };
+    match cli.command.unwrap_or(Command::Run(cli.run)) {
+        Command::Run(args) => return run(&args, &machine),
+        Command::Disasm { rom } => disassemble_rom(&rom),
+        Command::Decompile { rom } => print!("{}", decompile::decompile(&read_or_exit(&rom))),
+        Command::Sprites { rom, output, guess } => save_sprites(&rom, output, guess),
+        Command::Asm { source, output } => assemble_file(&source, output),
+        Command::Info { rom } => println!("{}", info::report(&read_or_exit(&rom))),
+        Command::Lint { rom } => lint_rom(&rom, machine.variant),
+        Command::Patch { rom, patch, output } => patch_file(&rom, &patch, output),
+        Command::Bench { rom, frames } => bench(&rom, frames, &machine),
+        Command::Test {
+            rom: Some(rom),
+            frames,
+            expect,
+            ..
+        } => test_rom(&rom, frames, expect, &machine),
+        Command::Test {
+            rom: None,
+            suite,
+            record,
+            ..
+        } => test_suite(&suite, record, &machine),
+        Command::Stats { rom, frames, json } => rom_stats(&rom, frames, json, &machine),
+        Command::Soak { dir, frames } => soak_dir(&dir, frames, &machine),
+        Command::Trace {
+            rom,
+            frames,
+            output,
+        } => trace_rom(&rom, frames, output, &machine),
+        Command::CompareTrace { expected, actual } => compare_traces(&expected, &actual),
+        Command::Verify { rom, frames, seed } => verify_rom(&rom, frames, seed, &machine),
+        Command::Compare {
+            rom,
+            a,
+            b,
+            frames,
+            png,
+        } => compare_quirks(&rom, [&a, &b], frames, png.as_deref(), &machine),
+        Command::StateDiff { before, after, png } => diff_states(&before, &after, png.as_deref()),
+    }
+    Ok(())
+}
+
+fn parse_variant(name: &str) -> Result<Variant, String> {
+    Variant::parse(name).ok_or_else(|| String::from("expected chip8, schip or xo-chip"))
+}
+
+fn run(args: &RunArgs, machine: &Machine) -> Result<(), Error> {
+    let mut rom_paths: Vec<&str> = args.roms.iter().map(String::as_str).collect();
+    // One window that moves through the ROMs, instead of a window each
+    let playlist = args.playlist.then(|| {
+        Playlist::open(&rom_paths).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+    let first_rom = playlist.as_ref().map(|list| list.current().to_string());
+    if let Some(rom_path) = &first_rom {
+        rom_paths = vec![rom_path.as_str()];
+    }
+    let patches = &args.patch;
+    let (tui, sdl, fb, headless) = (args.tui, args.sdl, args.fb, args.headless);
+    let led_path = args.led.as_deref();
+    let (websocket_address, read_only) = match &args.broadcast {
+        Some(address) => (Some(address.as_str()), true),
+        None => (args.websocket.as_deref(), false),
+    };
+    let twitch_channel = args.twitch.as_deref();
+    let vote_window = args
+        .vote
+        .map_or(chat::DEFAULT_WINDOW, Duration::from_millis);
+    let monitor_address = args.monitor.as_deref();
+    let metrics_address = args.metrics.as_deref();
+    let script_path = args.script.as_deref();
+    let plugin_paths: Vec<&str> = args.plugin.iter().map(String::as_str).collect();
+    let (host_address, join_address) = (args.host.as_deref(), args.join.as_deref());
+    let delay = args.delay;
+    let (link_host, link_join) = (args.link_host.as_deref(), args.link_join.as_deref());
+    // Counted in emulated frames rather than on the clock, so a run stops at
+    // the same point every time
+    let limit = args
+        .frames
+        .or(args.seconds.map(|seconds| (seconds * 60.0).round().max(0.0) as u64));
+    let dump_path = args.dump_state.clone();
+    if args.screenshot_at.len() != args.out.len() {
+        eprintln!("Each --screenshot-at needs its own --out");
+        std::process::exit(2);
+    }
+    let mut shots: Vec<(u64, PathBuf)> =
+        args.screenshot_at.iter().copied().zip(args.out.iter().cloned()).collect();
+    shots.sort_by_key(|(frame, _)| *frame);
+    // The run goes on to whichever comes last, None being less than any Some
+    let limit = limit.max(shots.last().map(|(frame, _)| *frame));
+    if dump_path.is_some() && limit.is_none() {
+        eprintln!("--dump-state needs --frames, --seconds or --screenshot-at");
+        std::process::exit(2);
+    }
+    if args.screensaver.is_some() && args.idle.is_none() && !rom_paths.is_empty() {
+        eprintln!("--screensaver runs on its own, or after the ROMs given with --idle");
+        std::process::exit(2);
+    }
+    // A screensaver without --idle is attract mode that input ends
+    let asleep = args.screensaver.is_some() && args.idle.is_none();
+    let rotation = match asleep {
+        true => args.screensaver.as_ref(),
+        false => args.attract.as_ref(),
+    };
+    let mut attract = rotation.map(|dir| {
+        Attract::open(dir, args.attract_seconds).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+    let mut screensaver = match (&args.screensaver, args.idle) {
+        (Some(dir), Some(seconds)) => Some(
+            Screensaver::open(dir, args.attract_seconds, Duration::from_secs(seconds))
+                .unwrap_or_else(|e| {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }),
+        ),
+        _ => None,
+    };
+    // Without a ROM the window opens on the recent ones, when there are some
+    let start_screen = rom_paths.is_empty()
+        && attract.is_none()
+        && !machine.config.recent.is_empty()
+        && !(tui || sdl || fb || headless)
+        && host_address.or(join_address).is_none()
+        && limit.is_none()
+        && args.split.is_none()
+        && !args.versus;
+    if rom_paths.is_empty() && !start_screen && attract.is_none() {
+        rom_paths.push(demo::NAME);
+    }
+    // Patches and the single-screen frontends only make sense for one ROM
+    if rom_paths.len() > 1 && (!patches.is_empty() || tui || sdl || fb || headless) {
+        eprintln!("--patch, --tui, --sdl, --fb and --headless only work with a single ROM");
+        std::process::exit(2);
+    }
+    if (args.split.is_some() || args.versus) && rom_paths.len() > 1 {
+        eprintln!("--split and --versus only work with a single ROM");
+        std::process::exit(2);
+    }
+    if let Some(spec) = &args.split {
+        if let Err(e) = compare::config(machine.quirks, spec) {
+            eprintln!("{}", e);
+            std::process::exit(2);
+        }
+    }
+    if websocket_address.is_some() && (tui || sdl || fb) {
+        eprintln!("--websocket and --broadcast work with the window or --headless");
+        std::process::exit(2);
+    }
+    let window_only = twitch_channel.is_some()
+        || monitor_address.is_some()
+        || metrics_address.is_some()
+        || script_path.is_some()
+        || !plugin_paths.is_empty()
+        || link_host.is_some()
+        || link_join.is_some();
+    if window_only && (tui || sdl || fb || headless) {
+        eprintln!(
+            "--twitch, --monitor, --metrics, --script, --plugin and --link-* work with the window"
+        );
+        std::process::exit(2);
+    }
+    if limit.is_some() && (tui || sdl || fb || (headless && websocket_address.is_some())) {
+        eprintln!(
+            "--frames, --seconds and --screenshot-at work with the window or --headless on its own"
         );
-        let nnn: usize = (opcode & 0x0FFF).into();
-        let nn: usize = (opcode & 0x00FF).into();
-        let x: usize = nibbles.1.into();
-        let y: usize = nibbles.2.into();
-        let n: usize = nibbles.3.into();
-
-        self.pc = match nibbles {
-            (0x00, 0x00, 0x0E, 0x00) => self.opcode_00e0(),
-            (0x00, 0x00, 0x0E, 0x0E) => self.opcode_00ee(),
-            (0x01, _, _, _) => self.opcode_1nnn(nnn),
-            (0x02, _, _, _) => self.opcode_2nnn(nnn),
-            (0x03, _, _, _) => self.opcode_3xnn(x, nn),
-            (0x04, _, _, _) => self.opcode_4xnn(x, nn),
-            (0x05, _, _, _) => self.opcode_5xnn(x, y),
-            (0x06, _, _, _) => self.opcode_6xnn(x, nn),
-            (0x07, _, _, _) => self.opcode_7xnn(x, nn),
-            (0x08, _, _, 0x00) => self.opcode_8xy0(x, y),
-            (0x08, _, _, 0x01) => self.opcode_8xy1(x, y),
-            (0x08, _, _, 0x02) => self.opcode_8xy2(x, y),
-            (0x08, _, _, 0x03) => self.opcode_8xy3(x, y),
-            (0x08, _, _, 0x04) => self.opcode_8xy4(x, y),
-            (0x08, _, _, 0x05) => self.opcode_8xy5(x, y),
-            (0x08, _, _, 0x06) => self.opcode_8xy6(x),
-            (0x08, _, _, 0x07) => self.opcode_8xy7(x, y),
-            (0x08, _, _, 0x0E) => self.opcode_8xye(x),
-            (0x09, _, _, _) => self.opcode_9xy0(x, y),
-            (0x0A, _, _, _) => self.opcode_annn(nnn),
-            (0x0B, _, _, _) => self.opcode_bnnn(nnn),
-            (0x0C, _, _, _) => self.opcode_cxnn(x, nn),
-            (0x0D, _, _, _) => self.opcode_dxyn(x, y, n),
-            (0x0E, _, 0x09, 0x0E) => self.opcode_ex9e(x),
-            (0x0E, _, 0x0A, 0x01) => self.opcode_exa1(x),
-            (0x0F, _, 0x00, 0x07) => self.opcode_fx07(x),
-            (0x0F, _, 0x00, 0x0A) => self.opcode_fx0a(x),
-            (0x0F, _, 0x01, 0x05) => self.opcode_fx15(x),
-            (0x0F, _, 0x01, 0x08) => self.opcode_fx18(x),
-            (0x0F, _, 0x01, 0x0E) => self.opcode_fx1e(x),
-            (0x0F, _, 0x02, 0x09) => self.opcode_fx29(x),
-            (0x0F, _, 0x03, 0x03) => self.opcode_fx33(x),
-            (0x0F, _, 0x05, 0x05) => self.opcode_fx55(x),
-            (0x0F, _, 0x06, 0x05) => self.opcode_fx65(x),
-            _ => self.pc, // Do Nothing
-        }
-    }
-    // Clear Screen
-    fn opcode_00e0(&mut self) -> usize {
-        debug!("00E0, Clear Screen");
-        self.video_buffer = [0; 32 * 64];
-        self.redraw = true;
-        self.pc + 2
-    }
-    // Return
-    fn opcode_00ee(&mut self) -> usize {
-        debug!("00EE, Return");
-        let pc = self.stack[self.sp as usize] as u16;
-        self.sp -= 1;
-        pc.into()
-    }
-    // Jump to nnn
-    fn opcode_1nnn(&mut self, nnn: usize) -> usize {
-        debug!("1NNN, Jmp to {:#04x}", nnn);
-        nnn.into()
-    }
-    // Call sub-routine at nnn
-    fn opcode_2nnn(&mut self, nnn: usize) -> usize {
-        log::debug!("2NNN, Call {:#04x}", nnn);
-        self.sp += 1;
-        self.stack[self.sp] = self.pc + 2;
-        nnn.into()
-    }
-    // If (Vx == NN)
-    fn opcode_3xnn(&mut self, x: usize, nn: usize) -> usize {
-        log::debug!("3xNN, Vx == NN");
-        match self.registers[x] == nn as u8 {
-            true => self.pc + 4,
-            false => self.pc + 2,
-        }
-    }
-    // If (Vx != NN)
-    fn opcode_4xnn(&mut self, x: usize, nn: usize) -> usize {
-        log::debug!("4xNN, Vx != NN");
-        match self.registers[x] != nn as u8 {
-            true => self.pc + 4,
-            false => self.pc + 2,
-        }
-    }
-    // If (Vx == Vy)
-    fn opcode_5xnn(&mut self, x: usize, y: usize) -> usize {
-        log::debug!("5xNN, Vy == Vx");
-        match self.registers[x] == self.registers[y] {
-            true => self.pc + 4,
-            false => self.pc + 2,
-        }
-    }
-    // Set Vx to NN
-    fn opcode_6xnn(&mut self, x: usize, nn: usize) -> usize {
-        self.registers[x] = nn as u8;
-        self.pc + 2
-    }
-    // Add Vx to NN
-    fn opcode_7xnn(&mut self, x: usize, nn: usize) -> usize {
-        let (sum, _overflow) = self.registers[x].overflowing_add(nn as u8);
-        self.registers[x] = sum;
-        self.pc + 2
-    }
-
-    // Vx = Vy
-    fn opcode_8xy0(&mut self, x: usize, y: usize) -> usize {
-        self.registers[x] = self.registers[y];
-        self.pc + 2
-    }
-    // Vx | Vy
-    fn opcode_8xy1(&mut self, x: usize, y: usize) -> usize {
-        self.registers[x] |= self.registers[y];
-        self.pc + 2
-    }
-    // Vx & Vy
-    fn opcode_8xy2(&mut self, x: usize, y: usize) -> usize {
-        self.registers[x] &= self.registers[y];
-        self.pc + 2
-    }
-    // Vx ^ Vy
-    fn opcode_8xy3(&mut self, x: usize, y: usize) -> usize {
-        self.registers[x] ^= self.registers[y];
-        self.pc + 2
-    }
-    // Vx += Vy with Carry
-    fn opcode_8xy4(&mut self, x: usize, y: usize) -> usize {
-        let (sum, overflow) = self.registers[x].overflowing_add(self.registers[y]);
-        if overflow {
-            self.registers[0xF] = 1;
-        }
-        self.registers[x] = sum;
-        self.pc + 2
-    }
-    // Vx -= Vy with Borrow Flag
-    fn opcode_8xy5(&mut self, x: usize, y: usize) -> usize {
-        let (difference, overflow) = self.registers[x].overflowing_sub(self.registers[y]);
-        match overflow {
-            true => self.registers[0xF] = 0,
-            false => self.registers[0xF] = 1,
-        }
-        self.registers[x] = difference;
-        self.pc + 2
-    }
-    // Vx >>= 1, save LSB in Flag
-    fn opcode_8xy6(&mut self, x: usize) -> usize {
-        self.registers[0xF] = self.registers[x] & 0x01;
-        self.registers[x] >>= 1;
-        self.pc + 2
-    }
-    // Vx = Vy - Vx with Borrow Flag
-    fn opcode_8xy7(&mut self, x: usize, y: usize) -> usize {
-        let (difference, overflow) = self.registers[x].overflowing_sub(self.registers[y]);
-        if overflow {
-            self.registers[0xF] = 1;
-        }
-        self.registers[x] = difference;
-        self.pc + 2
-    }
-
-    // Vx <<= 1, save MSB in Flag
-    fn opcode_8xye(&mut self, x: usize) -> usize {
-        self.registers[0xF] = self.registers[x] & 0x80;
-        self.registers[x] <<= 1;
-        self.pc + 2
-    }
-    // If (Vx != Vy)
-    fn opcode_9xy0(&mut self, x: usize, y: usize) -> usize {
-        match self.registers[x] != self.registers[y] {
-            true => self.pc + 4,
-            false => self.pc + 2,
-        }
-    }
-    // I = nnn
-    fn opcode_annn(&mut self, nnn: usize) -> usize {
-        self.address_reg = nnn as u16;
-        self.pc + 2
-    }
-    // PC = V0 + nnn
-    fn opcode_bnnn(&mut self, nnn: usize) -> usize {
-        self.registers[0] as usize + nnn
-    }
-    // Vx = rand & nn
-    fn opcode_cxnn(&mut self, x: usize, nn: usize) -> usize {
-        let mut rng = rand::thread_rng();
-        let num: u8 = rng.gen();
-        self.registers[x] = (nn & num as usize) as u8;
-        self.pc + 2
-    }
-    // Draw(Vx, Vy, N), N = height
-    fn opcode_dxyn(&mut self, x: usize, y: usize, n: usize) -> usize {
-        self.registers[0xF] = 0; // Reset collision detection
-        for row in 0..n {
-            let x_coord: usize = self.registers[x] as usize;
-            let y_coord: usize = self.registers[y] as usize;
-            let video_addr: usize = (y_coord + row) * 64 + (x_coord);
-
-            for bit in 0..8 {
-                let color = self.memory[self.address_reg as usize + row] >> (7 - bit) & 0x1;
-                // Any pixel collision anywhere may flip this to true
-                self.registers[0xF] |= self.video_buffer[(video_addr + bit) % 2048] & color;
-                self.video_buffer[(video_addr + bit) % 2048] ^= color;
+        std::process::exit(2);
+    }
+    if args.telemetry.is_some() && (tui || sdl || fb || (headless && limit.is_none())) {
+        eprintln!("--telemetry works with the window, or --headless with --frames or --seconds");
+        std::process::exit(2);
+    }
+    let netplay_address = host_address.or(join_address);
+    if netplay_address.is_some() && (rom_paths.len() > 1 || tui || sdl || fb || headless) {
+        eprintln!("--host and --join only work in a window with a single ROM");
+        std::process::exit(2);
+    }
+    if tui && cfg!(not(feature = "terminal")) {
+        eprintln!("--tui needs the terminal feature (cargo run --features terminal)");
+        std::process::exit(2);
+    }
+    if sdl && cfg!(not(feature = "sdl2")) {
+        eprintln!("--sdl needs the sdl2 feature (cargo run --features sdl2)");
+        std::process::exit(2);
+    }
+    if script_path.is_some() && cfg!(not(feature = "scripting")) {
+        eprintln!("--script needs the scripting feature (cargo run --features scripting)");
+        std::process::exit(2);
+    }
+    if !plugin_paths.is_empty() && cfg!(not(feature = "plugins")) {
+        eprintln!("--plugin needs the plugins feature (cargo run --features plugins)");
+        std::process::exit(2);
+    }
+    if args.timeline.is_some() && cfg!(not(feature = "timeline")) {
+        eprintln!("--timeline needs the timeline feature (cargo run --features timeline)");
+        std::process::exit(2);
+    }
+    // Before any ROM loads, so the first frame is on it
+    #[cfg(feature = "timeline")]
+    let mut timeline = args.timeline.as_deref().map(|path| {
+        Timeline::start(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+    let mut telemetry = args.telemetry.as_deref().map(|path| {
+        Telemetry::create(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+    if fb && cfg!(not(feature = "framebuffer")) {
+        eprintln!("--fb needs the framebuffer feature (cargo run --features framebuffer)");
+        std::process::exit(2);
+    }
+
+    // Only the first window gets the stdin console. The terminal frontend owns
+    // stdin itself and headless runs take commands over the control socket.
+    let mut instances: Vec<Instance> = match (&mut attract, start_screen) {
+        (Some(attract), _) => {
+            let mut instance = attract_instance(attract, machine);
+            if asleep {
+                instance.asleep = Some(Asleep::new(None));
+            }
+            vec![instance]
+        }
+        (None, true) => vec![start_instance(&machine.config.recent)],
+        (None, false) => rom_paths
+            .iter()
+            .enumerate()
+            .map(|(i, rom_path)| {
+                load_instance(rom_path, patches, i == 0 && !tui && !headless, machine)
+            })
+            .collect(),
+    };
+    // The copy goes on the right from the same seed, each half saying to start
+    // with what's different about it
+    if let Some(spec) = &args.split {
+        let mut partner = load_instance(rom_paths[0], patches, false, machine);
+        partner.cpu = instances[0].cpu.clone();
+        // Checked above
+        partner.cpu.quirks = compare::config(partner.cpu.quirks, spec).unwrap_or_default();
+        for instance in [&mut instances[0], &mut partner] {
+            let quirks = quirk_names(&instance.cpu.quirks).replace(',', " ");
+            instance.osd.show(&format!("Quirks\n{}", quirks));
+        }
+        instances.push(partner);
+    }
+    if args.versus {
+        let mut partner = load_instance(rom_paths[0], patches, false, machine);
+        partner.cpu = instances[0].cpu.clone();
+        partner.cpu.keymap = Keymap::player_two();
+        instances[0].osd.show("Player 1\n1234 qwer\nasdf zxcv");
+        partner.osd.show("Player 2\n6789 yuio\nhjkl bnm0");
+        instances.push(partner);
+    }
+    let mut recent = machine.config.recent.clone();
+    for rom_path in &rom_paths {
+        remember(&machine.config_path, rom_path, &mut recent);
+    }
+    // Only the first ROM waits, as it's the one with the debugger
+    if args.start_paused {
+        let first = &mut instances[0];
+        println!("{}", first.debugger.execute(&mut first.cpu, "pause"));
+        println!("Paused, type c to continue");
+    }
+    if let Some(path) = &args.mix {
+        instances[0].debugger.save_mix_on_exit(path);
+    }
+    // Headless with a WebSocket runs in real time for viewers, otherwise it
+    // only steps when the control socket says so
+    if headless {
+        let mut first = instances.into_iter().next().unwrap();
+        let (cpu, debugger) = (&mut first.cpu, &mut first.debugger);
+        // With a limit there's nobody to wait for, the frames run flat out
+        if let Some(frames) = limit {
+            take_screenshots(cpu, 0, &mut shots);
+            // Through the debugger as in the window, for cheats and --mix
+            for frame in 1..=frames {
+                let _frame = cpu.frame_span().entered();
+                debugger.run(cpu, cpu.speed);
+                cpu.decrement_timers();
+                debugger.end_frame(cpu);
+                take_screenshots(cpu, frame, &mut shots);
+                if let Some(telemetry) = &mut telemetry {
+                    telemetry.record(frame, cpu).unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    });
+                }
+            }
+            #[cfg(feature = "timeline")]
+            drop(timeline);
+            stop(cpu, debugger, frames, dump_path.as_deref());
+        }
+        let result = match websocket_address {
+            Some(address) => websocket::serve(cpu, debugger, address, read_only),
+            None => Headless::new().serve(cpu, debugger, CONTROL_ADDRESS),
+        };
+        if let Err(e) = result {
+            eprintln!("Server error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    #[cfg(feature = "terminal")]
+    if tui {
+        let mut first = instances.into_iter().next().unwrap();
+        if let Err(e) = terminal::run(&mut first.cpu, &mut first.debugger) {
+            eprintln!("Terminal error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    #[cfg(feature = "sdl2")]
+    if sdl {
+        let mut first = instances.into_iter().next().unwrap();
+        if let Err(e) = sdl::run(
+            &mut first.cpu,
+            &mut first.debugger,
+            &first.title,
+            machine.scale,
+            machine.config.pause_on_focus_loss,
+        ) {
+            eprintln!("SDL error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    #[cfg(feature = "framebuffer")]
+    if fb {
+        let mut first = instances.into_iter().next().unwrap();
+        let watchdog = args
+            .watchdog
+            .map(|seconds| Watchdog::new(Duration::from_secs(seconds)));
+        if let Err(e) = framebuffer::run(
+            &mut first.cpu,
+            &mut first.debugger,
+            Path::new(FRAMEBUFFER_PATH),
+            watchdog,
+        ) {
+            eprintln!("Framebuffer error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    #[cfg(feature = "dap")]
+    let mut dap_server = match dap::DapServer::bind(DAP_ADDRESS) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            error!("Unable to start the DAP server on {}: {}", DAP_ADDRESS, e);
+            None
+        }
+    };
+    #[cfg(feature = "gdb")]
+    let mut gdb_server = match gdb::GdbServer::bind(GDB_ADDRESS) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            error!("Unable to start the gdb server on {}: {}", GDB_ADDRESS, e);
+            None
+        }
+    };
+    #[cfg(feature = "http")]
+    let mut http_server = match http::HttpServer::bind(HTTP_ADDRESS) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            error!("Unable to start the HTTP API on {}: {}", HTTP_ADDRESS, e);
+            None
+        }
+    };
+
+    // Both players start from the same seed before the window opens
+    let mut netplay = netplay_address.map(|address| {
+        let cpu = &mut instances[0].cpu;
+        let session = match host_address {
+            Some(_) => Netplay::host(address, cpu, delay),
+            None => Netplay::join(address, cpu),
+        };
+        session.unwrap_or_else(|e| {
+            eprintln!("Netplay failed: {}", e);
+            std::process::exit(1);
+        })
+    });
+
+    let mut monitor = monitor_address.map(|address| {
+        MonitorServer::bind(address).unwrap_or_else(|e| {
+            eprintln!("Unable to start the monitor on {}: {}", address, e);
+            std::process::exit(1);
+        })
+    });
+
+    let mut metrics = metrics_address.map(|address| {
+        MetricsServer::bind(address).unwrap_or_else(|e| {
+            eprintln!("Unable to export metrics on {}: {}", address, e);
+            std::process::exit(1);
+        })
+    });
+
+    #[cfg(feature = "scripting")]
+    let mut script = script_path.map(|path| {
+        Script::load(Path::new(path)).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+
+    #[cfg(feature = "plugins")]
+    let mut plugins = match plugin_paths.is_empty() {
+        true => None,
+        false => Some(Plugins::load(&plugin_paths).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })),
+    };
+
+    let link = match (link_host, link_join) {
+        (Some(address), _) => Some(LinkCable::host(address)),
+        (_, Some(address)) => Some(LinkCable::join(address)),
+        _ => None,
+    };
+    let mut link = link.map(|link| {
+        link.unwrap_or_else(|e| {
+            eprintln!("Unable to connect the link cable: {}", e);
+            std::process::exit(1);
+        })
+    });
+
+    // Mirrors the first window to an LED panel
+    let mut led = led_path.map(|path| match LedMatrix::open(Path::new(path)) {
+        Ok(matrix) => matrix,
+        Err(e) => {
+            eprintln!("Unable to open {}: {}", path, e);
+            std::process::exit(1);
+        }
+    });
+
+    // Chat votes go through netplay like local keys
+    let mut chat = twitch_channel.map(|channel| {
+        ChatInput::connect(chat::TWITCH_SERVER, channel, vote_window).unwrap_or_else(|e| {
+            eprintln!("Unable to join the chat of {}: {}", channel, e);
+            std::process::exit(1);
+        })
+    });
+
+    let mut websocket_server = websocket_address.map(|address| {
+        let mut server = WebSocketServer::bind(address).unwrap_or_else(|e| {
+            eprintln!("Unable to stream on {}: {}", address, e);
+            std::process::exit(1);
+        });
+        server.read_only = read_only;
+        server
+    });
+
+    // Each window's watchdog, in the same order
+    let mut watchdogs: Vec<Watchdog> = match args.watchdog {
+        Some(seconds) => (0..instances.len())
+            .map(|_| Watchdog::new(Duration::from_secs(seconds)))
+            .collect(),
+        None => Vec::new(),
+    };
+    // Moved along by the keys in on_load and by a watchdog in on_update
+    let playlist = Rc::new(RefCell::new(playlist));
+    let load_playlist = Rc::clone(&playlist);
+    // Each window's ROM with --watch, in the same order
+    let mut watchers: Vec<Option<(String, RomWatcher)>> = match args.watch {
+        true => rom_paths.iter().map(|rom_path| watch(rom_path)).collect(),
+        false => Vec::new(),
+    };
+    let patches = patches.clone();
+    // For machines on_update starts itself
+    let restart_machine = machine.clone();
+    let on_update = move |index: usize, instance: &mut Instance| {
+        if let Some(Some((rom_path, watcher))) = watchers.get_mut(index) {
+            if watcher.poll() {
+                reload(instance, rom_path, &patches, &restart_machine);
+            }
+        }
+        // Servers and outputs follow the first window
+        if index != 0 {
+            return false;
+        }
+        // A ROM that looks hung or crashed moves on, or starts again
+        let stuck = watchdogs
+            .get_mut(index)
+            .is_some_and(|watchdog| watchdog.check(&instance.cpu));
+        if stuck {
+            warn!(
+                "Watchdog: {} has shown the same display with no keys for {} seconds",
+                instance.title,
+                watchdogs[index].timeout().as_secs()
+            );
+        }
+        if stuck && attract.is_none() {
+            let moved = match playlist.borrow_mut().as_mut() {
+                Some(playlist) => play_along(playlist, 1, instance, &restart_machine),
+                None => Err(String::from("Restarting it")),
+            };
+            match moved {
+                Ok(name) => instance.osd.show(&format!("Loaded\n{}", name)),
+                Err(e) => {
+                    warn!("Watchdog: {}", e);
+                    instance.cpu.hard_reset();
+                    instance.debugger.resume();
+                    instance.osd.show("Watchdog\nReset");
+                }
             }
         }
-        self.redraw = true;
-        self.pc + 2
-    }
-    // If key == Vx
-    fn opcode_ex9e(&mut self, x: usize) -> usize {
-        match self.registers[x] == self.input {
-            true => self.pc + 4,
-            false => self.pc + 2,
-        }
-    }
-    // If key != Vx
-    fn opcode_exa1(&mut self, x: usize) -> usize {
-        match self.registers[x] != self.input {
-            true => self.pc + 4,
-            false => self.pc + 2,
-        }
-    }
-    // Vx = get_delay()
-    fn opcode_fx07(&mut self, x: usize) -> usize {
-        self.registers[x] = self.delay_timer;
-        self.pc + 2
-    }
-    // Vx = get_key()
-    fn opcode_fx0a(&mut self, x: usize) -> usize {
-        self.registers[x] = self.input;
-        self.pc + 2
-    }
-    // Set Delay to Vx
-    fn opcode_fx15(&mut self, x: usize) -> usize {
-        self.delay_timer = x as u8;
-        self.pc + 2
-    }
-    // Set Sound to Vx
-    fn opcode_fx18(&mut self, x: usize) -> usize {
-        self.sound_timer = x as u8;
-        self.pc + 2
-    }
-    // Add Vx to I
-    fn opcode_fx1e(&mut self, x: usize) -> usize {
-        self.address_reg += x as u16;
-        self.pc + 2
-    }
-    // Set I to Sprite Address Location (Font)
-    fn opcode_fx29(&mut self, x: usize) -> usize {
-        self.address_reg = self.memory[x * 5] as u16;
-        self.pc + 2
-    }
-    // Store BCD of Vx into I (hundreds), I+1 (tens), I+2 (ones)
-    fn opcode_fx33(&mut self, x: usize) -> usize {
-        self.memory[self.address_reg as usize] = self.registers[x] / 100;
-        self.memory[self.address_reg as usize + 1] = (self.registers[x] % 100) / 10;
-        self.memory[self.address_reg as usize + 2] = self.registers[x] % 10;
-        self.pc + 2
-    }
-    // Dump registers from V0 to Vx into Memory, starting at I
-    fn opcode_fx55(&mut self, x: usize) -> usize {
-        for i in 0x0..x + 1 {
-            self.memory[self.address_reg as usize + i] = self.registers[i];
-        }
-        self.pc + 2
-    }
-    // Load registers from I
-    fn opcode_fx65(&mut self, x: usize) -> usize {
-        for i in 0x0..x + 1 {
-            self.registers[i] = self.memory[self.address_reg as usize + i];
-        }
-        self.pc + 2
-    }
-
-    fn draw(&mut self, frame: &mut [u8]) {
-        if self.redraw {
-            let mut rng = rand::thread_rng();
-            // Green for normal, amber on beeps
-            let color = match self.sound_timer {
-                0 => [0xFA, 0xFA, 0x10, 0xFF],
-                _ => [0x10, 0xFA, 0x10, 0xFF]
+        if let Some(attract) = &mut attract {
+            if attract.is_due(&instance.cpu) || stuck {
+                let (cpu, title) =
+                    next_attraction(attract, &restart_machine).unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    });
+                instance.cpu = cpu;
+                instance.debugger.reset();
+                instance.osd.show(&title);
+                instance.title = title;
+            }
+        }
+        // The game waits out the screensaver, debugger and all
+        if let Some(saver) = &mut screensaver {
+            let due = match instance.asleep {
+                Some(_) => {
+                    saver.wait();
+                    saver.attract.is_due(&instance.cpu)
+                }
+                None => saver.is_due(&instance.cpu),
             };
-            // Flip the buffer into the RGBA space
-            for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-                let mut rgba = [0; 4];
-                match self.video_buffer[i] {
-                    1 => rgba = color,
-                    _ => rgba = [0x10, 0x10, 0x10, 0xFF],
+            if due {
+                let (cpu, title) =
+                    next_attraction(&mut saver.attract, &restart_machine).unwrap_or_else(|e| {
+                        eprintln!("{}", e);
+                        std::process::exit(1);
+                    });
+                instance.osd.show(&title);
+                let game = (
+                    std::mem::replace(&mut instance.cpu, cpu),
+                    std::mem::replace(&mut instance.title, title),
+                );
+                // Moving on to the next ROM keeps the game that was waiting
+                if instance.asleep.is_none() {
+                    instance.asleep = Some(Asleep::new(Some(game)));
                 }
-                pixel.copy_from_slice(&rgba);
             }
         }
-        self.redraw = false;
-    }
-    fn load_font(&mut self) {
-        let font = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ];
-        for i in 0..80 {
-            self.memory[i] = font[i];
-        }
-    }
-    fn load_rom(&mut self) {
-        match std::fs::read("./roms/brix.ch8") {
-            Ok(bytes) => {
-                for (i, byte) in bytes.iter().enumerate() {
-                    self.memory[0x200 + i] = *byte;
+        let (cpu, debugger) = (&mut instance.cpu, &mut instance.debugger);
+        #[cfg(feature = "dap")]
+        if let Some(server) = &mut dap_server {
+            server.poll(debugger, cpu);
+        }
+        #[cfg(feature = "gdb")]
+        if let Some(server) = &mut gdb_server {
+            server.poll(debugger, cpu);
+        }
+        #[cfg(feature = "http")]
+        if let Some(server) = &mut http_server {
+            server.poll(debugger, cpu, |bytes| {
+                let (rom, cart_options) = unpack(bytes.to_vec(), HTTP_ROM)?;
+                machine_for(&rom, cart_options.as_ref(), HTTP_ROM, &restart_machine)
+                    .map(|(cpu, _)| cpu)
+            });
+        }
+        if let Some(server) = &mut monitor {
+            server.poll(debugger, cpu);
+        }
+        if let Some(server) = &mut metrics {
+            server.poll(debugger, cpu);
+        }
+        if let Some(server) = &mut websocket_server {
+            server.poll(cpu);
+        }
+        if let Some(chat) = &mut chat {
+            chat.poll(cpu);
+        }
+        if let Some(link) = &mut link {
+            link.poll(cpu);
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &mut script {
+            script.update(cpu);
+        }
+        #[cfg(feature = "plugins")]
+        if let Some(plugins) = &mut plugins {
+            plugins.update(cpu);
+        }
+        #[cfg(feature = "timeline")]
+        if let Some(timeline) = &mut timeline {
+            timeline.poll();
+        }
+        // Between frames, before the next one's first instruction
+        if cpu.instructions.is_multiple_of(cpu.speed as u64) {
+            take_screenshots(cpu, cpu.instructions / cpu.speed as u64, &mut shots);
+        }
+        if let Some(frames) = limit {
+            if cpu.instructions >= frames * cpu.speed as u64 {
+                #[cfg(feature = "timeline")]
+                drop(timeline.take());
+                stop(cpu, debugger, frames, dump_path.as_deref());
+            }
+        }
+        if let (true, Some(matrix)) = (cpu.redraw, &mut led) {
+            if let Err(e) = matrix.send(cpu) {
+                error!("LED matrix stopped: {}", e);
+                led = None;
+            }
+        }
+        match &mut netplay {
+            Some(session) => {
+                if let Err(e) = session.frame(cpu, debugger) {
+                    eprintln!("Netplay ended: {}", e);
+                    std::process::exit(1);
                 }
+                true
             }
-            Err(e) => {
-                panic!("{}", e);
+            None => false,
+        }
+    };
+    // Dropped and recent ROMs start without patches, and can't change the
+    // game under the other netplay player
+    let scale = machine.scale;
+    let machine = machine.clone();
+    let netplaying = netplay_address.is_some();
+    // The other player and the chat don't wait for this window
+    let background = !netplaying && twitch_channel.is_none();
+    let pause_unfocused = machine.config.pause_on_focus_loss && background;
+    let pause_hidden = machine.config.pause_when_hidden && background;
+    let on_load = move |load: Load, instance: &mut Instance| {
+        if netplaying {
+            return Err(String::from("ROMs can't be changed during netplay"));
+        }
+        let rom_path = match load {
+            Load::Dropped(path) => path.to_string_lossy().into_owned(),
+            Load::Recent(n) => recent
+                .get(n)
+                .cloned()
+                .ok_or_else(|| format!("There's no recent ROM {}", n + 1))?,
+            Load::Next | Load::Previous => {
+                let step = match load {
+                    Load::Next => 1,
+                    _ => -1,
+                };
+                return match load_playlist.borrow_mut().as_mut() {
+                    Some(playlist) => play_along(playlist, step, instance, &machine),
+                    None => Err(String::from("There's no --playlist to move through")),
+                };
             }
+        };
+        let (cpu, title) = load_machine(&rom_path, &[], &machine)?;
+        // Loaded over the playlist's ROM, which then has nothing to keep
+        if let Some(playlist) = load_playlist.borrow_mut().as_mut() {
+            playlist.leave();
         }
+        remember(&machine.config_path, &rom_path, &mut recent);
+        instance.cpu = cpu;
+        instance.title = title;
+        instance.debugger.reset();
+        load_debug_files(&mut instance.debugger, &rom_path);
+        info!("Loaded {}", rom_path);
+        Ok(rom_name(&rom_path))
+    };
+    let split = args.split.is_some() || args.versus;
+    window::run(
+        instances,
+        scale,
+        pause_unfocused,
+        pause_hidden,
+        split,
+        telemetry,
+        on_update,
+        on_load,
+    )
+}
+
+// Move a window along the playlist, keeping the game it leaves for later
+fn play_along(
+    playlist: &mut Playlist,
+    step: isize,
+    instance: &mut Instance,
+    machine: &Machine,
+) -> Result<String, String> {
+    let index = playlist.along(step);
+    let rom_path = playlist.rom(index).to_string();
+    let (mut cpu, title) = load_machine(&rom_path, &[], machine)?;
+    // Back where it was left, if it's been played before
+    if let Some(saved) = playlist.switch(index, &instance.cpu) {
+        state::load(&mut cpu, saved)?;
     }
+    instance.cpu = cpu;
+    instance.title = title;
+    instance.debugger.reset();
+    load_debug_files(&mut instance.debugger, &rom_path);
+    info!("Loaded {}", rom_path);
+    Ok(rom_name(&rom_path))
 }
 
-fn main() -> Result<(), Error> {
-    env_logger::init();
-    let event_loop = EventLoop::new();
-    let mut input = WinitInputHelper::new();
-    let window = {
-        let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
-        WindowBuilder::new()
-            .with_title("Chip8")
-            .with_inner_size(size)
-            .with_min_inner_size(size)
-            .build(&event_loop)
-            .unwrap()
+// Machine, title and debugger for a ROM, with its patches, symbols and cheats
+fn load_instance(
+    rom_path: &str,
+    patches: &[String],
+    console: bool,
+    machine: &Machine,
+) -> Instance {
+    let (cpu, title) = load_machine(rom_path, patches, machine).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let mut debugger = match console {
+        true => Debugger::new(),
+        false => Debugger::without_console(),
     };
+    load_debug_files(&mut debugger, rom_path);
+    Instance {
+        cpu,
+        debugger,
+        title,
+        osd: Osd::default(),
+        asleep: None,
+    }
+}
 
-    let mut pixels = {
-        let window_size = window.inner_size();
-        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH, HEIGHT, surface_texture)?
-    };
+// An empty machine listing the recent ROMs until one is picked
+fn start_instance(recent: &[String]) -> Instance {
+    let mut cpu = Chip8::new();
+    cpu.load_font();
+    // There's nothing to run
+    let mut debugger = Debugger::new();
+    debugger.pause();
+    let mut menu = vec![String::from("Ctrl+1-9 loads")];
+    menu.extend(recent.iter().enumerate().map(|(i, rom_path)| {
+        let line = format!("{} {}", i + 1, rom_name(rom_path));
+        line.chars().take(osd::COLUMNS).collect::<String>()
+    }));
+    let mut osd = Osd::default();
+    osd.pin(&menu.join("\n"));
+    Instance {
+        cpu,
+        debugger,
+        title: String::new(),
+        osd,
+        asleep: None,
+    }
+}
+
+// A watcher for a ROM file, none for stdin, links and the demo
+fn watch(rom_path: &str) -> Option<(String, RomWatcher)> {
+    if !on_disk(rom_path) {
+        return None;
+    }
+    match RomWatcher::new(Path::new(rom_path)) {
+        Ok(watcher) => Some((rom_path.to_string(), watcher)),
+        Err(e) => {
+            error!("{}", e);
+            None
+        }
+    }
+}
+
+// Whether a ROM is a file, rather than stdin, a link or the demo
+fn on_disk(rom_path: &str) -> bool {
+    rom_path != "-" && rom_path != demo::NAME && !rom_path.contains("://")
+}
+
+// Restart an instance with its ROM as the file is now, leaving the old one
+// running when the new one doesn't load, e.g. a source with a typo
+fn reload(instance: &mut Instance, rom_path: &str, patches: &[String], machine: &Machine) {
+    match load_machine(rom_path, patches, machine) {
+        Ok((cpu, title)) => {
+            instance.cpu = cpu;
+            instance.title = title;
+            instance.debugger.reset();
+            load_debug_files(&mut instance.debugger, rom_path);
+            instance.osd.show("Reloaded");
+            info!("Reloaded {}", rom_path);
+        }
+        Err(e) => {
+            error!("{}", e);
+            instance.osd.show("Can't reload it");
+        }
+    }
+}
+
+// Attract mode's first ROM, named on the display like the rest
+fn attract_instance(attract: &mut Attract, machine: &Machine) -> Instance {
+    let (cpu, title) = next_attraction(attract, machine).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let mut osd = Osd::default();
+    osd.show(&title);
+    Instance {
+        cpu,
+        debugger: Debugger::new(),
+        title,
+        osd,
+        asleep: None,
+    }
+}
+
+// A fresh machine for attract mode's next ROM, passing over any that don't
+// load so one bad file doesn't stop the show
+fn next_attraction(attract: &mut Attract, machine: &Machine) -> Result<(Chip8, String), String> {
+    for _ in 0..attract.len() {
+        let rom_path = attract.next_rom().to_string();
+        match load_machine(&rom_path, &[], machine) {
+            Ok(loaded) => return Ok(loaded),
+            Err(e) => error!("{}", e),
+        }
+    }
+    Err(String::from("None of the attract mode ROMs load"))
+}
 
-    let mut cpu = Chip8 {
-        status: EmulationStatus::Running,
-        pc: 0x200,
-        sp: 0,
-        memory: [0; 4000],
-        registers: [0; 16],
-        address_reg: 0,
-        stack: [0; 12],
-        delay_timer: 0,
-        sound_timer: 0,
-        input: 0,
-        video_buffer: [0; 64 * 32],
-        redraw: false,
+// Put a ROM at the top of the recent list, and in the config file. Files are
+// kept by their full path so they load from anywhere, and stdin can't be
+// read again.
+fn remember(config_path: &Path, rom_path: &str, recent: &mut Vec<String>) {
+    let rom_path = match rom_path {
+        "-" | demo::NAME => return,
+        url if url.starts_with("http://") || url.starts_with("https://") => url.to_string(),
+        path => std::fs::canonicalize(path)
+            .map_or_else(|_| path.to_string(), |path| path.to_string_lossy().into_owned()),
     };
+    match config::remember(config_path, &rom_path) {
+        Ok(list) => *recent = list,
+        Err(e) => error!("{}", e),
+    }
+}
+
+// File name of a ROM path or link
+fn rom_name(rom_path: &str) -> String {
+    Path::new(rom_path)
+        .file_name()
+        .map_or_else(|| rom_path.to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+// A fresh machine running the ROM, and its window title
+fn load_machine(
+    rom_path: &str,
+    patches: &[String],
+    machine: &Machine,
+) -> Result<(Chip8, String), String> {
+    // Octo source runs directly without a separate assemble step
+    let (mut rom, cart_options) = read_rom(rom_path)?;
+    for patch_path in patches {
+        rom = patch_or_exit(&rom, patch_path);
+    }
+    machine_for(&rom, cart_options.as_ref(), rom_path, machine)
+}
+
+// A fresh machine running ROM bytes that came from rom_path, set up the same
+// wherever they were read from
+fn machine_for(
+    rom: &[u8],
+    cart_options: Option<&Game>,
+    rom_path: &str,
+    machine: &Machine,
+) -> Result<(Chip8, String), String> {
+    let mut cpu = Chip8::new();
     cpu.load_font();
-    cpu.load_rom();
-
-    let mut current_delay_timer = std::time::Instant::now();
-    let mut current_sound_timer = std::time::Instant::now();
-
-    event_loop.run(move |event, _, control_flow| {
-        // Draw the current frame
-        if let Event::RedrawRequested(_) = event {
-            cpu.draw(pixels.get_frame());
-
-            if pixels
-                .render()
-                .map_err(|e| error!("pixels.render() failed: {}", e))
-                .is_err()
-            {
-                *control_flow = ControlFlow::Exit;
-                return;
+    load_rom(&mut cpu, rom, rom_path)?;
+    // Against the variant the ROM is run as, Chip8 unless --variant says
+    for warning in lint::lint(cpu.rom(), machine.variant) {
+        eprintln!("warning: {}", warning);
+    }
+    // Title, credits and colors from the CHIP-8 database when it's available
+    let mut title = rom_name(rom_path);
+    match database::lookup(Path::new(DATABASE_PATH), cpu.rom()) {
+        Ok(Some(program)) => {
+            title = program.full_title();
+            cpu.colors = program.colors;
+            info!("{}", program.summary());
+        }
+        Ok(None) => {}
+        Err(e) => error!("{}", e),
+    }
+    configure(&mut cpu, rom_path, cart_options, machine);
+    // Battery RAM, kept next to the ROM
+    let game = machine.config.game(Path::new(rom_path), cpu.rom());
+    if let Some(range) = game.and_then(|game| game.save_ram.clone()) {
+        match on_disk(rom_path) {
+            true => Battery::insert(&mut cpu, Path::new(rom_path), range)?,
+            false => error!("Save RAM needs a ROM file to keep it next to"),
+        }
+    }
+    Ok((cpu, title))
+}
+
+// Symbols, cheats and achievements kept next to the ROM
+fn load_debug_files(debugger: &mut Debugger, rom_path: &str) {
+    if let Some(symbols) = Symbols::find(Path::new(rom_path)) {
+        debugger.load_symbols(symbols);
+        info!("Loaded {} symbols for {}", debugger.symbols().len(), rom_path);
+    }
+    if let Some(cheats) = Cheats::find(Path::new(rom_path)) {
+        info!("Loaded {} cheats for {}", cheats.len(), rom_path);
+        debugger.load_cheats(cheats);
+    }
+    if let Some(achievements) = Achievements::find(Path::new(rom_path)) {
+        info!(
+            "Loaded {} achievements for {}, {} unlocked",
+            achievements.len(),
+            rom_path,
+            achievements.unlocked()
+        );
+        debugger.load_achievements(achievements);
+    }
+}
+
+// Code as instructions with the raw opcode, including code nothing reaches,
+// and data a byte per line with sprites drawn out, see analysis.rs
+fn disassemble_rom(rom_path: &str) {
+    let rom = read_or_exit(rom_path);
+    let analysis = analysis::analyze(&rom);
+    let end = 0x200 + rom.len();
+    let mut addr = 0x200;
+    while addr < end {
+        let offset = addr - 0x200;
+        let unreachable = analysis.is_unreachable(addr);
+        if (analysis.is_instruction(addr) || unreachable) && addr + 1 < end {
+            let opcode = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+            let note = match unreachable {
+                true => "  ; unreachable",
+                false => "",
+            };
+            let text = disasm::disassemble(opcode);
+            println!("{:#05x}  {:04X}  {}{}", addr, opcode, text, note);
+            addr += 2;
+        } else {
+            let byte = rom[offset];
+            let note = match analysis.class(addr) {
+                Class::Sprite => format!("  ; {}", analysis::pixels(byte)),
+                _ => String::new(),
+            };
+            println!("{:#05x}  {:02X}    DB {:#04x}{}", addr, byte, byte, note);
+            addr += 1;
+        }
+    }
+}
+
+// Writes <source>.ch8 unless told otherwise
+fn assemble_file(source_path: &str, output_path: Option<PathBuf>) {
+    let output_path = output_path.unwrap_or_else(|| Path::new(source_path).with_extension("ch8"));
+    let bytes = assemble_or_exit(source_path);
+    if let Err(e) = std::fs::write(&output_path, &bytes) {
+        eprintln!("Unable to write {}: {}", output_path.display(), e);
+        std::process::exit(1);
+    }
+    println!("Wrote {} bytes to {}", bytes.len(), output_path.display());
+}
+
+// Writes <rom>.sprites.png unless told otherwise
+fn save_sprites(rom_path: &str, output_path: Option<PathBuf>, guess: bool) {
+    let output_path =
+        output_path.unwrap_or_else(|| Path::new(rom_path).with_extension("sprites.png"));
+    let sprites = sheet::find(&read_or_exit(rom_path), guess);
+    if sprites.is_empty() {
+        match guess {
+            true => eprintln!("No sprites found in {}", rom_path),
+            false => eprintln!("No sprites found in {}, try --guess", rom_path),
+        }
+        std::process::exit(1);
+    }
+    for sprite in &sprites {
+        println!("{:#05x}  {}x{}", sprite.addr, sprite.width(), sprite.height());
+    }
+    if let Err(e) = sheet::save(&sprites, &output_path) {
+        eprintln!("Unable to write {}: {}", output_path.display(), e);
+        std::process::exit(1);
+    }
+    println!("Wrote {} sprites to {}", sprites.len(), output_path.display());
+}
+
+fn lint_rom(rom_path: &str, variant: Variant) {
+    let warnings = lint::lint(&read_or_exit(rom_path), variant);
+    for warning in &warnings {
+        println!("{}", warning);
+    }
+    if !warnings.is_empty() {
+        std::process::exit(1);
+    }
+    println!("No problems found for {}", variant);
+}
+
+// Writes <rom>.patched.ch8 unless told otherwise
+fn patch_file(rom_path: &str, patch_path: &str, output_path: Option<PathBuf>) {
+    let output_path =
+        output_path.unwrap_or_else(|| Path::new(rom_path).with_extension("patched.ch8"));
+    let rom = patch_or_exit(&read_or_exit(rom_path), patch_path);
+    if let Err(e) = std::fs::write(&output_path, &rom) {
+        eprintln!("Unable to write {}: {}", output_path.display(), e);
+        std::process::exit(1);
+    }
+    println!("Wrote {} bytes to {}", rom.len(), output_path.display());
+}
+
+// Frames back to back with no window or pacing, to time the core
+fn bench(rom_path: &str, frames: u64, machine: &Machine) {
+    let mut cpu = headless_cpu(rom_path, machine);
+    let start = Instant::now();
+    if let Err(fault) = snapshot::run_for_frames(&mut cpu, frames) {
+        eprintln!("Stopped: {}", fault);
+        std::process::exit(1);
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    println!(
+        "{} frames, {} instructions in {:.3}s",
+        frames,
+        cpu.cycles(),
+        seconds
+    );
+    println!(
+        "{:.0} instructions a second, {:.1}x real time",
+        cpu.instructions as f64 / seconds,
+        frames as f64 / 60.0 / seconds
+    );
+    let mut classes = cpu.opcode_counts();
+    classes.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+    let busiest: Vec<String> = classes
+        .iter()
+        .take(5)
+        .map(|(class, count)| {
+            format!(
+                "{} {:.1}%",
+                class,
+                *count as f64 * 100.0 / cpu.cycles() as f64
+            )
+        })
+        .collect();
+    println!("Busiest: {}", busiest.join(", "));
+}
+
+// Runs with no keys held, so the same ROM and options always give the same
+// screen. A fault shows the screen it stopped at and exits with 1.
+fn test_rom(rom_path: &str, frames: u64, expect: Option<String>, machine: &Machine) {
+    let mut cpu = headless_cpu(rom_path, machine);
+    let hash = suite::screen_hash(&mut cpu, frames);
+    print!("{}", snapshot::framebuffer_as_ascii(&cpu));
+    let hash = hash.unwrap_or_else(|fault| {
+        println!("Stopped: {}", fault);
+        std::process::exit(1);
+    });
+    println!("Screen crc32 {} after {} frames", hash, frames);
+    if let Some(expected) = expect {
+        match expected.trim_start_matches("0x").eq_ignore_ascii_case(&hash) {
+            true => println!("PASS"),
+            false => {
+                println!("FAIL, expected {}", expected);
+                std::process::exit(1);
             }
         }
-        /*    Key Mappings
-         * Chip8       QWERTY
-         * 1 2 3 C     1 2 3 4
-         * 4 5 6 D >>> Q W E R
-         * 7 8 9 E >>> A S D F
-         * A 0 B F     Z X C V
-         *
-         */
-        if input.update(&event) {
-            // Close events
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
-                *control_flow = ControlFlow::Exit;
-                return;
+    }
+}
+
+// Each test ROM's screen against the hash it had when it last passed, so a
+// change that breaks a game shows up at once. Anything short of every test
+// passing exits with 1, as a missing ROM or hash tests nothing.
+fn test_suite(dir: &Path, record: bool, machine: &Machine) {
+    let cases = suite::load(dir).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    if cases.is_empty() {
+        eprintln!("No tests in {}", dir.join(suite::MANIFEST).display());
+        std::process::exit(1);
+    }
+    let width = cases.iter().map(|case| case.name.len()).max().unwrap_or(0);
+    let (mut passed, mut failed, mut new, mut missing) = (0, 0, 0, 0);
+    let mut hashes = Vec::new();
+    for case in &cases {
+        if !case.rom.is_file() {
+            println!(
+                "MISSING  {:<w$}  {}",
+                case.name,
+                case.rom.display(),
+                w = width
+            );
+            missing += 1;
+            hashes.push(None);
+            continue;
+        }
+        let mut cpu = headless_cpu(&case.rom.to_string_lossy(), machine);
+        let hash = match case.check(&mut cpu) {
+            suite::Outcome::Pass => {
+                println!("PASS     {}", case.name);
+                passed += 1;
+                case.crc32.clone()
             }
-            if input.key_held(VirtualKeyCode::Key1) {
-                cpu.input = 0x01;
-            } else if input.key_held(VirtualKeyCode::Key2) {
-                cpu.input = 0x02;
-            } else if input.key_held(VirtualKeyCode::Key2) {
-                cpu.input = 0x03;
-            } else if input.key_held(VirtualKeyCode::Key3) {
-                cpu.input = 0x03;
-            } else if input.key_held(VirtualKeyCode::Key4) {
-                cpu.input = 0x0C;
-            } else if input.key_held(VirtualKeyCode::Q) {
-                cpu.input = 0x04;
-            } else if input.key_held(VirtualKeyCode::W) {
-                cpu.input = 0x05;
-            } else if input.key_held(VirtualKeyCode::E) {
-                cpu.input = 0x06;
-            } else if input.key_held(VirtualKeyCode::R) {
-                cpu.input = 0x0D;
-            } else if input.key_held(VirtualKeyCode::A) {
-                cpu.input = 0x07;
-            } else if input.key_held(VirtualKeyCode::S) {
-                cpu.input = 0x08;
-            } else if input.key_held(VirtualKeyCode::D) {
-                cpu.input = 0x09;
-            } else if input.key_held(VirtualKeyCode::F) {
-                cpu.input = 0x0E;
-            } else if input.key_held(VirtualKeyCode::Z) {
-                cpu.input = 0x0A;
-            } else if input.key_held(VirtualKeyCode::X) {
-                cpu.input = 0x00;
-            } else if input.key_held(VirtualKeyCode::C) {
-                cpu.input = 0x0B;
-            } else if input.key_held(VirtualKeyCode::V) {
-                cpu.input = 0x0F;
-            } else {
-                cpu.input = 0x00;
+            suite::Outcome::Fail(hash) => {
+                println!(
+                    "FAIL     {:<w$}  crc32 {}, expected {}",
+                    case.name,
+                    hash,
+                    case.crc32.as_deref().unwrap_or_default(),
+                    w = width
+                );
+                failed += 1;
+                Some(hash)
             }
-
-            // Resize the window
-            if let Some(size) = input.window_resized() {
-                pixels.resize_surface(size.width, size.height);
+            suite::Outcome::Fault(fault) => {
+                println!("FAULT    {:<w$}  {}", case.name, fault, w = width);
+                failed += 1;
+                None
             }
-            // Update internal state and request a redraw
-            cpu.tick();
-            window.request_redraw();
-            // 60 Hz Delay Clock
-            let delay_check = current_delay_timer.elapsed();
-            if delay_check.as_secs() > 1 {
-                let (value, overflow) = cpu.delay_timer.overflowing_sub(1);
-                match overflow {
-                    true => cpu.delay_timer = 0,
-                    false => cpu.delay_timer -= 1,
-                }
-                current_delay_timer = std::time::Instant::now();
+            suite::Outcome::New(hash) => {
+                println!(
+                    "NEW      {:<w$}  crc32 {}, not recorded yet",
+                    case.name,
+                    hash,
+                    w = width
+                );
+                new += 1;
+                Some(hash)
             }
-            // 60 Hz Sound Clock
-            let sound_check = current_sound_timer.elapsed();
-            if sound_check.as_secs() > 1 {
-                let (value, overflow) = cpu.sound_timer.overflowing_sub(1);
-                match overflow {
-                    true => cpu.sound_timer = 0,
-                    false => cpu.sound_timer -= 1,
-                }
-                current_sound_timer = std::time::Instant::now();
+        };
+        hashes.push(hash);
+    }
+    println!(
+        "{} passed, {} failed, {} not recorded, {} missing",
+        passed, failed, new, missing
+    );
+    if record {
+        if let Err(e) = suite::record(dir, &hashes) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        println!(
+            "Recorded the hashes in {}",
+            dir.join(suite::MANIFEST).display()
+        );
+        // A missing ROM or a fault leaves its test unrecorded
+        if hashes.contains(&None) {
+            std::process::exit(1);
+        }
+    } else if failed + new + missing > 0 {
+        std::process::exit(1);
+    }
+}
+
+// From seed 0 with no keys held, like chip8 test
+fn rom_stats(rom_path: &str, frames: u64, json: bool, machine: &Machine) {
+    let mut cpu = headless_cpu(rom_path, machine);
+    let stats = stats::run(&mut cpu, frames);
+    if json {
+        println!("{}", stats.to_json());
+        return;
+    }
+    println!("{} frames, {} instructions", stats.frames, stats.instructions);
+    println!("{} draws, {} sounds", stats.draws, stats.sounds);
+    println!("State hash {:08x}", stats.hash);
+    if let Some(fault) = stats.fault {
+        println!("Stopped at a fault: {}", fault);
+    }
+}
+
+// For checking a change to the core against a whole collection at once
+fn soak_dir(dir: &Path, frames: u64, machine: &Machine) {
+    let roms = soak::roms(dir).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let names: Vec<String> = roms
+        .iter()
+        .map(|rom| {
+            rom.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    let width = names.iter().map(String::len).max().unwrap_or(0);
+    // Caught and reported with the ROM instead
+    std::panic::set_hook(Box::new(|_| {}));
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (rom, name) in roms.iter().zip(&names) {
+        let (verdict, text) = match try_headless_cpu(&rom.to_string_lossy(), machine) {
+            Ok(mut cpu) => {
+                let soak = soak::soak(&mut cpu, frames);
+                (soak.verdict(), soak.describe())
+            }
+            Err(e) => ("ERROR", e),
+        };
+        println!("{:<8} {:<w$}  {}", verdict, name, text, w = width);
+        *counts.entry(verdict).or_insert(0) += 1;
+    }
+    drop(std::panic::take_hook());
+    let summary: Vec<String> = counts
+        .iter()
+        .map(|(verdict, count)| format!("{} {}", count, verdict))
+        .collect();
+    println!("{} ROMs: {}", roms.len(), summary.join(", "));
+    if counts.contains_key("PANIC") {
+        std::process::exit(1);
+    }
+}
+
+// From seed 0 with no keys held, like chip8 test
+fn trace_rom(rom_path: &str, frames: u64, output: Option<PathBuf>, machine: &Machine) {
+    let mut cpu = headless_cpu(rom_path, machine);
+    let written = match &output {
+        Some(path) => std::fs::File::create(path)
+            .map(std::io::BufWriter::new)
+            .and_then(|mut file| golden::record(&mut cpu, frames, &mut file)),
+        None => golden::record(&mut cpu, frames, &mut std::io::stdout().lock()),
+    };
+    match (written, output) {
+        (Ok(count), Some(path)) => println!("Traced {} instructions to {}", count, path.display()),
+        (Ok(_), None) => {}
+        (Err(e), _) => {
+            eprintln!("Unable to write the trace: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn compare_traces(expected_path: &Path, actual_path: &Path) {
+    let read = |path: &Path| {
+        std::fs::read_to_string(path).unwrap_or_else(|e| {
+            eprintln!("Unable to read {}: {}", path.display(), e);
+            std::process::exit(1);
+        })
+    };
+    let (expected_text, actual_text) = (read(expected_path), read(actual_path));
+    let (expected_name, actual_name) = (
+        expected_path.display().to_string(),
+        actual_path.display().to_string(),
+    );
+    let expected = golden::Trace {
+        name: &expected_name,
+        text: &expected_text,
+    };
+    let actual = golden::Trace {
+        name: &actual_name,
+        text: &actual_text,
+    };
+    match golden::compare(&expected, &actual) {
+        Ok(summary) => println!("{}", summary),
+        Err(report) => {
+            println!("{}", report);
+            std::process::exit(1);
+        }
+    }
+}
+
+// A safety net for rewind, replay and netplay, which all need the same
+// inputs to give the same machine
+fn verify_rom(rom_path: &str, frames: u64, seed: u64, machine: &Machine) {
+    let cpu = headless_cpu(rom_path, machine);
+    match verify::verify(&cpu, frames, seed) {
+        Ok(verified) => {
+            println!(
+                "Deterministic over {} frames, rolling hash {:08x}",
+                verified.frames, verified.hash
+            );
+            if let Some(fault) = verified.fault {
+                println!("Both runs stopped at the same fault: {}", fault);
             }
         }
+        Err(report) => {
+            println!("{}", report);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn compare_quirks(
+    rom_path: &str,
+    specs: [&str; 2],
+    frames: u64,
+    png_path: Option<&Path>,
+    machine: &Machine,
+) {
+    let cpu = headless_cpu(rom_path, machine);
+    let [mut a, mut b] = specs.map(|spec| {
+        let mut cpu = cpu.clone();
+        cpu.quirks = compare::config(cpu.quirks, spec).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        cpu
     });
+    for (name, spec, cpu) in [("A", specs[0], &a), ("B", specs[1], &b)] {
+        println!("{}: {}, quirks {}", name, spec, quirk_names(&cpu.quirks));
+    }
+    let result = compare::compare(&mut a, &mut b, frames);
+    if let Some(path) = png_path {
+        if let Err(e) = screenshot::save_side_by_side(&a, &b, path) {
+            eprintln!("Unable to write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+    match result {
+        Ok(summary) => println!("{}", summary),
+        Err(report) => {
+            println!("{}", report);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn diff_states(before_path: &Path, after_path: &Path, png_path: Option<&Path>) {
+    let [before, after] = [before_path, after_path].map(|path| {
+        diff::load(path).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    });
+    if let Some(path) = png_path {
+        if let Err(e) = screenshot::save_side_by_side(&before, &after, path) {
+            eprintln!("Unable to write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+    let changes = diff::diff(&before, &after);
+    println!("{}", changes.report());
+    if !changes.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+// Those that are on, or none
+fn quirk_names(quirks: &Quirks) -> String {
+    match quirks.to_string() {
+        names if names.is_empty() => String::from("none"),
+        names => names,
+    }
+}
+
+// Machine for the tools that run a ROM without a frontend
+fn headless_cpu(rom_path: &str, machine: &Machine) -> Chip8 {
+    try_headless_cpu(rom_path, machine).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+fn try_headless_cpu(rom_path: &str, machine: &Machine) -> Result<Chip8, String> {
+    let mut cpu = Chip8::new();
+    cpu.load_font();
+    let (rom, cart_options) = read_rom(rom_path)?;
+    load_rom(&mut cpu, &rom, rom_path)?;
+    configure(&mut cpu, rom_path, cart_options.as_ref(), machine);
+    Ok(cpu)
+}
+
+// Speed, quirks, palette and keys: the variant's, then an Octo cartridge's,
+// then the game's config, then whatever was given on the command line
+fn configure(cpu: &mut Chip8, rom_path: &str, cart_options: Option<&Game>, machine: &Machine) {
+    cpu.quirks = machine.quirks;
+    if let Some(options) = cart_options {
+        options.apply(cpu);
+    }
+    if let Some(game) = machine.config.game(Path::new(rom_path), cpu.rom()) {
+        info!("Using the config for {}", rom_path);
+        game.apply(cpu);
+    }
+    if let Some(speed) = machine.speed {
+        cpu.speed = speed;
+    }
+    if let Some(frames) = machine.run_ahead {
+        cpu.run_ahead = frames;
+    }
+    if let Some(Ok(quirks)) = machine.quirk_list.as_ref().map(|list| cpu.quirks.parse(list)) {
+        cpu.quirks = quirks;
+    }
+}
+
+// Save the --screenshot-at images due by this frame
+fn take_screenshots(cpu: &Chip8, frame: u64, shots: &mut Vec<(u64, PathBuf)>) {
+    while let Some((_, path)) = shots.first().filter(|(at, _)| *at <= frame) {
+        if let Err(e) = screenshot::save(cpu, path) {
+            eprintln!("Unable to write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+        println!("Saved frame {} to {}", frame, path.display());
+        shots.remove(0);
+    }
+}
+
+// End a run that reached --frames, --seconds or the last --screenshot-at
+fn stop(cpu: &Chip8, debugger: &Debugger, frames: u64, dump_path: Option<&Path>) -> ! {
+    debugger.save_mix();
+    cpu.peripherals.flush();
+    if let Some(path) = dump_path {
+        if let Err(e) = std::fs::write(path, state::save(cpu)) {
+            eprintln!("Unable to write {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+    println!("Stopped after {} frames, {} instructions", frames, cpu.instructions);
+    std::process::exit(0);
+}
+
+// IPS or BPS, told apart by the patch header
+fn patch_or_exit(rom: &[u8], patch_path: &str) -> Vec<u8> {
+    let patched = std::fs::read(patch_path)
+        .map_err(|e| e.to_string())
+        .and_then(|patch| patch::apply(rom, &patch));
+    match patched {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("Unable to apply {}: {}", patch_path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// ROM bytes, assembling .8o sources and Octo cartridges first
+fn read_or_exit(rom_path: &str) -> Vec<u8> {
+    read_rom_or_exit(rom_path).0
+}
+
+fn read_rom_or_exit(rom_path: &str) -> (Vec<u8>, Option<Game>) {
+    read_rom(rom_path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+// The program, and the options of an Octo cartridge, which is recognised by
+// its contents wherever it was read from
+fn read_rom(rom_path: &str) -> Result<(Vec<u8>, Option<Game>), String> {
+    unpack(read_bytes(rom_path)?, rom_path)
+}
+
+fn unpack(bytes: Vec<u8>, rom_path: &str) -> Result<(Vec<u8>, Option<Game>), String> {
+    match cart::is_cart(&bytes) {
+        true => match cart::load(&bytes) {
+            Ok((rom, options)) => Ok((rom, Some(options))),
+            Err(e) => Err(format!("{}: {}", rom_path, e)),
+        },
+        false => Ok((bytes, None)),
+    }
+}
+
+// A ROM's bytes from wherever the path points: the built-in demo by its
+// name, stdin for - (e.g. piped from an assembler), a download for an http://
+// or https:// link, the assembled program for an .8o source, otherwise the
+// file as it is
+fn read_bytes(rom_path: &str) -> Result<Vec<u8>, String> {
+    if rom_path == demo::NAME {
+        return Ok(demo::rom());
+    }
+    if rom_path == "-" {
+        let mut rom = Vec::new();
+        std::io::stdin()
+            .lock()
+            .read_to_end(&mut rom)
+            .map_err(|e| format!("Unable to read stdin: {}", e))?;
+        return match rom.is_empty() {
+            true => Err(String::from("No ROM on stdin")),
+            false => Ok(rom),
+        };
+    }
+    if rom_path.starts_with("http://") || rom_path.starts_with("https://") {
+        return download_rom(rom_path);
+    }
+    match rom_path.ends_with(".8o") {
+        true => assemble(rom_path),
+        false => std::fs::read(rom_path).map_err(|e| format!("Unable to read {}: {}", rom_path, e)),
+    }
+}
+
+#[cfg(feature = "download")]
+fn download_rom(url: &str) -> Result<Vec<u8>, String> {
+    download::fetch(url).map_err(|e| format!("Unable to download {}: {}", url, e))
+}
+
+#[cfg(not(feature = "download"))]
+fn download_rom(url: &str) -> Result<Vec<u8>, String> {
+    Err(format!(
+        "{} needs the download feature (cargo run --features download)",
+        url
+    ))
+}
+
+// The tools read ROMs of any size, but running one needs it to fit in memory
+fn load_rom(cpu: &mut Chip8, rom: &[u8], rom_path: &str) -> Result<(), String> {
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(format!(
+            "{} is {} bytes, more than the {} that fit in memory from 0x200",
+            rom_path,
+            rom.len(),
+            MAX_ROM_SIZE
+        ));
+    }
+    cpu.load_program(rom);
+    Ok(())
+}
+
+fn assemble_or_exit(source_path: &str) -> Vec<u8> {
+    assemble(source_path).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+// .8o files use Octo syntax, anything else Cowgod mnemonics
+fn assemble(source_path: &str) -> Result<Vec<u8>, String> {
+    let source = std::fs::read_to_string(source_path)
+        .map_err(|e| format!("Unable to read {}: {}", source_path, e))?;
+    let assembled = match source_path.ends_with(".8o") {
+        true => octo::assemble(&source),
+        false => asm::assemble_file(&source, Path::new(source_path)),
+    };
+    assembled.map_err(|e| format!("{}: {}", source_path, e))
 }
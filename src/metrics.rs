@@ -0,0 +1,158 @@
+use crate::chip8::Chip8;
+use crate::debugger::Debugger;
+use log::{error, info};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::Instant;
+
+struct Client {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+}
+
+// Prometheus exporter, polled from the event loop. Scrapes of /metrics get
+// the text format, anything else a 404.
+pub struct MetricsServer {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    started: Instant,
+    // Display updates, counted as the redraw flag goes up
+    frames: u64,
+    redraw: bool,
+    sound_seconds: f64,
+    last_observed: Instant,
+}
+
+impl MetricsServer {
+    pub fn bind(address: &str) -> io::Result<MetricsServer> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        info!("Metrics on http://{}/metrics", address);
+        Ok(MetricsServer {
+            listener,
+            clients: Vec::new(),
+            started: Instant::now(),
+            frames: 0,
+            redraw: false,
+            sound_seconds: 0.0,
+            last_observed: Instant::now(),
+        })
+    }
+
+    // Sample the machine, then answer any scrapes
+    pub fn poll(&mut self, debugger: &Debugger, cpu: &Chip8) {
+        if cpu.redraw && !self.redraw {
+            self.frames += 1;
+        }
+        self.redraw = cpu.redraw;
+        let elapsed = self.last_observed.elapsed().as_secs_f64();
+        self.last_observed = Instant::now();
+        if cpu.sound_timer > 0 {
+            self.sound_seconds += elapsed;
+        }
+
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => match stream.set_nonblocking(true) {
+                    Ok(()) => self.clients.push(Client {
+                        stream,
+                        buffer: Vec::new(),
+                    }),
+                    Err(e) => error!("Metrics connection failed: {}", e),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    error!("Metrics accept failed: {}", e);
+                    break;
+                }
+            }
+        }
+        // Polled before every instruction, so only render for a scrape
+        let body = match self.clients.is_empty() {
+            true => String::new(),
+            false => self.render(debugger, cpu),
+        };
+        self.clients.retain_mut(|client| {
+            let mut chunk = [0; 1024];
+            loop {
+                match client.stream.read(&mut chunk) {
+                    Ok(0) => return false,
+                    Ok(len) => client.buffer.extend_from_slice(&chunk[..len]),
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => return false,
+                }
+            }
+            if !client.buffer.windows(4).any(|window| window == b"\r\n\r\n") {
+                return true;
+            }
+            let response = match client.buffer.starts_with(b"GET /metrics ") {
+                true => format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                ),
+                false => String::from(
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                ),
+            };
+            let sent = client
+                .stream
+                .set_nonblocking(false)
+                .and_then(|_| client.stream.write_all(response.as_bytes()));
+            if let Err(e) = sent {
+                error!("Metrics reply failed: {}", e);
+            }
+            false
+        });
+    }
+
+    fn render(&self, debugger: &Debugger, cpu: &Chip8) -> String {
+        let metrics = [
+            (
+                "chip8_instructions_total",
+                "counter",
+                "Instructions executed",
+                cpu.instructions as f64,
+            ),
+            (
+                "chip8_draws_total",
+                "counter",
+                "Clear and sprite draw instructions executed",
+                cpu.draws as f64,
+            ),
+            (
+                "chip8_frames_total",
+                "counter",
+                "Display updates",
+                self.frames as f64,
+            ),
+            (
+                "chip8_sound_seconds_total",
+                "counter",
+                "Time spent beeping",
+                self.sound_seconds,
+            ),
+            (
+                "chip8_paused",
+                "gauge",
+                "1 while paused in the debugger",
+                debugger.is_paused() as u8 as f64,
+            ),
+            (
+                "chip8_uptime_seconds",
+                "gauge",
+                "Time since the emulator started",
+                self.started.elapsed().as_secs_f64(),
+            ),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            text.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+                name, help, name, kind, name, value
+            ));
+        }
+        text
+    }
+}
@@ -0,0 +1,125 @@
+use crate::disasm::opcode_class;
+use log::{error, info};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/*    Instruction Mix
+ * What the program spends its instructions on, counted from the first one
+ * run until the emulator exits, across ROMs and resets:
+ *   draws       DXYN           skips      3XNN 4XNN 5XY0 9XY0 EX9E EXA1
+ *   clears      00E0           random     CXNN
+ *   jumps       1NNN BNNN      BCD        FX33
+ *   calls       2NNN           memory     FX55 FX65
+ *   returns     00EE           timers     FX07 FX15 FX18
+ *   arithmetic  7XNN 8XY4-7 8XY6 8XYE FX1E
+ *   logic       8XY1 8XY2 8XY3
+ *   loads       6XNN 8XY0 ANNN FX29
+ *   keys        FX0A           link       FX70 FX71
+ * followed by every opcode class on its own, busiest first.
+ */
+const GROUPS: [(&str, &[&str]); 15] = [
+    ("draws", &["DXYN"]),
+    ("clears", &["00E0"]),
+    ("jumps", &["1NNN", "BNNN"]),
+    ("calls", &["2NNN"]),
+    ("returns", &["00EE"]),
+    ("skips", &["3XNN", "4XNN", "5XY0", "9XY0", "EX9E", "EXA1"]),
+    ("random", &["CXNN"]),
+    ("BCD", &["FX33"]),
+    ("memory", &["FX55", "FX65"]),
+    ("timers", &["FX07", "FX15", "FX18"]),
+    (
+        "arithmetic",
+        &["7XNN", "8XY4", "8XY5", "8XY6", "8XY7", "8XYE", "FX1E"],
+    ),
+    ("logic", &["8XY1", "8XY2", "8XY3"]),
+    ("loads", &["6XNN", "8XY0", "ANNN", "FX29"]),
+    ("keys", &["FX0A"]),
+    ("link", &["FX70", "FX71"]),
+];
+
+#[derive(Debug, Default)]
+pub struct Mix {
+    classes: BTreeMap<&'static str, u64>,
+    total: u64,
+    // Written here when dropped, with --mix
+    path: Option<PathBuf>,
+}
+
+impl Mix {
+    pub fn record(&mut self, opcode: u16) {
+        *self.classes.entry(opcode_class(opcode)).or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    pub fn reset(&mut self) {
+        self.classes.clear();
+        self.total = 0;
+    }
+
+    pub fn save_on_exit(&mut self, path: &Path) {
+        self.path = Some(path.to_path_buf());
+    }
+
+    pub fn save(&self) {
+        if let Some(path) = &self.path {
+            match std::fs::write(path, self.report() + "\n") {
+                Ok(()) => info!("Instruction mix written to {}", path.display()),
+                Err(e) => error!("Unable to write {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    // Instructions in any of the classes
+    fn count(&self, classes: &[&str]) -> u64 {
+        classes
+            .iter()
+            .filter_map(|class| self.classes.get(class))
+            .sum()
+    }
+
+    pub fn report(&self) -> String {
+        if self.is_empty() {
+            return String::from("No instructions run yet");
+        }
+        let mut lines = vec![format!("{} instructions", self.total)];
+        for (name, classes) in GROUPS {
+            let count = self.count(classes);
+            if count > 0 {
+                lines.push(format!(
+                    "  {:<11} {:>10} {:>5.1}%",
+                    name,
+                    count,
+                    self.percent(count)
+                ));
+            }
+        }
+        let mut classes: Vec<(&&str, &u64)> = self.classes.iter().collect();
+        classes.sort_by(|a, b| b.1.cmp(a.1));
+        lines.push(String::from("Opcode classes:"));
+        for (class, count) in classes {
+            lines.push(format!(
+                "  {:<11} {:>10} {:>5.1}%",
+                class,
+                count,
+                self.percent(*count)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        count as f64 * 100.0 / self.total as f64
+    }
+}
+
+// Windows close by dropping their machines, which is when --mix is written
+impl Drop for Mix {
+    fn drop(&mut self) {
+        self.save();
+    }
+}
@@ -0,0 +1,142 @@
+use crate::chip8::Chip8;
+use crate::debugger::Debugger;
+use log::{error, info};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+const PROMPT: &str = "> ";
+
+/*    Monitor Session
+ * $ nc 127.0.0.1 4715
+ * > bp 220
+ * Breakpoint set at 0x220
+ * > m 200 20
+ * ...
+ * Stopped at 0x220
+ * > poke 300 ff
+ *
+ * Every debugger console command works, one per line, with plain text
+ * replies and a prompt after each. "quit" hangs up, and the monitor says
+ * when a breakpoint stops the machine. Meant for netcat and expect scripts
+ * where gdb or DAP are too much.
+ */
+// Polled from the event loop like the debugger servers, one client at a time
+pub struct MonitorServer {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    buffer: Vec<u8>,
+    // Tells the client once a breakpoint pauses it
+    running: bool,
+}
+
+impl MonitorServer {
+    pub fn bind(address: &str) -> io::Result<MonitorServer> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        info!("Monitor listening on {}", address);
+        Ok(MonitorServer {
+            listener,
+            client: None,
+            buffer: Vec::new(),
+            running: false,
+        })
+    }
+
+    pub fn poll(&mut self, debugger: &mut Debugger, cpu: &mut Chip8) {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((stream, peer)) => {
+                    if let Err(e) = stream.set_nonblocking(true) {
+                        error!("Monitor connection failed: {}", e);
+                        return;
+                    }
+                    info!("Monitor client connected from {}", peer);
+                    self.client = Some(stream);
+                    self.buffer.clear();
+                    self.running = !debugger.is_paused();
+                    self.send(PROMPT);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    error!("Monitor accept failed: {}", e);
+                    return;
+                }
+            }
+        }
+        if !self.receive() {
+            info!("Monitor client disconnected");
+            self.client = None;
+            return;
+        }
+        while let Some(end) = self.buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_string();
+            if line == "quit" || line == "exit" {
+                info!("Monitor client hung up");
+                self.client = None;
+                return;
+            }
+            let output = plain(&debugger.execute(cpu, &line));
+            self.running = !debugger.is_paused();
+            match output.is_empty() {
+                true => self.send(PROMPT),
+                false => self.send(&format!("{}\n{}", output, PROMPT)),
+            }
+        }
+        if self.running && debugger.is_paused() {
+            self.running = false;
+            self.send(&format!("\nStopped at {:#05x}\n{}", cpu.pc, PROMPT));
+        }
+    }
+
+    // Read what's arrived, false once the client has gone
+    fn receive(&mut self) -> bool {
+        let mut chunk = [0; 1024];
+        let client = match &mut self.client {
+            Some(client) => client,
+            None => return false,
+        };
+        loop {
+            match client.read(&mut chunk) {
+                Ok(0) => return false,
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return true,
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn send(&mut self, text: &str) {
+        if let Some(client) = &mut self.client {
+            // Long dumps can fill the socket buffer, so write them out fully
+            let sent = client
+                .set_nonblocking(false)
+                .and_then(|_| client.write_all(text.as_bytes()))
+                .and_then(|_| client.set_nonblocking(true));
+            if let Err(e) = sent {
+                error!("Monitor write failed: {}", e);
+                self.client = None;
+            }
+        }
+    }
+}
+
+// Console output without its terminal colours, which scripts would trip over
+fn plain(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                // Escape sequences here all end with the 'm' of a colour
+                for c in chars.by_ref() {
+                    if c == 'm' {
+                        break;
+                    }
+                }
+            }
+            c => output.push(c),
+        }
+    }
+    output
+}
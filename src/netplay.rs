@@ -0,0 +1,257 @@
+use crate::chip8::{Chip8, Quirks};
+use crate::debugger::Debugger;
+use log::info;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+// 60 Hz, matching the timers
+const FRAME: Duration = Duration::from_micros(16_667);
+const VERSION: u64 = 3;
+// About 50ms, enough to hide the round trip on most home connections
+pub const DEFAULT_DELAY: usize = 3;
+// Frames between state hashes, a second
+const CHECK_EVERY: u64 = 60;
+// Comes before a state hash, keys being 0 to F
+const HASH: u8 = 0xFF;
+// Most instructions a frame a host can ask for, well past any real game, so
+// a bad handshake can't stall the frame loop
+const MAX_SPEED: u64 = 10_000;
+// Longest handshake line, several times a real one, and how long to wait for
+// it, so a peer that sends nothing or never ends the line can't hang or fill
+// memory
+const MAX_LINE: usize = 4096;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/*    Netplay Protocol
+ * Host -> guest   {"version": 3, "seed": 1234, "delay": 3, "crc32": 5678,
+ *                  "speed": 10, "quirks": "shift,clip"}
+ * Guest -> host   {"ok": true} or {"error": "..."}
+ *
+ * One JSON line each to start, then both sides send one byte a frame, the
+ * key they hold. A key is used delay frames after it was pressed, so the
+ * other side's has usually arrived by then. The host's key wins when both
+ * players hold one, the core only reads a single key.
+ *
+ * Every 60th frame both sides also send 0xFF and the big-endian
+ * Chip8::state_hash after it. Netplay ends if they differ, the games having
+ * gone out of step.
+ */
+// Both machines run the same frames with the same inputs and random numbers,
+// so they stay in step without sending any of the display
+enum Message {
+    Key(u8),
+    Hash(u32),
+}
+
+pub struct Netplay {
+    stream: TcpStream,
+    host: bool,
+    // Keys waiting for their frame, starting with delay frames of nothing
+    local: VecDeque<u8>,
+    remote: VecDeque<u8>,
+    // Hashes waiting for the other side's, with their frame
+    local_hashes: VecDeque<(u64, u32)>,
+    remote_hashes: VecDeque<u32>,
+    // Read on a thread, so a frame only waits when the other side is behind
+    inputs: Receiver<Message>,
+    pub frame: u64,
+    frame_start: Instant,
+}
+
+impl Netplay {
+    // Wait for the other player, then send them the seed and settings
+    pub fn host(address: &str, cpu: &mut Chip8, delay: usize) -> io::Result<Netplay> {
+        let listener = TcpListener::bind(address)?;
+        info!("Waiting for the other player on {}", address);
+        let (mut stream, peer) = listener.accept()?;
+        let seed = rand::random::<u64>();
+        let hello = json!({
+            "version": VERSION,
+            "seed": seed,
+            "delay": delay,
+            "crc32": crc32fast::hash(cpu.rom()),
+            "speed": cpu.speed,
+            "quirks": cpu.quirks.to_string(),
+        });
+        writeln!(stream, "{}", hello)?;
+        let reply = read_line(&mut stream)?;
+        if let Some(error) = reply["error"].as_str() {
+            return Err(io::Error::new(ErrorKind::InvalidData, error.to_string()));
+        }
+        info!("Playing with {}", peer);
+        cpu.seed(seed);
+        Netplay::start(stream, true, delay)
+    }
+
+    // Connect to a host running the same ROM and take its settings
+    pub fn join(address: &str, cpu: &mut Chip8) -> io::Result<Netplay> {
+        let mut stream = TcpStream::connect(address)?;
+        let hello = read_line(&mut stream)?;
+        let error = match (
+            hello["version"].as_u64(),
+            hello["seed"].as_u64(),
+            hello["delay"].as_u64(),
+            hello["crc32"].as_u64(),
+        ) {
+            (Some(VERSION), Some(seed), Some(delay), Some(crc)) => {
+                // The host's settings, which would desync the games if they differed
+                let speed = hello["speed"].as_u64().unwrap_or(cpu.speed as u64);
+                let quirks = match hello["quirks"].as_str() {
+                    Some(list) => Quirks::default().parse(list).map(Some),
+                    None => Ok(None),
+                };
+                match (crc == crc32fast::hash(cpu.rom()) as u64, quirks) {
+                    (false, _) => "The players have different ROMs",
+                    _ if !(1..=MAX_SPEED).contains(&speed) => "The host sent an invalid speed",
+                    (true, Err(_)) => "The host sent quirks this version doesn't know",
+                    (true, Ok(quirks)) => {
+                        writeln!(stream, "{}", json!({ "ok": true }))?;
+                        info!("Playing with {}", address);
+                        cpu.seed(seed);
+                        cpu.speed = speed as usize;
+                        if let Some(quirks) = quirks {
+                            cpu.quirks = quirks;
+                        }
+                        return Netplay::start(stream, false, delay as usize);
+                    }
+                }
+            }
+            (Some(version), ..) if version != VERSION => "The host runs another netplay version",
+            _ => "Invalid netplay handshake",
+        };
+        writeln!(stream, "{}", json!({ "error": error }))?;
+        Err(io::Error::new(ErrorKind::InvalidData, error))
+    }
+
+    fn start(stream: TcpStream, host: bool, delay: usize) -> io::Result<Netplay> {
+        stream.set_nodelay(true)?;
+        let (sender, inputs) = mpsc::channel();
+        let mut reader = stream.try_clone()?;
+        std::thread::spawn(move || {
+            let mut byte = [0];
+            while reader.read_exact(&mut byte).is_ok() {
+                let message = match byte[0] {
+                    HASH => {
+                        let mut hash = [0; 4];
+                        if reader.read_exact(&mut hash).is_err() {
+                            break;
+                        }
+                        Message::Hash(u32::from_be_bytes(hash))
+                    }
+                    key => Message::Key(key),
+                };
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Netplay {
+            stream,
+            host,
+            local: VecDeque::from(vec![0; delay]),
+            remote: VecDeque::from(vec![0; delay]),
+            local_hashes: VecDeque::new(),
+            remote_hashes: VecDeque::new(),
+            inputs,
+            frame: 0,
+            frame_start: Instant::now(),
+        })
+    }
+
+    // Run the next frame once it's due, with cpu.input as the local key. An
+    // error means the other player has gone.
+    pub fn frame(&mut self, cpu: &mut Chip8, debugger: &mut Debugger) -> io::Result<()> {
+        if self.frame_start.elapsed() < FRAME {
+            return Ok(());
+        }
+        self.frame_start = Instant::now();
+        let _frame = cpu.frame_span().entered();
+        self.stream.write_all(&[cpu.input])?;
+        self.local.push_back(cpu.input);
+        let messages: Vec<Message> = self.inputs.try_iter().collect();
+        self.receive(messages);
+        while self.remote.is_empty() {
+            let message = self.inputs.recv().map_err(|_| {
+                io::Error::new(ErrorKind::ConnectionAborted, "The other player left")
+            })?;
+            self.receive(vec![message]);
+        }
+        let local = self.local.pop_front().unwrap_or_default();
+        let remote = self.remote.pop_front().unwrap_or_default();
+        let (host, guest) = match self.host {
+            true => (local, remote),
+            false => (remote, local),
+        };
+        cpu.input = match host {
+            0 => guest,
+            key => key,
+        };
+        debugger.run(cpu, cpu.speed);
+        cpu.decrement_timers();
+        debugger.end_frame(cpu);
+        self.frame += 1;
+        if self.frame.is_multiple_of(CHECK_EVERY) {
+            let hash = cpu.state_hash();
+            self.stream.write_all(&[HASH])?;
+            self.stream.write_all(&hash.to_be_bytes())?;
+            self.local_hashes.push_back((self.frame, hash));
+        }
+        self.check()
+    }
+
+    fn receive(&mut self, messages: Vec<Message>) {
+        for message in messages {
+            match message {
+                Message::Key(key) => self.remote.push_back(key),
+                Message::Hash(hash) => self.remote_hashes.push_back(hash),
+            }
+        }
+    }
+
+    // Both sides' hashes for the frames they've each reached
+    fn check(&mut self) -> io::Result<()> {
+        while let (Some(&(frame, local)), Some(&remote)) =
+            (self.local_hashes.front(), self.remote_hashes.front())
+        {
+            if local != remote {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("The games went out of step by frame {}", frame),
+                ));
+            }
+            self.local_hashes.pop_front();
+            self.remote_hashes.pop_front();
+        }
+        Ok(())
+    }
+}
+
+// One handshake line, a byte at a time so nothing after it gets read early
+fn read_line(stream: &mut TcpStream) -> io::Result<Value> {
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut line = Vec::new();
+    let mut byte = [0];
+    while byte[0] != b'\n' {
+        if line.len() == MAX_LINE {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "The netplay handshake is too long",
+            ));
+        }
+        stream.read_exact(&mut byte).map_err(|e| match e.kind() {
+            ErrorKind::WouldBlock | ErrorKind::TimedOut => io::Error::new(
+                ErrorKind::TimedOut,
+                "The other player didn't finish the handshake in time",
+            ),
+            _ => e,
+        })?;
+        line.push(byte[0]);
+    }
+    // The reader thread shares the socket, and waits as long as it takes
+    stream.set_read_timeout(None)?;
+    serde_json::from_slice(&line).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))
+}
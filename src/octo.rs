@@ -0,0 +1,828 @@
+use crate::asm::AsmError;
+use std::collections::HashMap;
+
+const START: usize = 0x200;
+const END: usize = 0x1000;
+// Stops a macro that expands into itself
+const MAX_EXPANSIONS: usize = 10_000;
+
+/*    Octo Syntax
+ * : name  or  :name   Label, a bare label name calls it
+ * :const name value   Constant
+ * :alias name v3      Register alias
+ * :org addr           Move the output address
+ * v0 := 5  v0 += v1   Register operations (:= += -= =- |= &= ^= >>= <<=)
+ * v0 := random 0xFF   Also delay and key
+ * i := label          Also i := hex v0 and i += v0
+ * delay := v0         Also buzzer := v0
+ * if v0 == 5 then     Also != key -key, guarding the next statement
+ * jump  jump0  native  sprite  bcd  save  load  clear  return  ;
+ * 0xFF                Bare numbers are data bytes
+ * # comment
+ *
+ * and the structured parts, compiled to skips and jumps:
+ * if v0 == 5 begin    Closed by end, with an optional else
+ * loop ... again      Forever, until a while v0 != 0 inside fails
+ * :macro m a b { }    Macro, m 1 2 expands the body with a and b replaced
+ * :calc name { }      Constant from an expression, e.g. { WIDTH - 8 }
+ * :byte { }           Data byte from an expression
+ *
+ * Expressions are whole numbers and, as in Octo, run right to left with no
+ * precedence, so 2 * 3 + 1 is 8. They take + - * / % & | ^ << >> pow min max
+ * and the comparisons, unary - ~ ! abs, @ for a byte already assembled and
+ * HERE for the address. Labels in them must be defined further up.
+ */
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let tokens = source
+        .lines()
+        .enumerate()
+        .flat_map(|(index, text)| {
+            let code = match text.find('#') {
+                Some(comment) => &text[..comment],
+                None => text,
+            };
+            code.split_whitespace().map(move |token| (index + 1, token))
+        })
+        .collect();
+    let mut assembler = Assembler {
+        tokens,
+        pos: 0,
+        image: Vec::new(),
+        addr: START,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+        macros: HashMap::new(),
+        expansions: 0,
+    };
+    while assembler.pos < assembler.tokens.len() {
+        assembler.statement()?;
+    }
+    if let Some(block) = assembler.blocks.last() {
+        let (line, message) = match block {
+            Block::If { line, .. } | Block::Else { line, .. } => (line, "begin without end"),
+            Block::Loop { line, .. } => (line, "loop without again"),
+        };
+        return Err(AsmError {
+            line: *line,
+            file: None,
+            message: String::from(message),
+        });
+    }
+    assembler.resolve()?;
+    Ok(assembler.image)
+}
+
+// Octo-style rendering of one instruction, raw bytes if it is not one
+pub fn disassemble(opcode: u16) -> String {
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+    let nn = opcode & 0x00FF;
+    let nnn = opcode & 0x0FFF;
+    match (opcode & 0xF000, n) {
+        _ if opcode == 0x00E0 => String::from("clear"),
+        _ if opcode == 0x00EE => String::from("return"),
+        (0x0000, _) => format!("native {:#05x}", nnn),
+        (0x1000, _) => format!("jump {:#05x}", nnn),
+        (0x2000, _) => format!(":call {:#05x}", nnn),
+        (0x3000, _) => format!("if v{:x} != {:#04x} then", x, nn),
+        (0x4000, _) => format!("if v{:x} == {:#04x} then", x, nn),
+        (0x5000, 0x0) => format!("if v{:x} != v{:x} then", x, y),
+        (0x6000, _) => format!("v{:x} := {:#04x}", x, nn),
+        (0x7000, _) => format!("v{:x} += {:#04x}", x, nn),
+        (0x8000, 0x0) => format!("v{:x} := v{:x}", x, y),
+        (0x8000, 0x1) => format!("v{:x} |= v{:x}", x, y),
+        (0x8000, 0x2) => format!("v{:x} &= v{:x}", x, y),
+        (0x8000, 0x3) => format!("v{:x} ^= v{:x}", x, y),
+        (0x8000, 0x4) => format!("v{:x} += v{:x}", x, y),
+        (0x8000, 0x5) => format!("v{:x} -= v{:x}", x, y),
+        (0x8000, 0x6) => format!("v{:x} >>= v{:x}", x, y),
+        (0x8000, 0x7) => format!("v{:x} =- v{:x}", x, y),
+        (0x8000, 0xE) => format!("v{:x} <<= v{:x}", x, y),
+        (0x9000, 0x0) => format!("if v{:x} == v{:x} then", x, y),
+        (0xA000, _) => format!("i := {:#05x}", nnn),
+        (0xB000, _) => format!("jump0 {:#05x}", nnn),
+        (0xC000, _) => format!("v{:x} := random {:#04x}", x, nn),
+        (0xD000, _) => format!("sprite v{:x} v{:x} {}", x, y, n),
+        (0xE000, _) if nn == 0x9E => format!("if v{:x} -key then", x),
+        (0xE000, _) if nn == 0xA1 => format!("if v{:x} key then", x),
+        (0xF000, _) => match nn {
+            0x07 => format!("v{:x} := delay", x),
+            0x0A => format!("v{:x} := key", x),
+            0x15 => format!("delay := v{:x}", x),
+            0x18 => format!("buzzer := v{:x}", x),
+            0x1E => format!("i += v{:x}", x),
+            0x29 => format!("i := hex v{:x}", x),
+            0x33 => format!("bcd v{:x}", x),
+            0x55 => format!("save v{:x}", x),
+            0x65 => format!("load v{:x}", x),
+            _ => format!("{:#04x} {:#04x}", opcode >> 8, nn),
+        },
+        _ => format!("{:#04x} {:#04x}", opcode >> 8, nn),
+    }
+}
+
+// Address operand waiting for a label defined later in the source
+struct Fixup {
+    offset: usize,
+    name: String,
+    line: usize,
+}
+
+// A begin or loop waiting for its end or again, with the offsets of the
+// jumps to point past it
+enum Block {
+    If {
+        jump: usize,
+        line: usize,
+    },
+    Else {
+        jump: usize,
+        line: usize,
+    },
+    Loop {
+        start: usize,
+        exits: Vec<usize>,
+        line: usize,
+    },
+}
+
+#[derive(Clone)]
+struct Macro<'a> {
+    params: Vec<&'a str>,
+    body: Vec<(usize, &'a str)>,
+}
+
+const BINARY: [&str; 19] = [
+    "+", "-", "*", "/", "%", "&", "|", "^", "<<", ">>", "pow", "min", "max", "<", "<=", "==", "!=",
+    ">=", ">",
+];
+
+struct Assembler<'a> {
+    tokens: Vec<(usize, &'a str)>,
+    pos: usize,
+    image: Vec<u8>,
+    addr: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, usize>,
+    aliases: HashMap<String, u16>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<Block>,
+    macros: HashMap<String, Macro<'a>>,
+    expansions: usize,
+}
+
+impl<'a> Assembler<'a> {
+    fn line(&self) -> usize {
+        match self.tokens.get(self.pos.saturating_sub(1)) {
+            Some((line, _)) => *line,
+            None => 0,
+        }
+    }
+
+    fn error(&self, message: String) -> AsmError {
+        AsmError {
+            line: self.line(),
+            file: None,
+            message,
+        }
+    }
+
+    fn next(&mut self) -> Result<&'a str, AsmError> {
+        match self.tokens.get(self.pos) {
+            Some((_, token)) => {
+                self.pos += 1;
+                Ok(token)
+            }
+            None => Err(self.error(String::from("unexpected end of source"))),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(self.error(format!("expected {} but found {}", expected, token))),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|(_, token)| *token)
+    }
+
+    fn register(&self, token: &str) -> Option<u16> {
+        if let Some(register) = self.aliases.get(token) {
+            return Some(*register);
+        }
+        match token.to_ascii_lowercase().strip_prefix('v') {
+            Some(digit) if digit.len() == 1 => u16::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    fn expect_register(&mut self) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        self.register(token)
+            .ok_or_else(|| self.error(format!("expected a register but found {}", token)))
+    }
+
+    // Literal or constant, labels only when already defined
+    fn number(&self, token: &str) -> Option<usize> {
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            usize::from_str_radix(hex, 16).ok()
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            usize::from_str_radix(binary, 2).ok()
+        } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+            digits.parse().ok()
+        } else {
+            self.constants
+                .get(digits)
+                .or_else(|| self.labels.get(digits))
+                .copied()
+        }?;
+        // Negative bytes wrap the way Octo writes them
+        match negative {
+            true => Some((0x100 - (value & 0xFF)) & 0xFF),
+            false => Some(value),
+        }
+    }
+
+    fn byte(&mut self) -> Result<u16, AsmError> {
+        let token = self.next()?;
+        match self.number(token) {
+            Some(value) if value <= 0xFF => Ok(value as u16),
+            Some(_) => Err(self.error(format!("{} does not fit in a byte", token))),
+            None => Err(self.error(format!("unknown value {}", token))),
+        }
+    }
+
+    // 12-bit operand of the instruction about to be emitted, which may
+    // name a label further down
+    fn address(&mut self, opcode: u16) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match self.number(token) {
+            Some(value) if value <= 0xFFF => self.emit(opcode | value as u16),
+            Some(_) => Err(self.error(format!("address {} is out of range", token))),
+            None if is_name(token) => {
+                self.fixups.push(Fixup {
+                    offset: self.addr - START,
+                    name: token.to_string(),
+                    line: self.line(),
+                });
+                self.emit(opcode)
+            }
+            None => Err(self.error(format!("unknown address {}", token))),
+        }
+    }
+
+    fn emit(&mut self, opcode: u16) -> Result<(), AsmError> {
+        self.emit_bytes(&opcode.to_be_bytes())
+    }
+
+    fn emit_bytes(&mut self, bytes: &[u8]) -> Result<(), AsmError> {
+        if self.addr + bytes.len() > END {
+            return Err(self.error(format!("program runs past {:#05x}", END)));
+        }
+        let offset = self.addr - START;
+        if self.image.len() < offset + bytes.len() {
+            self.image.resize(offset + bytes.len(), 0);
+        }
+        self.image[offset..offset + bytes.len()].copy_from_slice(bytes);
+        self.addr += bytes.len();
+        Ok(())
+    }
+
+    // A jump whose target is filled in by patch when its block closes
+    fn jump_forward(&mut self) -> Result<usize, AsmError> {
+        let offset = self.addr - START;
+        self.emit(0x1000)?;
+        Ok(offset)
+    }
+
+    fn patch(&mut self, offset: usize) {
+        set_address(&mut self.image, offset, self.addr);
+    }
+
+    fn define_label(&mut self, name: &str) -> Result<(), AsmError> {
+        if !is_name(name) || self.labels.insert(name.to_string(), self.addr).is_some() {
+            return Err(self.error(format!("invalid or duplicate label {}", name)));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        match token {
+            ":" => {
+                let name = self.next()?;
+                self.define_label(name)
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.next()?;
+                match self.number(value) {
+                    Some(value) if is_name(name) => {
+                        self.constants.insert(name.to_string(), value);
+                        Ok(())
+                    }
+                    _ => Err(self.error(format!("invalid constant {} {}", name, value))),
+                }
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.expect_register()?;
+                self.aliases.insert(name.to_string(), register);
+                Ok(())
+            }
+            ":org" => {
+                let value = self.next()?;
+                match self.number(value) {
+                    Some(addr) if (START..END).contains(&addr) => {
+                        self.addr = addr;
+                        Ok(())
+                    }
+                    _ => Err(self.error(format!("invalid origin {}", value))),
+                }
+            }
+            ":byte" if self.peek() == Some("{") => {
+                self.next()?;
+                let value = wrap(self.expression()?);
+                self.expect("}")?;
+                match value {
+                    value if value <= 0xFF => self.emit_bytes(&[value as u8]),
+                    _ => Err(self.error(format!("{} does not fit in a byte", value))),
+                }
+            }
+            ":byte" => {
+                let value = self.byte()?;
+                self.emit_bytes(&[value as u8])
+            }
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let value = self.expression()?;
+                self.expect("}")?;
+                match is_name(name) {
+                    true => {
+                        self.constants.insert(name.to_string(), wrap(value));
+                        Ok(())
+                    }
+                    false => Err(self.error(format!("invalid constant {}", name))),
+                }
+            }
+            ":macro" => {
+                let name = self.next()?;
+                let mut params = Vec::new();
+                loop {
+                    match self.next()? {
+                        "{" => break,
+                        param => params.push(param),
+                    }
+                }
+                let body = self.braces()?;
+                match is_name(name) {
+                    true => {
+                        self.macros.insert(name.to_string(), Macro { params, body });
+                        Ok(())
+                    }
+                    false => Err(self.error(format!("invalid macro name {}", name))),
+                }
+            }
+            ":call" => self.address(0x2000),
+            "clear" => self.emit(0x00E0),
+            "return" | ";" => self.emit(0x00EE),
+            "jump" => self.address(0x1000),
+            "jump0" => self.address(0xB000),
+            "native" => self.address(0x0000),
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                match self.byte()? {
+                    n if n <= 0xF => self.emit(0xD000 | x << 8 | y << 4 | n),
+                    _ => Err(self.error(String::from("sprite height must be 0-15"))),
+                }
+            }
+            "bcd" => {
+                let x = self.expect_register()?;
+                self.emit(0xF033 | x << 8)
+            }
+            "save" => {
+                let x = self.expect_register()?;
+                self.emit(0xF055 | x << 8)
+            }
+            "load" => {
+                let x = self.expect_register()?;
+                self.emit(0xF065 | x << 8)
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.expect_register()?;
+                match token {
+                    "delay" => self.emit(0xF015 | x << 8),
+                    _ => self.emit(0xF018 | x << 8),
+                }
+            }
+            "i" => match self.next()? {
+                ":=" if self.peek() == Some("hex") => {
+                    self.next()?;
+                    let x = self.expect_register()?;
+                    self.emit(0xF029 | x << 8)
+                }
+                ":=" => self.address(0xA000),
+                "+=" => {
+                    let x = self.expect_register()?;
+                    self.emit(0xF01E | x << 8)
+                }
+                operator => Err(self.error(format!("unknown operator i {}", operator))),
+            },
+            "if" => {
+                self.comparison()?;
+                match self.next()? {
+                    "then" => Ok(()),
+                    "begin" => {
+                        self.invert_skip();
+                        let jump = self.jump_forward()?;
+                        let line = self.line();
+                        self.blocks.push(Block::If { jump, line });
+                        Ok(())
+                    }
+                    token => Err(self.error(format!("expected then or begin but found {}", token))),
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump, line }) => {
+                    let end = self.jump_forward()?;
+                    self.patch(jump);
+                    self.blocks.push(Block::Else { jump: end, line });
+                    Ok(())
+                }
+                _ => Err(self.error(String::from("else without begin"))),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump, .. }) | Some(Block::Else { jump, .. }) => {
+                    self.patch(jump);
+                    Ok(())
+                }
+                _ => Err(self.error(String::from("end without begin"))),
+            },
+            "loop" => {
+                let (start, line) = (self.addr, self.line());
+                self.blocks.push(Block::Loop {
+                    start,
+                    exits: Vec::new(),
+                    line,
+                });
+                Ok(())
+            }
+            "while" => {
+                self.comparison()?;
+                self.invert_skip();
+                let jump = self.jump_forward()?;
+                let exits = self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                });
+                match exits {
+                    Some(exits) => {
+                        exits.push(jump);
+                        Ok(())
+                    }
+                    None => Err(self.error(String::from("while outside a loop"))),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits, .. }) => {
+                    self.emit(0x1000 | start as u16)?;
+                    for exit in exits {
+                        self.patch(exit);
+                    }
+                    Ok(())
+                }
+                _ => Err(self.error(String::from("again without loop"))),
+            },
+            _ if token.starts_with(':') && token.len() > 1 => self.define_label(&token[1..]),
+            _ if self.macros.contains_key(token) => self.expand(token),
+            _ if self.register(token).is_some() => self.register_statement(token),
+            _ => match self.number(token) {
+                Some(value) if value <= 0xFF => self.emit_bytes(&[value as u8]),
+                Some(_) => Err(self.error(format!("{} does not fit in a byte", token))),
+                // Anything else is a call to a label
+                None => {
+                    self.pos -= 1;
+                    self.address(0x2000)
+                }
+            },
+        }
+    }
+
+    fn register_statement(&mut self, token: &str) -> Result<(), AsmError> {
+        let x = self.register(token).unwrap_or_default() << 8;
+        let operator = self.next()?;
+        let operand = self.next()?;
+        let y = self.register(operand).map(|y| y << 4);
+        match (operator, y) {
+            (":=", Some(y)) => self.emit(0x8000 | x | y),
+            ("|=", Some(y)) => self.emit(0x8001 | x | y),
+            ("&=", Some(y)) => self.emit(0x8002 | x | y),
+            ("^=", Some(y)) => self.emit(0x8003 | x | y),
+            ("+=", Some(y)) => self.emit(0x8004 | x | y),
+            ("-=", Some(y)) => self.emit(0x8005 | x | y),
+            (">>=", Some(y)) => self.emit(0x8006 | x | y),
+            ("=-", Some(y)) => self.emit(0x8007 | x | y),
+            ("<<=", Some(y)) => self.emit(0x800E | x | y),
+            (":=", None) => match operand {
+                "random" => {
+                    let mask = self.byte()?;
+                    self.emit(0xC000 | x | mask)
+                }
+                "delay" => self.emit(0xF007 | x),
+                "key" => self.emit(0xF00A | x),
+                _ => {
+                    self.pos -= 1;
+                    let value = self.byte()?;
+                    self.emit(0x6000 | x | value)
+                }
+            },
+            ("+=", None) => {
+                self.pos -= 1;
+                let value = self.byte()?;
+                self.emit(0x7000 | x | value)
+            }
+            ("-=", None) => {
+                // Octo subtracts constants by adding their negation
+                self.pos -= 1;
+                let value = self.byte()?;
+                self.emit(0x7000 | x | ((0x100 - value) & 0xFF))
+            }
+            _ => Err(self.error(format!(
+                "unknown operation {} {} {}",
+                token, operator, operand
+            ))),
+        }
+    }
+
+    // Any setup, then a skip over the next instruction when the condition is
+    // false, the inverse of the condition guarding the statement
+    fn comparison(&mut self) -> Result<(), AsmError> {
+        let x = self.expect_register()?;
+        let operator = self.next()?;
+        match operator {
+            "key" => self.emit(0xE0A1 | x << 8),
+            "-key" => self.emit(0xE09E | x << 8),
+            "==" | "!=" => {
+                let operand = self.next()?;
+                match (operator, self.register(operand)) {
+                    ("==", Some(y)) => self.emit(0x9000 | x << 8 | y << 4),
+                    ("!=", Some(y)) => self.emit(0x5000 | x << 8 | y << 4),
+                    ("==", None) => {
+                        self.pos -= 1;
+                        let value = self.byte()?;
+                        self.emit(0x4000 | x << 8 | value)
+                    }
+                    _ => {
+                        self.pos -= 1;
+                        let value = self.byte()?;
+                        self.emit(0x3000 | x << 8 | value)
+                    }
+                }
+            }
+            _ => Err(self.error(format!("unknown comparison {}", operator))),
+        }
+    }
+
+    // Turn the skip just emitted around, for a jump taken when the
+    // condition is false
+    fn invert_skip(&mut self) {
+        let offset = self.addr - START - 2;
+        let skip = u16::from_be_bytes([self.image[offset], self.image[offset + 1]]);
+        self.image[offset..offset + 2].copy_from_slice(&inverse(skip).to_be_bytes());
+    }
+
+    // Tokens up to the } matching a { already read
+    fn braces(&mut self) -> Result<Vec<(usize, &'a str)>, AsmError> {
+        let mut depth = 1;
+        let mut body = Vec::new();
+        loop {
+            let token = self.next()?;
+            match token {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                return Ok(body);
+            }
+            body.push((self.line(), token));
+        }
+    }
+
+    // Swap a macro's body in for its name and arguments
+    fn expand(&mut self, name: &str) -> Result<(), AsmError> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(format!("{} keeps expanding", name)));
+        }
+        let Macro { params, body } = self.macros[name].clone();
+        let mut args = HashMap::new();
+        for param in params {
+            args.insert(param, self.next()?);
+        }
+        let expanded: Vec<(usize, &'a str)> = body
+            .into_iter()
+            .map(|(line, token)| (line, args.get(token).copied().unwrap_or(token)))
+            .collect();
+        self.tokens.splice(self.pos..self.pos, expanded);
+        Ok(())
+    }
+
+    // Right to left like Octo, so everything after an operator is its right
+    // hand side
+    fn expression(&mut self) -> Result<i64, AsmError> {
+        let left = self.term()?;
+        match self.peek() {
+            Some(operator) if BINARY.contains(&operator) => {
+                self.next()?;
+                let right = self.expression()?;
+                self.binary(operator, left, right)
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn term(&mut self) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        match token {
+            "(" => {
+                let value = self.expression()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            "-" => Ok(self.term()?.wrapping_neg()),
+            "~" => Ok(!self.term()?),
+            "!" => Ok((self.term()? == 0) as i64),
+            "abs" => Ok(self.term()?.wrapping_abs()),
+            "@" => {
+                let addr = self.term()?;
+                usize::try_from(addr)
+                    .ok()
+                    .and_then(|addr| addr.checked_sub(START))
+                    .and_then(|offset| self.image.get(offset))
+                    .map(|byte| *byte as i64)
+                    .ok_or_else(|| self.error(format!("nothing assembled at {:#05x}", addr)))
+            }
+            "HERE" => Ok(self.addr as i64),
+            _ => {
+                let value = match token.strip_prefix('-') {
+                    Some(digits) => self.number(digits).map(|value| -(value as i64)),
+                    None => self.number(token).map(|value| value as i64),
+                };
+                value.ok_or_else(|| self.error(format!("unknown value {}", token)))
+            }
+        }
+    }
+
+    fn binary(&self, operator: &str, left: i64, right: i64) -> Result<i64, AsmError> {
+        Ok(match operator {
+            "+" => left.wrapping_add(right),
+            "-" => left.wrapping_sub(right),
+            "*" => left.wrapping_mul(right),
+            "/" | "%" if right == 0 => return Err(self.error(String::from("division by zero"))),
+            "/" => left / right,
+            "%" => left % right,
+            "&" => left & right,
+            "|" => left | right,
+            "^" => left ^ right,
+            "<<" => left.wrapping_shl(right as u32),
+            ">>" => left.wrapping_shr(right as u32),
+            "pow" => left.wrapping_pow(right as u32),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "<" => (left < right) as i64,
+            "<=" => (left <= right) as i64,
+            "==" => (left == right) as i64,
+            "!=" => (left != right) as i64,
+            ">=" => (left >= right) as i64,
+            _ => (left > right) as i64,
+        })
+    }
+
+    fn resolve(&mut self) -> Result<(), AsmError> {
+        for fixup in &self.fixups {
+            let addr = match self.labels.get(&fixup.name) {
+                Some(addr) => *addr,
+                None => {
+                    return Err(AsmError {
+                        line: fixup.line,
+                        file: None,
+                        message: format!("unknown label {}", fixup.name),
+                    })
+                }
+            };
+            set_address(&mut self.image, fixup.offset, addr);
+        }
+        Ok(())
+    }
+}
+
+// The skip taken when the given one isn't
+pub fn inverse(skip: u16) -> u16 {
+    match skip & 0xF000 {
+        0x3000 => skip + 0x1000,
+        0x4000 => skip - 0x1000,
+        0x5000 => skip + 0x4000,
+        0x9000 => skip - 0x4000,
+        // EX9E and EXA1
+        _ => skip ^ 0x003F,
+    }
+}
+
+// Fill in the 12-bit address of the instruction at offset
+fn set_address(image: &mut [u8], offset: usize, addr: usize) {
+    image[offset] |= (addr >> 8) as u8 & 0x0F;
+    image[offset + 1] = addr as u8;
+}
+
+// Negative values become bytes the way Octo writes them
+fn wrap(value: i64) -> usize {
+    match value < 0 {
+        true => (0x100 - (value.unsigned_abs() as usize & 0xFF)) & 0xFF,
+        false => value as usize,
+    }
+}
+
+fn is_name(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && token
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(source: &str) -> String {
+        assemble(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn loops_jump_back_and_while_jumps_out() {
+        let source = ": main\n  v0 := 0\n  loop\n    v0 += 1\n    while v0 != 10\n  again";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x60, 0x00, 0x70, 0x01, 0x40, 0x0A, 0x12, 0x0A, 0x12, 0x02]
+        );
+    }
+
+    #[test]
+    fn if_else_skips_to_each_branch() {
+        let source = "if v0 == 5 begin v1 := 1 else v1 := 2 end";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x30, 0x05, 0x12, 0x08, 0x61, 0x01, 0x12, 0x0A, 0x61, 0x02]
+        );
+    }
+
+    #[test]
+    fn constants_and_macros() {
+        let source = ":const SPEED 3\n:macro bump reg { reg += SPEED }\nbump v1\nbump v2";
+        assert_eq!(assemble(source).unwrap(), [0x71, 0x03, 0x72, 0x03]);
+    }
+
+    // 2 * 3 + 1 runs right to left, and a bare label name is a call
+    #[test]
+    fn calc_data_and_calls() {
+        let source = ":calc X { 2 * 3 + 1 }\n:byte { X }\n: sprite 0xF0 0x90\n\
+                      i := sprite main\n: main clear jump main";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x08, 0xF0, 0x90, 0xA2, 0x01, 0x22, 0x07, 0x00, 0xE0, 0x12, 0x07]
+        );
+    }
+
+    #[test]
+    fn aliases_name_registers() {
+        let source = ":alias x v3\nx := 7\nif x != 7 then x += 1\ni := hex x\nbcd x save x load x";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x63, 0x07, 0x33, 0x07, 0x73, 0x01, 0xF3, 0x29, 0xF3, 0x33, 0xF3, 0x55, 0xF3, 0x65]
+        );
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(
+            error("if v0 == 1 begin\nclear"),
+            "line 1: begin without end"
+        );
+        assert_eq!(error("v0 := 256"), "line 1: 256 does not fit in a byte");
+        assert_eq!(error("jump nowhere"), "line 1: unknown label nowhere");
+        assert_eq!(error(": a\n: a"), "line 2: invalid or duplicate label a");
+    }
+}
@@ -0,0 +1,172 @@
+use std::time::{Duration, Instant};
+
+// How long a message stays up
+const SHOW_FOR: Duration = Duration::from_secs(2);
+const WIDTH: usize = 64;
+// 3x5 glyphs with a pixel of background around them
+const CELL_WIDTH: usize = 4;
+const CELL_HEIGHT: usize = 6;
+pub const COLUMNS: usize = WIDTH / CELL_WIDTH;
+pub const ROWS: usize = 32 / CELL_HEIGHT;
+
+/*    On-screen Display
+ * Messages from the frontend drawn over the top of the display, e.g. after
+ * loading a ROM, in a 3x5 font of capitals, digits and some punctuation.
+ * Lines wrap at 16 characters, and more than 5 lines take turns a page at a
+ * time. The text uses the ROM's palette inverted so it stands out from the
+ * game.
+ */
+#[derive(Debug, Default)]
+pub struct Osd {
+    lines: Vec<String>,
+    shown: Option<Instant>,
+    // Stays up until replaced or hidden
+    pinned: bool,
+}
+
+impl Osd {
+    pub fn show(&mut self, text: &str) {
+        self.lines = wrap(text);
+        self.shown = Some(Instant::now());
+        self.pinned = false;
+    }
+
+    pub fn pin(&mut self, text: &str) {
+        self.show(text);
+        self.pinned = true;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.shown
+            .is_some_and(|shown| self.pinned || shown.elapsed() < SHOW_FOR)
+    }
+
+    // Paint over a 64 pixel wide RGBA frame
+    pub fn draw(&self, frame: &mut [u8], (color, background): ([u8; 4], [u8; 4])) {
+        let shown = match (self.is_visible(), self.shown) {
+            (true, Some(shown)) => shown,
+            _ => return,
+        };
+        let pages = self.lines.len().div_ceil(ROWS).max(1);
+        let page = (shown.elapsed().as_millis() / SHOW_FOR.as_millis()) as usize % pages;
+        let lines: Vec<String> = self
+            .lines
+            .iter()
+            .skip(page * ROWS)
+            .take(ROWS)
+            .cloned()
+            .collect();
+        draw_lines(frame, &lines, (color, background));
+    }
+}
+
+// Lines of at most COLUMNS characters
+pub fn wrap(text: &str) -> Vec<String> {
+    text.lines()
+        .flat_map(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            chars
+                .chunks(COLUMNS)
+                .map(|chunk| chunk.iter().collect::<String>())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+// Up to ROWS lines from the top of a 64 pixel wide RGBA frame, in the palette
+// inverted
+pub fn draw_lines(frame: &mut [u8], lines: &[String], (color, background): ([u8; 4], [u8; 4])) {
+    for (row, line) in lines.iter().take(ROWS).enumerate() {
+        for (column, c) in line.chars().take(COLUMNS).enumerate() {
+            let rows = glyph(c);
+            for y in 0..CELL_HEIGHT {
+                for x in 0..CELL_WIDTH {
+                    let lit = match (x.checked_sub(1), y.checked_sub(1)) {
+                        (Some(gx), Some(gy)) if gx < 3 && gy < 5 => rows[gy] & (0b100 >> gx) != 0,
+                        _ => false,
+                    };
+                    let (px, py) = (column * CELL_WIDTH + x, row * CELL_HEIGHT + y);
+                    let offset = (py * WIDTH + px) * 4;
+                    if let Some(pixel) = frame.get_mut(offset..offset + 4) {
+                        pixel.copy_from_slice(match lit {
+                            true => &background,
+                            false => &color,
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Halve the brightness of an RGBA frame, e.g. under a pause message
+pub fn dim(frame: &mut [u8]) {
+    for pixel in frame.chunks_exact_mut(4) {
+        for channel in &mut pixel[..3] {
+            *channel /= 2;
+        }
+    }
+}
+
+// Rows of 3 pixels, high bit on the left. Anything missing is blank.
+pub fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b011, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b010, 0b010],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '?' => [0b110, 0b001, 0b010, 0b000, 0b010],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '[' => [0b110, 0b100, 0b100, 0b100, 0b110],
+        ']' => [0b011, 0b001, 0b001, 0b001, 0b011],
+        '>' => [0b100, 0b010, 0b001, 0b010, 0b100],
+        '<' => [0b001, 0b010, 0b100, 0b010, 0b001],
+        '#' => [0b101, 0b111, 0b101, 0b111, 0b101],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '*' => [0b000, 0b101, 0b010, 0b101, 0b000],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        _ => [0; 5],
+    }
+}
@@ -0,0 +1,305 @@
+// IPS and BPS patches, the formats ROM hacks are usually shared in
+
+// Largest ROM a patch may produce, far more than a CHIP-8 can load, so a
+// patch can't make it reserve or build anything huge
+const MAX_TARGET_SIZE: usize = 0x10000;
+
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, &patch[5..])
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err(String::from("not an IPS or BPS patch"))
+    }
+}
+
+/*    IPS Records
+ * offset (3 bytes) size (2 bytes) data (size bytes)
+ * offset (3 bytes) 0x0000 count (2 bytes) value (1 byte)   Run of one value
+ * "EOF" [truncated length (3 bytes)]
+ */
+fn apply_ips(rom: &[u8], records: &[u8]) -> Result<Vec<u8>, String> {
+    let mut output = rom.to_vec();
+    let mut position = 0;
+    let mut read = |len: usize| match records.get(position..position + len) {
+        Some(bytes) => {
+            position += len;
+            Ok(bytes
+                .iter()
+                .fold(0, |value, byte| value << 8 | *byte as usize))
+        }
+        None => Err(String::from("IPS patch ends in the middle of a record")),
+    };
+    loop {
+        let offset = read(3)?;
+        if offset == 0x454F46 {
+            // "EOF", optionally followed by the length to truncate to
+            if let Ok(len) = read(3) {
+                output.truncate(len);
+            }
+            return Ok(output);
+        }
+        let (data, len) = match read(2)? {
+            0 => {
+                let count = read(2)?;
+                (vec![read(1)? as u8; count], count)
+            }
+            size => {
+                let mut data = Vec::with_capacity(size);
+                for _ in 0..size {
+                    data.push(read(1)? as u8);
+                }
+                (data, size)
+            }
+        };
+        if offset + len > MAX_TARGET_SIZE {
+            return Err(format!("IPS patch writes past {:#x}", MAX_TARGET_SIZE));
+        }
+        if output.len() < offset + len {
+            output.resize(offset + len, 0);
+        }
+        output[offset..offset + len].copy_from_slice(&data);
+    }
+}
+
+/*    BPS Layout
+ * "BPS1" source size, target size, metadata size, metadata   (varints)
+ * actions, each a varint of (length - 1) << 2 | command:
+ *   0 SourceRead   copy from the source at the output position
+ *   1 TargetRead   copy bytes from the patch
+ *   2 SourceCopy   copy from a relative position in the source
+ *   3 TargetCopy   copy from a relative position in the output
+ * CRC-32 of the source, target and patch (4 bytes each, little-endian)
+ */
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < 16 {
+        return Err(String::from("BPS patch is too short"));
+    }
+    let checksum = |offset: usize| {
+        let bytes = &patch[patch.len() - offset..patch.len() - offset + 4];
+        u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    };
+    let (source_crc, target_crc, patch_crc) = (checksum(12), checksum(8), checksum(4));
+    if crc32fast::hash(&patch[..patch.len() - 4]) != patch_crc {
+        return Err(String::from("BPS patch is corrupt"));
+    }
+    if crc32fast::hash(rom) != source_crc {
+        return Err(String::from("BPS patch is for a different ROM"));
+    }
+
+    // Actions stop where the checksums start
+    let actions = &patch[..patch.len() - 12];
+    let mut position = 4;
+    let source_size = decode(actions, &mut position)?;
+    let target_size = decode(actions, &mut position)?;
+    let metadata_size = decode(actions, &mut position)?;
+    if source_size != rom.len() {
+        return Err(String::from("BPS patch is for a different ROM"));
+    }
+    if target_size > MAX_TARGET_SIZE {
+        return Err(format!("BPS patch makes a ROM of {} bytes", target_size));
+    }
+    position = position
+        .checked_add(metadata_size)
+        .ok_or("BPS patch metadata is too long")?;
+
+    let mut output = Vec::with_capacity(target_size);
+    let mut source_offset = 0isize;
+    let mut target_offset = 0isize;
+    let corrupt = || String::from("BPS patch reads outside the ROM");
+    while position < actions.len() {
+        let action = decode(actions, &mut position)?;
+        let len = (action >> 2) + 1;
+        // Every action adds len bytes, which mustn't go past the target
+        let start = output.len();
+        let end = start
+            .checked_add(len)
+            .filter(|end| *end <= target_size)
+            .ok_or("BPS patch writes past the end of the ROM")?;
+        match action & 3 {
+            0 => {
+                let bytes = rom.get(start..end).ok_or_else(corrupt)?;
+                output.extend_from_slice(bytes);
+            }
+            1 => {
+                let bytes = actions.get(position..position + len).ok_or_else(corrupt)?;
+                output.extend_from_slice(bytes);
+                position += len;
+            }
+            command => {
+                let data = decode(actions, &mut position)?;
+                let delta = match data & 1 {
+                    1 => -((data >> 1) as isize),
+                    _ => (data >> 1) as isize,
+                };
+                let offset = match command {
+                    2 => &mut source_offset,
+                    _ => &mut target_offset,
+                };
+                *offset = offset.checked_add(delta).ok_or_else(corrupt)?;
+                for _ in 0..len {
+                    let index = usize::try_from(*offset).map_err(|_| corrupt())?;
+                    let byte = match command {
+                        2 => rom.get(index),
+                        _ => output.get(index),
+                    };
+                    let byte = *byte.ok_or_else(corrupt)?;
+                    output.push(byte);
+                    *offset += 1;
+                }
+            }
+        }
+    }
+    if output.len() != target_size || crc32fast::hash(&output) != target_crc {
+        return Err(String::from("BPS patch produced the wrong output"));
+    }
+    Ok(output)
+}
+
+// Variable length numbers, 7 bits per byte with the top bit ending the number
+fn decode(bytes: &[u8], position: &mut usize) -> Result<usize, String> {
+    let too_big = || String::from("BPS patch has a number too big to use");
+    let mut value = 0usize;
+    let mut shift = 1usize;
+    loop {
+        let byte = match bytes.get(*position) {
+            Some(byte) => *byte as usize,
+            None => return Err(String::from("BPS patch ends in the middle of a number")),
+        };
+        *position += 1;
+        value = (byte & 0x7F)
+            .checked_mul(shift)
+            .and_then(|digit| value.checked_add(digit))
+            .ok_or_else(too_big)?;
+        if byte & 0x80 != 0 {
+            return Ok(value);
+        }
+        shift = shift.checked_mul(0x80).ok_or_else(too_big)?;
+        value = value.checked_add(shift).ok_or_else(too_big)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ips(records: &[u8]) -> Vec<u8> {
+        [b"PATCH", records, b"EOF"].concat()
+    }
+
+    // The inverse of decode
+    fn encode(mut value: usize, bytes: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte | 0x80);
+                return;
+            }
+            bytes.push(byte);
+            value -= 1;
+        }
+    }
+
+    // A BPS patch from source to a target of target_size bytes with the
+    // given checksum, through actions
+    fn bps(source: &[u8], target_size: usize, target_crc: u32, actions: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        for size in [source.len(), target_size, 0] {
+            encode(size, &mut patch);
+        }
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32fast::hash(source).to_le_bytes());
+        patch.extend_from_slice(&target_crc.to_le_bytes());
+        let patch_crc = crc32fast::hash(&patch);
+        patch.extend_from_slice(&patch_crc.to_le_bytes());
+        patch
+    }
+
+    const SOURCE: [u8; 4] = [1, 2, 3, 4];
+    const TARGET: [u8; 6] = [1, 2, 9, 9, 3, 4];
+    // Read 2 from the source, 9 9 from the patch, then copy 2 from source + 2
+    const ACTIONS: [u8; 6] = [0x84, 0x85, 9, 9, 0x86, 0x84];
+
+    #[test]
+    fn ips_records_and_runs() {
+        let patch = ips(&[0, 0, 1, 0, 2, 0xAA, 0xBB, 0, 0, 6, 0, 0, 0, 2, 7]);
+        assert_eq!(
+            apply(&[0; 4], &patch),
+            Ok(vec![0, 0xAA, 0xBB, 0, 0, 0, 7, 7])
+        );
+    }
+
+    #[test]
+    fn ips_truncates_after_eof() {
+        let patch = [ips(&[0, 0, 0, 0, 1, 0xAA]), vec![0, 0, 2]].concat();
+        assert_eq!(apply(&[0; 4], &patch), Ok(vec![0xAA, 0]));
+    }
+
+    #[test]
+    fn ips_cut_short() {
+        let patch = b"PATCH\x00\x00\x01\x00\x04\xAA";
+        assert_eq!(
+            apply(&[0; 4], patch),
+            Err(String::from("IPS patch ends in the middle of a record"))
+        );
+    }
+
+    #[test]
+    fn ips_past_the_limit() {
+        let patch = ips(&[0xFF, 0xFF, 0x00, 0, 0, 0xFF, 0xFF, 0]);
+        assert_eq!(
+            apply(&[0; 4], &patch),
+            Err(String::from("IPS patch writes past 0x10000"))
+        );
+    }
+
+    #[test]
+    fn bps_round_trip() {
+        let patch = bps(&SOURCE, TARGET.len(), crc32fast::hash(&TARGET), &ACTIONS);
+        assert_eq!(apply(&SOURCE, &patch), Ok(TARGET.to_vec()));
+    }
+
+    #[test]
+    fn bps_checksums() {
+        let mut patch = bps(&SOURCE, TARGET.len(), crc32fast::hash(&TARGET), &ACTIONS);
+        assert_eq!(
+            apply(&[1, 2, 3, 5], &patch),
+            Err(String::from("BPS patch is for a different ROM"))
+        );
+        let wrong = bps(&SOURCE, TARGET.len(), 0, &ACTIONS);
+        assert_eq!(
+            apply(&SOURCE, &wrong),
+            Err(String::from("BPS patch produced the wrong output"))
+        );
+        patch[8] ^= 1;
+        assert_eq!(
+            apply(&SOURCE, &patch),
+            Err(String::from("BPS patch is corrupt"))
+        );
+    }
+
+    #[test]
+    fn bps_reads_outside_the_rom() {
+        // Read 6 from a 4 byte source
+        let past_the_end = bps(&SOURCE, 6, 0, &[0x94]);
+        // Copy from one before the start of the source
+        let before_the_start = bps(&SOURCE, 1, 0, &[0x82, 0x83]);
+        for patch in [past_the_end, before_the_start] {
+            assert_eq!(
+                apply(&SOURCE, &patch),
+                Err(String::from("BPS patch reads outside the ROM"))
+            );
+        }
+    }
+
+    #[test]
+    fn bps_too_big() {
+        let patch = bps(&SOURCE, MAX_TARGET_SIZE + 1, 0, &[]);
+        assert_eq!(
+            apply(&SOURCE, &patch),
+            Err(String::from("BPS patch makes a ROM of 65537 bytes"))
+        );
+    }
+}
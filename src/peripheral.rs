@@ -0,0 +1,193 @@
+use crate::chip8::{Chip8, MEMORY_SIZE};
+use crate::disasm::{class_index, opcode_class};
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/*    Peripherals
+ * Devices an embedder plugs into the machine, layered over the core so a
+ * machine with none attached runs exactly as before:
+ *   opcodes   Any the core doesn't run itself, e.g. 5XY1 or FX99, are offered
+ *             to each device in turn. One that takes it runs it with the PC
+ *             already past it. 0NNN goes to devices before Chip8::on_sys.
+ *   memory    A device can map a range of memory. FX65 and DXYN read it
+ *             from the device, FX33 and FX55 write through to it. Everything
+ *             else sees the bytes as last read or written.
+ *
+ *   struct Rumble;
+ *   impl Peripheral for Rumble {
+ *       fn name(&self) -> &str { "rumble" }
+ *       fn handles(&self, opcode: u16) -> bool { opcode & 0xF0FF == 0xF0F0 }
+ *       fn execute(&mut self, cpu: &mut Chip8, opcode: u16) { ... }
+ *   }
+ *   cpu.attach(Rumble);
+ *
+ * A machine's clones share its devices, as they're hardware outside it.
+ */
+pub trait Peripheral: Send {
+    fn name(&self) -> &str;
+
+    // Whether it runs an opcode the core doesn't
+    fn handles(&self, _opcode: u16) -> bool {
+        false
+    }
+
+    fn execute(&mut self, _cpu: &mut Chip8, _opcode: u16) {}
+
+    // The addresses it maps, if any
+    fn region(&self) -> Option<Range<usize>> {
+        None
+    }
+
+    fn read(&mut self, _addr: usize) -> u8 {
+        0
+    }
+
+    fn write(&mut self, _addr: usize, _value: u8) {}
+
+    // Before an exit that skips dropping the machine
+    fn flush(&mut self) {}
+}
+
+type Device = Arc<Mutex<dyn Peripheral>>;
+
+#[derive(Clone, Default)]
+pub struct Peripherals(Vec<Device>);
+
+impl fmt::Debug for Peripherals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<String> = self
+            .0
+            .iter()
+            .map(|device| lock(device).name().to_string())
+            .collect();
+        write!(f, "Peripherals({})", names.join(", "))
+    }
+}
+
+impl Peripherals {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // Whether a device will run the opcode instead of the core
+    pub fn handles(&self, opcode: u16) -> bool {
+        self.device_for(opcode).is_some()
+    }
+
+    pub fn flush(&self) {
+        for device in &self.0 {
+            lock(device).flush();
+        }
+    }
+
+    fn device_for(&self, opcode: u16) -> Option<Device> {
+        if !matches!(opcode_class(opcode), "unknown" | "0NNN") {
+            return None;
+        }
+        self.0
+            .iter()
+            .find(|device| lock(device).handles(opcode))
+            .cloned()
+    }
+}
+
+impl Chip8 {
+    pub fn attach(&mut self, device: impl Peripheral + 'static) {
+        self.peripherals.0.push(Arc::new(Mutex::new(device)));
+    }
+
+    // tick with the devices attached, see above
+    pub(crate) fn exec_with_peripherals(&mut self) {
+        if self.pc + 1 >= MEMORY_SIZE {
+            return self.exec_opcode();
+        }
+        let opcode = self.opcode_at(self.pc);
+        if let Some(device) = self.peripherals.device_for(opcode) {
+            self.class_counts[class_index(opcode)] += 1;
+            self.pc += 2;
+            lock(&device).execute(self, opcode);
+            return;
+        }
+        let (reads, writes) = accesses(self, opcode);
+        let devices = self.peripherals.0.clone();
+        for device in &devices {
+            let mut device = lock(device);
+            for addr in overlap(device.region(), &reads) {
+                self.memory[addr] = device.read(addr);
+            }
+        }
+        self.exec_opcode();
+        for device in &devices {
+            let mut device = lock(device);
+            for addr in overlap(device.region(), &writes) {
+                device.write(addr, self.memory[addr]);
+            }
+        }
+    }
+}
+
+// Memory the instruction will read and write, from I as it is before
+fn accesses(cpu: &Chip8, opcode: u16) -> (Range<usize>, Range<usize>) {
+    let i = cpu.address_reg as usize;
+    let x = (opcode as usize & 0x0F00) >> 8;
+    match opcode_class(opcode) {
+        "FX65" => (i..i + x + 1, 0..0),
+        "DXYN" => (i..i + (opcode as usize & 0x000F), 0..0),
+        "FX33" => (0..0, i..i + 3),
+        "FX55" => (0..0, i..i + x + 1),
+        _ => (0..0, 0..0),
+    }
+}
+
+fn overlap(region: Option<Range<usize>>, access: &Range<usize>) -> Range<usize> {
+    match region {
+        Some(region) => region.start.max(access.start)..region.end.min(access.end).min(MEMORY_SIZE),
+        None => 0..0,
+    }
+}
+
+// A device that panicked mid-call is still usable
+fn lock(device: &Device) -> MutexGuard<'_, dyn Peripheral + 'static> {
+    device.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/*    Clock
+ * A real time clock mapped at a base address, as an example device:
+ *   base      hours, UTC
+ *   base + 1  minutes
+ *   base + 2  seconds
+ * so i := base load v2 reads the time into v0 to v2.
+ */
+pub struct Clock {
+    base: usize,
+}
+
+impl Clock {
+    pub fn new(base: usize) -> Clock {
+        Clock { base }
+    }
+}
+
+impl Peripheral for Clock {
+    fn name(&self) -> &str {
+        "clock"
+    }
+
+    fn region(&self) -> Option<Range<usize>> {
+        Some(self.base..self.base + 3)
+    }
+
+    fn read(&mut self, addr: usize) -> u8 {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let value = match addr - self.base {
+            0 => seconds / 3600 % 24,
+            1 => seconds / 60 % 60,
+            _ => seconds % 60,
+        };
+        value as u8
+    }
+}
@@ -0,0 +1,97 @@
+use crate::chip8::Chip8;
+use crate::state;
+use std::path::Path;
+
+/*    Playlist
+ * ROMs played one at a time in the same window, for demo nights:
+ *   chip8 run --playlist brix.ch8 pong.ch8 games.m3u
+ * An .m3u file lists ROMs or links a line each, files relative to it,
+ * skipping blank lines and # comments. Ctrl+PageDown and Ctrl+PageUp move to
+ * the next and previous ROM, wrapping around. The machine being left is
+ * saved, and picks up where it was when its ROM comes round again, for as
+ * long as the emulator runs.
+ */
+#[derive(Debug)]
+pub struct Playlist {
+    roms: Vec<String>,
+    // Save states, for ROMs that have been left
+    states: Vec<Option<Vec<u8>>>,
+    current: usize,
+    // Another ROM was loaded over the current one, so there's nothing to save
+    away: bool,
+}
+
+impl Playlist {
+    // The ROMs given, with any .m3u files among them expanded
+    pub fn open(rom_paths: &[&str]) -> Result<Playlist, String> {
+        let mut roms = Vec::new();
+        for rom_path in rom_paths {
+            let path = Path::new(rom_path);
+            match path.extension().is_some_and(|ext| ext == "m3u") {
+                true => roms.extend(read_m3u(path)?),
+                false => roms.push(rom_path.to_string()),
+            }
+        }
+        if roms.is_empty() {
+            return Err(String::from("The playlist has no ROMs in it"));
+        }
+        Ok(Playlist {
+            states: vec![None; roms.len()],
+            roms,
+            current: 0,
+            away: false,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    pub fn rom(&self, index: usize) -> &str {
+        &self.roms[index]
+    }
+
+    pub fn current(&self) -> &str {
+        self.rom(self.current)
+    }
+
+    // The ROM step places on from the current one, wrapping around
+    pub fn along(&self, step: isize) -> usize {
+        (self.current as isize + step).rem_euclid(self.roms.len() as isize) as usize
+    }
+
+    // Save the machine playing now and make index current, returning the
+    // state its ROM was left in, if it's been played before
+    pub fn switch(&mut self, index: usize, leaving: &Chip8) -> Option<&[u8]> {
+        if !self.away {
+            self.states[self.current] = Some(state::save(leaving));
+        }
+        self.current = index;
+        self.away = false;
+        self.states[index].as_deref()
+    }
+
+    // A ROM from outside the playlist replaced the current one
+    pub fn leave(&mut self) {
+        self.away = true;
+    }
+}
+
+fn read_m3u(path: &Path) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match line.contains("://") {
+            true => line.to_string(),
+            false => dir.join(line).to_string_lossy().into_owned(),
+        })
+        .collect())
+}
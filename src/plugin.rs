@@ -0,0 +1,189 @@
+use crate::chip8::Chip8;
+use libloading::Library;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+pub const PLUGIN_VERSION: u32 = 1;
+pub const OVERLAY_SIZE: usize = 64 * 32 * 4;
+// 60 Hz, matching the timers
+const FRAME: Duration = Duration::from_micros(16_667);
+
+/*    Plugin ABI, version 1
+ * A plugin is a shared library exporting C functions, all optional but the
+ * first:
+ *   uint32_t chip8_plugin_version(void)             Must return PLUGIN_VERSION
+ *   int32_t  chip8_plugin_frame(const Frame *frame, uint8_t *overlay)
+ *                                                   60 times a second, returns
+ *                                                   a key to hold or -1
+ *   void     chip8_plugin_sound(bool on)            When the beep starts or stops
+ *   void     chip8_plugin_key(int32_t key)          When the held key changes,
+ *                                                   -1 on release
+ *   void     chip8_plugin_unload(void)              Before the library closes
+ *
+ * overlay is 64x32 RGBA, cleared before every frame and drawn over the
+ * display, where any alpha covers the game's pixel. Plugins are called in
+ * load order, so later ones draw on top and their held key wins.
+ *
+ * e.g. in Rust, a cdylib showing which key is held:
+ *   #[no_mangle]
+ *   pub extern "C" fn chip8_plugin_version() -> u32 { 1 }
+ *   #[no_mangle]
+ *   pub unsafe extern "C" fn chip8_plugin_frame(frame: &Frame, overlay: *mut u8) -> i32 {
+ *       overlay.add(frame.key as usize * 4).write_bytes(0xFF, 4);
+ *       -1
+ *   }
+ */
+#[repr(C)]
+pub struct Frame {
+    // 64x32, one byte per pixel, 1 for lit
+    pub pixels: *const u8,
+    // Frames since the plugin loaded
+    pub frame: u64,
+    pub pc: u16,
+    pub key: u8,
+    pub sound: bool,
+}
+
+type VersionFn = unsafe extern "C" fn() -> u32;
+type FrameFn = unsafe extern "C" fn(*const Frame, *mut u8) -> i32;
+type SoundFn = unsafe extern "C" fn(bool);
+type KeyFn = unsafe extern "C" fn(i32);
+type UnloadFn = unsafe extern "C" fn();
+
+struct Plugin {
+    frame: Option<FrameFn>,
+    sound: Option<SoundFn>,
+    key: Option<KeyFn>,
+    unload: Option<UnloadFn>,
+    // Keeps the functions above loaded, so it's dropped last
+    _library: Library,
+}
+
+impl Plugin {
+    fn load(path: &Path) -> Result<Plugin, String> {
+        let error = |e: libloading::Error| format!("{}: {}", path.display(), e);
+        // Running the library's initialisers is the point of loading a plugin,
+        // and dlopen's errors already name the file
+        let library = unsafe { Library::new(path) }.map_err(|e| e.to_string())?;
+        let version =
+            unsafe { library.get::<VersionFn>(b"chip8_plugin_version") }.map_err(error)?;
+        let version = unsafe { version() };
+        if version != PLUGIN_VERSION {
+            return Err(format!(
+                "{} is for plugin version {}, this is version {}",
+                path.display(),
+                version,
+                PLUGIN_VERSION
+            ));
+        }
+        // Copied out of their Symbols, the library outlives them in the Plugin
+        let frame = unsafe { library.get::<FrameFn>(b"chip8_plugin_frame") }.map(|f| *f);
+        let sound = unsafe { library.get::<SoundFn>(b"chip8_plugin_sound") }.map(|f| *f);
+        let key = unsafe { library.get::<KeyFn>(b"chip8_plugin_key") }.map(|f| *f);
+        let unload = unsafe { library.get::<UnloadFn>(b"chip8_plugin_unload") }.map(|f| *f);
+        Ok(Plugin {
+            frame: frame.ok(),
+            sound: sound.ok(),
+            key: key.ok(),
+            unload: unload.ok(),
+            _library: library,
+        })
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        if let Some(unload) = self.unload {
+            unsafe { unload() }
+        }
+    }
+}
+
+// Native plugins loaded with --plugin, fed events from the event loop
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+    overlay: Vec<u8>,
+    frame: u64,
+    frame_start: Instant,
+    sound: bool,
+    key: u8,
+    // Key the plugins asked for at the last frame
+    held: Option<u8>,
+}
+
+impl Plugins {
+    pub fn load(paths: &[&str]) -> Result<Plugins, String> {
+        let plugins = paths
+            .iter()
+            .map(|path| Plugin::load(Path::new(path)))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Plugins {
+            plugins,
+            overlay: vec![0; OVERLAY_SIZE],
+            frame: 0,
+            frame_start: Instant::now(),
+            sound: false,
+            key: 0,
+            held: None,
+        })
+    }
+
+    // Send whatever has happened since the last call, before the next
+    // instruction
+    pub fn update(&mut self, cpu: &mut Chip8) {
+        let sound = cpu.sound_timer > 0;
+        if sound != self.sound {
+            self.sound = sound;
+            for plugin in &self.plugins {
+                if let Some(on_sound) = plugin.sound {
+                    unsafe { on_sound(sound) }
+                }
+            }
+        }
+        // Keys the plugins hold aren't reported back to them
+        if let Some(key) = self.held {
+            cpu.input = key;
+        } else if cpu.input != self.key {
+            self.key = cpu.input;
+            // 0 is also "no key" to the core
+            let key = match cpu.input {
+                0 => -1,
+                key => key as i32,
+            };
+            for plugin in &self.plugins {
+                if let Some(on_key) = plugin.key {
+                    unsafe { on_key(key) }
+                }
+            }
+        }
+        if self.frame_start.elapsed() >= FRAME {
+            self.frame_start = Instant::now();
+            self.frame(cpu);
+        }
+    }
+
+    fn frame(&mut self, cpu: &mut Chip8) {
+        let frame = Frame {
+            pixels: cpu.video_buffer.as_ptr(),
+            frame: self.frame,
+            pc: cpu.pc as u16,
+            key: cpu.input,
+            sound: self.sound,
+        };
+        self.frame += 1;
+        self.overlay.fill(0);
+        self.held = None;
+        for plugin in &self.plugins {
+            if let Some(on_frame) = plugin.frame {
+                let key = unsafe { on_frame(&frame, self.overlay.as_mut_ptr()) };
+                if (0..=0xF).contains(&key) {
+                    self.held = Some(key as u8);
+                }
+            }
+        }
+        if cpu.overlay != self.overlay {
+            cpu.overlay.clone_from(&self.overlay);
+            cpu.redraw = true;
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use crate::chip8::Chip8;
+use crate::disasm::{disassemble, opcode_class};
+use std::collections::BTreeMap;
+
+// Number of addresses listed in the hotspot report
+const HOTSPOTS: usize = 10;
+
+// Counts executed instructions per address and per opcode class
+pub struct Profiler {
+    counts: Vec<u64>,
+    classes: BTreeMap<&'static str, u64>,
+    total: u64,
+}
+
+impl Profiler {
+    pub fn new(memory_size: usize) -> Profiler {
+        Profiler {
+            counts: vec![0; memory_size],
+            classes: BTreeMap::new(),
+            total: 0,
+        }
+    }
+
+    pub fn record(&mut self, addr: usize, opcode: u16) {
+        if let Some(count) = self.counts.get_mut(addr) {
+            *count += 1;
+        }
+        *self.classes.entry(opcode_class(opcode)).or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.iter_mut().for_each(|count| *count = 0);
+        self.classes.clear();
+        self.total = 0;
+    }
+
+    // Busiest addresses followed by the split across opcode classes
+    pub fn report(&self, cpu: &Chip8) -> String {
+        if self.total == 0 {
+            return String::from("No instructions profiled (profile on to start)");
+        }
+        let mut lines = vec![format!("{} instructions profiled", self.total)];
+
+        let mut hotspots: Vec<(usize, u64)> = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(addr, count)| (addr, *count))
+            .collect();
+        hotspots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        lines.push(String::from("Hotspots:"));
+        for (addr, count) in hotspots.iter().take(HOTSPOTS) {
+            let opcode = cpu.opcode_at(*addr);
+            lines.push(format!(
+                "  {:#05x}: {:>10} {:>5.1}%  {}",
+                addr,
+                count,
+                self.percent(*count),
+                disassemble(opcode)
+            ));
+        }
+
+        let mut classes: Vec<(&&str, &u64)> = self.classes.iter().collect();
+        classes.sort_by(|a, b| b.1.cmp(a.1));
+        lines.push(String::from("Opcode classes:"));
+        for (class, count) in classes {
+            lines.push(format!(
+                "  {:<8} {:>10} {:>5.1}%",
+                class,
+                count,
+                self.percent(*count)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    // Every executed instruction in address order, coloured from blue (cold)
+    // to red (hottest)
+    pub fn heatmap(&self, cpu: &Chip8) -> String {
+        let hottest = self.counts.iter().max().copied().unwrap_or(0);
+        if hottest == 0 {
+            return String::from("No instructions profiled (profile on to start)");
+        }
+        let mut lines = Vec::new();
+        let mut last_addr = None;
+        for (addr, count) in self.counts.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            // Mark gaps between executed regions
+            if let Some(last) = last_addr {
+                if addr > last + 2 {
+                    lines.push(String::from("  ..."));
+                }
+            }
+            last_addr = Some(addr);
+            let color = match count * 4 / (hottest + 1) {
+                0 => "\x1b[34m",
+                1 => "\x1b[32m",
+                2 => "\x1b[33m",
+                _ => "\x1b[31m",
+            };
+            let opcode = cpu.opcode_at(addr);
+            lines.push(format!(
+                "{}{:#05x}: {:04x}  {:<18} {:>10} {:>5.1}%\x1b[0m",
+                color,
+                addr,
+                opcode,
+                disassemble(opcode),
+                count,
+                self.percent(*count)
+            ));
+        }
+        lines.join("\n")
+    }
+
+    fn percent(&self, count: u64) -> f64 {
+        count as f64 * 100.0 / self.total as f64
+    }
+}
@@ -0,0 +1,69 @@
+use crate::environment::{self, Score};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+// maturin build --release --features python, then in Python:
+//   import rust_chip8
+//   env = rust_chip8.Environment(open("brix.ch8", "rb").read(), seed=1)
+//   env.set_score(0x3F0, 1)
+//   screen, reward, done = env.step(4)
+#[pyclass(unsendable)]
+struct Environment(environment::Environment);
+
+#[pymethods]
+impl Environment {
+    #[new]
+    #[pyo3(signature = (rom, seed=0, frame_skip=1, max_frames=None))]
+    fn new(rom: Vec<u8>, seed: u64, frame_skip: u64, max_frames: Option<u64>) -> PyResult<Self> {
+        let mut inner = environment::Environment::new(&rom, seed).map_err(PyValueError::new_err)?;
+        inner.frame_skip = frame_skip;
+        inner.max_frames = max_frames;
+        Ok(Environment(inner))
+    }
+
+    // The screen as 2048 bytes, row by row
+    #[pyo3(signature = (seed=None))]
+    fn reset(&mut self, seed: Option<u64>) -> Vec<u8> {
+        if let Some(seed) = seed {
+            self.0.seed(seed);
+        }
+        self.0.reset().to_vec()
+    }
+
+    #[pyo3(signature = (key=None))]
+    fn step(&mut self, key: Option<u8>) -> PyResult<(Vec<u8>, i64, bool)> {
+        if key.is_some_and(|key| key > 0xF) {
+            return Err(PyValueError::new_err("key must be 0-15 or None"));
+        }
+        let step = self.0.step(key);
+        Ok((step.screen.to_vec(), step.reward, step.done))
+    }
+
+    fn set_score(&mut self, addr: usize, length: usize) {
+        self.0.set_score(Some(Score { addr, length }));
+    }
+
+    fn set_done_when(&mut self, addr: usize, value: u8) {
+        self.0.done_when = Some((addr, value));
+    }
+
+    fn peek(&self, addr: usize) -> Option<u8> {
+        self.0.cpu().memory.get(addr).copied()
+    }
+
+    #[getter]
+    fn frame(&self) -> u64 {
+        self.0.frame()
+    }
+
+    // Why the episode ended early, None unless it did
+    #[getter]
+    fn fault(&self) -> Option<String> {
+        self.0.fault().map(str::to_string)
+    }
+}
+
+#[pymodule]
+fn rust_chip8(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Environment>()
+}
@@ -0,0 +1,259 @@
+use crate::chip8::Quirks;
+use crate::osd;
+use crate::variant::Variant;
+
+// The top row shows the search
+const LIST_ROWS: usize = osd::ROWS - 1;
+// Short names to keep the table to a line an entry
+const CHIP8: Variant = Variant::Chip8;
+const SCHIP: Variant = Variant::SChip;
+const XO_CHIP: Variant = Variant::XoChip;
+
+// One instruction, as the quick reference lists it
+pub struct Entry {
+    pub pattern: &'static str,
+    pub variant: Variant,
+    pub mnemonic: &'static str,
+    pub summary: &'static str,
+}
+
+const fn entry(
+    pattern: &'static str,
+    variant: Variant,
+    mnemonic: &'static str,
+    summary: &'static str,
+) -> Entry {
+    Entry {
+        pattern,
+        variant,
+        mnemonic,
+        summary,
+    }
+}
+
+/*    Opcode Reference
+ * Every instruction of CHIP-8 and the SCHIP and XO-CHIP extensions, with
+ * Cowgod's mnemonics. This core only runs CHIP-8 and the link cable's FX70
+ * and FX71, the rest are listed so ROMs for the others make sense.
+ */
+pub const ENTRIES: &[Entry] = &[
+    entry("00E0", CHIP8, "CLS", "Clear the display"),
+    entry("00EE", CHIP8, "RET", "Return from a subroutine"),
+    entry("0NNN", CHIP8, "SYS NNN", "Machine code at NNN, VIP only"),
+    entry("1NNN", CHIP8, "JP NNN", "Jump to NNN"),
+    entry("2NNN", CHIP8, "CALL NNN", "Call the subroutine at NNN"),
+    entry("3XNN", CHIP8, "SE VX, NN", "Skip if VX == NN"),
+    entry("4XNN", CHIP8, "SNE VX, NN", "Skip if VX != NN"),
+    entry("5XY0", CHIP8, "SE VX, VY", "Skip if VX == VY"),
+    entry("6XNN", CHIP8, "LD VX, NN", "VX = NN"),
+    entry("7XNN", CHIP8, "ADD VX, NN", "VX += NN, no carry"),
+    entry("8XY0", CHIP8, "LD VX, VY", "VX = VY"),
+    entry("8XY1", CHIP8, "OR VX, VY", "VX = VX OR VY"),
+    entry("8XY2", CHIP8, "AND VX, VY", "VX = VX AND VY"),
+    entry("8XY3", CHIP8, "XOR VX, VY", "VX = VX XOR VY"),
+    entry("8XY4", CHIP8, "ADD VX, VY", "VX += VY, VF = carry"),
+    entry("8XY5", CHIP8, "SUB VX, VY", "VX -= VY, VF = no borrow"),
+    entry("8XY6", CHIP8, "SHR VX, VY", "Shift right, VF = bit out"),
+    entry("8XY7", CHIP8, "SUBN VX, VY", "VX = VY - VX, VF = no borrow"),
+    entry("8XYE", CHIP8, "SHL VX, VY", "Shift left, VF = bit out"),
+    entry("9XY0", CHIP8, "SNE VX, VY", "Skip if VX != VY"),
+    entry("ANNN", CHIP8, "LD I, NNN", "I = NNN"),
+    entry("BNNN", CHIP8, "JP V0, NNN", "Jump to NNN plus a register"),
+    entry("CXNN", CHIP8, "RND VX, NN", "VX = random AND NN"),
+    entry(
+        "DXYN",
+        CHIP8,
+        "DRW VX, VY, N",
+        "Draw N rows from I at VX, VY, VF = collision",
+    ),
+    entry("EX9E", CHIP8, "SKP VX", "Skip if key VX is held"),
+    entry("EXA1", CHIP8, "SKNP VX", "Skip if key VX isn't held"),
+    entry("FX07", CHIP8, "LD VX, DT", "VX = delay timer"),
+    entry("FX0A", CHIP8, "LD VX, K", "Wait for a key, VX = key"),
+    entry("FX15", CHIP8, "LD DT, VX", "Delay timer = VX"),
+    entry(
+        "FX18",
+        CHIP8,
+        "LD ST, VX",
+        "Sound timer = VX, beeps until 0",
+    ),
+    entry("FX1E", CHIP8, "ADD I, VX", "I += VX"),
+    entry("FX29", CHIP8, "LD F, VX", "I = font sprite for digit VX"),
+    entry("FX33", CHIP8, "LD B, VX", "VX in decimal at I, I+1, I+2"),
+    entry("FX55", CHIP8, "LD [I], VX", "Store V0 to VX at I"),
+    entry("FX65", CHIP8, "LD VX, [I]", "Load V0 to VX from I"),
+    entry("FX70", CHIP8, "SEND VX", "Send VX down the link cable"),
+    entry(
+        "FX71",
+        CHIP8,
+        "RECV VX",
+        "VX = link cable byte, VF = got one",
+    ),
+    entry("00CN", SCHIP, "SCD N", "Scroll down N rows"),
+    entry("00FB", SCHIP, "SCR", "Scroll right 4 pixels"),
+    entry("00FC", SCHIP, "SCL", "Scroll left 4 pixels"),
+    entry("00FD", SCHIP, "EXIT", "Exit the interpreter"),
+    entry("00FE", SCHIP, "LOW", "64x32 display"),
+    entry("00FF", SCHIP, "HIGH", "128x64 display"),
+    entry("DXY0", SCHIP, "DRW VX, VY, 0", "Draw a 16x16 sprite from I"),
+    entry(
+        "FX30",
+        SCHIP,
+        "LD HF, VX",
+        "I = big font sprite for digit VX",
+    ),
+    entry("FX75", SCHIP, "LD R, VX", "Save V0 to VX to the flags"),
+    entry("FX85", SCHIP, "LD VX, R", "Load V0 to VX from the flags"),
+    entry("00DN", XO_CHIP, "SCU N", "Scroll up N rows"),
+    entry("5XY2", XO_CHIP, "SAVE VX, VY", "Store VX to VY at I"),
+    entry("5XY3", XO_CHIP, "LOAD VX, VY", "Load VX to VY from I"),
+    entry(
+        "F000",
+        XO_CHIP,
+        "LD I, NNNN",
+        "I = the 16-bit word after this",
+    ),
+    entry("FN01", XO_CHIP, "PLANE N", "Draw on the bit planes in N"),
+    entry("F002", XO_CHIP, "AUDIO", "16-byte audio pattern from I"),
+    entry("FX3A", XO_CHIP, "PITCH VX", "Audio pitch = VX"),
+];
+
+impl Entry {
+    // How the machine's quirks make it behave, for the instructions they
+    // change
+    pub fn quirk(&self, quirks: &Quirks) -> Option<&'static str> {
+        let note = match self.pattern {
+            "8XY1" | "8XY2" | "8XY3" => match quirks.vf_reset {
+                true => "Here VF is cleared after (vf-reset)",
+                false => "Here VF is left alone",
+            },
+            "8XY6" | "8XYE" => match quirks.shift_vy {
+                true => "Here VY is shifted into VX (shift)",
+                false => "Here VX is shifted in place",
+            },
+            "FX55" | "FX65" => match quirks.increment_i {
+                true => "Here I ends past the last (memory)",
+                false => "Here I is left alone",
+            },
+            "BNNN" => match quirks.jump_vx {
+                true => "Here it's XNN + VX (jump)",
+                false => "Here it's NNN + V0",
+            },
+            "DXYN" => match quirks.clip {
+                true => "Here sprites clip at the edges (clip)",
+                false => "Here sprites wrap at the edges",
+            },
+            // Not a quirk, nothing held reads as key 0 on this core
+            "FX0A" => "Here key 0 doesn't end the wait",
+            _ => return None,
+        };
+        Some(note)
+    }
+
+    // One line for the console
+    pub fn describe(&self, quirks: &Quirks) -> String {
+        let mut line = format!(
+            "{}  {:<8} {:<14} {}",
+            self.pattern,
+            self.variant.to_string(),
+            self.mnemonic,
+            self.summary
+        );
+        if let Some(quirk) = self.quirk(quirks) {
+            line.push_str(&format!(". {}", quirk));
+        }
+        line
+    }
+
+    fn matches(&self, query: &str) -> bool {
+        let query = query.to_ascii_uppercase();
+        [
+            self.pattern.to_string(),
+            self.variant.to_string(),
+            self.mnemonic.to_string(),
+            self.summary.to_string(),
+        ]
+        .iter()
+        .any(|text| text.to_ascii_uppercase().contains(&query))
+    }
+}
+
+// Entries mentioning query anywhere, all of them for an empty one
+pub fn search(query: &str) -> Vec<&'static Entry> {
+    ENTRIES
+        .iter()
+        .filter(|entry| entry.matches(query))
+        .collect()
+}
+
+/*    Reference Overlay
+ * ? SHR              The search so far, typed with letters and digits
+ * 8XY6 SHR VX, VY:   Pattern, mnemonic, summary and the machine's quirk,
+ * SHIFT RIGHT, ...   wrapped at 16
+ *
+ * Drawn over the dimmed display like the log console.
+ */
+#[derive(Debug, Default)]
+pub struct RefView {
+    pub open: bool,
+    query: String,
+    // Rows down from the first match
+    scroll: usize,
+}
+
+impl RefView {
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.query.clear();
+        self.scroll = 0;
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        self.query.push(c);
+        self.scroll = 0;
+    }
+
+    pub fn backspace(&mut self) {
+        self.query.pop();
+        self.scroll = 0;
+    }
+
+    // Positive rows go further down the list
+    pub fn scroll(&mut self, rows: isize, quirks: &Quirks) {
+        let most = self.rows(quirks).len().saturating_sub(LIST_ROWS);
+        self.scroll = self.scroll.saturating_add_signed(rows).min(most);
+    }
+
+    // Paint over a 64 pixel wide RGBA frame
+    pub fn draw(&self, frame: &mut [u8], quirks: &Quirks, colors: ([u8; 4], [u8; 4])) {
+        osd::dim(frame);
+        let mut lines = vec![format!("? {}", self.query)];
+        lines.extend(
+            self.rows(quirks)
+                .into_iter()
+                .skip(self.scroll)
+                .take(LIST_ROWS),
+        );
+        osd::draw_lines(frame, &lines, colors);
+    }
+
+    fn rows(&self, quirks: &Quirks) -> Vec<String> {
+        let matches = search(&self.query);
+        if matches.is_empty() {
+            return vec![String::from("No match")];
+        }
+        matches
+            .iter()
+            .flat_map(|entry| {
+                let mut text = format!("{} {}: {}", entry.pattern, entry.mnemonic, entry.summary);
+                if let Some(quirk) = entry.quirk(quirks) {
+                    text.push_str(&format!(". {}", quirk));
+                }
+                if entry.variant != Variant::Chip8 {
+                    text.push_str(&format!(". {} only", entry.variant));
+                }
+                osd::wrap(&text)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,50 @@
+use crate::chip8::Chip8;
+use std::collections::VecDeque;
+
+// Ring buffer of machine snapshots, newest at the back
+pub struct Rewind {
+    history: VecDeque<Chip8>,
+    capacity: usize,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Rewind {
+        Rewind {
+            history: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    // Save the machine state, dropping the oldest snapshot once full
+    pub fn record(&mut self, cpu: &Chip8) {
+        if self.history.len() == self.capacity {
+            self.history.pop_front();
+        }
+        self.history.push_back(cpu.clone());
+    }
+
+    // Take back the most recent snapshot
+    pub fn step_back(&mut self) -> Option<Chip8> {
+        self.history.pop_back()
+    }
+
+    // The most recent snapshot, left in place
+    pub fn latest(&self) -> Option<&Chip8> {
+        self.history.back()
+    }
+
+    // Up to count of the newest snapshots, oldest first
+    pub fn last(&self, count: usize) -> impl Iterator<Item = &Chip8> {
+        self.history
+            .iter()
+            .skip(self.history.len().saturating_sub(count))
+    }
+
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+}
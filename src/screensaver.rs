@@ -0,0 +1,57 @@
+use crate::attract::Attract;
+use crate::chip8::Chip8;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/*    Screensaver
+ * Attract mode's rotation of a folder of ROMs, full screen with no cursor, for
+ * demos that look good left alone. A key, click or mouse move ends it, after a
+ * second's grace for the window to settle:
+ *   --screensaver <dir>                   Starts straight away, input exits
+ *   --screensaver <dir> --idle <seconds>  Runs the ROMs given until no key
+ *                                         has been held for that long, input
+ *                                         goes back to the game
+ */
+pub struct Screensaver {
+    pub attract: Attract,
+    idle: Duration,
+    last_input: Instant,
+}
+
+// A window's screensaver, with the game to go back to or none to exit
+pub struct Asleep {
+    pub game: Option<(Chip8, String)>,
+    pub since: Instant,
+}
+
+impl Asleep {
+    pub fn new(game: Option<(Chip8, String)>) -> Asleep {
+        Asleep {
+            game,
+            since: Instant::now(),
+        }
+    }
+}
+
+impl Screensaver {
+    pub fn open(dir: &Path, seconds: u64, idle: Duration) -> Result<Screensaver, String> {
+        Ok(Screensaver {
+            attract: Attract::open(dir, seconds)?,
+            idle,
+            last_input: Instant::now(),
+        })
+    }
+
+    // True once no key has been held for the idle time
+    pub fn is_due(&mut self, cpu: &Chip8) -> bool {
+        if cpu.input != 0 {
+            self.last_input = Instant::now();
+        }
+        self.last_input.elapsed() >= self.idle
+    }
+
+    // While the screensaver runs, so the idle time starts again after it
+    pub fn wait(&mut self) {
+        self.last_input = Instant::now();
+    }
+}
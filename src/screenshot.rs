@@ -0,0 +1,79 @@
+use crate::chip8::Chip8;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 32;
+// Between the two halves of a side by side image
+const GAP: usize = 2;
+const SEPARATOR: [u8; 4] = [0x80, 0x80, 0x80, 0xFF];
+const DIFFERENT: [u8; 4] = [0xFF, 0x30, 0x30, 0xFF];
+
+// The display as a 64x32 PNG in the current palette, whether or not a redraw
+// is pending
+pub fn png(cpu: &Chip8) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write(cpu, &mut bytes).expect("writing a PNG to memory");
+    bytes
+}
+
+pub fn save(cpu: &Chip8, path: &Path) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    write(cpu, &mut file)?;
+    file.flush()
+}
+
+fn write(cpu: &Chip8, output: impl Write) -> io::Result<()> {
+    let (color, background) = cpu.palette();
+    let rgba: Vec<u8> = cpu
+        .video_buffer
+        .iter()
+        .flat_map(|pixel| match pixel {
+            1 => color,
+            _ => background,
+        })
+        .collect();
+    encode(&rgba, WIDTH, HEIGHT, output)
+}
+
+// Two displays with a gap between, and the pixels that differ in red, for
+// chip8 compare
+pub fn save_side_by_side(left: &Chip8, right: &Chip8, path: &Path) -> io::Result<()> {
+    let width = WIDTH as usize;
+    let mut rgba = Vec::with_capacity((width * 2 + GAP) * HEIGHT as usize * 4);
+    for (row, other) in left
+        .video_buffer
+        .chunks(width)
+        .zip(right.video_buffer.chunks(width))
+    {
+        rgba.extend(side(left, row, other));
+        rgba.extend([SEPARATOR; GAP].iter().flatten());
+        rgba.extend(side(right, other, row));
+    }
+    let mut file = BufWriter::new(File::create(path)?);
+    encode(&rgba, WIDTH * 2 + GAP as u32, HEIGHT, &mut file)?;
+    file.flush()
+}
+
+// One row of one side, red where it isn't the same as the other
+fn side<'a>(cpu: &Chip8, row: &'a [u8], other: &'a [u8]) -> impl Iterator<Item = u8> + 'a {
+    let (color, background) = cpu.palette();
+    row.iter()
+        .zip(other)
+        .flat_map(move |(pixel, other)| match (pixel == other, pixel) {
+            (false, _) => DIFFERENT,
+            (true, 1) => color,
+            _ => background,
+        })
+}
+
+pub fn encode(rgba: &[u8], width: u32, height: u32, output: impl Write) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(output, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(rgba)?;
+    writer.finish()?;
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use crate::chip8::{Chip8, MEMORY_SIZE};
+use log::error;
+use rhai::{CallFnOptions, Dynamic, Engine, Map, Scope, AST};
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// 60 Hz, matching the timers
+const FRAME: Duration = Duration::from_micros(16_667);
+
+/*    Script Hooks
+ * fn on_frame()          60 times a second
+ * fn on_opcode(opcode)   Before each instruction
+ * fn on_draw()           After the display changes
+ * fn on_key(key)         When the held key changes, -1 on release
+ *
+ * Hooks are optional and see the machine as `this`:
+ *   this.pc  this.i  this.delay  this.sound  this.key   read and write
+ *   this.v(x)  this.set_v(x, value)
+ *   this.peek(addr)  this.poke(addr, value)
+ *   this.pixel(x, y)
+ *
+ * Functions can't see the script's variables, so anything kept between
+ * calls goes through store(name, value) and fetch(name), () until stored.
+ *
+ * e.g. infinite lives:
+ *   fn on_frame() { this.poke(0x3F0, 3); }
+ */
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    hooks: Vec<&'static str>,
+    frame_start: Instant,
+    redraw: bool,
+    key: u8,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Script, String> {
+        let mut engine = Engine::new();
+        let stored = Rc::new(RefCell::new(Map::new()));
+        let fetched = stored.clone();
+        engine
+            .register_fn("store", move |name: &str, value: Dynamic| {
+                stored.borrow_mut().insert(name.into(), value);
+            })
+            .register_fn("fetch", move |name: &str| {
+                fetched.borrow().get(name).cloned().unwrap_or(Dynamic::UNIT)
+            });
+        engine
+            .register_type_with_name::<Chip8>("Machine")
+            .register_get_set(
+                "pc",
+                |cpu: &mut Chip8| cpu.pc as i64,
+                |cpu: &mut Chip8, pc: i64| cpu.pc = pc as usize & 0xFFE,
+            )
+            .register_get_set(
+                "i",
+                |cpu: &mut Chip8| cpu.address_reg as i64,
+                |cpu: &mut Chip8, i: i64| cpu.address_reg = i as u16 & 0xFFF,
+            )
+            .register_get_set(
+                "delay",
+                |cpu: &mut Chip8| cpu.delay_timer as i64,
+                |cpu: &mut Chip8, delay: i64| cpu.delay_timer = delay as u8,
+            )
+            .register_get_set(
+                "sound",
+                |cpu: &mut Chip8| cpu.sound_timer as i64,
+                |cpu: &mut Chip8, sound: i64| cpu.sound_timer = sound as u8,
+            )
+            .register_get_set(
+                "key",
+                |cpu: &mut Chip8| cpu.input as i64,
+                |cpu: &mut Chip8, key: i64| cpu.input = key as u8 & 0xF,
+            )
+            .register_fn("v", |cpu: &mut Chip8, x: i64| {
+                cpu.registers[x as usize & 0xF] as i64
+            })
+            .register_fn("set_v", |cpu: &mut Chip8, x: i64, value: i64| {
+                cpu.registers[x as usize & 0xF] = value as u8
+            })
+            .register_fn("peek", |cpu: &mut Chip8, addr: i64| {
+                cpu.memory[addr as usize % MEMORY_SIZE] as i64
+            })
+            .register_fn("poke", |cpu: &mut Chip8, addr: i64, value: i64| {
+                cpu.memory[addr as usize % MEMORY_SIZE] = value as u8
+            })
+            .register_fn("pixel", |cpu: &mut Chip8, x: i64, y: i64| {
+                cpu.video_buffer[(y as usize % 32) * 64 + x as usize % 64] == 1
+            });
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut hooks = Vec::new();
+        for (name, params) in [
+            ("on_frame", 0),
+            ("on_opcode", 1),
+            ("on_draw", 0),
+            ("on_key", 1),
+        ] {
+            if ast
+                .iter_functions()
+                .any(|function| function.name == name && function.params.len() == params)
+            {
+                hooks.push(name);
+            }
+        }
+        // Top-level statements run once, e.g. to store starting values
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(Script {
+            engine,
+            ast,
+            scope,
+            hooks,
+            frame_start: Instant::now(),
+            redraw: false,
+            key: 0,
+        })
+    }
+
+    // Run whichever hooks are due, before the next instruction
+    pub fn update(&mut self, cpu: &mut Chip8) {
+        if self.frame_start.elapsed() >= FRAME {
+            self.frame_start = Instant::now();
+            self.call("on_frame", cpu, ());
+        }
+        if cpu.redraw && !self.redraw {
+            self.call("on_draw", cpu, ());
+        }
+        self.redraw = cpu.redraw;
+        if cpu.input != self.key {
+            self.key = cpu.input;
+            // 0 is also "no key" to the core
+            let key = match cpu.input {
+                0 => -1,
+                key => key as i64,
+            };
+            self.call("on_key", cpu, (key,));
+        }
+        let opcode = cpu.opcode_at(cpu.pc) as i64;
+        self.call("on_opcode", cpu, (opcode,));
+    }
+
+    // A hook that fails is dropped rather than failing again every frame
+    fn call(&mut self, hook: &'static str, cpu: &mut Chip8, args: impl rhai::FuncArgs) {
+        if !self.hooks.contains(&hook) {
+            return;
+        }
+        let mut this = Dynamic::from(cpu.clone());
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut this);
+        let result = self.engine.call_fn_with_options::<Dynamic>(
+            options,
+            &mut self.scope,
+            &self.ast,
+            hook,
+            args,
+        );
+        match result {
+            Ok(_) => *cpu = this.cast::<Chip8>(),
+            Err(e) => {
+                error!("Script {} failed, disabling it: {}", hook, e);
+                self.hooks.retain(|name| *name != hook);
+            }
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::chip8::Chip8;
+use crate::debugger::Debugger;
+use crate::keypad::Keymap;
+use crate::osd;
+use crate::window;
+use log::error;
+use sdl2::audio::{AudioCallback, AudioSpecDesired};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Mod, Scancode};
+use sdl2::pixels::PixelFormatEnum;
+use std::time::{Duration, Instant};
+use tracing::info_span;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 32;
+// 60 Hz, matching the timers
+const FRAME: Duration = Duration::from_micros(16_667);
+const TONE_HZ: f32 = 440.0;
+const VOLUME: f32 = 0.1;
+
+// Plays while the sound timer is running
+struct SquareWave {
+    step: f32,
+    phase: f32,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = match self.phase < 0.5 {
+                true => VOLUME,
+                false => -VOLUME,
+            };
+            self.phase = (self.phase + self.step) % 1.0;
+        }
+    }
+}
+
+// Window, keys and sound through SDL2 instead of winit and pixels, for GPUs
+// and platforms wgpu doesn't handle. Runs until Escape or the window closes.
+// The window starts at scale times the display, which scales with it. With
+// pause_unfocused, the machine and its beep stop while the window is in the
+// background.
+pub fn run(
+    cpu: &mut Chip8,
+    debugger: &mut Debugger,
+    title: &str,
+    scale: u32,
+    pause_unfocused: bool,
+) -> Result<(), String> {
+    let sdl = sdl2::init()?;
+    let video = sdl.video()?;
+    let mut shown_title = window::title(title, debugger);
+    let window = video
+        .window(&shown_title, WIDTH * scale, HEIGHT * scale)
+        .resizable()
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    canvas
+        .set_logical_size(WIDTH, HEIGHT)
+        .map_err(|e| e.to_string())?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGBA32, WIDTH, HEIGHT)
+        .map_err(|e| e.to_string())?;
+    let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+
+    // No audio device just means no beep
+    let beep = sdl.audio().and_then(|audio| {
+        let desired = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+        audio.open_playback(None, &desired, |spec| SquareWave {
+            step: TONE_HZ / spec.freq as f32,
+            phase: 0.0,
+        })
+    });
+    let beep = match beep {
+        Ok(device) => Some(device),
+        Err(e) => {
+            error!("Unable to open an audio device: {}", e);
+            None
+        }
+    };
+
+    let mut events = sdl.event_pump()?;
+    let mut held: Option<u8> = None;
+    let mut focused = true;
+    // Whether the texture holds a dimmed display
+    let mut dimmed = false;
+    cpu.redraw = true;
+    loop {
+        let frame_start = Instant::now();
+        let _frame = cpu.frame_span().entered();
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    scancode: Some(Scancode::Escape),
+                    ..
+                } => return Ok(()),
+                // F5 restarts the program, Shift+F5 also clears memory
+                Event::KeyDown {
+                    scancode: Some(Scancode::F5),
+                    keymod,
+                    ..
+                } => match keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                    true => cpu.hard_reset(),
+                    false => cpu.soft_reset(),
+                },
+                // Space or P pauses, unless the game's keys use P
+                Event::KeyDown {
+                    scancode: Some(scancode @ (Scancode::Space | Scancode::P)),
+                    repeat: false,
+                    ..
+                } if scancode == Scancode::Space || cpu.keymap.key('p').is_none() => {
+                    match debugger.is_paused() {
+                        true => debugger.resume(),
+                        false => debugger.pause(),
+                    }
+                }
+                // Period runs one frame at a time while paused
+                Event::KeyDown {
+                    scancode: Some(Scancode::Period),
+                    ..
+                } if debugger.is_paused() => debugger.step_frame(cpu),
+                Event::KeyDown {
+                    scancode: Some(scancode),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(value) = keypad(scancode, &cpu.keymap) {
+                        held = Some(value);
+                    }
+                }
+                Event::KeyUp {
+                    scancode: Some(scancode),
+                    ..
+                } if held.is_some() && keypad(scancode, &cpu.keymap) == held => held = None,
+                Event::Window {
+                    win_event: WindowEvent::FocusGained,
+                    ..
+                } => focused = true,
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } => focused = false,
+                _ => {}
+            }
+        }
+
+        cpu.input = held.unwrap_or(0);
+        debugger.poll(cpu);
+        let title = window::title(title, debugger);
+        if title != shown_title {
+            canvas
+                .window_mut()
+                .set_title(&title)
+                .map_err(|e| e.to_string())?;
+            shown_title = title;
+        }
+        let paused = (pause_unfocused && !focused) || debugger.is_paused();
+        if !paused {
+            debugger.run(cpu, cpu.speed);
+            cpu.decrement_timers();
+            debugger.end_frame(cpu);
+        }
+        if let Some(device) = &beep {
+            match cpu.sound_timer > 0 && !paused {
+                true => device.resume(),
+                false => device.pause(),
+            }
+        }
+        let render = info_span!("render").entered();
+        if cpu.redraw || dimmed != debugger.is_paused() {
+            cpu.draw(&mut frame);
+            dimmed = debugger.is_paused();
+            if dimmed {
+                osd::dim(&mut frame);
+            }
+            texture
+                .update(None, &frame, (WIDTH * 4) as usize)
+                .map_err(|e| e.to_string())?;
+            cpu.redraw = false;
+        }
+        // Present every frame, SDL may have lost the last one on resize
+        canvas.clear();
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+        drop(render);
+        std::thread::sleep(FRAME.saturating_sub(frame_start.elapsed()));
+    }
+}
+
+// Scancodes name keys by position, so the layout matches on any keyboard
+fn keypad(scancode: Scancode, keymap: &Keymap) -> Option<u8> {
+    match scancode.name().chars().collect::<Vec<char>>().as_slice() {
+        [key] => keymap.key(*key),
+        _ => None,
+    }
+}
@@ -0,0 +1,73 @@
+use crate::chip8::Chip8;
+
+// Candidates listed after each filter
+const SHOW_LIMIT: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Filter {
+    Equal(u8),
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+}
+
+impl Filter {
+    fn keeps(self, before: u8, now: u8) -> bool {
+        match self {
+            Filter::Equal(value) => now == value,
+            Filter::Changed => now != before,
+            Filter::Unchanged => now == before,
+            Filter::Increased => now > before,
+            Filter::Decreased => now < before,
+        }
+    }
+}
+
+// Narrows down which byte holds a value by comparing snapshots of memory,
+// e.g. lose a life then keep only the bytes that decreased
+#[derive(Default)]
+pub struct RamSearch {
+    snapshot: Vec<u8>,
+    candidates: Vec<usize>,
+}
+
+impl RamSearch {
+    pub fn new() -> RamSearch {
+        RamSearch::default()
+    }
+
+    // Every address is a candidate again, compared against memory as it is now
+    pub fn start(&mut self, cpu: &Chip8) {
+        self.snapshot = cpu.memory.to_vec();
+        self.candidates = (0..cpu.memory.len()).collect();
+    }
+
+    pub fn is_started(&self) -> bool {
+        !self.snapshot.is_empty()
+    }
+
+    // Keep the candidates passing the filter, then snapshot memory for the
+    // next comparison
+    pub fn filter(&mut self, cpu: &Chip8, filter: Filter) -> usize {
+        let snapshot = &self.snapshot;
+        self.candidates
+            .retain(|addr| filter.keeps(snapshot[*addr], cpu.memory[*addr]));
+        self.snapshot = cpu.memory.to_vec();
+        self.candidates.len()
+    }
+
+    pub fn results(&self, cpu: &Chip8) -> String {
+        let mut lines = vec![format!("{} candidates", self.candidates.len())];
+        for addr in self.candidates.iter().take(SHOW_LIMIT) {
+            lines.push(format!(
+                "  {:#05x}: {:02x} ({})",
+                addr, cpu.memory[*addr], cpu.memory[*addr]
+            ));
+        }
+        if self.candidates.len() > SHOW_LIMIT {
+            lines.push(String::from("  ..."));
+        }
+        lines.join("\n")
+    }
+}
@@ -0,0 +1,173 @@
+use crate::analysis::{analyze, Class};
+use crate::osd::glyph;
+use crate::screenshot::encode;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const START: usize = 0x200;
+const COLUMNS: usize = 8;
+// Room for a label of 4 characters, 4 pixels each
+const CELL_WIDTH: usize = 16;
+const LABEL_HEIGHT: usize = 6;
+const GAP: usize = 4;
+// Each pixel of the sheet as a block of this many
+const SCALE: usize = 4;
+// Longest run of guessed bytes drawn as one sprite
+const MAX_GUESSED: usize = 15;
+const BACKGROUND: [u8; 4] = [0x10, 0x10, 0x10, 0xFF];
+const LIT: [u8; 4] = [0xFA, 0xFA, 0x10, 0xFF];
+const UNLIT: [u8; 4] = [0x30, 0x30, 0x30, 0xFF];
+const LABEL: [u8; 4] = [0x80, 0x80, 0x80, 0xFF];
+
+/*    Sprite Sheet
+ * The sprites a ROM draws, from static analysis of where I points at each
+ * DXYN, on a grid eight across with the address in hex over each one:
+ *   2A0    2A5    2AA
+ *   ###    # #    ###
+ *   ...
+ * A sprite drawn at different heights is shown at the tallest, and a table
+ * stepped through with i += is cut into sprites of the height drawn. DXY0
+ * sprites are 16x16. With guess, data nothing is seen drawing is added too,
+ * up to 15 rows at a time, and labelled with a ?.
+ */
+pub struct Sprite {
+    pub addr: usize,
+    pub rows: Vec<u8>,
+    pub wide: bool,
+    pub guessed: bool,
+}
+
+impl Sprite {
+    pub fn width(&self) -> usize {
+        match self.wide {
+            true => 16,
+            false => 8,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        match self.wide {
+            true => self.rows.len().div_ceil(2),
+            false => self.rows.len(),
+        }
+    }
+
+    pub fn is_lit(&self, x: usize, y: usize) -> bool {
+        let index = match self.wide {
+            true => y * 2 + x / 8,
+            false => y,
+        };
+        self.rows
+            .get(index)
+            .is_some_and(|byte| byte << (x % 8) & 0x80 != 0)
+    }
+
+    pub fn label(&self) -> String {
+        match self.guessed {
+            true => format!("{:03X}?", self.addr),
+            false => format!("{:03X}", self.addr),
+        }
+    }
+}
+
+pub fn find(rom: &[u8], guess: bool) -> Vec<Sprite> {
+    let analysis = analyze(rom);
+    let end = START + rom.len();
+    let bytes = |addr: usize, len: usize| rom[addr - START..(addr + len).min(end) - START].to_vec();
+    let mut sprites: Vec<Sprite> = analysis
+        .sprites
+        .iter()
+        .map(|(addr, len)| Sprite {
+            addr: *addr,
+            rows: bytes(*addr, *len),
+            wide: *len == 32,
+            guessed: false,
+        })
+        .collect();
+    if guess {
+        let mut addr = START;
+        while addr < end {
+            let unknown = |addr: usize| {
+                analysis.class(addr) == Class::Unknown && !analysis.is_unreachable(addr)
+            };
+            if !unknown(addr) {
+                addr += 1;
+                continue;
+            }
+            let len = (addr..end.min(addr + MAX_GUESSED))
+                .take_while(|addr| unknown(*addr))
+                .count();
+            let rows = bytes(addr, len);
+            // Padding and empty space isn't worth a cell
+            if rows.iter().any(|row| *row != 0) {
+                sprites.push(Sprite {
+                    addr,
+                    rows,
+                    wide: false,
+                    guessed: true,
+                });
+            }
+            addr += len;
+        }
+        sprites.sort_by_key(|sprite| sprite.addr);
+    }
+    sprites
+}
+
+// RGBA pixels of the sheet, with its width and height
+pub fn draw(sprites: &[Sprite]) -> (Vec<u8>, usize, usize) {
+    let rows: Vec<&[Sprite]> = sprites.chunks(COLUMNS).collect();
+    let heights: Vec<usize> = rows
+        .iter()
+        .map(|row| LABEL_HEIGHT + row.iter().map(Sprite::height).max().unwrap_or(0) + GAP)
+        .collect();
+    let width = GAP + COLUMNS.min(sprites.len().max(1)) * (CELL_WIDTH + GAP);
+    let height = GAP + heights.iter().sum::<usize>();
+    let mut pixels = vec![BACKGROUND; width * height];
+    let mut top = GAP;
+    for (row, row_height) in rows.iter().zip(heights) {
+        for (column, sprite) in row.iter().enumerate() {
+            let left = GAP + column * (CELL_WIDTH + GAP);
+            for (index, c) in sprite.label().chars().enumerate() {
+                let rows = glyph(c);
+                for (y, bits) in rows.iter().enumerate() {
+                    for x in 0..3 {
+                        if bits & (0b100 >> x) != 0 {
+                            pixels[(top + y) * width + left + index * 4 + x] = LABEL;
+                        }
+                    }
+                }
+            }
+            for y in 0..sprite.height() {
+                for x in 0..sprite.width() {
+                    let color = match sprite.is_lit(x, y) {
+                        true => LIT,
+                        false => UNLIT,
+                    };
+                    pixels[(top + LABEL_HEIGHT + y) * width + left + x] = color;
+                }
+            }
+        }
+        top += row_height;
+    }
+
+    let mut rgba = Vec::with_capacity(width * height * SCALE * SCALE * 4);
+    for line in pixels.chunks(width) {
+        for _ in 0..SCALE {
+            for pixel in line {
+                for _ in 0..SCALE {
+                    rgba.extend(pixel);
+                }
+            }
+        }
+    }
+    (rgba, width * SCALE, height * SCALE)
+}
+
+pub fn save(sprites: &[Sprite], path: &Path) -> io::Result<()> {
+    let (rgba, width, height) = draw(sprites);
+    let mut file = BufWriter::new(File::create(path)?);
+    encode(&rgba, width as u32, height as u32, &mut file)?;
+    file.flush()
+}
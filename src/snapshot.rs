@@ -0,0 +1,100 @@
+use crate::chip8::Chip8;
+use crate::crash;
+use std::path::Path;
+
+const WIDTH: usize = 64;
+// Set to write snapshots that are missing or don't match instead of failing
+const UPDATE: &str = "CHIP8_UPDATE_SNAPSHOTS";
+
+/*    Display Snapshots
+ * Golden image tests for programs, here or in crates that use this one:
+ *   let mut cpu = Chip8::new();
+ *   cpu.load_font();
+ *   cpu.load_program(&rom);
+ *   snapshot::run_for_frames(&mut cpu, 60).unwrap();
+ *   snapshot::assert_framebuffer_matches(&cpu, "tests/snapshots/ibm.txt");
+ *
+ * A snapshot is the display as text, 32 lines of 64 '#' for lit pixels and
+ * '.' for the rest, as chip8 test prints it. One that is missing or doesn't
+ * match fails the test, unless CHIP8_UPDATE_SNAPSHOTS is set in the
+ * environment, when it's written from the display instead. Frames run the
+ * machine's speed in instructions, then tick the timers, stopping at the
+ * first instruction that can't run, see crash::fault. Seed the machine first
+ * if the program uses CXNN.
+ */
+pub fn run_for_frames(cpu: &mut Chip8, frames: u64) -> Result<(), String> {
+    for _ in 0..frames {
+        let _frame = cpu.frame_span().entered();
+        for _ in 0..cpu.speed {
+            if let Some(fault) = crash::fault(cpu) {
+                return Err(format!("{} at {:#05x}", fault, cpu.pc));
+            }
+            cpu.tick();
+        }
+        cpu.decrement_timers();
+    }
+    Ok(())
+}
+
+pub fn framebuffer_as_ascii(cpu: &Chip8) -> String {
+    let mut text = String::with_capacity(cpu.video_buffer.len() + 32);
+    for row in cpu.video_buffer.chunks(WIDTH) {
+        text.extend(row.iter().map(|pixel| match pixel {
+            1 => '#',
+            _ => '.',
+        }));
+        text.push('\n');
+    }
+    text
+}
+
+// Panics with the rows that differ, so it fails a test like assert_eq
+#[track_caller]
+pub fn assert_framebuffer_matches(cpu: &Chip8, path: impl AsRef<Path>) {
+    let path = path.as_ref();
+    let actual = framebuffer_as_ascii(cpu);
+    let update = std::env::var_os(UPDATE).is_some();
+    let expected = match std::fs::read_to_string(path) {
+        Ok(expected) => expected,
+        Err(_) if update => return write(path, &actual),
+        Err(e) => panic!(
+            "Unable to read {}: {}, set {} to write it",
+            path.display(),
+            e,
+            UPDATE
+        ),
+    };
+    if expected.lines().eq(actual.lines()) {
+        return;
+    }
+    if update {
+        return write(path, &actual);
+    }
+    let mut report = vec![format!(
+        "The display doesn't match {}, set {} to update it",
+        path.display(),
+        UPDATE
+    )];
+    let (expected_rows, actual_rows): (Vec<&str>, Vec<&str>) =
+        (expected.lines().collect(), actual.lines().collect());
+    for row in 0..expected_rows.len().max(actual_rows.len()) {
+        let (old, new) = (
+            expected_rows.get(row).copied().unwrap_or(""),
+            actual_rows.get(row).copied().unwrap_or(""),
+        );
+        if old != new {
+            report.push(format!("row {:>2} expected {}", row, old));
+            report.push(format!("          actual {}", new));
+        }
+    }
+    panic!("{}", report.join("\n"));
+}
+
+fn write(path: &Path, text: &str) {
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Err(e) = std::fs::write(path, text) {
+        panic!("Unable to write {}: {}", path.display(), e);
+    }
+}
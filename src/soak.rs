@@ -0,0 +1,114 @@
+use crate::chip8::Chip8;
+use crate::crash;
+use crate::info::extension;
+use std::any::Any;
+use std::collections::BTreeSet;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+
+/*    Soak Test
+ * chip8 soak <dir> runs every ROM in a folder for a while with no keys held
+ * and sorts them by how it went:
+ *   OK       ran every frame and drew something
+ *   BLANK    ran every frame, the display never changed
+ *   FAULT    reached an instruction the core can't carry out, see crash.rs
+ *   PANIC    the core panicked, which is a bug in the emulator
+ *   ERROR    couldn't be loaded
+ *
+ * Instructions from later variants, which the core passes over, are listed
+ * after any of them. Hidden files are skipped, as in attract mode.
+ */
+#[derive(Debug, Default)]
+pub struct Soak {
+    pub frames: u64,
+    pub instructions: u64,
+    pub fault: Option<String>,
+    pub panic: Option<String>,
+    pub drew: bool,
+    // Other variants' instructions, e.g. "SCHIP 00FF"
+    pub skipped: BTreeSet<String>,
+}
+
+// The ROMs in a folder, in name order
+pub fn roms(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Unable to read {}: {}", dir.display(), e))?;
+    let mut roms: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+        .map(|entry| entry.path())
+        .collect();
+    roms.sort();
+    Ok(roms)
+}
+
+// Run frames from seed 0, stopping at a fault or a panic. Panics are caught,
+// set a quiet panic hook first to keep their messages off the terminal.
+pub fn soak(cpu: &mut Chip8, frames: u64) -> Soak {
+    cpu.seed(0);
+    let start = cpu.video_buffer;
+    let mut soak = Soak::default();
+    'frames: for frame in 0..frames {
+        for _ in 0..cpu.speed {
+            if let Some(fault) = crash::fault(cpu) {
+                soak.fault = Some(format!("frame {}: {}", frame, fault));
+                break 'frames;
+            }
+            let opcode = cpu.opcode_at(cpu.pc);
+            if let Some((variant, class)) = extension(opcode) {
+                soak.skipped.insert(format!("{} {}", variant, class));
+            }
+            if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(|| cpu.tick())) {
+                soak.panic = Some(format!(
+                    "frame {}: {:04X} at {:#05x}: {}",
+                    frame,
+                    opcode,
+                    cpu.pc,
+                    message(payload)
+                ));
+                break 'frames;
+            }
+        }
+        cpu.decrement_timers();
+        soak.drew |= cpu.video_buffer != start;
+        soak.frames = frame + 1;
+    }
+    soak.instructions = cpu.instructions;
+    soak
+}
+
+fn message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => String::from("no message"),
+        },
+    }
+}
+
+impl Soak {
+    pub fn verdict(&self) -> &'static str {
+        match (&self.panic, &self.fault, self.drew) {
+            (Some(_), _, _) => "PANIC",
+            (_, Some(_), _) => "FAULT",
+            (_, _, true) => "OK",
+            (_, _, false) => "BLANK",
+        }
+    }
+
+    // What happened, after the verdict and name
+    pub fn describe(&self) -> String {
+        let mut text = match (&self.panic, &self.fault) {
+            (Some(panic), _) => panic.clone(),
+            (_, Some(fault)) => fault.clone(),
+            _ => format!("{} frames, {} instructions", self.frames, self.instructions),
+        };
+        if !self.skipped.is_empty() {
+            let skipped: Vec<&str> = self.skipped.iter().map(String::as_str).collect();
+            text.push_str(&format!(", passed over {}", skipped.join(", ")));
+        }
+        text
+    }
+}
@@ -0,0 +1,101 @@
+use crate::chip8::{Chip8, EmulationStatus, MAX_ROM_SIZE, MEMORY_SIZE};
+
+const MAGIC: &[u8] = b"C8ST";
+const VERSION: u8 = 2;
+// Version 1 states are from when memory stopped at 4000 bytes, and still load
+const V1_MEMORY_SIZE: usize = 4000;
+const DISPLAY_SIZE: usize = 64 * 32;
+const SIZE: usize = size(MEMORY_SIZE);
+
+/*    Save State Layout
+ * "C8ST" 2           magic and format version
+ * pc:2 i:2 sp:1      big-endian like the instructions
+ * v0-vF:16
+ * stack:12x2
+ * delay:1 sound:1 waiting for key:1
+ * rom size:2
+ * memory:4096       4000 in version 1, the rest then loading as 0
+ * display:2048       one byte per pixel
+ *
+ * The palette and random number generator aren't saved, a loaded state keeps
+ * the ones of the machine it's loaded into.
+ */
+pub fn save(cpu: &Chip8) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(SIZE);
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&(cpu.pc as u16).to_be_bytes());
+    bytes.extend_from_slice(&cpu.address_reg.to_be_bytes());
+    bytes.push(cpu.sp as u8);
+    bytes.extend_from_slice(&cpu.registers);
+    for entry in cpu.stack {
+        bytes.extend_from_slice(&(entry as u16).to_be_bytes());
+    }
+    bytes.push(cpu.delay_timer);
+    bytes.push(cpu.sound_timer);
+    bytes.push(matches!(cpu.status, EmulationStatus::WaitingForKey) as u8);
+    bytes.extend_from_slice(&(cpu.rom_size as u16).to_be_bytes());
+    bytes.extend_from_slice(&cpu.memory);
+    bytes.extend_from_slice(&cpu.video_buffer);
+    bytes
+}
+
+const fn size(memory_size: usize) -> usize {
+    5 + 5 + 16 + 24 + 3 + 2 + memory_size + DISPLAY_SIZE
+}
+
+// Replace the machine state, leaving it untouched if the bytes aren't a state
+pub fn load(cpu: &mut Chip8, bytes: &[u8]) -> Result<(), String> {
+    if bytes.get(..4) != Some(MAGIC) {
+        return Err(String::from("Not a save state"));
+    }
+    let memory_size = match bytes[4] {
+        1 => V1_MEMORY_SIZE,
+        VERSION => MEMORY_SIZE,
+        version => return Err(format!("Unsupported save state version {}", version)),
+    };
+    if bytes.len() != size(memory_size) {
+        return Err(format!(
+            "Save state is {} bytes, expected {}",
+            bytes.len(),
+            size(memory_size)
+        ));
+    }
+    let mut rest = &bytes[5..];
+    let mut take = |length: usize| {
+        let (field, tail) = rest.split_at(length);
+        rest = tail;
+        field
+    };
+    let mut loaded = cpu.clone();
+    let word = |field: &[u8]| u16::from_be_bytes([field[0], field[1]]);
+    loaded.pc = word(take(2)) as usize;
+    loaded.address_reg = word(take(2));
+    loaded.sp = take(1)[0] as usize;
+    loaded.registers.copy_from_slice(take(16));
+    for entry in loaded.stack.iter_mut() {
+        *entry = word(take(2)) as usize;
+    }
+    loaded.delay_timer = take(1)[0];
+    loaded.sound_timer = take(1)[0];
+    loaded.status = match take(1)[0] {
+        0 => EmulationStatus::Running,
+        _ => EmulationStatus::WaitingForKey,
+    };
+    loaded.rom_size = word(take(2)) as usize;
+    loaded.memory = [0; MEMORY_SIZE];
+    loaded.memory[..memory_size].copy_from_slice(take(memory_size));
+    loaded.video_buffer.copy_from_slice(take(DISPLAY_SIZE));
+    // Anything that would index out of bounds later
+    if loaded.pc >= MEMORY_SIZE - 1 || loaded.sp >= loaded.stack.len() {
+        return Err(String::from(
+            "Save state has an invalid pc or stack pointer",
+        ));
+    }
+    if loaded.rom_size > MAX_ROM_SIZE {
+        return Err(String::from("Save state has an invalid ROM size"));
+    }
+    loaded.redraw = true;
+    *cpu = loaded;
+    Ok(())
+}
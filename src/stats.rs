@@ -0,0 +1,70 @@
+use crate::chip8::Chip8;
+use crate::crash;
+use serde_json::{json, Value};
+
+/*    Run Statistics
+ * What a ROM did over a run without a frontend, for scripts to act on:
+ *   {"frames": 600, "instructions": 6000, "draws": 412, "sounds": 3,
+ *    "hash": 2766380591, "fault": null}
+ *
+ * draws counts 00E0 and DXYN, sounds the times the buzzer started, and hash
+ * is Chip8::state_hash at the end. A fault stops the run early, with frames
+ * saying how far it got.
+ */
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub frames: u64,
+    pub instructions: u64,
+    pub draws: u64,
+    pub sounds: u64,
+    pub hash: u32,
+    pub fault: Option<String>,
+}
+
+impl Stats {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "frames": self.frames,
+            "instructions": self.instructions,
+            "draws": self.draws,
+            "sounds": self.sounds,
+            "hash": self.hash,
+            "fault": self.fault,
+        })
+    }
+}
+
+// A ROM on a machine with the default speed and quirks, from seed 0 with no
+// keys held
+pub fn run_headless(rom: &[u8], frames: u64) -> Stats {
+    let mut cpu = Chip8::new();
+    cpu.load_font();
+    cpu.load_program(rom);
+    run(&mut cpu, frames)
+}
+
+// The same for a machine that's already set up, counting from where it is
+pub fn run(cpu: &mut Chip8, frames: u64) -> Stats {
+    cpu.seed(0);
+    let (instructions, draws) = (cpu.instructions, cpu.draws);
+    let mut stats = Stats::default();
+    'frames: for frame in 0..frames {
+        for _ in 0..cpu.speed {
+            if let Some(fault) = crash::fault(cpu) {
+                stats.fault = Some(fault);
+                break 'frames;
+            }
+            let silent = cpu.sound_timer == 0;
+            cpu.tick();
+            if silent && cpu.sound_timer > 0 {
+                stats.sounds += 1;
+            }
+        }
+        cpu.decrement_timers();
+        stats.frames = frame + 1;
+    }
+    stats.instructions = cpu.instructions - instructions;
+    stats.draws = cpu.draws - draws;
+    stats.hash = cpu.state_hash();
+    stats
+}
@@ -0,0 +1,144 @@
+use crate::chip8::{Chip8, Quirks};
+use crate::snapshot;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+pub const MANIFEST: &str = "suite.toml";
+
+/*    Test Suite
+ * [[test]]
+ * name = "Timendus flags"     Shown in the results
+ * rom = "4-flags.ch8"         Next to suite.toml
+ * frames = 600                Run before hashing the screen
+ * key = 1                     Held throughout, for menus, 0 to F
+ * quirks = "vf-reset"         On top of the machine's
+ * crc32 = "1a2b3c4d"          The screen when it passed, see --record
+ *
+ * Every test runs from seed 0 so CXNN gives the same numbers each time.
+ */
+#[derive(Debug, Clone)]
+pub struct Case {
+    pub name: String,
+    pub rom: PathBuf,
+    pub frames: u64,
+    pub key: Option<u8>,
+    pub quirks: Option<String>,
+    pub crc32: Option<String>,
+}
+
+pub enum Outcome {
+    Pass,
+    Fail(String),
+    // Never recorded, with the hash it has now
+    New(String),
+    // Stopped at an instruction that can't run, see crash::fault
+    Fault(String),
+}
+
+pub fn load(dir: &Path) -> Result<Vec<Case>, String> {
+    let path = dir.join(MANIFEST);
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    let table: Table = text
+        .parse()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    let tests = match table.get("test") {
+        Some(tests) => tests
+            .as_array()
+            .ok_or_else(|| format!("{}: test must be a list of [[test]]", path.display()))?,
+        None => return Ok(Vec::new()),
+    };
+    tests
+        .iter()
+        .enumerate()
+        .map(|(i, test)| {
+            Case::parse(dir, test).map_err(|e| format!("{}: test {}: {}", path.display(), i + 1, e))
+        })
+        .collect()
+}
+
+impl Case {
+    fn parse(dir: &Path, test: &Value) -> Result<Case, String> {
+        let test = test.as_table().ok_or("must be a table")?;
+        let text = |name: &str| test.get(name).and_then(Value::as_str).map(String::from);
+        let rom = text("rom").ok_or("needs a rom")?;
+        let frames = match test.get("frames").map(Value::as_integer) {
+            Some(Some(frames)) if frames > 0 => frames as u64,
+            None => 600,
+            _ => return Err(String::from("frames must be a positive number")),
+        };
+        let key = match test.get("key").map(Value::as_integer) {
+            Some(Some(key)) if (0..16).contains(&key) => Some(key as u8),
+            None => None,
+            _ => return Err(String::from("key must be 0 to 15")),
+        };
+        let quirks = text("quirks");
+        if let Some(list) = &quirks {
+            Quirks::default().parse(list)?;
+        }
+        Ok(Case {
+            name: text("name").unwrap_or_else(|| rom.clone()),
+            rom: dir.join(rom),
+            frames,
+            key,
+            quirks,
+            crc32: text("crc32").filter(|hash| !hash.is_empty()),
+        })
+    }
+
+    // Run a machine with the ROM loaded, as set up for any other run
+    pub fn check(&self, cpu: &mut Chip8) -> Outcome {
+        if let Some(Ok(quirks)) = self.quirks.as_ref().map(|list| cpu.quirks.parse(list)) {
+            cpu.quirks = quirks;
+        }
+        if let Some(key) = self.key {
+            cpu.input = key;
+        }
+        let hash = match screen_hash(cpu, self.frames) {
+            Ok(hash) => hash,
+            Err(fault) => return Outcome::Fault(fault),
+        };
+        match &self.crc32 {
+            Some(expected)
+                if expected
+                    .trim_start_matches("0x")
+                    .eq_ignore_ascii_case(&hash) =>
+            {
+                Outcome::Pass
+            }
+            Some(_) => Outcome::Fail(hash),
+            None => Outcome::New(hash),
+        }
+    }
+}
+
+// The crc32 of the screen after frames from seed 0, as chip8 test prints it,
+// or the fault that stopped it first
+pub fn screen_hash(cpu: &mut Chip8, frames: u64) -> Result<String, String> {
+    cpu.seed(0);
+    snapshot::run_for_frames(cpu, frames)?;
+    Ok(format!("{:08x}", crc32fast::hash(&cpu.video_buffer)))
+}
+
+// Write each test's hash into the manifest, in order, leaving the rest of the
+// file as it was. None leaves that test's hash alone.
+pub fn record(dir: &Path, hashes: &[Option<String>]) -> Result<(), String> {
+    let path = dir.join(MANIFEST);
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+    let mut document: toml_edit::DocumentMut = text
+        .parse()
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    if let Some(tests) = document
+        .get_mut("test")
+        .and_then(|tests| tests.as_array_of_tables_mut())
+    {
+        for (test, hash) in tests.iter_mut().zip(hashes) {
+            if let Some(hash) = hash {
+                test["crc32"] = toml_edit::value(hash.as_str());
+            }
+        }
+    }
+    std::fs::write(&path, document.to_string())
+        .map_err(|e| format!("Unable to write {}: {}", path.display(), e))
+}
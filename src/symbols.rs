@@ -0,0 +1,148 @@
+use log::error;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/*    Symbol Files
+ * Text, one label per line in either order, with # or ; comments:
+ *   main 0x200
+ *   0x20a draw_paddle
+ *   score = 0x300
+ *
+ * JSON, an object of names to addresses, optionally under "labels":
+ *   {"labels": {"main": 512, "draw_paddle": "0x20a"}}
+ */
+#[derive(Default)]
+pub struct Symbols {
+    by_name: HashMap<String, usize>,
+    by_addr: BTreeMap<usize, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Symbols, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        let parsed = match text.trim_start().starts_with('{') {
+            true => Symbols::parse_json(&text),
+            false => Symbols::parse_text(&text),
+        };
+        parsed.map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Symbol file next to the ROM, trying rom.sym then rom.json
+    pub fn find(rom_path: &Path) -> Option<Symbols> {
+        ["sym", "json"]
+            .iter()
+            .map(|extension| rom_path.with_extension(extension))
+            .find(|path| path.is_file() && path != rom_path)
+            .and_then(|path| match Symbols::load(&path) {
+                Ok(symbols) => Some(symbols),
+                Err(e) => {
+                    error!("{}", e);
+                    None
+                }
+            })
+    }
+
+    pub fn parse_text(text: &str) -> Result<Symbols, String> {
+        let mut symbols = Symbols::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line
+                .split(['#', ';'])
+                .next()
+                .unwrap_or("")
+                .replace('=', " ");
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let parsed = match fields.as_slice() {
+                [] => continue,
+                [first, second] => match (parse_number(first), parse_number(second)) {
+                    (None, Some(addr)) => Some((first, addr)),
+                    (Some(addr), None) => Some((second, addr)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            match parsed {
+                Some((name, addr)) => symbols.insert(name, addr),
+                None => {
+                    return Err(format!(
+                        "line {}: expected a name and an address",
+                        index + 1
+                    ))
+                }
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn parse_json(text: &str) -> Result<Symbols, String> {
+        let json: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        let labels = json
+            .get("labels")
+            .unwrap_or(&json)
+            .as_object()
+            .ok_or("expected an object of labels")?;
+        let mut symbols = Symbols::new();
+        for (name, value) in labels {
+            let addr = match value {
+                Value::Number(number) => number.as_u64().map(|addr| addr as usize),
+                Value::String(text) => parse_number(text),
+                _ => None,
+            };
+            match addr {
+                Some(addr) => symbols.insert(name, addr),
+                None => return Err(format!("invalid address for {}", name)),
+            }
+        }
+        Ok(symbols)
+    }
+
+    // The first name seen for an address is the one displayed
+    pub fn insert(&mut self, name: &str, addr: usize) {
+        self.by_name.insert(name.to_string(), addr);
+        self.by_addr.entry(addr).or_insert_with(|| name.to_string());
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    pub fn name_at(&self, addr: usize) -> Option<&str> {
+        self.by_addr.get(&addr).map(String::as_str)
+    }
+
+    pub fn addr_of(&self, name: &str) -> Option<usize> {
+        self.by_name.get(name).copied()
+    }
+
+    // Nearest label at or before addr, as name or name+offset
+    pub fn describe(&self, addr: usize) -> Option<String> {
+        self.by_addr
+            .range(..=addr)
+            .next_back()
+            .map(|(label, name)| match addr - label {
+                0 => name.clone(),
+                offset => format!("{}+{}", name, offset),
+            })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&usize, &String)> {
+        self.by_addr.iter()
+    }
+}
+
+// Symbol files write addresses as 0x hex or plain decimal
+fn parse_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
@@ -0,0 +1,65 @@
+use crate::chip8::Chip8;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/*    Telemetry
+ * --telemetry FILE writes a CSV row each time the frontend paces a 60 Hz
+ * frame, for looking into pacing and determinism with a spreadsheet or
+ * pandas:
+ *   frame,instructions,draws,sound_timer,state_hash
+ *   1,10,3,0,7f06e19b
+ *   2,10,4,0,c5d6b26a
+ *   5,30,7,0,1f11aed3
+ * frame counts 60 Hz frames from the start, including any the window was
+ * too late for, so a gap in it with more than speed instructions is the
+ * window catching up, and a gap with no more is frames it gave up on.
+ * instructions and draws count those executed since the row before,
+ * sound_timer is where the timer was left and state_hash is
+ * Chip8::state_hash() in hex, so two runs with the same keys can be diffed
+ * down to the frame they part at. Rows are written as they come, so a run
+ * that stops suddenly still leaves all of them.
+ */
+pub struct Telemetry {
+    file: File,
+    path: PathBuf,
+    // The counters at the last row
+    instructions: u64,
+    draws: u64,
+}
+
+impl Telemetry {
+    pub fn create(path: &Path) -> Result<Telemetry, String> {
+        let file =
+            File::create(path).map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+        let mut telemetry = Telemetry {
+            file,
+            path: path.to_path_buf(),
+            instructions: 0,
+            draws: 0,
+        };
+        telemetry.write("frame,instructions,draws,sound_timer,state_hash")?;
+        Ok(telemetry)
+    }
+
+    // Call at the end of each frame the frontend paces
+    pub fn record(&mut self, frame: u64, cpu: &Chip8) -> Result<(), String> {
+        // Counters go back on a reset or a rewind, which the row starts from
+        let row = format!(
+            "{},{},{},{},{:08x}",
+            frame,
+            cpu.instructions.saturating_sub(self.instructions),
+            cpu.draws.saturating_sub(self.draws),
+            cpu.sound_timer,
+            cpu.state_hash()
+        );
+        self.instructions = cpu.instructions;
+        self.draws = cpu.draws;
+        self.write(&row)
+    }
+
+    fn write(&mut self, line: &str) -> Result<(), String> {
+        writeln!(self.file, "{}", line)
+            .map_err(|e| format!("Unable to write {}: {}", self.path.display(), e))
+    }
+}
@@ -0,0 +1,129 @@
+use crate::chip8::Chip8;
+use crate::debugger::Debugger;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, Event, KeyCode, KeyEventKind, KeyModifiers, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use tracing::info_span;
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+// 60 Hz, matching the timers
+const FRAME: Duration = Duration::from_micros(16_667);
+// Terminals without key release events only repeat held keys, so a key
+// counts as held until its repeats stop for this long
+const KEY_HOLD: Duration = Duration::from_millis(150);
+
+// Runs until Esc or Ctrl-C, drawing two pixels per character cell with the
+// upper half block so the 64x32 display fits in 64x16 characters
+pub fn run(cpu: &mut Chip8, debugger: &mut Debugger) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    execute!(stdout, EnterAlternateScreen, Hide)?;
+    if releases {
+        execute!(
+            stdout,
+            PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES)
+        )?;
+    }
+    let result = run_frames(cpu, debugger, &mut stdout, releases);
+    // Always hand the terminal back, even when a frame failed
+    if releases {
+        execute!(stdout, PopKeyboardEnhancementFlags)?;
+    }
+    execute!(stdout, ResetColor, Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn run_frames(
+    cpu: &mut Chip8,
+    debugger: &mut Debugger,
+    out: &mut impl Write,
+    releases: bool,
+) -> io::Result<()> {
+    let mut held: Option<(u8, Instant)> = None;
+    cpu.redraw = true;
+    loop {
+        let frame_start = Instant::now();
+        let _frame = cpu.frame_span().entered();
+        while event::poll(Duration::ZERO)? {
+            match event::read()? {
+                Event::Key(key) => {
+                    let quit = key.code == KeyCode::Esc
+                        || (key.code == KeyCode::Char('c')
+                            && key.modifiers.contains(KeyModifiers::CONTROL));
+                    if quit {
+                        return Ok(());
+                    }
+                    let value = match key.code {
+                        KeyCode::Char(c) => cpu.keymap.key(c),
+                        _ => None,
+                    };
+                    match (value, key.kind) {
+                        (Some(value), KeyEventKind::Release) => {
+                            if held.map(|(held, _)| held) == Some(value) {
+                                held = None;
+                            }
+                        }
+                        (Some(value), _) => held = Some((value, Instant::now())),
+                        (None, _) => {}
+                    }
+                }
+                Event::Resize(_, _) => cpu.redraw = true,
+                _ => {}
+            }
+        }
+        if let Some((_, pressed)) = held {
+            if !releases && pressed.elapsed() > KEY_HOLD {
+                held = None;
+            }
+        }
+
+        cpu.input = held.map(|(value, _)| value).unwrap_or(0);
+        debugger.run(cpu, cpu.speed);
+        cpu.decrement_timers();
+        debugger.end_frame(cpu);
+        if cpu.redraw {
+            let _render = info_span!("render").entered();
+            draw(cpu, out)?;
+            cpu.redraw = false;
+        }
+        std::thread::sleep(FRAME.saturating_sub(frame_start.elapsed()));
+    }
+}
+
+fn draw(cpu: &Chip8, out: &mut impl Write) -> io::Result<()> {
+    let (color, background) = cpu.palette();
+    let pixel = |x: usize, y: usize| {
+        let rgba = match cpu.video_buffer[y * WIDTH + x] {
+            1 => color,
+            _ => background,
+        };
+        Color::Rgb {
+            r: rgba[0],
+            g: rgba[1],
+            b: rgba[2],
+        }
+    };
+    for row in 0..HEIGHT / 2 {
+        queue!(out, MoveTo(0, row as u16))?;
+        for x in 0..WIDTH {
+            queue!(
+                out,
+                SetForegroundColor(pixel(x, row * 2)),
+                SetBackgroundColor(pixel(x, row * 2 + 1)),
+                Print('\u{2580}')
+            )?;
+        }
+    }
+    queue!(out, ResetColor)?;
+    out.flush()
+}
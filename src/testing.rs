@@ -0,0 +1,196 @@
+use crate::chip8::{Chip8, EmulationStatus, MEMORY_SIZE};
+use crate::crash;
+use proptest::prelude::*;
+use proptest::test_runner::TestCaseError;
+use std::ops::Range;
+
+// Where a machine_at PC can be, leaving room for the opcode
+const PROGRAM: Range<usize> = 0x200..MEMORY_SIZE - 1;
+
+/*    Property Tests
+ * Strategies for machines and opcodes, and checks on what an instruction is
+ * allowed to change, e.g. for 8XY4 across every pair of registers:
+ *   proptest! {
+ *       #[test]
+ *       fn add_with_carry(mut cpu in testing::machine_at("8XY4")) {
+ *           let before = cpu.clone();
+ *           let (vx, vy) = testing::operands(&before);
+ *           cpu.tick();
+ *           let (sum, carry) = vx.overflowing_add(vy);
+ *           testing::result_and_flag(&before, &cpu, sum, carry as u8)?;
+ *           testing::only_changed(&before, &cpu, &["VX", "VF"])?;
+ *           testing::next_pc(&before, &cpu, 2)?;
+ *       }
+ *   }
+ *
+ * Patterns are the ones disasm::opcode_class gives, with X, Y, N, NN and
+ * NNN filled in at random and hex digits kept.
+ */
+pub fn register() -> impl Strategy<Value = usize> {
+    0..16usize
+}
+
+pub fn nnn() -> impl Strategy<Value = u16> {
+    0..0x1000u16
+}
+
+pub fn nn() -> impl Strategy<Value = u8> {
+    any::<u8>()
+}
+
+pub fn n() -> impl Strategy<Value = u8> {
+    0..16u8
+}
+
+// Any opcode matching the pattern, e.g. 0x8a34 for "8XY4"
+pub fn opcode(pattern: &'static str) -> impl Strategy<Value = u16> {
+    any::<u16>().prop_map(move |bits| fill(pattern, bits))
+}
+
+fn fill(pattern: &str, bits: u16) -> u16 {
+    pattern.chars().enumerate().fold(0, |opcode, (i, c)| {
+        let shift = 12 - 4 * i as u16;
+        let nibble = match c.to_digit(16) {
+            Some(digit) => digit as u16,
+            None => (bits >> shift) & 0x0F,
+        };
+        opcode | nibble << shift
+    })
+}
+
+// A machine in any state a program could leave it in: registers, I, timers,
+// the stack, memory after the font and the display. I can be anywhere, so
+// DXYN, FX33, FX55 and FX65 may reach past the end of memory from it.
+pub fn machine() -> impl Strategy<Value = Chip8> {
+    (
+        any::<[u8; 16]>(),
+        0..MEMORY_SIZE as u16,
+        any::<(u8, u8)>(),
+        prop::collection::vec(PROGRAM.prop_map(|addr| addr & !1), 0..12),
+        prop::collection::vec(any::<u8>(), MEMORY_SIZE - 0x200),
+        prop::collection::vec(0..2u8, 64 * 32),
+    )
+        .prop_map(|(registers, i, timers, stack, memory, display)| {
+            let mut cpu = Chip8::new();
+            cpu.seed(0);
+            cpu.load_font();
+            cpu.registers = registers;
+            cpu.address_reg = i;
+            (cpu.delay_timer, cpu.sound_timer) = timers;
+            // Entry 0 is never used, calls push from 1
+            cpu.sp = stack.len();
+            for (entry, addr) in stack.into_iter().enumerate() {
+                cpu.stack[entry + 1] = addr;
+            }
+            cpu.memory[0x200..].copy_from_slice(&memory);
+            cpu.video_buffer.copy_from_slice(&display);
+            cpu
+        })
+}
+
+// A machine about to run an instruction matching the pattern, leaving out
+// the ones crash::fault says it can't carry out
+pub fn machine_at(pattern: &'static str) -> impl Strategy<Value = Chip8> {
+    (machine(), PROGRAM, opcode(pattern))
+        .prop_map(|(mut cpu, pc, opcode)| {
+            cpu.pc = pc;
+            cpu.memory[pc..pc + 2].copy_from_slice(&opcode.to_be_bytes());
+            cpu
+        })
+        .prop_filter("the instruction faults", |cpu| crash::fault(cpu).is_none())
+}
+
+// The values of VX and VY the instruction at the PC names
+pub fn operands(cpu: &Chip8) -> (u8, u8) {
+    let opcode = cpu.opcode_at(cpu.pc) as usize;
+    (
+        cpu.registers[(opcode & 0x0F00) >> 8],
+        cpu.registers[(opcode & 0x00F0) >> 4],
+    )
+}
+
+// The parts of the machine that differ, named as only_changed takes them
+pub fn changed(before: &Chip8, after: &Chip8) -> Vec<String> {
+    let mut names: Vec<String> = (0..16)
+        .filter(|&i| before.registers[i] != after.registers[i])
+        .map(|i| format!("V{:X}", i))
+        .collect();
+    let others = [
+        ("I", before.address_reg != after.address_reg),
+        ("SP", before.sp != after.sp),
+        ("stack", before.stack != after.stack),
+        ("DT", before.delay_timer != after.delay_timer),
+        ("ST", before.sound_timer != after.sound_timer),
+        ("memory", before.memory != after.memory),
+        ("display", before.video_buffer != after.video_buffer),
+        ("status", waiting(before) != waiting(after)),
+    ];
+    names.extend(
+        others
+            .iter()
+            .filter(|(_, differs)| *differs)
+            .map(|(name, _)| name.to_string()),
+    );
+    names
+}
+
+fn waiting(cpu: &Chip8) -> bool {
+    matches!(cpu.status, EmulationStatus::WaitingForKey)
+}
+
+// Fails unless everything that changed is in allowed, where VX and VY stand
+// for the registers the instruction at before's PC names. The PC and
+// instruction count always change, so aren't checked.
+pub fn only_changed(before: &Chip8, after: &Chip8, allowed: &[&str]) -> Result<(), TestCaseError> {
+    let opcode = before.opcode_at(before.pc);
+    let allowed: Vec<String> = allowed
+        .iter()
+        .map(|name| match *name {
+            "VX" => format!("V{:X}", (opcode & 0x0F00) >> 8),
+            "VY" => format!("V{:X}", (opcode & 0x00F0) >> 4),
+            name => name.to_string(),
+        })
+        .collect();
+    let unexpected: Vec<String> = changed(before, after)
+        .into_iter()
+        .filter(|name| !allowed.contains(name))
+        .collect();
+    prop_assert!(
+        unexpected.is_empty(),
+        "{:04X} at {:#05x} changed {}",
+        opcode,
+        before.pc,
+        unexpected.join(", ")
+    );
+    Ok(())
+}
+
+// Fails unless VX holds result and VF holds flag, for the instructions
+// that set both. With VF as VX the flag is written last, so only it's checked.
+pub fn result_and_flag(
+    before: &Chip8,
+    after: &Chip8,
+    result: u8,
+    flag: u8,
+) -> Result<(), TestCaseError> {
+    let opcode = before.opcode_at(before.pc);
+    let x = (opcode as usize & 0x0F00) >> 8;
+    if x != 0xF {
+        prop_assert_eq!(after.registers[x], result, "{:04X} left V{:X}", opcode, x);
+    }
+    prop_assert_eq!(after.registers[0xF], flag, "{:04X} left VF", opcode);
+    Ok(())
+}
+
+// Fails unless the PC moved on by bytes, 2 for the next instruction and 4
+// for a skip
+pub fn next_pc(before: &Chip8, after: &Chip8, bytes: usize) -> Result<(), TestCaseError> {
+    prop_assert_eq!(
+        after.pc,
+        before.pc + bytes,
+        "{:04X} at {:#05x}",
+        before.opcode_at(before.pc),
+        before.pc
+    );
+    Ok(())
+}
@@ -0,0 +1,50 @@
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
+use tracing_subscriber::prelude::*;
+
+// How often what's been recorded reaches the file, so a run that gets killed
+// still leaves most of its timeline
+const FLUSH_EVERY: Duration = Duration::from_secs(1);
+
+/*    Timelines
+ * --timeline FILE writes Chrome's trace event JSON, which ui.perfetto.dev and
+ * chrome://tracing open, with a span for each of:
+ *   frame    one 60 Hz update, with the machine's instruction count
+ *   batch    instructions run back to back, with the PC and opcode of the
+ *            first and how many there were
+ *   render   drawing the display and handing it over to be shown
+ *
+ * Frames and batches come from the core and every frontend, render from the
+ * window and SDL. The file is finished when the emulator exits normally.
+ */
+pub struct Timeline {
+    guard: FlushGuard,
+    flushed: Instant,
+}
+
+impl Timeline {
+    pub fn start(path: &Path) -> Result<Timeline, String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+        let (layer, guard) = ChromeLayerBuilder::new()
+            .writer(file)
+            .include_args(true)
+            .build();
+        // Not try_init, which would take over the log crate from the console
+        tracing::subscriber::set_global_default(tracing_subscriber::registry().with(layer))
+            .map_err(|e| e.to_string())?;
+        Ok(Timeline {
+            guard,
+            flushed: Instant::now(),
+        })
+    }
+
+    pub fn poll(&mut self) {
+        if self.flushed.elapsed() >= FLUSH_EVERY {
+            self.guard.flush();
+            self.flushed = Instant::now();
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use crate::chip8::Chip8;
+use crate::disasm::disassemble;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+// One executed instruction and everything it changed
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub addr: usize,
+    pub opcode: u16,
+    pub registers: Vec<(String, usize, usize)>, // Name, old, new
+    pub memory: Vec<(usize, u8, u8)>,           // Address, old, new
+}
+
+impl TraceEntry {
+    pub fn new(before: &Chip8, after: &Chip8) -> TraceEntry {
+        let mut registers = Vec::new();
+        for i in 0..before.registers.len() {
+            if before.registers[i] != after.registers[i] {
+                registers.push((
+                    format!("V{:X}", i),
+                    before.registers[i] as usize,
+                    after.registers[i] as usize,
+                ));
+            }
+        }
+        let others = [
+            ("I", before.address_reg as usize, after.address_reg as usize),
+            ("SP", before.sp, after.sp),
+            (
+                "DT",
+                before.delay_timer as usize,
+                after.delay_timer as usize,
+            ),
+            (
+                "ST",
+                before.sound_timer as usize,
+                after.sound_timer as usize,
+            ),
+        ];
+        for (name, old, new) in others {
+            if old != new {
+                registers.push((name.to_string(), old, new));
+            }
+        }
+        let memory = before
+            .memory
+            .iter()
+            .zip(after.memory.iter())
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(addr, (old, new))| (addr, *old, *new))
+            .collect();
+        TraceEntry {
+            addr: before.pc,
+            opcode: before.opcode_at(before.pc),
+            registers,
+            memory,
+        }
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:#05x}: {:04x}  {:<18}",
+            self.addr,
+            self.opcode,
+            disassemble(self.opcode)
+        )?;
+        for (name, old, new) in &self.registers {
+            write!(f, " {}:{:x}->{:x}", name, old, new)?;
+        }
+        for (addr, old, new) in &self.memory {
+            write!(f, " [{:#05x}]:{:02x}->{:02x}", addr, old, new)?;
+        }
+        Ok(())
+    }
+}
+
+// Keeps the most recent entries in memory and optionally streams them to a file
+pub struct Tracer {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+    file: Option<BufWriter<File>>,
+    range: Option<(usize, usize)>, // Inclusive address filter
+}
+
+impl Tracer {
+    pub fn new(capacity: usize) -> Tracer {
+        Tracer {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            file: None,
+            range: None,
+        }
+    }
+
+    pub fn record(&mut self, before: &Chip8, after: &Chip8) {
+        if let Some((start, end)) = self.range {
+            if before.pc < start || before.pc > end {
+                return;
+            }
+        }
+        let entry = TraceEntry::new(before, after);
+        if let Some(file) = &mut self.file {
+            if let Err(e) = writeln!(file, "{}", entry) {
+                log::error!("Trace file write failed: {}", e);
+                self.file = None;
+            }
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn open_file(&mut self, path: &str) -> std::io::Result<()> {
+        self.file = Some(BufWriter::new(File::create(path)?));
+        Ok(())
+    }
+
+    pub fn close_file(&mut self) {
+        if let Some(mut file) = self.file.take() {
+            let _ = file.flush();
+        }
+    }
+
+    pub fn set_range(&mut self, range: Option<(usize, usize)>) {
+        self.range = range;
+    }
+
+    // Up to count of the newest entries, oldest first
+    pub fn last(&self, count: usize) -> impl Iterator<Item = &TraceEntry> {
+        self.entries
+            .iter()
+            .skip(self.entries.len().saturating_sub(count))
+    }
+}
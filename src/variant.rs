@@ -0,0 +1,75 @@
+use crate::chip8::Quirks;
+use std::fmt;
+
+// Interpreter families, each a superset of the one before
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Variant {
+    #[default]
+    Chip8,
+    SChip,
+    XoChip,
+}
+
+impl Variant {
+    pub fn parse(name: &str) -> Option<Variant> {
+        match name.to_ascii_lowercase().as_str() {
+            "chip8" | "chip-8" | "vip" => Some(Variant::Chip8),
+            "schip" | "superchip" | "super-chip" => Some(Variant::SChip),
+            "xochip" | "xo-chip" => Some(Variant::XoChip),
+            _ => None,
+        }
+    }
+
+    pub fn supports(self, required: Variant) -> bool {
+        required <= self
+    }
+
+    // End of the memory a program may use. The VIP interpreter keeps its
+    // stack, variables and display buffer in the top 352 bytes.
+    pub fn memory_end(self) -> usize {
+        match self {
+            Variant::Chip8 => 0xEA0,
+            Variant::SChip => 0x1000,
+            Variant::XoChip => 0x10000,
+        }
+    }
+
+    // How the usual interpreter for each family behaves: the COSMAC VIP,
+    // SCHIP 1.1 on the HP48 and Octo
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Variant::Chip8 => Quirks {
+                vf_reset: true,
+                shift_vy: true,
+                increment_i: true,
+                jump_vx: false,
+                clip: true,
+            },
+            Variant::SChip => Quirks {
+                vf_reset: false,
+                shift_vy: false,
+                increment_i: false,
+                jump_vx: true,
+                clip: true,
+            },
+            Variant::XoChip => Quirks {
+                vf_reset: false,
+                shift_vy: true,
+                increment_i: true,
+                jump_vx: false,
+                clip: false,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Variant::Chip8 => "CHIP-8",
+            Variant::SChip => "SCHIP",
+            Variant::XoChip => "XO-CHIP",
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -0,0 +1,142 @@
+use crate::chip8::Chip8;
+use crate::snapshot;
+use crate::state;
+use crate::trace::TraceEntry;
+use crc32fast::Hasher;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::thread;
+
+// Frames either side may run ahead of the comparison
+const AHEAD: usize = 60;
+// Chance in a frame of the held key changing
+const KEY_CHANGE: f64 = 1.0 / 20.0;
+
+/*    Determinism Check
+ * Two copies of the machine run the same frames on their own threads, from
+ * the same seed and with the same keys pressed, and after every frame each
+ * folds its state hash and next random number into a rolling crc32:
+ *   straight     runs as the window does
+ *   round trip   also saves and reloads its state every frame, as rewind,
+ *                replay and netplay resyncs do
+ *
+ * The first frame the hashes differ is reported with what differs between
+ * the two machines. Keys come from the seed too, changing now and then, so
+ * games get past their title screens. A run stops at an instruction that
+ * can't run, see crash::fault, and both stopping at the same one on the same
+ * frame is as deterministic as running to the end.
+ */
+pub struct Verified {
+    pub hash: u32,
+    // Frames run, fewer than asked for if both stopped at a fault
+    pub frames: u64,
+    pub fault: Option<String>,
+}
+
+// A frame's rolling hash, save state and the fault that ended the run there
+type Frame = (u32, Vec<u8>, Option<String>);
+
+pub fn verify(cpu: &Chip8, frames: u64, seed: u64) -> Result<Verified, String> {
+    thread::scope(|scope| {
+        let (straight_send, straight) = mpsc::sync_channel(AHEAD);
+        let (round_trip_send, round_trip) = mpsc::sync_channel(AHEAD);
+        scope.spawn(|| run(cpu.clone(), frames, seed, false, straight_send));
+        scope.spawn(|| run(cpu.clone(), frames, seed, true, round_trip_send));
+        compare(straight, round_trip)
+    })
+}
+
+// Sends each frame's rolling hash and save state until done, a fault or the
+// other end stops listening
+fn run(mut cpu: Chip8, frames: u64, seed: u64, round_trip: bool, results: SyncSender<Frame>) {
+    cpu.seed(seed);
+    let mut keys = StdRng::seed_from_u64(seed);
+    let mut hash = 0;
+    for _ in 0..frames {
+        if keys.gen_bool(KEY_CHANGE) {
+            cpu.input = match keys.gen_bool(0.5) {
+                true => keys.gen_range(0..16),
+                false => 0,
+            };
+        }
+        let fault = snapshot::run_for_frames(&mut cpu, 1).err();
+        let mut bytes = state::save(&cpu);
+        if round_trip {
+            // Loading back what was just saved can't fail
+            let _ = state::load(&mut cpu, &bytes);
+            bytes = state::save(&cpu);
+        }
+        let mut hasher = Hasher::new_with_initial(hash);
+        hasher.update(&cpu.state_hash().to_be_bytes());
+        hasher.update(&cpu.rng.clone().gen::<u64>().to_be_bytes());
+        hash = hasher.finalize();
+        let stop = fault.is_some();
+        if results.send((hash, bytes, fault)).is_err() || stop {
+            return;
+        }
+    }
+}
+
+// The final hash, or the first frame the two differ and how
+fn compare(straight: Receiver<Frame>, round_trip: Receiver<Frame>) -> Result<Verified, String> {
+    let mut verified = Verified {
+        hash: 0,
+        frames: 0,
+        fault: None,
+    };
+    for (frame, (a, b)) in straight.iter().zip(round_trip.iter()).enumerate() {
+        if a.0 != b.0 || a.2 != b.2 {
+            return Err(divergence(frame as u64 + 1, &a, &b));
+        }
+        verified.hash = a.0;
+        verified.frames = frame as u64 + 1;
+        verified.fault = a.2;
+    }
+    Ok(verified)
+}
+
+fn divergence(frame: u64, a: &Frame, b: &Frame) -> String {
+    let mut lines = vec![format!(
+        "Diverged at frame {}, rolling hash {:08x} vs {:08x}",
+        frame, a.0, b.0
+    )];
+    for (run, fault) in [("Straight", &a.2), ("Round trip", &b.2)] {
+        if let Some(fault) = fault {
+            lines.push(format!("  {} stopped: {}", run, fault));
+        }
+    }
+    let mut straight = Chip8::new();
+    let mut round_trip = Chip8::new();
+    if state::load(&mut straight, &a.1).is_err() || state::load(&mut round_trip, &b.1).is_err() {
+        return lines.join("\n");
+    }
+    if straight.pc != round_trip.pc {
+        lines.push(format!(
+            "  PC {:#05x} vs {:#05x}",
+            straight.pc, round_trip.pc
+        ));
+    }
+    let entry = TraceEntry::new(&straight, &round_trip);
+    for (name, old, new) in &entry.registers {
+        lines.push(format!("  {} {:#04x} vs {:#04x}", name, old, new));
+    }
+    for (addr, old, new) in &entry.memory {
+        lines.push(format!("  [{:#05x}] {:#04x} vs {:#04x}", addr, old, new));
+    }
+    let pixels = straight
+        .video_buffer
+        .iter()
+        .zip(round_trip.video_buffer.iter())
+        .filter(|(a, b)| a != b)
+        .count();
+    if pixels > 0 {
+        lines.push(format!("  {} pixels differ", pixels));
+    }
+    if lines.len() == 1 {
+        lines.push(String::from(
+            "  The states match, so it's the random number generator",
+        ));
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,65 @@
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::time::{Duration, Instant};
+
+// Quiet time after the last write before reloading, so a file written in
+// pieces isn't read half done
+const SETTLE: Duration = Duration::from_millis(100);
+
+// Notices a ROM or source file being rewritten. The folder is watched rather
+// than the file, as many editors and assemblers replace the file instead of
+// writing into it.
+pub struct RomWatcher {
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    name: PathBuf,
+    changed: Option<Instant>,
+}
+
+impl RomWatcher {
+    pub fn new(rom_path: &Path) -> Result<RomWatcher, String> {
+        let name = rom_path
+            .file_name()
+            .ok_or_else(|| format!("{} is not a file", rom_path.display()))?;
+        let dir = match rom_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(|e| e.to_string())?;
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("Unable to watch {}: {}", dir.display(), e))?;
+        Ok(RomWatcher {
+            _watcher: watcher,
+            events,
+            name: PathBuf::from(name),
+            changed: None,
+        })
+    }
+
+    // True once the file has been written and left alone for a moment
+    pub fn poll(&mut self) -> bool {
+        while let Ok(event) = self.events.try_recv() {
+            let written = event.is_ok_and(|event| {
+                matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                    && event
+                        .paths
+                        .iter()
+                        .any(|path| path.file_name() == Some(self.name.as_os_str()))
+            });
+            if written {
+                self.changed = Some(Instant::now());
+            }
+        }
+        match self.changed {
+            Some(changed) if changed.elapsed() >= SETTLE => {
+                self.changed = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
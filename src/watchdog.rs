@@ -0,0 +1,62 @@
+use crate::chip8::{Chip8, EmulationStatus};
+use crate::crash;
+use std::time::{Duration, Instant};
+
+// How often the display is looked at
+const CHECK_EVERY: Duration = Duration::from_millis(100);
+
+/*    Watchdog
+ * For kiosks left on their own. A ROM that has crashed, halted or hung shows
+ * the same display with nobody pressing anything, and once that has gone on
+ * for the timeout the emulator starts it again, or moves on to the next ROM
+ * in attract mode or a playlist. It's counted on the clock, as a crash stops
+ * the machine, but time paused other than at a crash doesn't count, and nor
+ * does waiting for a key with FX0A.
+ */
+pub struct Watchdog {
+    timeout: Duration,
+    // crc32 of the display when last looked at
+    screen: u32,
+    instructions: u64,
+    quiet_since: Instant,
+    checked: Instant,
+}
+
+impl Watchdog {
+    pub fn new(timeout: Duration) -> Watchdog {
+        Watchdog {
+            timeout,
+            screen: 0,
+            instructions: 0,
+            quiet_since: Instant::now(),
+            checked: Instant::now(),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    // True once the machine looks stuck, after which the timeout starts again
+    pub fn check(&mut self, cpu: &Chip8) -> bool {
+        if self.checked.elapsed() < CHECK_EVERY {
+            return false;
+        }
+        self.checked = Instant::now();
+        let screen = crc32fast::hash(&cpu.video_buffer);
+        let stopped = cpu.instructions == self.instructions && crash::fault(cpu).is_none();
+        let waiting = matches!(cpu.status, EmulationStatus::WaitingForKey);
+        if screen != self.screen || cpu.input != 0 || stopped || waiting {
+            self.quiet_since = Instant::now();
+        }
+        self.screen = screen;
+        self.instructions = cpu.instructions;
+        match self.quiet_since.elapsed() >= self.timeout {
+            true => {
+                self.quiet_since = Instant::now();
+                true
+            }
+            false => false,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::chip8::Chip8;
+use crate::crash;
+use crate::keypad::from_qwerty;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::Clamped;
+use web_sys::{CanvasRenderingContext2d, ImageData};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 32;
+// Instructions per animation frame, about 600 a second at 60 frames
+const CYCLES_PER_FRAME: usize = 10;
+
+// Browser frontend driven from requestAnimationFrame, see www/index.js
+#[wasm_bindgen]
+pub struct WebChip8 {
+    cpu: Chip8,
+    frame: Vec<u8>,
+    key: Option<u8>,
+    // Why the program stopped, it doesn't run again until a ROM is loaded
+    fault: Option<String>,
+}
+
+#[wasm_bindgen]
+impl WebChip8 {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WebChip8 {
+        let mut cpu = Chip8::new();
+        cpu.load_font();
+        WebChip8 {
+            cpu,
+            frame: vec![0; (WIDTH * HEIGHT * 4) as usize],
+            key: None,
+            fault: None,
+        }
+    }
+
+    // Start over with a ROM picked from the file input
+    pub fn load_rom(&mut self, rom: &[u8]) {
+        self.cpu = Chip8::new();
+        self.cpu.load_font();
+        self.cpu.load_program(rom);
+        self.cpu.redraw = true;
+        self.fault = None;
+    }
+
+    // What stopped the program, for the page to show
+    pub fn fault(&self) -> Option<String> {
+        self.fault.clone()
+    }
+
+    // KeyboardEvent.code, laid out like the desktop key mapping
+    pub fn key_down(&mut self, code: &str) {
+        if let Some(key) = keypad(code) {
+            self.key = Some(key);
+        }
+    }
+
+    pub fn key_up(&mut self, code: &str) {
+        if self.key.is_some() && keypad(code) == self.key {
+            self.key = None;
+        }
+    }
+
+    // Run one frame's worth of instructions, tick the timers and draw
+    pub fn frame(&mut self, context: &CanvasRenderingContext2d) -> Result<(), JsValue> {
+        if self.cpu.rom_size == 0 || self.fault.is_some() {
+            return Ok(());
+        }
+        self.cpu.input = self.key.unwrap_or(0);
+        for _ in 0..CYCLES_PER_FRAME {
+            // An instruction the core can't carry out would panic, and the
+            // whole page with it
+            if let Some(fault) = crash::fault(&self.cpu) {
+                self.fault = Some(format!("Stopped at {:#05x}: {}", self.cpu.pc, fault));
+                break;
+            }
+            self.cpu.tick();
+        }
+        self.cpu.decrement_timers();
+        if self.cpu.redraw {
+            self.cpu.draw(&mut self.frame);
+            let image =
+                ImageData::new_with_u8_clamped_array_and_sh(Clamped(&self.frame), WIDTH, HEIGHT)?;
+            context.put_image_data(&image, 0.0, 0.0)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for WebChip8 {
+    fn default() -> WebChip8 {
+        WebChip8::new()
+    }
+}
+
+// KeyboardEvent.code names keys by position, e.g. "KeyQ" or "Digit1"
+fn keypad(code: &str) -> Option<u8> {
+    let name = code
+        .strip_prefix("Key")
+        .or_else(|| code.strip_prefix("Digit"))?;
+    match name.chars().collect::<Vec<char>>().as_slice() {
+        [key] => from_qwerty(*key),
+        _ => None,
+    }
+}
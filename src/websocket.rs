@@ -0,0 +1,378 @@
+use crate::chip8::Chip8;
+use crate::debugger::Debugger;
+use log::{error, info};
+use serde_json::{json, Value};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+// 60 Hz, matching the timers
+const FRAME: Duration = Duration::from_micros(16_667);
+// Fixed by RFC 6455 for the handshake
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xA;
+// Close codes for a frame that breaks the protocol and one too big to take
+const CLOSE_PROTOCOL: u16 = 1002;
+const CLOSE_TOO_BIG: u16 = 1009;
+// The most taken from a viewer, its handshake and then each message, which
+// are only ever small JSON
+const MAX_REQUEST: usize = 4096;
+const MAX_MESSAGE: usize = 1024;
+// Bytes waiting for a viewer that reads too slowly, about a second of frames,
+// before it's dropped
+const MAX_QUEUED: usize = 32 * 1024;
+
+/*    Streaming Protocol
+ * Server -> client  text, {"read_only": false} on connect
+ *                   binary, 256 bytes: the 64x32 display at 1 bit per pixel,
+ *                   row by row, most significant bit leftmost. Sent on
+ *                   connect and after every redraw.
+ *                   text, {"crc32": 1234} of the binary message before it,
+ *                   to check every viewer sees the same thing
+ * Client -> server  text, {"key": 5} holds a key 0-F, {"key": null} lets go.
+ *                   Ignored when broadcasting read only. Frames must be
+ *                   masked and at most MAX_MESSAGE bytes, or the server
+ *                   closes with 1002 or 1009.
+ *
+ * www/stream.html is a viewer for it.
+ */
+struct Client {
+    stream: TcpStream,
+    buffer: Vec<u8>,
+    // Sent but not yet taken by the socket, written on later polls
+    queued: Vec<u8>,
+    // Upgraded from HTTP yet
+    open: bool,
+}
+
+// Polled like the debugger servers, so it works next to a window or headless
+pub struct WebSocketServer {
+    listener: TcpListener,
+    clients: Vec<Client>,
+    // Held by a remote client, overrides the local keyboard
+    key: Option<u8>,
+    // Spectators only watch, e.g. a class following a demo
+    pub read_only: bool,
+}
+
+impl WebSocketServer {
+    pub fn bind(address: &str) -> io::Result<WebSocketServer> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        info!("Streaming over WebSocket on ws://{}", address);
+        Ok(WebSocketServer {
+            listener,
+            clients: Vec::new(),
+            key: None,
+            read_only: false,
+        })
+    }
+
+    // Accept viewers, apply their keys and send them the display if it changed
+    pub fn poll(&mut self, cpu: &mut Chip8) {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, peer)) => match stream.set_nonblocking(true) {
+                    Ok(()) => {
+                        info!("WebSocket viewer connected from {}", peer);
+                        self.clients.push(Client {
+                            stream,
+                            buffer: Vec::new(),
+                            queued: Vec::new(),
+                            open: false,
+                        });
+                    }
+                    Err(e) => error!("WebSocket connection failed: {}", e),
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    error!("WebSocket accept failed: {}", e);
+                    break;
+                }
+            }
+        }
+        let frame = pack(cpu);
+        let mut key = self.key;
+        let read_only = self.read_only;
+        self.clients
+            .retain_mut(|client| match client.update(&frame, &mut key, read_only) {
+                Ok(connected) => connected,
+                Err(e) => {
+                    info!("WebSocket viewer dropped: {}", e);
+                    false
+                }
+            });
+        self.key = key;
+        if let Some(key) = self.key {
+            cpu.input = key;
+        }
+        if cpu.redraw {
+            let hash = json!({ "crc32": crc32fast::hash(&frame) }).to_string();
+            for client in self.clients.iter_mut().filter(|client| client.open) {
+                client.send(OP_BINARY, &frame);
+                client.send(OP_TEXT, hash.as_bytes());
+            }
+        }
+        self.clients.retain_mut(|client| match client.flush() {
+            Ok(()) => true,
+            Err(e) => {
+                info!("WebSocket viewer dropped: {}", e);
+                false
+            }
+        });
+    }
+}
+
+impl Client {
+    // Read what's arrived, false once the viewer has gone
+    fn update(&mut self, frame: &[u8], key: &mut Option<u8>, read_only: bool) -> io::Result<bool> {
+        // A whole message with its header, or the handshake, and no more
+        let limit = match self.open {
+            true => MAX_MESSAGE + 14,
+            false => MAX_REQUEST,
+        };
+        let mut chunk = [0; 1024];
+        while self.buffer.len() <= limit {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Ok(false),
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if !self.open {
+            let end = match find(&self.buffer, b"\r\n\r\n") {
+                Some(end) => end,
+                None if self.buffer.len() > MAX_REQUEST => {
+                    return Err(io::Error::new(ErrorKind::InvalidData, "handshake too long"))
+                }
+                None => return Ok(true),
+            };
+            let request = String::from_utf8_lossy(&self.buffer[..end]).to_string();
+            self.buffer.drain(..end + 4);
+            self.handshake(&request)?;
+            self.open = true;
+            let hello = json!({ "read_only": read_only }).to_string();
+            self.send(OP_TEXT, hello.as_bytes());
+            self.send(OP_BINARY, frame);
+        }
+        loop {
+            let (opcode, payload) = match self.message() {
+                Ok(Some(message)) => message,
+                Ok(None) => break,
+                Err(code) => {
+                    self.send(OP_CLOSE, &code.to_be_bytes());
+                    let _ = self.flush();
+                    return Ok(false);
+                }
+            };
+            match opcode {
+                OP_TEXT if read_only => {}
+                OP_TEXT => match serde_json::from_slice::<Value>(&payload) {
+                    Ok(message) => match &message["key"] {
+                        Value::Null => *key = None,
+                        value => match value.as_u64() {
+                            Some(value) if value <= 0xF => *key = Some(value as u8),
+                            _ => error!("WebSocket key out of range: {}", value),
+                        },
+                    },
+                    Err(e) => error!("Invalid WebSocket message: {}", e),
+                },
+                OP_PING => self.send(OP_PONG, &payload),
+                OP_CLOSE => {
+                    self.send(OP_CLOSE, &[]);
+                    let _ = self.flush();
+                    return Ok(false);
+                }
+                _ => {}
+            }
+        }
+        Ok(true)
+    }
+
+    // A GET asking to upgrade to a WebSocket, anything else gets a 400
+    fn handshake(&mut self, request: &str) -> io::Result<()> {
+        let header = |wanted: &str| {
+            request
+                .lines()
+                .skip(1)
+                .filter_map(|line| line.split_once(':'))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case(wanted))
+                .map(|(_, value)| value.trim())
+        };
+        let upgrade =
+            header("Upgrade").is_some_and(|value| value.eq_ignore_ascii_case("websocket"));
+        let key = match header("Sec-WebSocket-Key") {
+            Some(key) if request.starts_with("GET ") && upgrade => key,
+            _ => {
+                let _ = self
+                    .stream
+                    .write_all(b"HTTP/1.1 400 Bad Request\r\nConnection: close\r\n\r\n");
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    "not a WebSocket request",
+                ));
+            }
+        };
+        let digest = sha1_smol::Sha1::from(format!("{}{}", key, ACCEPT_GUID)).digest();
+        let response = format!(
+            "HTTP/1.1 101 Switching Protocols\r\n\
+             Upgrade: websocket\r\n\
+             Connection: Upgrade\r\n\
+             Sec-WebSocket-Accept: {}\r\n\r\n",
+            base64(&digest.bytes())
+        );
+        self.queued.extend_from_slice(response.as_bytes());
+        Ok(())
+    }
+
+    // Take one complete client message off the buffer, none until it's all
+    // arrived. Client frames must be masked, and the messages here are small
+    // enough to be unfragmented. A frame that breaks either gives the close
+    // code to send.
+    fn message(&mut self) -> Result<Option<(u8, Vec<u8>)>, u16> {
+        let (opcode, masked, length, position) = match header(&self.buffer) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        if !masked {
+            return Err(CLOSE_PROTOCOL);
+        }
+        if length > MAX_MESSAGE as u64 {
+            return Err(CLOSE_TOO_BIG);
+        }
+        let (length, end) = (length as usize, position + 4 + length as usize);
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+        let mask = [
+            self.buffer[position],
+            self.buffer[position + 1],
+            self.buffer[position + 2],
+            self.buffer[position + 3],
+        ];
+        let payload = self.buffer[end - length..end]
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4])
+            .collect();
+        self.buffer.drain(..end);
+        Ok(Some((opcode, payload)))
+    }
+
+    // Write what the socket takes now and keep the rest for the next poll
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.queued.is_empty() {
+            match self.stream.write(&self.queued) {
+                Ok(0) => return Err(io::Error::new(ErrorKind::WriteZero, "connection closed")),
+                Ok(len) => {
+                    self.queued.drain(..len);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        match self.queued.len() > MAX_QUEUED {
+            true => Err(io::Error::new(
+                ErrorKind::TimedOut,
+                "not keeping up with the stream",
+            )),
+            false => Ok(()),
+        }
+    }
+
+    // Server frames are queued unmasked in one piece
+    fn send(&mut self, opcode: u8, payload: &[u8]) {
+        let mut frame = vec![0x80 | opcode];
+        match payload.len() {
+            length if length < 126 => frame.push(length as u8),
+            length if length <= 0xFFFF => {
+                frame.push(126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                frame.push(127);
+                frame.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        self.queued.extend_from_slice(&frame);
+    }
+}
+
+// A frame's opcode, whether it's masked, its payload length and where its
+// mask starts, once that much has arrived
+fn header(buffer: &[u8]) -> Option<(u8, bool, u64, usize)> {
+    let (first, second) = (*buffer.first()?, *buffer.get(1)?);
+    let (length, position) = match second & 0x7F {
+        126 => (
+            u16::from_be_bytes(buffer.get(2..4)?.try_into().ok()?) as u64,
+            4,
+        ),
+        127 => (u64::from_be_bytes(buffer.get(2..10)?.try_into().ok()?), 10),
+        length => (length as u64, 2),
+    };
+    Some((first & 0x0F, second & 0x80 != 0, length, position))
+}
+
+// The display at 1 bit per pixel
+fn pack(cpu: &Chip8) -> Vec<u8> {
+    cpu.video_buffer
+        .chunks(8)
+        .map(|pixels| {
+            pixels
+                .iter()
+                .fold(0, |byte, pixel| (byte << 1) | (*pixel & 1))
+        })
+        .collect()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - i * 8));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => text.push(ALPHABET[(n >> (18 - i * 6) & 0x3F) as usize] as char),
+                false => text.push('='),
+            }
+        }
+    }
+    text
+}
+
+// Headless server mode, running in real time for the viewers until killed
+pub fn serve(
+    cpu: &mut Chip8,
+    debugger: &mut Debugger,
+    address: &str,
+    read_only: bool,
+) -> io::Result<()> {
+    let mut server = WebSocketServer::bind(address)?;
+    server.read_only = read_only;
+    loop {
+        let frame_start = Instant::now();
+        let _frame = cpu.frame_span().entered();
+        cpu.input = 0;
+        server.poll(cpu);
+        cpu.redraw = false;
+        debugger.run(cpu, cpu.speed);
+        cpu.decrement_timers();
+        debugger.end_frame(cpu);
+        std::thread::sleep(FRAME.saturating_sub(frame_start.elapsed()));
+    }
+}
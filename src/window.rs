@@ -0,0 +1,868 @@
+use crate::chip8::{Chip8, Hooks};
+use crate::crash;
+use crate::debugger::Debugger;
+use crate::logview::LogView;
+use crate::osd::{self, Osd};
+use crate::reference::RefView;
+use crate::screensaver::Asleep;
+use crate::telemetry::Telemetry;
+use log::error;
+use pixels::{Error, Pixels, SurfaceTexture};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::{info_span, Span};
+use winit::dpi::{LogicalSize, PhysicalSize};
+use winit::event::{ElementState, Event, TouchPhase, VirtualKeyCode, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoop};
+use winit::window::{Fullscreen, Window, WindowBuilder};
+use winit_input_helper::WinitInputHelper;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 32;
+// Between the halves of a split window
+const GAP: u32 = 2;
+const SEPARATOR: [u8; 4] = [0x80, 0x80, 0x80, 0xFF];
+// 60 Hz, matching the timers
+const FRAME: Duration = Duration::from_micros(16_667);
+// Input ends a screensaver only after this, so it isn't woken by the window
+// going full screen
+const WAKE_AFTER: Duration = Duration::from_secs(1);
+// Most frames to catch up on after a stall, rather than racing through them
+const MAX_CATCH_UP: u32 = 6;
+// Phones get a keypad under the display
+const TOUCH: bool = cfg!(target_os = "android");
+const KEY_WIDTH: usize = 16;
+const KEY_HEIGHT: usize = 8;
+// Keyboard keys a Keymap can use
+const KEYS: [VirtualKeyCode; 36] = [
+    VirtualKeyCode::Key0,
+    VirtualKeyCode::Key1,
+    VirtualKeyCode::Key2,
+    VirtualKeyCode::Key3,
+    VirtualKeyCode::Key4,
+    VirtualKeyCode::Key5,
+    VirtualKeyCode::Key6,
+    VirtualKeyCode::Key7,
+    VirtualKeyCode::Key8,
+    VirtualKeyCode::Key9,
+    VirtualKeyCode::A,
+    VirtualKeyCode::B,
+    VirtualKeyCode::C,
+    VirtualKeyCode::D,
+    VirtualKeyCode::E,
+    VirtualKeyCode::F,
+    VirtualKeyCode::G,
+    VirtualKeyCode::H,
+    VirtualKeyCode::I,
+    VirtualKeyCode::J,
+    VirtualKeyCode::K,
+    VirtualKeyCode::L,
+    VirtualKeyCode::M,
+    VirtualKeyCode::N,
+    VirtualKeyCode::O,
+    VirtualKeyCode::P,
+    VirtualKeyCode::Q,
+    VirtualKeyCode::R,
+    VirtualKeyCode::S,
+    VirtualKeyCode::T,
+    VirtualKeyCode::U,
+    VirtualKeyCode::V,
+    VirtualKeyCode::W,
+    VirtualKeyCode::X,
+    VirtualKeyCode::Y,
+    VirtualKeyCode::Z,
+];
+/*    Touch Keypad
+ * 1 2 3 C
+ * 4 5 6 D
+ * 7 8 9 E
+ * A 0 B F
+ */
+const KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, 0x4, 0x5, 0x6, 0xD, 0x7, 0x8, 0x9, 0xE, 0xA, 0x0, 0xB, 0xF,
+];
+
+// One machine with its own window
+pub struct Instance {
+    pub cpu: Chip8,
+    pub debugger: Debugger,
+    // The ROM's, the window adds its own name and the machine's state
+    pub title: String,
+    pub osd: Osd,
+    // Full screen with its own input, see screensaver.rs
+    pub asleep: Option<Asleep>,
+}
+
+// A ROM for on_load to put in a window
+pub enum Load<'a> {
+    Dropped(&'a Path),
+    // Ctrl+1 to Ctrl+9, from 0
+    Recent(usize),
+    // Ctrl+PageDown and Ctrl+PageUp, through a --playlist
+    Next,
+    Previous,
+}
+
+// Window, surface and input state for an Instance
+struct Open {
+    index: usize,
+    instance: Instance,
+    window: Window,
+    pixels: Option<Pixels>,
+    input: WinitInputHelper,
+    // Keypad values held by each finger
+    touches: HashMap<u64, u8>,
+    // Paces instructions to cpu.speed a frame
+    frame_start: Instant,
+    due: usize,
+    // 60 Hz frames paced, counting any too late to run
+    frame: u64,
+    telemetry: Option<Telemetry>,
+    // Last title set, to only touch the window's when it changes
+    title: String,
+    focused: bool,
+    // Minimized, which winit reports as a size of zero
+    hidden: bool,
+    fullscreen: bool,
+    // Shown over the dimmed display while the debugger has it stopped
+    paused_label: Osd,
+    logs: LogView,
+    reference: RefView,
+    partner: Option<Partner>,
+}
+
+// The right half of a split window. It runs alongside the left with the same
+// keys through its own keymap, and resets, pauses and loads with it. Each half
+// keeps its own buffer, as Chip8::draw only touches one when there's something
+// new.
+struct Partner {
+    instance: Instance,
+    left: Vec<u8>,
+    right: Vec<u8>,
+}
+
+// Every instance gets a window on the same event loop, which exits once the
+// last one closes. The surface only exists between Resumed and Suspended on
+// Android, and emulation pauses without it. Windows open at scale times the
+// display size, rounded to a whole number of screen pixels to each display
+// pixel at the monitor's scale factor, and keep a whole number when they move
+// to a monitor with another one, so the display stays sharp. on_update runs
+// before each instruction with the instance and its index, for servers and
+// other outputs, and may swap its machine. It returns true when it ran the
+// machine itself, as netplay does in lockstep, and the window then leaves the
+// instructions and timers alone. With pause_unfocused, machines stop while
+// none of the windows has focus and on_update only runs once a frame, so
+// servers still answer. Minimized windows aren't drawn, and while they all
+// are the loop also wakes once a frame instead of spinning, with the machines
+// stopped too under pause_hidden. winit can't tell when a window is covered,
+// so that only counts as hidden when the system minimizes it. on_load
+// restarts an instance with a ROM dropped on its window or picked from the
+// recent ones, and names it. With
+// split, instances go two to a window side by side, the second following the
+// first as a Partner. Only the first is passed to on_update and on_load.
+// telemetry gets a row for each frame the first window paces.
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    instances: Vec<Instance>,
+    scale: u32,
+    pause_unfocused: bool,
+    pause_hidden: bool,
+    split: bool,
+    mut telemetry: Option<Telemetry>,
+    mut on_update: impl FnMut(usize, &mut Instance) -> bool + 'static,
+    mut on_load: impl FnMut(Load, &mut Instance) -> Result<String, String> + 'static,
+) -> Result<(), Error> {
+    let event_loop = EventLoop::new();
+    let mut open = Vec::new();
+    let mut instances = instances.into_iter();
+    while let Some(instance) = instances.next() {
+        let index = open.len();
+        let partner = match split {
+            true => instances.next().map(|instance| Partner {
+                instance,
+                left: vec![0; (WIDTH * HEIGHT * 4) as usize],
+                right: vec![0; (WIDTH * HEIGHT * 4) as usize],
+            }),
+            false => None,
+        };
+        let width = match partner {
+            Some(_) => WIDTH * 2 + GAP,
+            None => WIDTH,
+        };
+        let title = title(&instance.title, &instance.debugger);
+        let window = {
+            let size = LogicalSize::new(width as f64, HEIGHT as f64);
+            // Windows usually open on the primary monitor, and are told of
+            // the scale factor if not
+            let factor = event_loop
+                .primary_monitor()
+                .map_or(1.0, |monitor| monitor.scale_factor());
+            WindowBuilder::new()
+                .with_title(title.as_str())
+                .with_inner_size(sharp_size(width, scale as f64 * factor))
+                .with_min_inner_size(size)
+                .build(&event_loop)
+                .unwrap()
+        };
+        let pixels = match TOUCH {
+            true => None,
+            false => Some(surface(&window, width)?),
+        };
+        open.push(Open {
+            index,
+            instance,
+            window,
+            pixels,
+            input: WinitInputHelper::new(),
+            touches: HashMap::new(),
+            frame_start: Instant::now(),
+            due: 0,
+            frame: 0,
+            telemetry: telemetry.take(),
+            title,
+            focused: true,
+            hidden: false,
+            fullscreen: false,
+            paused_label: {
+                let mut label = Osd::default();
+                label.pin("Paused");
+                label
+            },
+            logs: LogView::default(),
+            reference: RefView::default(),
+            partner,
+        });
+    }
+
+    event_loop.run(move |mut event, _, control_flow| {
+        // Only here can the size the window is about to get be changed
+        if let Event::WindowEvent {
+            window_id,
+            event: WindowEvent::ScaleFactorChanged { new_inner_size, .. },
+        } = &mut event
+        {
+            if let Some(open) = open.iter().find(|open| open.window.id() == *window_id) {
+                open.rescale(new_inner_size);
+            }
+        }
+        let hidden = !open.is_empty() && open.iter().all(|open| open.hidden);
+        let paused =
+            (pause_unfocused && !open.iter().any(|open| open.focused)) || (pause_hidden && hidden);
+        open.retain_mut(|open| open.update(&event, paused, &mut on_update, &mut on_load));
+        // Wake once a frame while paused or hidden rather than spinning
+        *control_flow = match (open.is_empty(), paused || hidden) {
+            (true, _) => ControlFlow::Exit,
+            (false, true) => ControlFlow::WaitUntil(Instant::now() + FRAME),
+            (false, false) => ControlFlow::Poll,
+        };
+    });
+}
+
+impl Open {
+    // Handle one event, false once the window has closed
+    fn update(
+        &mut self,
+        event: &Event<()>,
+        paused: bool,
+        on_update: &mut impl FnMut(usize, &mut Instance) -> bool,
+        on_load: &mut impl FnMut(Load, &mut Instance) -> Result<String, String>,
+    ) -> bool {
+        // Window events only go to the window they're for
+        let id = self.window.id();
+        match event {
+            Event::WindowEvent { window_id, .. } | Event::RedrawRequested(window_id)
+                if *window_id != id =>
+            {
+                return true;
+            }
+            _ => {}
+        }
+        // Keys and the mouse go to a screensaver, and the first after a grace
+        // period ends it. The machine never sees them.
+        if let Some(asleep) = &self.instance.asleep {
+            let input = matches!(
+                event,
+                Event::WindowEvent {
+                    event: WindowEvent::KeyboardInput { .. }
+                        | WindowEvent::MouseInput {
+                            state: ElementState::Pressed,
+                            ..
+                        }
+                        | WindowEvent::MouseWheel { .. }
+                        | WindowEvent::CursorMoved { .. },
+                    ..
+                }
+            );
+            if input && asleep.since.elapsed() >= WAKE_AFTER {
+                match self.instance.asleep.take().and_then(|asleep| asleep.game) {
+                    Some((cpu, title)) => {
+                        self.instance.cpu = cpu;
+                        self.instance.cpu.redraw = true;
+                        self.instance.title = title;
+                        self.due = 0;
+                    }
+                    None => return false,
+                }
+            }
+            if input {
+                return true;
+            }
+        }
+        if let Event::WindowEvent {
+            event: WindowEvent::DroppedFile(path),
+            ..
+        } = event
+        {
+            self.load(Load::Dropped(path), on_load);
+        }
+        let width = self.width();
+        let cpu = &mut self.instance.cpu;
+        let debugger = &mut self.instance.debugger;
+        match event {
+            Event::Resumed if self.pixels.is_none() => match surface(&self.window, width) {
+                Ok(surface) => {
+                    self.pixels = Some(surface);
+                    cpu.redraw = true;
+                }
+                Err(e) => error!("Unable to create a surface: {}", e),
+            },
+            Event::Suspended => self.pixels = None,
+            Event::WindowEvent {
+                event: WindowEvent::Focused(focused),
+                ..
+            } => self.focused = *focused,
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => self.hidden = size.width == 0 || size.height == 0,
+            // Not always followed by Resized, which would leave the surface
+            // stretched to the new size
+            Event::WindowEvent {
+                event: WindowEvent::ScaleFactorChanged { new_inner_size, .. },
+                ..
+            } => {
+                if let Some(pixels) = &mut self.pixels {
+                    pixels.resize_surface(new_inner_size.width, new_inner_size.height);
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::Touch(touch),
+                ..
+            } => {
+                let key = self.pixels.as_ref().and_then(|pixels| {
+                    let position = (touch.location.x as f32, touch.location.y as f32);
+                    let (x, y) = pixels.window_pos_to_pixel(position).ok()?;
+                    key_at(x, y)
+                });
+                match (touch.phase, key) {
+                    (TouchPhase::Started | TouchPhase::Moved, Some(key)) => {
+                        self.touches.insert(touch.id, key);
+                    }
+                    _ => {
+                        self.touches.remove(&touch.id);
+                    }
+                }
+            }
+            _ => {}
+        }
+        let pixels = match &mut self.pixels {
+            Some(pixels) => pixels,
+            None => return true,
+        };
+
+        // Draw the current frame
+        if let Event::RedrawRequested(_) = event {
+            let _render = info_span!("render").entered();
+            let frame = pixels.get_frame();
+            let (screen, keypad) = frame.split_at_mut((width * HEIGHT * 4) as usize);
+            let display = match &mut self.partner {
+                Some(partner) => {
+                    let right = &mut partner.instance;
+                    let (cpu, debugger) = (&mut right.cpu, &right.debugger);
+                    draw_display(
+                        &mut partner.right,
+                        cpu,
+                        debugger,
+                        &right.osd,
+                        &self.paused_label,
+                    );
+                    &mut partner.left[..]
+                }
+                None => &mut *screen,
+            };
+            draw_display(
+                display,
+                cpu,
+                debugger,
+                &self.instance.osd,
+                &self.paused_label,
+            );
+            if self.logs.open {
+                self.logs.draw(display, cpu.palette());
+            }
+            if self.reference.open {
+                self.reference.draw(display, &cpu.quirks, cpu.palette());
+            }
+            if let Some(partner) = &self.partner {
+                join(screen, &partner.left, &partner.right);
+            }
+            if TOUCH {
+                let pressed = self.touches.values().next().copied();
+                draw_keypad(keypad, cpu, pressed);
+            }
+
+            if pixels
+                .render()
+                .map_err(|e| error!("pixels.render() failed: {}", e))
+                .is_err()
+            {
+                return false;
+            }
+        }
+        let input = &mut self.input;
+        if input.update(event) {
+            // Close events
+            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+                return false;
+            }
+            // ` opens the log console, which takes the keys while it's open:
+            // arrows scroll, L and M filter by level and module
+            if input.key_pressed(VirtualKeyCode::Grave) {
+                self.logs.toggle();
+                self.reference.open = false;
+            }
+            // F1 opens the opcode reference, searched by typing letters,
+            // digits and spaces while it's open
+            if input.key_pressed(VirtualKeyCode::F1) {
+                self.reference.toggle();
+                self.logs.open = false;
+            }
+            if self.reference.open {
+                let scrolls = [
+                    (VirtualKeyCode::Up, -1),
+                    (VirtualKeyCode::Down, 1),
+                    (VirtualKeyCode::PageUp, -4),
+                    (VirtualKeyCode::PageDown, 4),
+                ];
+                for (code, rows) in scrolls {
+                    if input.key_pressed(code) {
+                        self.reference.scroll(rows, &cpu.quirks);
+                    }
+                }
+                for code in KEYS {
+                    if let (true, Some(c)) = (input.key_pressed(code), key_char(code)) {
+                        self.reference.type_char(c);
+                    }
+                }
+                if input.key_pressed(VirtualKeyCode::Space) {
+                    self.reference.type_char(' ');
+                }
+                if input.key_pressed(VirtualKeyCode::Back) {
+                    self.reference.backspace();
+                }
+            }
+            if self.logs.open {
+                let scrolls = [
+                    (VirtualKeyCode::Up, 1),
+                    (VirtualKeyCode::Down, -1),
+                    (VirtualKeyCode::PageUp, 4),
+                    (VirtualKeyCode::PageDown, -4),
+                ];
+                for (code, rows) in scrolls {
+                    if input.key_pressed(code) {
+                        self.logs.scroll(rows);
+                    }
+                }
+                if input.key_pressed(VirtualKeyCode::L) {
+                    self.logs.next_level();
+                }
+                if input.key_pressed(VirtualKeyCode::M) {
+                    self.logs.next_module();
+                }
+            }
+            // Ctrl+1 to Ctrl+9 switch to a recent ROM, Ctrl+PageDown and
+            // Ctrl+PageUp to the next or previous one in a --playlist
+            if input.held_control() {
+                if let Some(n) = KEYS[1..10].iter().position(|code| input.key_pressed(*code)) {
+                    self.load(Load::Recent(n), on_load);
+                    return true;
+                }
+                if input.key_pressed(VirtualKeyCode::PageDown) {
+                    self.load(Load::Next, on_load);
+                    return true;
+                }
+                if input.key_pressed(VirtualKeyCode::PageUp) {
+                    self.load(Load::Previous, on_load);
+                    return true;
+                }
+            }
+            // F5 restarts the program, Shift+F5 also clears memory. The halves of
+            // a split window restart together from a new seed they share, so a
+            // race starts fair.
+            if input.key_pressed(VirtualKeyCode::F5) {
+                let hard = input.held_shift();
+                let seed = self.partner.as_ref().map(|_| rand::random());
+                let partner = self.partner.as_mut().map(|partner| &mut partner.instance);
+                for (cpu, osd) in [(&mut *cpu, &mut self.instance.osd)]
+                    .into_iter()
+                    .chain(partner.map(|right| (&mut right.cpu, &mut right.osd)))
+                {
+                    match hard {
+                        true => {
+                            cpu.hard_reset();
+                            osd.show("Hard reset");
+                        }
+                        false => {
+                            cpu.soft_reset();
+                            osd.show("Reset");
+                        }
+                    }
+                    if let Some(seed) = seed {
+                        cpu.seed(seed);
+                    }
+                }
+                self.due = 0;
+            }
+            // Space or P pauses, unless the game's keys use P. There's nothing
+            // to resume before a ROM is loaded.
+            let pause_key = !self.reference.open
+                && (input.key_pressed(VirtualKeyCode::Space)
+                    || (input.key_pressed(VirtualKeyCode::P) && cpu.keymap.key('p').is_none()));
+            if pause_key && cpu.rom_size > 0 {
+                let paused = debugger.is_paused();
+                let partner = self
+                    .partner
+                    .as_mut()
+                    .map(|partner| &mut partner.instance.debugger);
+                for debugger in [&mut *debugger].into_iter().chain(partner) {
+                    match paused {
+                        true => debugger.resume(),
+                        false => debugger.pause(),
+                    }
+                }
+            }
+            // Period runs one frame at a time while paused
+            if input.key_pressed(VirtualKeyCode::Period)
+                && debugger.is_paused()
+                && !self.reference.open
+            {
+                debugger.step_frame(cpu);
+                if let Some(Partner { instance, .. }) = &mut self.partner {
+                    instance.debugger.step_frame(&mut instance.cpu);
+                }
+            }
+            // Letters and digits go through the ROM's keymap, see keypad.rs
+            cpu.input = KEYS
+                .iter()
+                .filter(|code| input.key_held(**code))
+                .find_map(|code| cpu.keymap.key(key_char(*code)?))
+                .unwrap_or(0x00);
+            if let Some(key) = self.touches.values().next() {
+                cpu.input = *key;
+            }
+            if self.logs.open || self.reference.open {
+                cpu.input = 0;
+            }
+            if let Some(Partner { instance, .. }) = &mut self.partner {
+                let right = &mut instance.cpu;
+                right.input = KEYS
+                    .iter()
+                    .filter(|code| input.key_held(**code))
+                    .find_map(|code| right.keymap.key(key_char(*code)?))
+                    .unwrap_or(0x00);
+                if self.logs.open || self.reference.open {
+                    right.input = 0;
+                }
+            }
+
+            // Resize the window
+            if let Some(size) = input.window_resized().filter(|_| !self.hidden) {
+                pixels.resize_surface(size.width, size.height);
+            }
+            // Update internal state and request a redraw
+            debugger.poll(cpu);
+            if let Some(Partner { instance, .. }) = &mut self.partner {
+                instance.debugger.poll(&mut instance.cpu);
+            }
+            // Full screen for as long as a screensaver runs
+            let asleep = self.instance.asleep.is_some();
+            if asleep != self.fullscreen {
+                let fullscreen = asleep.then_some(Fullscreen::Borderless(None));
+                self.window.set_fullscreen(fullscreen);
+                self.window.set_cursor_visible(!asleep);
+                self.fullscreen = asleep;
+            }
+            let title = title(&self.instance.title, debugger);
+            if title != self.title {
+                self.window.set_title(&title);
+                self.title = title;
+            }
+            if !self.hidden {
+                self.window.request_redraw();
+            }
+            if paused {
+                self.frame_start = Instant::now();
+                self.due = 0;
+                on_update(self.index, &mut self.instance);
+                return true;
+            }
+            let frames = (self.frame_start.elapsed().as_micros() / FRAME.as_micros()) as u32;
+            // Polls between frames stay off a --timeline
+            let _frame = match frames > 0 {
+                true => cpu.frame_span(),
+                false => Span::none(),
+            }
+            .entered();
+            if frames > 0 {
+                self.frame_start += FRAME * frames;
+                self.due += cpu.speed * frames.min(MAX_CATCH_UP) as usize;
+                self.frame += frames as u64;
+            }
+            let batch = match self.due > 0 {
+                true => cpu.batch_span(self.due),
+                false => Span::none(),
+            }
+            .entered();
+            while self.due > 0 {
+                if on_update(self.index, &mut self.instance) {
+                    self.due = 0;
+                    self.record(frames);
+                    return true;
+                }
+                let instance = &mut self.instance;
+                instance.debugger.tick(&mut instance.cpu);
+                if let Some(Partner { instance, .. }) = &mut self.partner {
+                    instance.debugger.tick(&mut instance.cpu);
+                }
+                self.due -= 1;
+            }
+            drop(batch);
+            for instance in self.instances() {
+                if let Some(folder) = instance.debugger.take_crash() {
+                    let name = folder.file_name().unwrap_or_default().to_string_lossy();
+                    instance
+                        .osd
+                        .pin(&format!("Crashed\nDump saved to\n{}", name));
+                }
+                for title in instance.debugger.take_unlocked() {
+                    instance.osd.show(&format!("Achievement\n{}", title));
+                }
+            }
+            // A tick of each timer and the cheats for every frame run, as
+            // the other frontends do. Timers stop with the debugger.
+            for instance in self.instances() {
+                if instance.debugger.is_paused() {
+                    continue;
+                }
+                for _ in 0..frames.min(MAX_CATCH_UP) {
+                    let cpu = &mut instance.cpu;
+                    cpu.decrement_timers();
+                    instance.debugger.end_frame(cpu);
+                }
+            }
+            self.record(frames);
+        }
+        true
+    }
+
+    fn width(&self) -> u32 {
+        match self.partner {
+            Some(_) => WIDTH * 2 + GAP,
+            None => WIDTH,
+        }
+    }
+
+    // The size winit suggests after a scale factor change keeps the window's
+    // logical size, which can put a fraction of a screen pixel to each display
+    // pixel. It's rounded to the nearest whole number instead. Full screen
+    // windows are left as they are.
+    fn rescale(&self, suggested: &mut PhysicalSize<u32>) {
+        if self.fullscreen {
+            return;
+        }
+        let zoom = (suggested.width as f64 / self.width() as f64)
+            .min(suggested.height as f64 / HEIGHT as f64);
+        *suggested = sharp_size(self.width(), zoom);
+    }
+
+    // A telemetry row once frames have been paced, after they've run
+    fn record(&mut self, frames: u32) {
+        let result = match (frames, &mut self.telemetry) {
+            (1.., Some(telemetry)) => telemetry.record(self.frame, &self.instance.cpu),
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            error!("Telemetry stopped: {}", e);
+            self.telemetry = None;
+        }
+    }
+
+    // The window's machines, left then right
+    fn instances(&mut self) -> impl Iterator<Item = &mut Instance> {
+        let partner = self.partner.as_mut().map(|partner| &mut partner.instance);
+        std::iter::once(&mut self.instance).chain(partner)
+    }
+
+    // Restart the machine with another ROM, saying how it went on the display
+    fn load(
+        &mut self,
+        load: Load,
+        on_load: &mut impl FnMut(Load, &mut Instance) -> Result<String, String>,
+    ) {
+        match on_load(load, &mut self.instance) {
+            Ok(name) => {
+                self.instance.osd.show(&format!("Loaded\n{}", name));
+                self.due = 0;
+                // The other half runs the same ROM with its own quirks and keys
+                if let Some(Partner { instance, .. }) = &mut self.partner {
+                    let (quirks, keymap) = (instance.cpu.quirks, instance.cpu.keymap);
+                    instance.cpu = self.instance.cpu.clone();
+                    instance.cpu.quirks = quirks;
+                    instance.cpu.keymap = keymap;
+                    instance.debugger.reset();
+                }
+            }
+            Err(e) => {
+                error!("{}", e);
+                self.instance.osd.show("Can't load it");
+            }
+        }
+    }
+}
+
+// The machine's display with the paused dimming and messages over it. With
+// run-ahead it's a copy's, run on the frames with the keys held now so a press
+// shows up sooner. The copy is thrown away, which rolls back to the machine,
+// and the next draw starts again from wherever it's got to with the keys then.
+fn draw_display(
+    display: &mut [u8],
+    cpu: &mut Chip8,
+    debugger: &Debugger,
+    osd: &Osd,
+    paused_label: &Osd,
+) {
+    match (cpu.run_ahead, debugger.is_paused()) {
+        (0, _) | (_, true) => cpu.draw(display),
+        (frames, false) => {
+            let mut ahead = cpu.clone();
+            // Frames shown ahead are run again for real, so only then call back
+            ahead.hooks = Hooks::default();
+            // Ticked rather than run, to stay off a --timeline, and paced like
+            // the machine with a tick of the timers a frame. A copy heading
+            // for a fault stops short of it, leaving the debugger to dump the
+            // machine when it gets there.
+            'frames: for _ in 0..frames {
+                for _ in 0..ahead.speed {
+                    if crash::fault(&ahead).is_some() {
+                        break 'frames;
+                    }
+                    ahead.tick();
+                }
+                ahead.decrement_timers();
+            }
+            ahead.redraw = true;
+            ahead.draw(display);
+            cpu.redraw = false;
+        }
+    }
+    if debugger.is_paused() {
+        osd::dim(display);
+        if !osd.is_visible() {
+            paused_label.draw(display, cpu.palette());
+        }
+    }
+    osd.draw(display, cpu.palette());
+}
+
+// The two halves of a split window into its frame, row by row
+fn join(frame: &mut [u8], left: &[u8], right: &[u8]) {
+    let row = (WIDTH * 4) as usize;
+    let rows = left.chunks(row).zip(right.chunks(row));
+    for (line, (left, right)) in frame.chunks_mut(row * 2 + GAP as usize * 4).zip(rows) {
+        let (start, rest) = line.split_at_mut(row);
+        let (gap, end) = rest.split_at_mut(GAP as usize * 4);
+        start.copy_from_slice(left);
+        gap.copy_from_slice(&SEPARATOR.repeat(GAP as usize));
+        end.copy_from_slice(right);
+    }
+}
+
+// "<ROM title> — Chip8", then markers for the machine's state
+pub fn title(rom_title: &str, debugger: &Debugger) -> String {
+    let mut title = match rom_title.is_empty() {
+        true => String::from("Chip8"),
+        false => format!("{} — Chip8", rom_title),
+    };
+    if debugger.is_paused() {
+        title.push_str(" [PAUSED]");
+    }
+    // The debugger's trace is recording every instruction
+    if debugger.is_tracing() {
+        title.push_str(" [REC]");
+    }
+    title
+}
+
+// The window size for zoom screen pixels to each display pixel, rounded to a
+// whole number
+fn sharp_size(width: u32, zoom: f64) -> PhysicalSize<u32> {
+    let zoom = (zoom.round() as u32).max(1);
+    PhysicalSize::new(width * zoom, HEIGHT * zoom)
+}
+
+// The keypad doubles the buffer height, pixels then scales both to the screen
+fn surface(window: &Window, width: u32) -> Result<Pixels, Error> {
+    let window_size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, window);
+    let height = match TOUCH {
+        true => HEIGHT * 2,
+        false => HEIGHT,
+    };
+    Pixels::new(width, height, surface_texture)
+}
+
+// Key1 to '1', A to 'a'
+fn key_char(code: VirtualKeyCode) -> Option<char> {
+    let name = format!("{:?}", code);
+    match name
+        .strip_prefix("Key")
+        .unwrap_or(&name)
+        .chars()
+        .collect::<Vec<char>>()
+        .as_slice()
+    {
+        [key] => Some(key.to_ascii_lowercase()),
+        _ => None,
+    }
+}
+
+// Buffer pixel to the keypad value under it
+fn key_at(x: usize, y: usize) -> Option<u8> {
+    let y = y.checked_sub(HEIGHT as usize)?;
+    KEYPAD.get((y / KEY_HEIGHT) * 4 + x / KEY_WIDTH).copied()
+}
+
+// Each key is outlined with its font glyph in the middle, held keys inverted
+fn draw_keypad(frame: &mut [u8], cpu: &Chip8, pressed: Option<u8>) {
+    let (color, background) = cpu.palette();
+    for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
+        let (x, y) = (i % WIDTH as usize, i / WIDTH as usize);
+        let key = KEYPAD[(y / KEY_HEIGHT) * 4 + x / KEY_WIDTH];
+        let (kx, ky) = (x % KEY_WIDTH, y % KEY_HEIGHT);
+        let edge = kx == 0 || ky == 0 || kx == KEY_WIDTH - 1 || ky == KEY_HEIGHT - 1;
+        // 4x5 glyphs from the font in memory
+        let glyph = match (kx.checked_sub(6), ky.checked_sub(1)) {
+            (Some(gx), Some(gy)) if gx < 4 && gy < 5 => {
+                cpu.memory[key as usize * 5 + gy] & (0x80 >> gx) != 0
+            }
+            _ => false,
+        };
+        let lit = (edge || glyph) != (pressed == Some(key));
+        let rgba = match lit {
+            true => color,
+            false => background,
+        };
+        pixel.copy_from_slice(&rgba);
+    }
+}
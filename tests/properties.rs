@@ -0,0 +1,237 @@
+use proptest::prelude::*;
+use rust_chip8::builder::RomBuilder;
+use rust_chip8::chip8::Chip8;
+use rust_chip8::disasm::disassemble;
+use rust_chip8::testing;
+
+// Runs the instruction at the PC, checking it only touched VX and VF and
+// moved on to the next one, and gives back the machine before it
+fn run_arithmetic(cpu: &mut Chip8) -> Result<Chip8, TestCaseError> {
+    run_only(cpu, &["VX", "VF"])
+}
+
+// What 8XY6 and 8XYE shift, which the shift quirk decides
+fn shifted(cpu: &Chip8) -> u8 {
+    let (vx, vy) = testing::operands(cpu);
+    match cpu.quirks.shift_vy {
+        true => vy,
+        false => vx,
+    }
+}
+
+// The X the instruction at the PC names
+fn x(cpu: &Chip8) -> usize {
+    (cpu.opcode_at(cpu.pc) as usize & 0x0F00) >> 8
+}
+
+// Runs the instruction at the PC, checking it only touched what's allowed
+// and moved on to the next one, and gives back the machine before it
+fn run_only(cpu: &mut Chip8, allowed: &[&str]) -> Result<Chip8, TestCaseError> {
+    let before = cpu.clone();
+    cpu.tick();
+    testing::only_changed(&before, cpu, allowed)?;
+    testing::next_pc(&before, cpu, 2)?;
+    Ok(before)
+}
+
+// The opcode a builder put first
+fn first(builder: RomBuilder) -> u16 {
+    let rom = builder.build();
+    u16::from_be_bytes([rom[0], rom[1]])
+}
+
+proptest! {
+    #[test]
+    fn add_with_carry(mut cpu in testing::machine_at("8XY4")) {
+        let (vx, vy) = testing::operands(&cpu);
+        let before = run_arithmetic(&mut cpu)?;
+        let (sum, carry) = vx.overflowing_add(vy);
+        testing::result_and_flag(&before, &cpu, sum, carry as u8)?;
+    }
+
+    #[test]
+    fn subtract_with_borrow(mut cpu in testing::machine_at("8XY5")) {
+        let (vx, vy) = testing::operands(&cpu);
+        let before = run_arithmetic(&mut cpu)?;
+        testing::result_and_flag(&before, &cpu, vx.wrapping_sub(vy), (vx >= vy) as u8)?;
+    }
+
+    #[test]
+    fn subtract_from_with_borrow(mut cpu in testing::machine_at("8XY7")) {
+        let (vx, vy) = testing::operands(&cpu);
+        let before = run_arithmetic(&mut cpu)?;
+        testing::result_and_flag(&before, &cpu, vy.wrapping_sub(vx), (vy >= vx) as u8)?;
+    }
+
+    #[test]
+    fn shift_right(mut cpu in testing::machine_at("8XY6"), shift_vy in any::<bool>()) {
+        cpu.quirks.shift_vy = shift_vy;
+        let value = shifted(&cpu);
+        let before = run_arithmetic(&mut cpu)?;
+        testing::result_and_flag(&before, &cpu, value >> 1, value & 0x01)?;
+    }
+
+    #[test]
+    fn shift_left(mut cpu in testing::machine_at("8XYE"), shift_vy in any::<bool>()) {
+        cpu.quirks.shift_vy = shift_vy;
+        let value = shifted(&cpu);
+        let before = run_arithmetic(&mut cpu)?;
+        testing::result_and_flag(&before, &cpu, value << 1, value >> 7)?;
+    }
+
+    // 7XNN wraps without a carry, so VF only changes as VX
+    #[test]
+    fn add_immediate(mut cpu in testing::machine_at("7XNN")) {
+        let (vx, _) = testing::operands(&cpu);
+        let nn = cpu.opcode_at(cpu.pc) as u8;
+        let x = x(&cpu);
+        run_only(&mut cpu, &["VX"])?;
+        prop_assert_eq!(cpu.registers[x], vx.wrapping_add(nn));
+    }
+
+    #[test]
+    fn set_delay(mut cpu in testing::machine_at("FX15")) {
+        let (vx, _) = testing::operands(&cpu);
+        run_only(&mut cpu, &["DT"])?;
+        prop_assert_eq!(cpu.delay_timer, vx);
+    }
+
+    #[test]
+    fn set_sound(mut cpu in testing::machine_at("FX18")) {
+        let (vx, _) = testing::operands(&cpu);
+        run_only(&mut cpu, &["ST"])?;
+        prop_assert_eq!(cpu.sound_timer, vx);
+    }
+
+    #[test]
+    fn add_to_i(mut cpu in testing::machine_at("FX1E")) {
+        let (vx, _) = testing::operands(&cpu);
+        let before = run_only(&mut cpu, &["I"])?;
+        prop_assert_eq!(cpu.address_reg, before.address_reg.wrapping_add(vx as u16));
+    }
+
+    // The font starts at 0, five bytes a digit, and only the low nibble counts
+    #[test]
+    fn font_digit(mut cpu in testing::machine_at("FX29")) {
+        let (vx, _) = testing::operands(&cpu);
+        run_only(&mut cpu, &["I"])?;
+        prop_assert_eq!(cpu.address_reg, (vx & 0x0F) as u16 * 5);
+    }
+
+    #[test]
+    fn decimal_digits(mut cpu in testing::machine_at("FX33")) {
+        let (vx, _) = testing::operands(&cpu);
+        let before = run_only(&mut cpu, &["memory"])?;
+        let i = before.address_reg as usize;
+        prop_assert_eq!(&cpu.memory[i..i + 3], &[vx / 100, vx / 10 % 10, vx % 10]);
+        prop_assert_eq!(&cpu.memory[..i], &before.memory[..i]);
+        prop_assert_eq!(&cpu.memory[i + 3..], &before.memory[i + 3..]);
+    }
+
+    #[test]
+    fn save_registers(mut cpu in testing::machine_at("FX55"), increment_i in any::<bool>()) {
+        cpu.quirks.increment_i = increment_i;
+        let x = x(&cpu);
+        let before = run_only(&mut cpu, &["memory", "I"])?;
+        let i = before.address_reg as usize;
+        prop_assert_eq!(&cpu.memory[i..=i + x], &before.registers[..=x]);
+        prop_assert_eq!(&cpu.memory[..i], &before.memory[..i]);
+        prop_assert_eq!(&cpu.memory[i + x + 1..], &before.memory[i + x + 1..]);
+        let end = match increment_i {
+            true => i + x + 1,
+            false => i,
+        };
+        prop_assert_eq!(cpu.address_reg as usize, end);
+    }
+
+    #[test]
+    fn load_registers(mut cpu in testing::machine_at("FX65"), increment_i in any::<bool>()) {
+        cpu.quirks.increment_i = increment_i;
+        let x = x(&cpu);
+        let mut allowed: Vec<String> = (0..=x).map(|v| format!("V{:X}", v)).collect();
+        allowed.push(String::from("I"));
+        let allowed: Vec<&str> = allowed.iter().map(String::as_str).collect();
+        let before = run_only(&mut cpu, &allowed)?;
+        let i = before.address_reg as usize;
+        prop_assert_eq!(&cpu.registers[..=x], &before.memory[i..=i + x]);
+        let end = match increment_i {
+            true => i + x + 1,
+            false => i,
+        };
+        prop_assert_eq!(cpu.address_reg as usize, end);
+    }
+
+    // Each RomBuilder method against what disasm reads back from it
+    #[test]
+    fn builder_encodings(
+        x in testing::register(),
+        y in testing::register(),
+        nn in testing::nn(),
+        n in testing::n(),
+        addr in 0x200..0x1000u16,
+    ) {
+        let (x, y) = (x as u8, y as u8);
+        let cases = [
+            (RomBuilder::new().clear(), String::from("CLS")),
+            (RomBuilder::new().ret(), String::from("RET")),
+            (RomBuilder::new().sys(addr), format!("SYS {:#05x}", addr)),
+            (RomBuilder::new().jump(addr), format!("JP {:#05x}", addr)),
+            (RomBuilder::new().call(addr), format!("CALL {:#05x}", addr)),
+            (RomBuilder::new().skip_eq(x, nn), format!("SE V{:X}, {:#04x}", x, nn)),
+            (RomBuilder::new().skip_ne(x, nn), format!("SNE V{:X}, {:#04x}", x, nn)),
+            (RomBuilder::new().skip_eq_reg(x, y), format!("SE V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().set(x, nn), format!("LD V{:X}, {:#04x}", x, nn)),
+            (RomBuilder::new().add(x, nn), format!("ADD V{:X}, {:#04x}", x, nn)),
+            (RomBuilder::new().set_reg(x, y), format!("LD V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().or(x, y), format!("OR V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().and(x, y), format!("AND V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().xor(x, y), format!("XOR V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().add_reg(x, y), format!("ADD V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().sub(x, y), format!("SUB V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().shr(x, y), format!("SHR V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().subn(x, y), format!("SUBN V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().shl(x, y), format!("SHL V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().skip_ne_reg(x, y), format!("SNE V{:X}, V{:X}", x, y)),
+            (RomBuilder::new().load_i(addr), format!("LD I, {:#05x}", addr)),
+            (RomBuilder::new().jump0(addr), format!("JP V0, {:#05x}", addr)),
+            (RomBuilder::new().random(x, nn), format!("RND V{:X}, {:#04x}", x, nn)),
+            (RomBuilder::new().draw(x, y, n), format!("DRW V{:X}, V{:X}, {}", x, y, n)),
+            (RomBuilder::new().skip_key(x), format!("SKP V{:X}", x)),
+            (RomBuilder::new().skip_not_key(x), format!("SKNP V{:X}", x)),
+            (RomBuilder::new().get_delay(x), format!("LD V{:X}, DT", x)),
+            (RomBuilder::new().wait_key(x), format!("LD V{:X}, K", x)),
+            (RomBuilder::new().set_delay(x), format!("LD DT, V{:X}", x)),
+            (RomBuilder::new().set_sound(x), format!("LD ST, V{:X}", x)),
+            (RomBuilder::new().add_i(x), format!("ADD I, V{:X}", x)),
+            (RomBuilder::new().font(x), format!("LD F, V{:X}", x)),
+            (RomBuilder::new().bcd(x), format!("LD B, V{:X}", x)),
+            (RomBuilder::new().save(x), format!("LD [I], V{:X}", x)),
+            (RomBuilder::new().load(x), format!("LD V{:X}, [I]", x)),
+        ];
+        for (builder, expected) in cases {
+            prop_assert_eq!(disassemble(first(builder)), expected);
+        }
+    }
+
+    // A label given after the jump to it gets filled in by build
+    #[test]
+    fn builder_labels(skipped in 0..64usize) {
+        let end = 0x202 + 2 * skipped as u16;
+        let builder = (0..skipped).fold(RomBuilder::new().jump("end"), |rom, _| rom.clear());
+        let builder = builder.label("end").ret();
+        prop_assert_eq!(builder.here(), end + 2);
+        prop_assert_eq!(disassemble(first(builder)), format!("JP {:#05x}", end));
+    }
+
+    // 8XY4 from a built program rather than poked into memory
+    #[test]
+    fn built_add_with_carry(a in testing::nn(), b in testing::nn()) {
+        let rom = RomBuilder::new().set(0, a).set(1, b).add_reg(0, 1).build();
+        let mut cpu = Chip8::new();
+        cpu.load_program(&rom);
+        cpu.run(3);
+        let (sum, carry) = a.overflowing_add(b);
+        prop_assert_eq!(cpu.registers[0], sum);
+        prop_assert_eq!(cpu.registers[0xF], carry as u8);
+    }
+}
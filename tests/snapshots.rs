@@ -0,0 +1,52 @@
+use rust_chip8::chip8::Chip8;
+use rust_chip8::{demo, octo, snapshot, suite};
+use std::path::Path;
+
+fn machine(rom: &[u8]) -> Chip8 {
+    let mut cpu = Chip8::new();
+    cpu.seed(0);
+    cpu.load_font();
+    cpu.load_program(rom);
+    cpu
+}
+
+#[test]
+fn demo_screen() {
+    let mut cpu = machine(&demo::rom());
+    snapshot::run_for_frames(&mut cpu, 60).unwrap();
+    snapshot::assert_framebuffer_matches(&cpu, "tests/snapshots/demo.txt");
+}
+
+// The test ROMs in test-roms/, each against its screen here as well as its
+// recorded hash. They're in the repository, so a missing one fails too.
+#[test]
+fn test_roms() {
+    let cases = suite::load(Path::new("test-roms")).unwrap();
+    assert!(!cases.is_empty(), "test-roms/suite.toml lists no tests");
+    for case in &cases {
+        let bytes = std::fs::read(&case.rom)
+            .unwrap_or_else(|e| panic!("Unable to read {}: {}", case.rom.display(), e));
+        let rom = match case
+            .rom
+            .extension()
+            .is_some_and(|extension| extension == "8o")
+        {
+            true => octo::assemble(&String::from_utf8_lossy(&bytes)).unwrap(),
+            false => bytes,
+        };
+        let mut cpu = machine(&rom);
+        match case.check(&mut cpu) {
+            suite::Outcome::Pass => {}
+            suite::Outcome::Fail(hash) => {
+                let expected = case.crc32.as_deref().unwrap_or_default();
+                panic!("{} has crc32 {}, expected {}", case.name, hash, expected);
+            }
+            suite::Outcome::New(hash) => {
+                panic!("{} has no crc32 recorded, it's {}", case.name, hash)
+            }
+            suite::Outcome::Fault(fault) => panic!("{} stopped: {}", case.name, fault),
+        }
+        let name = case.rom.file_stem().unwrap().to_string_lossy();
+        snapshot::assert_framebuffer_matches(&cpu, format!("tests/snapshots/{}.txt", name));
+    }
+}
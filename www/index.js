@@ -0,0 +1,23 @@
+// Built by `wasm-pack build --target web --out-dir www/pkg`
+import init, { WebChip8 } from "./pkg/rust_chip8.js";
+
+await init();
+const emulator = new WebChip8();
+const context = document.getElementById("screen").getContext("2d");
+const status = document.getElementById("status");
+
+document.getElementById("rom").addEventListener("change", async (event) => {
+  const file = event.target.files[0];
+  if (file) {
+    emulator.load_rom(new Uint8Array(await file.arrayBuffer()));
+  }
+});
+window.addEventListener("keydown", (event) => emulator.key_down(event.code));
+window.addEventListener("keyup", (event) => emulator.key_up(event.code));
+
+function frame() {
+  emulator.frame(context);
+  status.textContent = emulator.fault() ?? "";
+  requestAnimationFrame(frame);
+}
+requestAnimationFrame(frame);
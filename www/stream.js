@@ -0,0 +1,61 @@
+// Viewer for `--websocket`, see the streaming protocol in src/websocket.rs
+const context = document.getElementById("screen").getContext("2d");
+const status = document.getElementById("status");
+const image = context.createImageData(64, 32);
+// Same layout as the desktop key mapping, by KeyboardEvent.code
+const KEYS = {
+  Digit1: 0x1, Digit2: 0x2, Digit3: 0x3, Digit4: 0xc,
+  KeyQ: 0x4, KeyW: 0x5, KeyE: 0x6, KeyR: 0xd,
+  KeyA: 0x7, KeyS: 0x8, KeyD: 0x9, KeyF: 0xe,
+  KeyZ: 0xa, KeyX: 0x0, KeyC: 0xb, KeyV: 0xf,
+};
+let socket = null;
+let held = null;
+let readOnly = false;
+
+function draw(bytes) {
+  for (let i = 0; i < 64 * 32; i++) {
+    const lit = (bytes[i >> 3] >> (7 - (i & 7))) & 1;
+    image.data.set(lit ? [0xfa, 0xfa, 0x10, 0xff] : [0x10, 0x10, 0x10, 0xff], i * 4);
+  }
+  context.putImageData(image, 0, 0);
+}
+
+function send(key) {
+  held = key;
+  if (socket && socket.readyState === WebSocket.OPEN && !readOnly) {
+    socket.send(JSON.stringify({ key }));
+  }
+}
+
+document.getElementById("connect").addEventListener("click", () => {
+  if (socket) {
+    socket.close();
+  }
+  socket = new WebSocket(document.getElementById("address").value);
+  socket.binaryType = "arraybuffer";
+  socket.onopen = () => (status.textContent = "Connected");
+  socket.onclose = () => (status.textContent = "Disconnected");
+  // Binary messages are the display, text ones the server's JSON
+  socket.onmessage = (event) => {
+    if (event.data instanceof ArrayBuffer) {
+      draw(new Uint8Array(event.data));
+    } else {
+      const message = JSON.parse(event.data);
+      if ("read_only" in message) {
+        readOnly = message.read_only;
+        status.textContent = readOnly ? "Watching" : "Connected";
+      }
+    }
+  };
+});
+window.addEventListener("keydown", (event) => {
+  if (event.code in KEYS && !event.repeat) {
+    send(KEYS[event.code]);
+  }
+});
+window.addEventListener("keyup", (event) => {
+  if (KEYS[event.code] === held) {
+    send(null);
+  }
+});